@@ -0,0 +1,40 @@
+use kube::CustomResourceExt;
+use kube_derive::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+/// CustomResource with fields that are only in the schema with the `experimental` feature
+///
+/// Run with and without `--features=experimental` to compare the generated crds.
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Canary", namespaced)]
+pub struct CanarySpec {
+    replicas: u32,
+    #[kube(feature = "experimental", max_length = 63)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    traffic_split: Option<String>,
+    #[kube(feature = "experimental")]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    mirror: Option<String>,
+}
+
+fn main() {
+    println!("{}", serde_yaml::to_string(&Canary::crd()).unwrap());
+}
+
+#[test]
+fn feature_gated_fields_track_the_feature() {
+    use kube::core::crd::FakeApiserver;
+    let crd = Canary::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let spec = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    let enabled = cfg!(feature = "experimental");
+    assert_eq!(spec["properties"].get("traffic_split").is_some(), enabled);
+    assert_eq!(spec["properties"].get("mirror").is_some(), enabled);
+    assert!(spec["properties"].get("replicas").is_some());
+    assert_eq!(spec["required"], serde_json::json!(["replicas"]));
+    if enabled {
+        assert_eq!(spec["properties"]["traffic_split"]["maxLength"], 63);
+    }
+    assert_eq!(FakeApiserver::accept(&crd), Ok(()));
+}
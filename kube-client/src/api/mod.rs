@@ -31,13 +31,15 @@ pub use kube_core::{
     object::{NotUsed, Object, ObjectList},
     request::Request,
     watch::WatchEvent,
-    Resource, ResourceExt,
+    CustomResourceExt, Resource, ResourceExt,
 };
 pub use params::{
     DeleteParams, ListParams, Patch, PatchParams, PostParams, Preconditions, PropagationPolicy,
 };
 
 use crate::Client;
+use k8s_openapi::NamespaceResourceScope;
+use kube_core::crd::CustomResourceScope;
 /// The generic Api abstraction
 ///
 /// This abstracts over a [`Request`] and a type `K` so that
@@ -136,6 +138,42 @@ where
     }
 }
 
+/// Typed [`Api`] constructors called on `#[derive(CustomResource)]` types
+///
+/// `Foo::all(client)` is a self documenting shorthand for `Api::<Foo>::all(client)`. Like it, the url is built from the
+/// group, version and plural compiled into the type, without discovery.
+pub trait CustomResourceApiExt: CustomResourceExt + Resource<DynamicType = ()> + Sized {
+    /// All objects of the resource (across all namespaces for namespaced resources), see [`Api::all`]
+    fn all(client: Client) -> Api<Self> {
+        Api::all(client)
+    }
+
+    /// Objects of a namespaced resource within `ns`, see [`Api::namespaced`]
+    ///
+    /// Only available for `#[kube(namespaced)]` resources:
+    ///
+    /// ```compile_fail
+    /// # use kube::{api::CustomResourceApiExt, Client, CustomResource};
+    /// # use schemars::JsonSchema;
+    /// # use serde::{Deserialize, Serialize};
+    /// #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+    /// #[kube(group = "clux.dev", version = "v1", kind = "Cluster")]
+    /// struct ClusterSpec {}
+    ///
+    /// fn clusters(client: Client) {
+    ///     Cluster::namespaced(client, "default");
+    /// }
+    /// ```
+    fn namespaced(client: Client, ns: &str) -> Api<Self>
+    where
+        Self: CustomResourceScope<Scope = NamespaceResourceScope>,
+    {
+        Api::namespaced(client, ns)
+    }
+}
+
+impl<K: CustomResourceExt + Resource<DynamicType = ()>> CustomResourceApiExt for K {}
+
 impl<K> From<Api<K>> for Client {
     fn from(api: Api<K>) -> Self {
         api.client
@@ -158,3 +196,39 @@ impl<K> Debug for Api<K> {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::CustomResourceApiExt;
+    use crate::Client;
+    use http::{Request, Response};
+    use hyper::Body;
+    use kube::CustomResource;
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+    use tower_test::mock;
+
+    #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+    #[kube(group = "clux.dev", version = "v1", kind = "Foo", namespaced)]
+    struct FooSpec {
+        name: String,
+    }
+
+    #[derive(CustomResource, Deserialize, Serialize, Clone, Debug, JsonSchema)]
+    #[kube(group = "clux.dev", version = "v1", kind = "Bar")]
+    struct BarSpec {
+        name: String,
+    }
+
+    #[tokio::test]
+    async fn custom_resource_apis_use_compiled_urls() {
+        let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(mock_service, "default");
+        assert_eq!(Foo::all(client.clone()).resource_url(), "/apis/clux.dev/v1/foos");
+        assert_eq!(
+            Foo::namespaced(client.clone(), "ns").resource_url(),
+            "/apis/clux.dev/v1/namespaces/ns/foos"
+        );
+        assert_eq!(Bar::all(client).resource_url(), "/apis/clux.dev/v1/bars");
+    }
+}
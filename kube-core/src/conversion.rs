@@ -0,0 +1,271 @@
+//! Contains types for implementing conversion webhooks of custom resources.
+//!
+//! For more information on conversion webhooks, see:
+//! <https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definition-versioning/#webhook-conversion>
+//! <https://github.com/kubernetes/apiextensions-apiserver/blob/master/pkg/apis/apiextensions/v1/types.go>
+
+use crate::{dynamic::DynamicObject, metadata::TypeMeta};
+
+use std::fmt::Display;
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::Status;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+#[error("failed to convert ConversionReview into ConversionRequest")]
+/// Failed to convert `ConversionReview` into `ConversionRequest`.
+pub struct ConvertConversionReviewError;
+
+/// The `kind` field in [`TypeMeta`].
+pub const META_KIND: &str = "ConversionReview";
+/// The `api_version` field in [`TypeMeta`] on the v1 version.
+pub const META_API_VERSION_V1: &str = "apiextensions.k8s.io/v1";
+
+/// The top level struct used for Serializing and Deserializing ConversionReview
+/// requests and responses.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionReview {
+    /// Contains the API version and type of the request.
+    #[serde(flatten)]
+    pub types: TypeMeta,
+    /// Describes the objects to convert.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<ConversionRequest>,
+    /// Describes the converted objects.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(default)]
+    pub response: Option<ConversionResponse>,
+}
+
+impl TryInto<ConversionRequest> for ConversionReview {
+    type Error = ConvertConversionReviewError;
+
+    fn try_into(self) -> Result<ConversionRequest, Self::Error> {
+        match self.request {
+            Some(mut req) => {
+                req.types = self.types;
+                Ok(req)
+            }
+            None => Err(ConvertConversionReviewError),
+        }
+    }
+}
+
+/// An incoming [`ConversionReview`] request.
+/// ```ignore
+/// use kube::core::conversion::{ConversionRequest, ConversionReview};
+///
+/// // The incoming ConversionReview received by the webhook.
+/// let body: ConversionReview;
+/// let req: ConversionRequest = body.try_into().unwrap();
+/// let res = req.for_each(|obj| convert(obj)).into_review();
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversionRequest {
+    /// Copied from the containing [`ConversionReview`] and used to specify
+    /// the response type and version when converting the request into a
+    /// [`ConversionResponse`].
+    #[serde(skip)]
+    pub types: TypeMeta,
+    /// An identifier for the individual request/response. It allows us to
+    /// distinguish instances of requests which are otherwise identical (parallel
+    /// requests, requests when earlier requests did not modify etc). The UID is
+    /// meant to track the round trip (request/response) between the apiserver and
+    /// the webhook, not the user request.
+    pub uid: String,
+    /// The version to convert the objects to, like `clux.dev/v2`.
+    #[serde(rename = "desiredAPIVersion")]
+    pub desired_api_version: String,
+    /// The objects to convert. They may be of different versions.
+    pub objects: Vec<DynamicObject>,
+}
+
+impl ConversionRequest {
+    /// Convert every object of the request with `f`, and respond with the converted objects.
+    ///
+    /// The apiserver requires every object to be returned converted, in order, with the
+    /// [`desired_api_version`](Self::desired_api_version) and the same `metadata.uid`.
+    /// The response fails (without converted objects) if `f` fails for any object, or
+    /// returns an object that does not meet these requirements.
+    pub fn for_each<E: Display>(
+        self,
+        mut f: impl FnMut(DynamicObject) -> Result<DynamicObject, E>,
+    ) -> ConversionResponse {
+        let mut res = ConversionResponse::from(&self);
+        let mut converted = Vec::with_capacity(self.objects.len());
+        for obj in self.objects {
+            let name = obj.metadata.name.clone().unwrap_or_default();
+            let uid = obj.metadata.uid.clone();
+            let obj = match f(obj) {
+                Ok(obj) => obj,
+                Err(err) => return res.fail(format!("failed to convert {}: {}", name, err)),
+            };
+            let api_version = obj.types.as_ref().map(|t| t.api_version.as_str());
+            if api_version != Some(self.desired_api_version.as_str()) {
+                return res.fail(format!(
+                    "converted {} has apiVersion {}, expected {}",
+                    name,
+                    api_version.unwrap_or_default(),
+                    self.desired_api_version
+                ));
+            }
+            if obj.metadata.uid != uid {
+                return res.fail(format!(
+                    "converted {} does not have the uid of the original",
+                    name
+                ));
+            }
+            converted.push(obj);
+        }
+        res.converted_objects = converted;
+        res
+    }
+}
+
+/// An outgoing [`ConversionReview`] response. Constructed from the corresponding
+/// [`ConversionRequest`], usually with [`ConversionRequest::for_each`].
+/// ```ignore
+/// use kube::core::conversion::{ConversionRequest, ConversionResponse};
+///
+/// // The incoming request received by the webhook.
+/// let req: ConversionRequest = body.try_into().unwrap();
+/// let res = ConversionResponse::from(&req)
+///     .fail("conversion to this version is not supported")
+///     .into_review();
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+#[non_exhaustive]
+pub struct ConversionResponse {
+    /// Copied from the corresponding consructing [`ConversionRequest`].
+    #[serde(skip)]
+    types: TypeMeta,
+    /// Identifier for the individual request/response. This must be copied over
+    /// from the corresponding ConversionRequest.
+    pub uid: String,
+    /// Whether the conversion succeeded (status `Success`), and why it failed otherwise.
+    pub result: Status,
+    /// The converted objects, in the order of the request. Empty if the conversion failed.
+    #[serde(default)]
+    pub converted_objects: Vec<DynamicObject>,
+}
+
+impl From<&ConversionRequest> for ConversionResponse {
+    fn from(req: &ConversionRequest) -> Self {
+        Self {
+            types: req.types.clone(),
+            uid: req.uid.clone(),
+            result: Status {
+                status: Some("Success".into()),
+                ..Default::default()
+            },
+            converted_objects: vec![],
+        }
+    }
+}
+
+impl ConversionResponse {
+    /// Fail the conversion with a message. The message will be sent to the original caller.
+    #[must_use]
+    pub fn fail<T: ToString>(mut self, message: T) -> Self {
+        self.result = Status {
+            status: Some("Failure".into()),
+            message: Some(message.to_string()),
+            ..Default::default()
+        };
+        self.converted_objects = vec![];
+        self
+    }
+
+    /// Converts a [`ConversionResponse`] into a [`ConversionReview`] that
+    /// can be used as a webhook response.
+    pub fn into_review(self) -> ConversionReview {
+        ConversionReview {
+            types: self.types.clone(),
+            request: None,
+            response: Some(self),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    const WEBHOOK_BODY: &str = r#"{"kind":"ConversionReview","apiVersion":"apiextensions.k8s.io/v1","request":{"uid":"705ab4f5-6393-11e8-b7cc-42010a800002","desiredAPIVersion":"clux.dev/v2","objects":[{"kind":"Foo","apiVersion":"clux.dev/v1","metadata":{"name":"foo-1","namespace":"default","uid":"0c9a8d74-9cb7-44dd-b98e-09fd62def2f4"},"spec":{"replicas":2}},{"kind":"Foo","apiVersion":"clux.dev/v2","metadata":{"name":"foo-2","namespace":"default","uid":"1d9a8d74-9cb7-44dd-b98e-09fd62def2f5"},"spec":{"size":3}}]}}"#;
+
+    use super::{ConversionRequest, ConversionReview, ConvertConversionReviewError};
+    use crate::{DynamicObject, TypeMeta};
+
+    fn convert(mut obj: DynamicObject) -> Result<DynamicObject, String> {
+        if let Some(replicas) = obj.data["spec"]
+            .as_object_mut()
+            .and_then(|s| s.remove("replicas"))
+        {
+            obj.data["spec"]["size"] = replicas;
+        }
+        obj.types = Some(TypeMeta {
+            api_version: "clux.dev/v2".into(),
+            kind: "Foo".into(),
+        });
+        Ok(obj)
+    }
+
+    #[test]
+    fn converts_every_object_and_preserves_uid() -> Result<(), ConvertConversionReviewError> {
+        let rev = serde_json::from_str::<ConversionReview>(WEBHOOK_BODY).unwrap();
+        let rev_typ = rev.types.clone();
+        let req: ConversionRequest = rev.try_into()?;
+        let res = req.for_each(convert).into_review();
+        assert_eq!(&rev_typ, &res.types);
+
+        let res = serde_json::to_value(res).unwrap();
+        assert_eq!(res["apiVersion"], "apiextensions.k8s.io/v1");
+        assert_eq!(res["response"]["uid"], "705ab4f5-6393-11e8-b7cc-42010a800002");
+        assert_eq!(res["response"]["result"]["status"], "Success");
+        let objects = res["response"]["convertedObjects"].as_array().unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0]["apiVersion"], "clux.dev/v2");
+        assert_eq!(
+            objects[0]["metadata"]["uid"],
+            "0c9a8d74-9cb7-44dd-b98e-09fd62def2f4"
+        );
+        assert_eq!(objects[0]["spec"], serde_json::json!({ "size": 2 }));
+        assert_eq!(
+            objects[1]["metadata"]["uid"],
+            "1d9a8d74-9cb7-44dd-b98e-09fd62def2f5"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn fails_on_errors_and_changed_objects() -> Result<(), ConvertConversionReviewError> {
+        let req: ConversionRequest = serde_json::from_str::<ConversionReview>(WEBHOOK_BODY)
+            .unwrap()
+            .try_into()?;
+        let res = req.clone().for_each(|_| Err::<DynamicObject, _>("unsupported"));
+        assert_eq!(res.result.status.as_deref(), Some("Failure"));
+        assert_eq!(
+            res.result.message.as_deref(),
+            Some("failed to convert foo-1: unsupported")
+        );
+        assert!(res.converted_objects.is_empty());
+
+        let res = req.clone().for_each(|mut obj| {
+            obj.metadata.uid = None;
+            convert(obj)
+        });
+        assert_eq!(
+            res.result.message.as_deref(),
+            Some("converted foo-1 does not have the uid of the original")
+        );
+
+        let res = req.for_each(Ok::<_, String>);
+        assert_eq!(
+            res.result.message.as_deref(),
+            Some("converted foo-1 has apiVersion clux.dev/v1, expected clux.dev/v2")
+        );
+        Ok(())
+    }
+}
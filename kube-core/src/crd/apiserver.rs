@@ -0,0 +1,238 @@
+//! An offline approximation of the apiserver validation of `CustomResourceDefinition`s
+use super::{
+    apiexts::v1::{
+        CustomResourceDefinition as Crd, JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool,
+    },
+    check_structural, names,
+};
+
+/// Validates crds like the apiserver does when they are created, without a cluster
+///
+/// This lets tests assert that a generated crd would be accepted (e.g. `FakeApiserver::accept(&MyCrd::crd())`)
+/// without running a control plane. The checked rules are:
+///
+/// - `metadata.name` is `<plural>.<group>`, the group contains a dot, and the scope is `Namespaced` or `Cluster`
+/// - the names are valid (as checked by [`CrdMerger::lint_names`](super::CrdMerger::lint_names))
+/// - there is at least one version, version names are unique DNS-1035 labels, and exactly one version is stored
+/// - every version has a [structural](super::check_structural) schema without `uniqueItems: true`
+/// - the conversion strategy is `None` without a webhook, or `Webhook` with a client config and a supported
+///   `ConversionReview` version, and webhook conversion is not combined with `preserveUnknownFields`
+///
+/// It is an approximation: admission webhooks, policies and feature gates of real clusters are not considered.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FakeApiserver;
+
+impl FakeApiserver {
+    /// Check whether the apiserver would accept `crd`
+    ///
+    /// # Errors
+    ///
+    /// Returns every reason for rejection, as `"<path>: <reason>"` entries modelled on the apiserver messages.
+    pub fn accept(crd: &Crd) -> Result<(), Vec<String>> {
+        let mut reasons = vec![];
+        let spec = &crd.spec;
+        let expected_name = format!("{}.{}", spec.names.plural, spec.group);
+        let name = crd.metadata.name.as_deref().unwrap_or_default();
+        if name != expected_name {
+            reasons.push(format!(
+                r#"metadata.name: Invalid value: {:?}: must be spec.names.plural+"."+spec.group"#,
+                name
+            ));
+        }
+        if !spec.group.contains('.') {
+            reasons.push(format!(
+                "spec.group: Invalid value: {:?}: should be a domain with at least one dot",
+                spec.group
+            ));
+        }
+        if spec.scope != "Namespaced" && spec.scope != "Cluster" {
+            reasons.push(format!(
+                r#"spec.scope: Unsupported value: {:?}: supported values: "Cluster", "Namespaced""#,
+                spec.scope
+            ));
+        }
+        reasons.extend(names::name_problems(crd));
+
+        if spec.versions.is_empty() {
+            reasons.push("spec.versions: Required value: must have at least one version".into());
+        }
+        for (i, version) in spec.versions.iter().enumerate() {
+            let path = format!("spec.versions[{}]", i);
+            if !names::is_dns1035_label(&version.name) {
+                reasons.push(format!(
+                    "{}.name: Invalid value: {:?}: must be a lowercase DNS-1035 label",
+                    path, version.name
+                ));
+            }
+            if spec.versions[..i].iter().any(|v| v.name == version.name) {
+                reasons.push(format!("{}.name: Duplicate value: {:?}", path, version.name));
+            }
+            let schema_path = format!("{}.schema.openAPIV3Schema", path);
+            match version
+                .schema
+                .as_ref()
+                .and_then(|s| s.open_api_v3_schema.as_ref())
+            {
+                None => reasons.push(format!("{}: Required value: schemas are required", schema_path)),
+                Some(schema) => {
+                    for violation in check_structural(schema) {
+                        reasons.push(format!("{}.{}", schema_path, violation));
+                    }
+                    unique_items(&schema_path, schema, &mut reasons);
+                }
+            }
+        }
+        let storage = spec.versions.iter().filter(|v| v.storage).count();
+        if !spec.versions.is_empty() && storage != 1 {
+            reasons.push(format!(
+                "spec.versions: Invalid value: {} storage versions: must have exactly one version marked as storage version",
+                storage
+            ));
+        }
+
+        if let Some(conversion) = &spec.conversion {
+            match (conversion.strategy.as_str(), &conversion.webhook) {
+                ("None", None) => {}
+                ("None", Some(_)) => reasons
+                    .push("spec.conversion.webhook: Forbidden: should be null if strategy is not set to Webhook".into()),
+                ("Webhook", None) => reasons
+                    .push("spec.conversion.webhook: Required value: required when strategy is set to Webhook".into()),
+                ("Webhook", Some(webhook)) => {
+                    if webhook.client_config.is_none() {
+                        reasons.push(
+                            "spec.conversion.webhook.clientConfig: Required value: required when strategy is set to Webhook"
+                                .into(),
+                        );
+                    }
+                    if !webhook
+                        .conversion_review_versions
+                        .iter()
+                        .any(|v| v == "v1" || v == "v1beta1")
+                    {
+                        reasons.push(
+                            "spec.conversion.webhook.conversionReviewVersions: Invalid value: must include at least one of v1, v1beta1"
+                                .into(),
+                        );
+                    }
+                    if spec.preserve_unknown_fields == Some(true) {
+                        reasons.push(
+                            "spec.conversion.strategy: Invalid value: \"Webhook\": must be None if spec.preserveUnknownFields is true"
+                                .into(),
+                        );
+                    }
+                }
+                (strategy, _) => reasons.push(format!(
+                    r#"spec.conversion.strategy: Unsupported value: {:?}: supported values: "None", "Webhook""#,
+                    strategy
+                )),
+            }
+        }
+
+        if reasons.is_empty() {
+            Ok(())
+        } else {
+            Err(reasons)
+        }
+    }
+}
+
+/// Report every node of `schema` that sets `uniqueItems: true`
+fn unique_items(path: &str, schema: &JSONSchemaProps, reasons: &mut Vec<String>) {
+    if schema.unique_items == Some(true) {
+        reasons.push(format!(
+            "{}.uniqueItems: Forbidden: uniqueItems cannot be set to true since the runtime complexity becomes quadratic",
+            path
+        ));
+    }
+    for (name, prop) in schema.properties.iter().flatten() {
+        unique_items(&format!("{}.properties[{}]", path, name), prop, reasons);
+    }
+    match &schema.items {
+        Some(JSONSchemaPropsOrArray::Schema(items)) => {
+            unique_items(&format!("{}.items", path), items, reasons)
+        }
+        Some(JSONSchemaPropsOrArray::Schemas(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                unique_items(&format!("{}.items[{}]", path, i), item, reasons);
+            }
+        }
+        None => {}
+    }
+    if let Some(JSONSchemaPropsOrBool::Schema(additional)) = &schema.additional_properties {
+        unique_items(&format!("{}.additionalProperties", path), additional, reasons);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Crd, FakeApiserver};
+    use serde_json::json;
+
+    fn crd(spec: serde_json::Value) -> Crd {
+        serde_json::from_value(json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": spec,
+        }))
+        .unwrap()
+    }
+
+    fn version(name: &str, storage: bool) -> serde_json::Value {
+        json!({
+            "name": name,
+            "served": true,
+            "storage": storage,
+            "schema": {
+                "openAPIV3Schema": {
+                    "type": "object",
+                    "properties": { "spec": { "type": "object" } }
+                }
+            }
+        })
+    }
+
+    #[test]
+    fn accepts_valid_crd() {
+        let valid = crd(json!({
+            "group": "clux.dev",
+            "names": { "kind": "Foo", "plural": "foos", "singular": "foo" },
+            "scope": "Namespaced",
+            "versions": [version("v1", false), version("v2", true)],
+            "conversion": {
+                "strategy": "Webhook",
+                "webhook": {
+                    "clientConfig": { "url": "https://conversion.clux.dev/convert" },
+                    "conversionReviewVersions": ["v1"]
+                }
+            }
+        }));
+        assert_eq!(FakeApiserver::accept(&valid), Ok(()));
+    }
+
+    #[test]
+    fn rejects_invalid_crd_with_apiserver_reasons() {
+        let mut v2 = version("v1", true);
+        v2["schema"]["openAPIV3Schema"]["properties"]["spec"] = json!({
+            "type": "object",
+            "properties": { "tags": { "type": "array", "uniqueItems": true, "items": { "type": "string" } } }
+        });
+        let invalid = crd(json!({
+            "group": "dev",
+            "names": { "kind": "Foo", "plural": "foos" },
+            "scope": "Global",
+            "versions": [version("v1", true), v2, { "name": "v3", "served": true, "storage": false }],
+            "conversion": { "strategy": "Webhook" }
+        }));
+        assert_eq!(FakeApiserver::accept(&invalid).unwrap_err(), vec![
+            r#"metadata.name: Invalid value: "foos.clux.dev": must be spec.names.plural+"."+spec.group"#,
+            r#"spec.group: Invalid value: "dev": should be a domain with at least one dot"#,
+            r#"spec.scope: Unsupported value: "Global": supported values: "Cluster", "Namespaced""#,
+            r#"spec.versions[1].name: Duplicate value: "v1""#,
+            "spec.versions[1].schema.openAPIV3Schema.properties[spec].properties[tags].uniqueItems: Forbidden: uniqueItems cannot be set to true since the runtime complexity becomes quadratic",
+            "spec.versions[2].schema.openAPIV3Schema: Required value: schemas are required",
+            "spec.versions: Invalid value: 2 storage versions: must have exactly one version marked as storage version",
+            "spec.conversion.webhook: Required value: required when strategy is set to Webhook",
+        ]);
+    }
+}
@@ -0,0 +1,105 @@
+//! `APIService` manifests accompanying webhook converted `CustomResourceDefinition`s
+use super::apiexts::v1::CustomResourceDefinition as Crd;
+use k8s_openapi::{
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    kube_aggregator::pkg::apis::apiregistration::v1::{APIService, APIServiceSpec, ServiceReference},
+};
+
+/// `groupPriorityMinimum` of the generated `APIService`s, as recommended for non-core groups
+const GROUP_PRIORITY_MINIMUM: i32 = 1000;
+/// `versionPriority` of the generated `APIService`s
+const VERSION_PRIORITY: i32 = 15;
+
+/// A skeleton `APIService` for the storage version of a webhook converted crd, served by `service`
+///
+/// This is for operators that serve the group of the crd through an aggregated apiserver (which also
+/// converts between versions). The `caBundle` is taken from the conversion webhook of the crd, so the
+/// apiserver trusts the same certificate for both. The `APIService` is named `<version>.<group>`.
+///
+/// Note that registering the `APIService` routes all requests for that group version to `service`,
+/// rather than to the crd.
+///
+/// Returns `None` if the crd does not use `Webhook` conversion.
+pub fn conversion_apiservice(crd: &Crd, service: ServiceReference) -> Option<APIService> {
+    let conversion = crd.spec.conversion.as_ref()?;
+    if conversion.strategy != "Webhook" {
+        return None;
+    }
+    let ca_bundle = conversion
+        .webhook
+        .as_ref()
+        .and_then(|w| w.client_config.as_ref())
+        .and_then(|c| c.ca_bundle.clone());
+    let versions = &crd.spec.versions;
+    let version = versions.iter().find(|v| v.storage).or_else(|| versions.first())?;
+    Some(APIService {
+        metadata: ObjectMeta {
+            name: Some(format!("{}.{}", version.name, crd.spec.group)),
+            ..ObjectMeta::default()
+        },
+        spec: Some(APIServiceSpec {
+            ca_bundle,
+            group: Some(crd.spec.group.clone()),
+            group_priority_minimum: GROUP_PRIORITY_MINIMUM,
+            service: Some(service),
+            version: Some(version.name.clone()),
+            version_priority: VERSION_PRIORITY,
+            ..APIServiceSpec::default()
+        }),
+        status: None,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{conversion_apiservice, Crd, ServiceReference};
+    use serde_json::json;
+
+    fn crd(conversion: serde_json::Value) -> Crd {
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [
+                    { "name": "v1", "served": true, "storage": false },
+                    { "name": "v2", "served": true, "storage": true }
+                ],
+                "conversion": conversion
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn webhook_converted_crds_get_an_apiservice() {
+        let webhook = crd(json!({
+            "strategy": "Webhook",
+            "webhook": {
+                "clientConfig": {
+                    "service": { "namespace": "foo-system", "name": "foo-webhook" },
+                    "caBundle": "Y2E="
+                },
+                "conversionReviewVersions": ["v1"]
+            }
+        }));
+        let service = ServiceReference {
+            name: Some("foo-apiserver".into()),
+            namespace: Some("foo-system".into()),
+            port: Some(443),
+        };
+        let apiservice = conversion_apiservice(&webhook, service.clone()).unwrap();
+        assert_eq!(apiservice.metadata.name.as_deref(), Some("v2.clux.dev"));
+        let spec = apiservice.spec.unwrap();
+        assert_eq!(spec.group.as_deref(), Some("clux.dev"));
+        assert_eq!(spec.version.as_deref(), Some("v2"));
+        assert_eq!(spec.service, Some(service.clone()));
+        assert_eq!(spec.ca_bundle.unwrap().0, b"ca");
+
+        assert_eq!(
+            conversion_apiservice(&crd(json!({ "strategy": "None" })), service),
+            None
+        );
+    }
+}
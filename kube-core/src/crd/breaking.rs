@@ -0,0 +1,368 @@
+//! Changes between crds that break existing objects or clients
+use super::{
+    apiexts::v1::{
+        CustomResourceDefinition as Crd, JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool,
+    },
+    compare::version_schema,
+};
+use std::fmt::Display;
+
+/// A change that breaks existing objects or clients, found by [`breaking_changes`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BreakingChange {
+    /// The version of the crd that changed
+    pub version: String,
+    /// Path of the changed field, like `.spec.ports[*].name` (empty for the version itself)
+    pub path: String,
+    /// What changed, like `type changed from integer to string`
+    pub description: String,
+}
+
+/// The changes from `old` to `new` that would break objects stored with, or clients using, a served version of `old`
+///
+/// Reported are, per served version of `old`:
+///
+/// - the version being removed or no longer served
+/// - fields being removed (so their values are pruned) or changing `type`, `format`, or `nullable: true`
+/// - fields becoming required, including new required fields
+/// - enum values being removed, or an enum being added
+/// - tightened constraints: raised minimums, lowered maximums (of values, lengths, items and properties), a new
+///   `pattern` or `multipleOf`, and new `x-kubernetes-validations` rules
+///
+/// Additive changes, like new optional fields or versions, are not breaking.
+pub fn breaking_changes(old: &Crd, new: &Crd) -> Vec<BreakingChange> {
+    let mut changes = vec![];
+    for old_version in old.spec.versions.iter().filter(|v| v.served) {
+        let mut report = |path: &str, description: String| {
+            changes.push(BreakingChange {
+                version: old_version.name.clone(),
+                path: path.to_string(),
+                description,
+            })
+        };
+        match new.spec.versions.iter().find(|v| v.name == old_version.name) {
+            None => report("", "version removed".into()),
+            Some(new_version) if !new_version.served => report("", "version no longer served".into()),
+            Some(new_version) => {
+                if let (Some(old), Some(new)) = (version_schema(old_version), version_schema(new_version)) {
+                    diff_schema("", old, new, &mut report);
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// Whether changing the schema of a version from `old` to `new` should be a new version instead
+///
+/// This is the case for any change [`breaking_changes`] reports for a schema (removed fields, type changes, newly
+/// required fields, tightened constraints), as objects stored with `old` or clients written against it may no
+/// longer work. Additive changes can be made in place. Meant to gate CI against silently breaking a released version.
+pub fn change_requires_new_version(old: &JSONSchemaProps, new: &JSONSchemaProps) -> bool {
+    let mut breaking = false;
+    diff_schema("", old, new, &mut |_: &str, _: String| breaking = true);
+    breaking
+}
+
+fn diff_schema(
+    path: &str,
+    old: &JSONSchemaProps,
+    new: &JSONSchemaProps,
+    report: &mut impl FnMut(&str, String),
+) {
+    match (&old.type_, &new.type_) {
+        (Some(old_type), Some(new_type)) if old_type != new_type => {
+            report(path, format!("type changed from {} to {}", old_type, new_type))
+        }
+        (None, Some(new_type)) if old.x_kubernetes_int_or_string != Some(true) => {
+            report(path, format!("type restricted to {}", new_type))
+        }
+        _ => {}
+    }
+    if old.x_kubernetes_int_or_string == Some(true) && new.x_kubernetes_int_or_string != Some(true) {
+        report(path, "no longer accepts both integers and strings".into());
+    }
+    if new.format.is_some() && old.format != new.format {
+        let format = new.format.as_deref().unwrap_or_default();
+        report(path, format!("format changed to {}", format));
+    }
+    if old.nullable == Some(true) && new.nullable != Some(true) {
+        report(path, "no longer nullable".into());
+    }
+    if old.x_kubernetes_preserve_unknown_fields == Some(true)
+        && new.x_kubernetes_preserve_unknown_fields != Some(true)
+    {
+        report(path, "unknown fields are no longer preserved".into());
+    }
+
+    let old_required = old.required.as_deref().unwrap_or_default();
+    for field in new.required.iter().flatten() {
+        if !old_required.contains(field) {
+            report(&format!("{}.{}", path, field), "field is now required".into());
+        }
+    }
+    if let Some(old_enum) = &old.enum_ {
+        for value in old_enum {
+            if !new.enum_.iter().flatten().any(|v| v == value) {
+                report(path, format!("enum value {} removed", value.0));
+            }
+        }
+    } else if new.enum_.is_some() {
+        report(path, "restricted to enum values".into());
+    }
+
+    raised(path, "minimum", old.minimum, new.minimum, report);
+    lowered(path, "maximum", old.maximum, new.maximum, report);
+    if old.exclusive_minimum != Some(true) && new.exclusive_minimum == Some(true) {
+        report(path, "minimum became exclusive".into());
+    }
+    if old.exclusive_maximum != Some(true) && new.exclusive_maximum == Some(true) {
+        report(path, "maximum became exclusive".into());
+    }
+    raised(path, "minLength", old.min_length, new.min_length, report);
+    lowered(path, "maxLength", old.max_length, new.max_length, report);
+    raised(path, "minItems", old.min_items, new.min_items, report);
+    lowered(path, "maxItems", old.max_items, new.max_items, report);
+    raised(
+        path,
+        "minProperties",
+        old.min_properties,
+        new.min_properties,
+        report,
+    );
+    lowered(
+        path,
+        "maxProperties",
+        old.max_properties,
+        new.max_properties,
+        report,
+    );
+    if new.pattern.is_some() && old.pattern != new.pattern {
+        let pattern = new.pattern.as_deref().unwrap_or_default();
+        report(path, format!("pattern changed to {:?}", pattern));
+    }
+    if new.multiple_of.is_some() && old.multiple_of != new.multiple_of {
+        let multiple_of = new.multiple_of.unwrap_or_default();
+        report(path, format!("multipleOf changed to {}", multiple_of));
+    }
+    // validation rules are only part of crd schemas from Kubernetes 1.23
+    k8s_openapi::k8s_if_ge_1_23! {
+        for rule in new.x_kubernetes_validations.iter().flatten() {
+            if !old
+                .x_kubernetes_validations
+                .iter()
+                .flatten()
+                .any(|r| r.rule == rule.rule)
+            {
+                report(path, format!("validation rule {:?} added", rule.rule));
+            }
+        }
+    }
+
+    let new_properties = new.properties.as_ref();
+    for (field, old_prop) in old.properties.iter().flatten() {
+        let field_path = format!("{}.{}", path, field);
+        match new_properties.and_then(|p| p.get(field)) {
+            Some(new_prop) => diff_schema(&field_path, old_prop, new_prop, report),
+            None if new.x_kubernetes_preserve_unknown_fields != Some(true) => {
+                report(&field_path, "field removed".into())
+            }
+            None => {}
+        }
+    }
+    if let (
+        Some(JSONSchemaPropsOrArray::Schema(old_items)),
+        Some(JSONSchemaPropsOrArray::Schema(new_items)),
+    ) = (&old.items, &new.items)
+    {
+        diff_schema(&format!("{}[*]", path), old_items, new_items, report);
+    }
+    if let (
+        Some(JSONSchemaPropsOrBool::Schema(old_values)),
+        Some(JSONSchemaPropsOrBool::Schema(new_values)),
+    ) = (&old.additional_properties, &new.additional_properties)
+    {
+        diff_schema(&format!("{}[*]", path), old_values, new_values, report);
+    }
+}
+
+/// Report a lower bound that was added or raised
+fn raised<T: PartialOrd + Display>(
+    path: &str,
+    keyword: &str,
+    old: Option<T>,
+    new: Option<T>,
+    report: &mut impl FnMut(&str, String),
+) {
+    match (old, new) {
+        (None, Some(new)) => report(path, format!("{} set to {}", keyword, new)),
+        (Some(old), Some(new)) if new > old => {
+            report(path, format!("{} raised from {} to {}", keyword, old, new))
+        }
+        _ => {}
+    }
+}
+
+/// Report an upper bound that was added or lowered
+fn lowered<T: PartialOrd + Display>(
+    path: &str,
+    keyword: &str,
+    old: Option<T>,
+    new: Option<T>,
+    report: &mut impl FnMut(&str, String),
+) {
+    match (old, new) {
+        (None, Some(new)) => report(path, format!("{} set to {}", keyword, new)),
+        (Some(old), Some(new)) if new < old => {
+            report(path, format!("{} lowered from {} to {}", keyword, old, new))
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{breaking_changes, change_requires_new_version, BreakingChange, Crd, JSONSchemaProps};
+    use serde_json::json;
+
+    fn crd(versions: serde_json::Value) -> Crd {
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": versions
+            }
+        }))
+        .unwrap()
+    }
+
+    fn version(name: &str, spec: serde_json::Value) -> serde_json::Value {
+        json!({
+            "name": name,
+            "served": true,
+            "storage": true,
+            "schema": {
+                "openAPIV3Schema": {
+                    "type": "object",
+                    "properties": { "spec": spec }
+                }
+            }
+        })
+    }
+
+    fn change(version: &str, path: &str, description: &str) -> BreakingChange {
+        BreakingChange {
+            version: version.into(),
+            path: path.into(),
+            description: description.into(),
+        }
+    }
+
+    #[test]
+    fn reports_breaking_but_not_additive_changes() {
+        let old = crd(json!([version(
+            "v1",
+            json!({
+                "type": "object",
+                "properties": {
+                    "replicas": { "type": "integer" },
+                    "mode": { "type": "string", "enum": ["fast", "safe"] }
+                }
+            })
+        )]));
+        let additive = crd(json!([version(
+            "v1",
+            json!({
+                "type": "object",
+                "properties": {
+                    "replicas": { "type": "integer" },
+                    "mode": { "type": "string", "enum": ["fast", "safe", "slow"] },
+                    "paused": { "type": "boolean" }
+                }
+            })
+        )]));
+        assert_eq!(breaking_changes(&old, &additive), vec![]);
+
+        let breaking = crd(json!([version(
+            "v1",
+            json!({
+                "type": "object",
+                "required": ["image"],
+                "properties": {
+                    "replicas": { "type": "string" },
+                    "mode": { "type": "string", "enum": ["fast"] },
+                    "image": { "type": "string", "minLength": 1 }
+                }
+            })
+        )]));
+        assert_eq!(breaking_changes(&old, &breaking), vec![
+            change("v1", ".spec.image", "field is now required"),
+            change("v1", ".spec.mode", r#"enum value "safe" removed"#),
+            change("v1", ".spec.replicas", "type changed from integer to string"),
+        ]);
+    }
+
+    #[test]
+    fn only_breaking_changes_require_a_new_version() {
+        let schema = |value: serde_json::Value| serde_json::from_value::<JSONSchemaProps>(value).unwrap();
+        let old = schema(json!({
+            "type": "object",
+            "properties": { "replicas": { "type": "integer" }, "image": { "type": "string" } }
+        }));
+        let additive = schema(json!({
+            "type": "object",
+            "properties": {
+                "replicas": { "type": "integer" },
+                "image": { "type": "string" },
+                "paused": { "type": "boolean" }
+            }
+        }));
+        assert!(!change_requires_new_version(&old, &old));
+        assert!(!change_requires_new_version(&old, &additive));
+
+        let removed =
+            schema(json!({ "type": "object", "properties": { "replicas": { "type": "integer" } } }));
+        let retyped = schema(json!({
+            "type": "object",
+            "properties": { "replicas": { "type": "string" }, "image": { "type": "string" } }
+        }));
+        let required = schema(json!({
+            "type": "object",
+            "required": ["image"],
+            "properties": { "replicas": { "type": "integer" }, "image": { "type": "string" } }
+        }));
+        assert!(change_requires_new_version(&old, &removed));
+        assert!(change_requires_new_version(&old, &retyped));
+        assert!(change_requires_new_version(&old, &required));
+    }
+
+    #[test]
+    fn reports_removed_versions_and_tightened_constraints() {
+        let old = crd(json!([
+            version(
+                "v1",
+                json!({
+                    "type": "object",
+                    "properties": { "ports": { "type": "array", "items": { "type": "integer", "maximum": 65535 } } }
+                })
+            ),
+            version("v1beta1", json!({ "type": "object" })),
+        ]));
+        let new = crd(json!([version(
+            "v1",
+            json!({
+                "type": "object",
+                "properties": {
+                    "ports": { "type": "array", "maxItems": 8, "items": { "type": "integer", "maximum": 1024 } }
+                }
+            })
+        )]));
+        assert_eq!(breaking_changes(&old, &new), vec![
+            change("v1", ".spec.ports", "maxItems set to 8"),
+            change("v1", ".spec.ports[*]", "maximum lowered from 65535 to 1024"),
+            change("v1beta1", "", "version removed"),
+        ]);
+    }
+}
@@ -0,0 +1,255 @@
+//! Best-effort static cost estimates of CEL validation rules
+use super::apiexts::v1::{JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool};
+
+/// The estimated cost of a rule above which the apiserver rejects a crd (`StaticEstimatedCostLimit`)
+pub const CEL_COST_LIMIT: u64 = 10_000_000;
+
+/// The size assumed for lists, maps and strings without `maxItems`, `maxProperties` or `maxLength`,
+/// which are only bounded by the maximum size of a request (3MiB)
+const UNBOUNDED_SIZE: u64 = 3 * 1024 * 1024;
+
+/// Macros calling their body once per element of a list (or key of a map)
+const MACROS: &[&str] = &["all", "exists", "exists_one", "map", "filter"];
+
+/// Functions traversing the string they are called on
+const STRING_FUNCTIONS: &[&str] = &[
+    "contains",
+    "endsWith",
+    "indexOf",
+    "lastIndexOf",
+    "lowerAscii",
+    "matches",
+    "replace",
+    "split",
+    "startsWith",
+    "trim",
+    "upperAscii",
+];
+
+/// The estimated cost of a CEL rule, see [`estimate_cel_cost`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CostEstimate {
+    /// Worst case cost of evaluating the rule once
+    pub cost: u64,
+    /// Paths of the rule (like `self.hosts`) iterated or traversed without a bound on their size
+    ///
+    /// Setting `maxItems`, `maxProperties` or `maxLength` on these is the usual way to reduce the cost.
+    pub unbounded: Vec<String>,
+}
+
+impl CostEstimate {
+    /// Whether the apiserver is likely to reject the rule for exceeding [`CEL_COST_LIMIT`]
+    pub fn exceeds_limit(&self) -> bool {
+        self.cost > CEL_COST_LIMIT
+    }
+}
+
+/// Estimate the cost of a CEL `rule` of the `x-kubernetes-validations` of `schema`
+///
+/// This is a best-effort static analysis, not the estimate of the apiserver: every path or function call
+/// costs one, macros (`all`, `exists`, `map`, ...) cost their body per element, and string functions
+/// (`contains`, `matches`, ...) cost a tenth of the length of the string. Sizes are taken from `maxItems`,
+/// `maxProperties` and `maxLength`, and collections without them are assumed as large as a request allows.
+/// Only paths that resolve in `schema` (from `self` or macro variables) are estimated this way.
+///
+/// Note that a rule of a schema nested in lists or maps is evaluated once per element, which multiplies
+/// its cost towards the per-crd limit.
+pub fn estimate_cel_cost(rule: &str, schema: &JSONSchemaProps) -> CostEstimate {
+    let mut estimate = CostEstimate::default();
+    estimate.cost = expression_cost(rule, &[("self", schema)], &mut estimate.unbounded);
+    estimate
+}
+
+fn expression_cost(expr: &str, scope: &[(&str, &JSONSchemaProps)], unbounded: &mut Vec<String>) -> u64 {
+    let bytes = expr.as_bytes();
+    let is_ident_start = |b: u8| b.is_ascii_alphabetic() || b == b'_';
+    let mut cost = 0u64;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'\'' || bytes[i] == b'"' {
+            i = string_end(bytes, i);
+            continue;
+        }
+        if !is_ident_start(bytes[i]) {
+            i += 1;
+            continue;
+        }
+        // a path like `self.spec.ports`, possibly ending in a function call
+        let mut segments = vec![];
+        loop {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            segments.push(&expr[start..i]);
+            if bytes.get(i) == Some(&b'.') && bytes.get(i + 1).map_or(false, |b| is_ident_start(*b)) {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+        if bytes.get(i) != Some(&b'(') {
+            cost = cost.saturating_add(1);
+            continue;
+        }
+        let end = closing_paren(bytes, i);
+        let args = &expr[i + 1..end];
+        i = end + 1;
+        let (function, receiver) = segments.split_last().expect("paths have a segment");
+        let node = match receiver {
+            [] => None,
+            [var, path @ ..] => resolve(scope, var, path),
+        };
+        let size = |unbounded: &mut Vec<String>| -> u64 {
+            node.map_or(1, |node| match bound(node) {
+                Some(bound) => bound,
+                None => {
+                    let path = receiver.join(".");
+                    if !unbounded.contains(&path) {
+                        unbounded.push(path);
+                    }
+                    UNBOUNDED_SIZE
+                }
+            })
+        };
+        let call_cost = match args.split_once(',') {
+            Some((var, body)) if MACROS.contains(function) && !receiver.is_empty() => {
+                let mut inner = scope.to_vec();
+                if let Some(element) = node.and_then(element) {
+                    inner.push((var.trim(), element));
+                }
+                let size = size(unbounded);
+                size.saturating_mul(expression_cost(body, &inner, unbounded).saturating_add(1))
+            }
+            _ if STRING_FUNCTIONS.contains(function) && !receiver.is_empty() => {
+                let traversal = size(unbounded) / 10 + 1;
+                traversal.saturating_add(expression_cost(args, scope, unbounded))
+            }
+            _ => expression_cost(args, scope, unbounded).saturating_add(1),
+        };
+        cost = cost.saturating_add(call_cost);
+    }
+    cost
+}
+
+/// The schema of `var.path`, if `var` is in scope and the path exists in its schema
+fn resolve<'a>(
+    scope: &[(&str, &'a JSONSchemaProps)],
+    var: &str,
+    path: &[&str],
+) -> Option<&'a JSONSchemaProps> {
+    let (_, mut node) = scope.iter().rev().find(|(name, _)| *name == var)?;
+    for segment in path {
+        let prop = node.properties.as_ref().and_then(|props| props.get(*segment));
+        node = match (prop, &node.additional_properties) {
+            (Some(prop), _) => prop,
+            (None, Some(JSONSchemaPropsOrBool::Schema(additional))) => additional,
+            (None, _) => return None,
+        };
+    }
+    Some(node)
+}
+
+/// The maximum size of a list, string or map
+fn bound(node: &JSONSchemaProps) -> Option<u64> {
+    let bound = match node.type_.as_deref() {
+        Some("array") => node.max_items,
+        Some("string") => node.max_length,
+        _ => node.max_properties,
+    };
+    bound.map(|b| b.max(0) as u64)
+}
+
+/// The schema of the elements of a list, which macros bind to their variable
+fn element(node: &JSONSchemaProps) -> Option<&JSONSchemaProps> {
+    match &node.items {
+        Some(JSONSchemaPropsOrArray::Schema(items)) => Some(items),
+        _ => None,
+    }
+}
+
+/// The index after the string literal starting at `start`
+fn string_end(bytes: &[u8], start: usize) -> usize {
+    let quote = bytes[start];
+    let mut i = start + 1;
+    while i < bytes.len() && bytes[i] != quote {
+        i += if bytes[i] == b'\\' { 2 } else { 1 };
+    }
+    i + 1
+}
+
+/// The index of the parenthesis closing the one at `open` (or the end of the expression)
+fn closing_paren(bytes: &[u8], open: usize) -> usize {
+    let mut depth = 0;
+    let mut i = open;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'\'' | b'"' => {
+                i = string_end(bytes, i);
+                continue;
+            }
+            b'(' => depth += 1,
+            b')' => {
+                depth -= 1;
+                if depth == 0 {
+                    return i;
+                }
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    bytes.len()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{estimate_cel_cost, JSONSchemaProps, UNBOUNDED_SIZE};
+    use serde_json::json;
+
+    fn schema() -> JSONSchemaProps {
+        serde_json::from_value(json!({
+            "type": "object",
+            "properties": {
+                "hosts": { "type": "array", "items": { "type": "string" } },
+                "ports": {
+                    "type": "array",
+                    "maxItems": 16,
+                    "items": {
+                        "type": "object",
+                        "properties": { "name": { "type": "string", "maxLength": 15 } }
+                    }
+                }
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn iterating_unbounded_lists_is_expensive() {
+        let schema = schema();
+        let estimate = estimate_cel_cost("self.hosts.all(h, self.hosts.exists_one(o, o == h))", &schema);
+        assert!(estimate.exceeds_limit());
+        assert!(estimate.cost > UNBOUNDED_SIZE * UNBOUNDED_SIZE);
+        assert_eq!(estimate.unbounded, vec!["self.hosts"]);
+
+        let estimate = estimate_cel_cost("self.hosts.all(h, h.startsWith('*.'))", &schema);
+        assert!(estimate.exceeds_limit());
+        assert_eq!(estimate.unbounded, vec!["self.hosts", "h"]);
+    }
+
+    #[test]
+    fn bounded_rules_are_cheap() {
+        let schema = schema();
+        let estimate = estimate_cel_cost(
+            "self.ports.all(p, p.name.startsWith('http') || p.name == 'grpc')",
+            &schema,
+        );
+        assert!(!estimate.exceeds_limit());
+        assert!(estimate.cost < 100, "{}", estimate.cost);
+        assert!(estimate.unbounded.is_empty());
+
+        let estimate = estimate_cel_cost("has(self.hosts) && size(self.hosts) > 0", &schema);
+        assert_eq!(estimate.cost, 4);
+    }
+}
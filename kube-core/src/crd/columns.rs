@@ -0,0 +1,182 @@
+//! The printer columns `kubectl get` shows for the versions of a crd
+use super::{
+    apiexts::v1::{CustomResourceColumnDefinition, CustomResourceDefinition as Crd},
+    compare::version_schema,
+    schema_paths,
+};
+
+/// A column of a version of a crd, as listed by [`printer_columns_for_version`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrinterColumn {
+    /// The column, with the definition of an `additionalPrinterColumns` entry
+    pub column: CustomResourceColumnDefinition,
+    /// Whether the column is added by the apiserver (`Name` and `Age`) rather than declared by the crd
+    pub builtin: bool,
+    /// Why the `jsonPath` of the column does not resolve in the schema of the version, if it does not
+    ///
+    /// Such columns are not rejected by the apiserver, but are always empty.
+    pub problem: Option<String>,
+}
+
+/// The printer columns of the served `version` of `crd`, in the order `kubectl get` shows them
+///
+/// Like the apiserver, this is the `Name` column followed by the `additionalPrinterColumns` of the version,
+/// or followed by an `Age` column if the version declares none. The `jsonPath` of every declared column
+/// is checked against the schema of the version (paths into `metadata` are not checked, and neither are
+/// the columns of versions without a schema).
+///
+/// Returns no columns if `version` is not a served version of `crd`.
+pub fn printer_columns_for_version(crd: &Crd, version: &str) -> Vec<PrinterColumn> {
+    let version = match crd.spec.versions.iter().find(|v| v.name == version && v.served) {
+        Some(version) => version,
+        None => return vec![],
+    };
+    let paths = version_schema(version).map(schema_paths);
+    let builtin = |name: &str, type_: &str, json_path: &str, description: &str| PrinterColumn {
+        column: CustomResourceColumnDefinition {
+            name: name.into(),
+            type_: type_.into(),
+            json_path: json_path.into(),
+            description: Some(description.into()),
+            ..CustomResourceColumnDefinition::default()
+        },
+        builtin: true,
+        problem: None,
+    };
+
+    let mut columns = vec![builtin(
+        "Name",
+        "string",
+        ".metadata.name",
+        "Name must be unique within a namespace.",
+    )];
+    let declared = version.additional_printer_columns.iter().flatten();
+    columns.extend(declared.map(|column| {
+        PrinterColumn {
+            column: column.clone(),
+            builtin: false,
+            problem: paths
+                .as_ref()
+                .and_then(|paths| path_problem(&column.json_path, paths)),
+        }
+    }));
+    if columns.len() == 1 {
+        columns.push(builtin(
+            "Age",
+            "date",
+            ".metadata.creationTimestamp",
+            "CreationTimestamp is a timestamp representing the server time when this object was created.",
+        ));
+    }
+    columns
+}
+
+/// Why `json_path` (like `.status.conditions[0].type`) is not one of the schema `paths`, if it is not
+fn path_problem(json_path: &str, paths: &[String]) -> Option<String> {
+    let path = json_path.strip_prefix('.').unwrap_or(json_path);
+    if path == "metadata" || path.starts_with("metadata.") {
+        return None;
+    }
+    // list indices (and filters) address the items of a list, like `[*]` in schema paths
+    let mut normalized = String::new();
+    let mut rest = path;
+    while let Some(open) = rest.find('[') {
+        normalized.push_str(&rest[..open]);
+        normalized.push_str("[*]");
+        rest = rest[open..]
+            .find(']')
+            .map_or("", |close| &rest[open + close + 1..]);
+    }
+    normalized.push_str(rest);
+    if paths.contains(&normalized) {
+        None
+    } else {
+        Some(format!("{} is not a field of the schema", json_path))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{printer_columns_for_version, Crd};
+    use serde_json::json;
+
+    fn crd(columns: serde_json::Value) -> Crd {
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [
+                    { "name": "v1", "served": false, "storage": false },
+                    {
+                        "name": "v2",
+                        "served": true,
+                        "storage": true,
+                        "additionalPrinterColumns": columns,
+                        "schema": {
+                            "openAPIV3Schema": {
+                                "type": "object",
+                                "properties": {
+                                    "status": {
+                                        "type": "object",
+                                        "properties": {
+                                            "phase": { "type": "string" },
+                                            "conditions": {
+                                                "type": "array",
+                                                "items": {
+                                                    "type": "object",
+                                                    "properties": { "type": { "type": "string" } }
+                                                }
+                                            }
+                                        }
+                                    }
+                                }
+                            }
+                        }
+                    }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn columns_follow_the_builtin_name_column() {
+        let crd = crd(json!([
+            { "name": "Phase", "type": "string", "jsonPath": ".status.phase" },
+            { "name": "Condition", "type": "string", "jsonPath": ".status.conditions[0].type" },
+            { "name": "Ready", "type": "string", "jsonPath": ".status.ready" },
+            { "name": "Created", "type": "date", "jsonPath": ".metadata.creationTimestamp" }
+        ]));
+        let columns = printer_columns_for_version(&crd, "v2");
+        let names = columns.iter().map(|c| c.column.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Name", "Phase", "Condition", "Ready", "Created"]);
+        let builtin = columns.iter().map(|c| c.builtin).collect::<Vec<_>>();
+        assert_eq!(builtin, vec![true, false, false, false, false]);
+        let problems = columns.iter().map(|c| c.problem.as_deref()).collect::<Vec<_>>();
+        assert_eq!(problems, vec![
+            None,
+            None,
+            None,
+            Some(".status.ready is not a field of the schema"),
+            None
+        ]);
+
+        assert!(printer_columns_for_version(&crd, "v1").is_empty());
+        assert!(printer_columns_for_version(&crd, "v3").is_empty());
+    }
+
+    #[test]
+    fn versions_without_columns_get_age() {
+        let columns = printer_columns_for_version(&crd(json!(null)), "v2");
+        let columns = columns
+            .iter()
+            .map(|c| (c.column.name.as_str(), c.column.json_path.as_str(), c.builtin))
+            .collect::<Vec<_>>();
+        assert_eq!(columns, vec![
+            ("Name", ".metadata.name", true),
+            ("Age", ".metadata.creationTimestamp", true)
+        ]);
+    }
+}
@@ -0,0 +1,470 @@
+//! Comparisons between versions of `CustomResourceDefinition`s
+use super::apiexts::v1::{
+    CustomResourceConversion, CustomResourceDefinition as Crd, JSONSchemaProps, JSONSchemaPropsOrArray,
+    JSONSchemaPropsOrBool,
+};
+use crate::Version;
+use serde_json::Value;
+
+/// Whether a crd needs a conversion strategy other than `None`
+///
+/// This is the case when more than one version is served, and the served versions do not all have identical schemas.
+/// With `None` conversion the apiserver only rewrites the `apiVersion` of objects, so clients reading through
+/// a version with a different schema would see objects that do not match it.
+///
+/// Unserved versions are ignored, as objects can not be read or written through them.
+pub fn requires_conversion(crd: &Crd) -> bool {
+    let mut schemas = crd.spec.versions.iter().filter(|v| v.served).map(version_schema);
+    match schemas.next() {
+        None => false,
+        Some(first) => schemas.any(|schema| schema != first),
+    }
+}
+
+/// A field that differs between two crds, found by [`semantic_diff`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrdFieldDiff {
+    /// Path of the field, like `spec.versions[0].served`
+    pub path: String,
+    /// Value of the field in the first crd (`None` if unset)
+    pub left: Option<Value>,
+    /// Value of the field in the second crd (`None` if unset)
+    pub right: Option<Value>,
+}
+
+/// Whether two crds are equivalent, ignoring ordering and server-side additions
+///
+/// See [`semantic_diff`] for what is normalized before comparing.
+pub fn semantic_eq(a: &Crd, b: &Crd) -> bool {
+    semantic_diff(a, b).is_empty()
+}
+
+/// The fields that differ between two crds, ignoring ordering and server-side additions
+///
+/// Before comparing, both crds are normalized so that a crd read back from the apiserver compares equal to the
+/// crd that was applied:
+///
+/// - versions are sorted by descending [`Version`] priority, and `shortNames` and `categories` are sorted
+/// - `status` and server managed metadata (`resourceVersion`, `generation`, `uid`, `creationTimestamp`,
+///   `managedFields`, ..) are ignored
+/// - server defaults (`None` conversion, `singular` and `listKind` names, `preserveUnknownFields: false`) are filled in
+/// - schemas are normalized with [`normalize_schema`]
+///
+/// Paths of versions refer to the sorted versions.
+pub fn semantic_diff(a: &Crd, b: &Crd) -> Vec<CrdFieldDiff> {
+    let a = serde_json::to_value(normalize(a)).unwrap_or_default();
+    let b = serde_json::to_value(normalize(b)).unwrap_or_default();
+    let mut diffs = vec![];
+    diff_values("", Some(&a), Some(&b), &mut diffs);
+    diffs
+}
+
+pub(crate) fn normalize(crd: &Crd) -> Crd {
+    let mut crd = crd.clone();
+    crd.status = None;
+    let meta = &mut crd.metadata;
+    meta.resource_version = None;
+    meta.generation = None;
+    meta.managed_fields = None;
+    meta.uid = None;
+    meta.creation_timestamp = None;
+    meta.self_link = None;
+
+    let spec = &mut crd.spec;
+    spec.versions
+        .sort_by_cached_key(|v| std::cmp::Reverse(Version::parse(&v.name).priority()));
+    if spec.conversion.is_none() {
+        spec.conversion = Some(CustomResourceConversion {
+            strategy: "None".into(),
+            webhook: None,
+        });
+    }
+    if spec.preserve_unknown_fields == Some(false) {
+        spec.preserve_unknown_fields = None;
+    }
+    let names = &mut spec.names;
+    if names.singular.is_none() {
+        names.singular = Some(names.kind.to_ascii_lowercase());
+    }
+    if names.list_kind.is_none() {
+        names.list_kind = Some(format!("{}List", names.kind));
+    }
+    for list in [&mut names.short_names, &mut names.categories] {
+        if let Some(list) = list {
+            list.sort();
+        }
+        if list.as_ref().map_or(false, Vec::is_empty) {
+            *list = None;
+        }
+    }
+    for version in &mut spec.versions {
+        if let Some(schema) = version
+            .schema
+            .as_mut()
+            .and_then(|s| s.open_api_v3_schema.as_mut())
+        {
+            normalize_schema(schema);
+        }
+    }
+    crd
+}
+
+/// Canonicalize a schema in place, so logically identical schemas serialize to identical bytes
+///
+/// On every node, `required` is sorted and deduplicated (and dropped when empty), and the defaults
+/// `nullable: false`, `uniqueItems: false` and `x-kubernetes-preserve-unknown-fields: false` are dropped.
+/// Object keys need no sorting, as properties are kept in sorted maps. Normalizing is idempotent.
+///
+/// This is applied by [`schema_hash`] and [`semantic_diff`].
+pub fn normalize_schema(schema: &mut JSONSchemaProps) {
+    if let Some(required) = &mut schema.required {
+        required.sort();
+        required.dedup();
+    }
+    if schema.required.as_ref().map_or(false, Vec::is_empty) {
+        schema.required = None;
+    }
+    for flag in [
+        &mut schema.nullable,
+        &mut schema.unique_items,
+        &mut schema.x_kubernetes_preserve_unknown_fields,
+    ] {
+        if *flag == Some(false) {
+            *flag = None;
+        }
+    }
+
+    let maps = [
+        &mut schema.properties,
+        &mut schema.pattern_properties,
+        &mut schema.definitions,
+    ];
+    for map in maps.into_iter().flatten() {
+        map.values_mut().for_each(normalize_schema);
+    }
+    let lists = [&mut schema.all_of, &mut schema.any_of, &mut schema.one_of];
+    for list in lists.into_iter().flatten() {
+        list.iter_mut().for_each(normalize_schema);
+    }
+    if let Some(not) = &mut schema.not {
+        normalize_schema(not);
+    }
+    match &mut schema.items {
+        Some(JSONSchemaPropsOrArray::Schema(items)) => normalize_schema(items),
+        Some(JSONSchemaPropsOrArray::Schemas(items)) => items.iter_mut().for_each(normalize_schema),
+        None => {}
+    }
+    for nested in [&mut schema.additional_properties, &mut schema.additional_items] {
+        if let Some(JSONSchemaPropsOrBool::Schema(nested)) = nested {
+            normalize_schema(nested);
+        }
+    }
+}
+
+/// Fields of a crd that do not survive deserialization, found by [`roundtrip_check`]
+#[derive(Debug, thiserror::Error)]
+pub enum RoundtripDiff {
+    /// The json is not a crd at all
+    #[error("invalid crd: {0}")]
+    Invalid(#[source] serde_json::Error),
+    /// Fields that were dropped (with `right: None`) or changed when deserializing the crd
+    #[error("crd fields do not survive deserialization: {}", .0.iter().map(|d| d.path.as_str()).collect::<Vec<_>>().join(", "))]
+    Lossy(Vec<CrdFieldDiff>),
+}
+
+/// Check that a hand written crd survives deserializing into a [`Crd`] and serializing back
+///
+/// Unknown keys, like a misspelled or misplaced `x-kubernetes-*` extension, are silently dropped when a crd is
+/// deserialized, so the applied crd lacks them. This reports every such field as a [`CrdFieldDiff`] whose `left` is the
+/// original value. `null`s and the representation of numbers (`1` vs `1.0`) are not considered changes.
+///
+/// This takes json (use `serde_yaml::from_str::<serde_json::Value>` for yaml), as a [`Crd`] has already lost the fields.
+///
+/// # Errors
+///
+/// Returns [`RoundtripDiff::Invalid`] if the json is not a crd, and [`RoundtripDiff::Lossy`] if fields were lost.
+pub fn roundtrip_check(crd: &Value) -> Result<(), RoundtripDiff> {
+    let parsed: Crd = serde_json::from_value(crd.clone()).map_err(RoundtripDiff::Invalid)?;
+    let roundtripped = serde_json::to_value(&parsed).map_err(RoundtripDiff::Invalid)?;
+    let mut diffs = vec![];
+    diff_values(
+        "",
+        Some(&canonical_json(crd.clone())),
+        Some(&canonical_json(roundtripped)),
+        &mut diffs,
+    );
+    if diffs.is_empty() {
+        Ok(())
+    } else {
+        Err(RoundtripDiff::Lossy(diffs))
+    }
+}
+
+/// Json without `null` fields, and with all numbers as floats
+fn canonical_json(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, canonical_json(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(canonical_json).collect()),
+        Value::Number(n) => n
+            .as_f64()
+            .and_then(serde_json::Number::from_f64)
+            .map_or(Value::Number(n), Value::Number),
+        value => value,
+    }
+}
+
+fn diff_values(path: &str, a: Option<&Value>, b: Option<&Value>, diffs: &mut Vec<CrdFieldDiff>) {
+    let join = |segment: &str| {
+        if path.is_empty() {
+            segment.to_string()
+        } else {
+            format!("{}.{}", path, segment)
+        }
+    };
+    match (a, b) {
+        (Some(Value::Object(a)), Some(Value::Object(b))) => {
+            let keys = a.keys().chain(b.keys().filter(|k| !a.contains_key(*k)));
+            for key in keys {
+                diff_values(&join(key), a.get(key), b.get(key), diffs);
+            }
+        }
+        (Some(Value::Array(a)), Some(Value::Array(b))) if a.len() == b.len() => {
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                diff_values(&format!("{}[{}]", path, i), Some(a), Some(b), diffs);
+            }
+        }
+        (a, b) if a != b => diffs.push(CrdFieldDiff {
+            path: path.to_string(),
+            left: a.cloned(),
+            right: b.cloned(),
+        }),
+        _ => {}
+    }
+}
+
+/// Annotation recording the [`schema_hash`] of a crd, set by [`annotate_schema_hash`]
+pub const SCHEMA_HASH_ANNOTATION: &str = "kube.rs/schema-hash";
+
+/// Record the [`schema_hash`] of a crd in its [`SCHEMA_HASH_ANNOTATION`] annotation
+///
+/// Applying the annotated crd lets later binaries tell whether their schemas changed without comparing them,
+/// see [`CustomResourceExt::matches_live`](super::v1::CustomResourceExt::matches_live).
+pub fn annotate_schema_hash(crd: &mut Crd) {
+    let hash = schema_hash(crd);
+    crd.metadata
+        .annotations
+        .get_or_insert_with(Default::default)
+        .insert(SCHEMA_HASH_ANNOTATION.to_string(), hash);
+}
+
+/// A stable hash of the schemas of all versions of a crd, as 16 hex digits
+///
+/// The hash covers the name and [normalized](normalize_schema) `openAPIV3Schema` of every version
+/// (in declaration order), and nothing else, so it only changes when the schemas do. Unlike [`std::hash::Hash`], it is stable across Rust releases and
+/// platforms, so it can be stored (e.g. in an annotation of the applied crd) and compared by later binaries.
+pub fn schema_hash(crd: &Crd) -> String {
+    let schemas = crd
+        .spec
+        .versions
+        .iter()
+        .map(|v| {
+            let schema = version_schema(v).cloned().map(|mut schema| {
+                normalize_schema(&mut schema);
+                schema
+            });
+            (&v.name, schema)
+        })
+        .collect::<Vec<_>>();
+    let bytes = serde_json::to_vec(&schemas).unwrap_or_default();
+    // 64 bit FNV-1a
+    let hash = bytes.iter().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    format!("{:016x}", hash)
+}
+
+/// The `openAPIV3Schema` of a version (if any)
+pub(crate) fn version_schema(
+    version: &super::apiexts::v1::CustomResourceDefinitionVersion,
+) -> Option<&JSONSchemaProps> {
+    version
+        .schema
+        .as_ref()
+        .and_then(|s| s.open_api_v3_schema.as_ref())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        annotate_schema_hash, normalize_schema, requires_conversion, roundtrip_check, schema_hash,
+        semantic_diff, semantic_eq, CrdFieldDiff, RoundtripDiff, SCHEMA_HASH_ANNOTATION,
+    };
+    use crate::crd::CrdMerger;
+    use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+        CustomResourceDefinition as Crd, JSONSchemaProps,
+    };
+
+    fn crd(version: &str, spec_props: serde_json::Value) -> Crd {
+        serde_json::from_value(serde_json::json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [{
+                    "name": version,
+                    "served": true,
+                    "storage": false,
+                    "schema": {
+                        "openAPIV3Schema": {
+                            "type": "object",
+                            "properties": { "spec": { "type": "object", "properties": spec_props } }
+                        }
+                    }
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn normalized_schemas_are_byte_identical() {
+        let a: JSONSchemaProps = serde_json::from_value(serde_json::json!({
+            "type": "object",
+            "required": ["b", "a"],
+            "nullable": false,
+            "properties": {
+                "b": { "type": "array", "items": { "type": "object", "required": ["y", "x", "y"] } },
+                "a": { "type": "string", "nullable": false }
+            }
+        }))
+        .unwrap();
+        let b: JSONSchemaProps = serde_json::from_value(serde_json::json!({
+            "properties": {
+                "a": { "type": "string" },
+                "b": { "items": { "required": ["x", "y"], "type": "object" }, "type": "array" }
+            },
+            "required": ["a", "b"],
+            "type": "object"
+        }))
+        .unwrap();
+        let normalized = |schema: &JSONSchemaProps| {
+            let mut schema = schema.clone();
+            normalize_schema(&mut schema);
+            serde_json::to_vec(&schema).unwrap()
+        };
+        assert_ne!(serde_json::to_vec(&a).unwrap(), serde_json::to_vec(&b).unwrap());
+        assert_eq!(normalized(&a), normalized(&b));
+
+        let mut twice = a.clone();
+        normalize_schema(&mut twice);
+        normalize_schema(&mut twice);
+        assert_eq!(serde_json::to_vec(&twice).unwrap(), normalized(&a));
+    }
+
+    #[test]
+    fn roundtrip_check_flags_misplaced_extensions() {
+        let mut hand_written = serde_json::to_value(crd(
+            "v1",
+            serde_json::json!({
+                "replicas": { "type": "integer", "minimum": 1 }
+            }),
+        ))
+        .unwrap();
+        assert!(roundtrip_check(&hand_written).is_ok());
+
+        // extensions belong on the schema, not on the version
+        hand_written["spec"]["versions"][0]["x-kubernetes-preserve-unknown-fields"] = true.into();
+        match roundtrip_check(&hand_written) {
+            Err(RoundtripDiff::Lossy(diffs)) => assert_eq!(diffs, vec![CrdFieldDiff {
+                path: "spec.versions[0].x-kubernetes-preserve-unknown-fields".into(),
+                left: Some(true.into()),
+                right: None,
+            }]),
+            other => panic!("expected lossy roundtrip, got {:?}", other),
+        }
+        assert!(matches!(
+            roundtrip_check(&serde_json::json!({ "spec": "foos" })),
+            Err(RoundtripDiff::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn annotate_schema_hash_records_the_hash() {
+        let mut crd = crd("v1", serde_json::json!({ "name": { "type": "string" } }));
+        let hash = schema_hash(&crd);
+        annotate_schema_hash(&mut crd);
+        assert_eq!(crd.metadata.annotations.unwrap()[SCHEMA_HASH_ANNOTATION], hash);
+    }
+
+    #[test]
+    fn schema_hash_only_changes_with_schemas() {
+        let props = serde_json::json!({ "name": { "type": "string" } });
+        let hash = schema_hash(&crd("v1", props.clone()));
+        assert_eq!(hash.len(), 16);
+        assert_eq!(hash, schema_hash(&crd("v1", props.clone())));
+
+        let mut relabelled = crd("v1", props);
+        relabelled.metadata.labels = Some([("a".to_string(), "b".to_string())].into());
+        assert_eq!(hash, schema_hash(&relabelled));
+
+        let extended = serde_json::json!({ "name": { "type": "string" }, "replicas": { "type": "integer" } });
+        assert_ne!(hash, schema_hash(&crd("v1", extended)));
+    }
+
+    #[test]
+    fn identical_schemas_do_not_require_conversion() {
+        let props = serde_json::json!({ "name": { "type": "string" } });
+        let merged = CrdMerger::new(vec![crd("v1", props.clone()), crd("v2", props)])
+            .storage("v2")
+            .merge()
+            .unwrap();
+        assert!(!requires_conversion(&merged));
+    }
+
+    #[test]
+    fn divergent_served_schemas_require_conversion() {
+        let v1 = crd("v1", serde_json::json!({ "name": { "type": "string" } }));
+        let v2 = crd(
+            "v2",
+            serde_json::json!({ "names": { "type": "array", "items": { "type": "string" } } }),
+        );
+        let mut merged = CrdMerger::new(vec![v1, v2]).storage("v2").merge().unwrap();
+        assert!(requires_conversion(&merged));
+
+        merged.spec.versions[0].served = false;
+        assert!(!requires_conversion(&merged));
+    }
+
+    #[test]
+    fn semantic_eq_ignores_ordering_and_server_fields() {
+        let props = serde_json::json!({ "name": { "type": "string" } });
+        let applied = CrdMerger::new(vec![crd("v1", props.clone()), crd("v2", props.clone())])
+            .storage("v2")
+            .merge()
+            .unwrap();
+        let mut live = CrdMerger::new(vec![crd("v2", props.clone()), crd("v1", props)])
+            .storage("v2")
+            .merge()
+            .unwrap();
+        live.metadata.resource_version = Some("1234".into());
+        live.metadata.generation = Some(3);
+        live.spec.names.singular = Some("foo".into());
+        live.spec.names.list_kind = Some("FooList".into());
+        live.spec.preserve_unknown_fields = Some(false);
+        assert!(semantic_eq(&applied, &live));
+
+        live.spec.versions[0].served = false;
+        assert_eq!(semantic_diff(&applied, &live), vec![CrdFieldDiff {
+            path: "spec.versions[0].served".into(),
+            left: Some(true.into()),
+            right: Some(false.into()),
+        }]);
+    }
+}
@@ -0,0 +1,118 @@
+//! Kubernetes version requirements of `CustomResourceDefinition` features
+use super::apiexts::v1::CustomResourceDefinition as Crd;
+use serde_json::Value;
+
+/// Schema extensions, and the minimum Kubernetes version accepting them
+const SCHEMA_FEATURES: &[(&str, (u32, u32))] = &[
+    ("x-kubernetes-list-type", (1, 16)),
+    ("x-kubernetes-list-map-keys", (1, 16)),
+    ("x-kubernetes-map-type", (1, 17)),
+    ("x-kubernetes-validations", (1, 25)),
+];
+
+/// Version fields, and the minimum Kubernetes version accepting them
+const VERSION_FEATURES: &[(&str, (u32, u32))] = &[("deprecated", (1, 19)), ("deprecationWarning", (1, 19))];
+
+/// Parse a `major.minor` Kubernetes version like `1.25`, `v1.25` or `1.25.3`
+pub(crate) fn parse_kube_version(version: &str) -> Option<(u32, u32)> {
+    let mut parts = version.trim_start_matches('v').split('.');
+    let major = parts.next()?.parse().ok()?;
+    let minor = parts.next()?.parse().ok()?;
+    Some((major, minor))
+}
+
+/// The first feature of `crd` that is not supported on Kubernetes `target`, with the version it requires
+pub(crate) fn unsupported_feature(crd: &Crd, target: (u32, u32)) -> Option<(&'static str, (u32, u32))> {
+    let versions = serde_json::to_value(&crd.spec.versions).unwrap_or_default();
+    let versions = versions.as_array().cloned().unwrap_or_default();
+    let used = |feature: &str| {
+        versions
+            .iter()
+            .any(|v| v.get(feature).is_some() || contains_key(&v["schema"], feature))
+    };
+    VERSION_FEATURES
+        .iter()
+        .chain(SCHEMA_FEATURES)
+        .find(|(feature, min)| *min > target && used(feature))
+        .copied()
+}
+
+/// Remove the features of `crd` that Kubernetes `target` does not support
+///
+/// Returns one `"version <name>: <path>: <reason>"` entry per removed feature.
+pub(crate) fn drop_unsupported_features(crd: &mut Crd, target: (u32, u32)) -> Vec<String> {
+    let mut dropped = vec![];
+    for version in &mut crd.spec.versions {
+        let mut value = serde_json::to_value(&*version).unwrap_or_default();
+        let mut paths = vec![];
+        if let Value::Object(fields) = &mut value {
+            for (feature, min) in VERSION_FEATURES {
+                if *min > target && fields.remove(*feature).is_some() {
+                    paths.push((feature.to_string(), *min));
+                }
+            }
+            if let Some(schema) = fields.get_mut("schema") {
+                drop_schema_features("schema", schema, target, &mut paths);
+            }
+        }
+        if paths.is_empty() {
+            continue;
+        }
+        if let Ok(lowered) = serde_json::from_value(value) {
+            *version = lowered;
+        }
+        for (path, (major, minor)) in paths {
+            dropped.push(format!(
+                "version {}: {}: dropped, requires Kubernetes >= {}.{}",
+                version.name, path, major, minor
+            ));
+        }
+    }
+    dropped
+}
+
+fn drop_schema_features(
+    path: &str,
+    value: &mut Value,
+    target: (u32, u32),
+    paths: &mut Vec<(String, (u32, u32))>,
+) {
+    let node = match value {
+        Value::Object(node) => node,
+        _ => return,
+    };
+    for (feature, min) in SCHEMA_FEATURES {
+        if *min > target && node.remove(*feature).is_some() {
+            paths.push((format!("{}.{}", path, feature), *min));
+        }
+    }
+    for (key, value) in node.iter_mut() {
+        match key.as_str() {
+            // literal values, rather than schemas
+            "default" | "enum" | "example" => {}
+            "properties" | "patternProperties" | "definitions" => {
+                if let Value::Object(schemas) = value {
+                    for (name, schema) in schemas.iter_mut() {
+                        drop_schema_features(&format!("{}.{}[{}]", path, key, name), schema, target, paths);
+                    }
+                }
+            }
+            "allOf" | "anyOf" | "oneOf" => {
+                if let Value::Array(schemas) = value {
+                    for (i, schema) in schemas.iter_mut().enumerate() {
+                        drop_schema_features(&format!("{}.{}[{}]", path, key, i), schema, target, paths);
+                    }
+                }
+            }
+            _ => drop_schema_features(&format!("{}.{}", path, key), value, target, paths),
+        }
+    }
+}
+
+fn contains_key(value: &Value, key: &str) -> bool {
+    match value {
+        Value::Object(map) => map.contains_key(key) || map.values().any(|v| contains_key(v, key)),
+        Value::Array(values) => values.iter().any(|v| contains_key(v, key)),
+        _ => false,
+    }
+}
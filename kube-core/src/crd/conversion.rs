@@ -0,0 +1,101 @@
+//! Conversion webhooks declared next to `#[derive(CustomResource)]` types
+use super::apiexts::v1::{
+    CustomResourceConversion, ServiceReference, WebhookClientConfig, WebhookConversion,
+};
+use k8s_openapi::ByteString;
+
+/// The service serving conversion for a kind, for `#[kube(conversion = "webhook", conversion_webhook = "CONST")]`
+///
+/// This is built in a `const` next to the type, so the crd generated by `crd()` includes its conversion webhook:
+///
+/// ```
+/// use kube_core::crd::ConversionWebhook;
+///
+/// const FOO_CONVERSION: ConversionWebhook = ConversionWebhook::service("foo-system", "foo-webhook").port(8443);
+/// ```
+///
+/// The service is checked when the crds of all versions are merged with `CrdMerger`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConversionWebhook {
+    namespace: &'static str,
+    name: &'static str,
+    path: &'static str,
+    port: i32,
+    ca_bundle: Option<&'static [u8]>,
+}
+
+impl ConversionWebhook {
+    /// The service `name` in `namespace`, on the default path `/convert` and port `443`
+    #[must_use]
+    pub const fn service(namespace: &'static str, name: &'static str) -> Self {
+        Self {
+            namespace,
+            name,
+            path: "/convert",
+            port: 443,
+            ca_bundle: None,
+        }
+    }
+
+    /// Set the url path of the service
+    #[must_use]
+    pub const fn path(mut self, path: &'static str) -> Self {
+        self.path = path;
+        self
+    }
+
+    /// Set the port of the service
+    #[must_use]
+    pub const fn port(mut self, port: i32) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Set the PEM encoded CA certificate the apiserver uses to verify the service (like `include_bytes!("ca.pem")`)
+    #[must_use]
+    pub const fn ca_bundle(mut self, pem: &'static [u8]) -> Self {
+        self.ca_bundle = Some(pem);
+        self
+    }
+
+    /// The `Webhook` conversion of a crd using this service, with `ConversionReview` `v1`
+    pub fn conversion(&self) -> CustomResourceConversion {
+        CustomResourceConversion {
+            strategy: "Webhook".into(),
+            webhook: Some(WebhookConversion {
+                client_config: Some(WebhookClientConfig {
+                    ca_bundle: self.ca_bundle.map(|pem| ByteString(pem.to_vec())),
+                    service: Some(ServiceReference {
+                        namespace: self.namespace.into(),
+                        name: self.name.into(),
+                        path: Some(self.path.into()),
+                        port: Some(self.port),
+                    }),
+                    url: None,
+                }),
+                conversion_review_versions: vec!["v1".into()],
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ConversionWebhook;
+
+    #[test]
+    fn builds_webhook_conversion() {
+        const WEBHOOK: ConversionWebhook = ConversionWebhook::service("foo-system", "foo-webhook")
+            .path("/v1/convert")
+            .port(8443);
+        let conversion = WEBHOOK.conversion();
+        assert_eq!(conversion.strategy, "Webhook");
+        let webhook = conversion.webhook.unwrap();
+        assert_eq!(webhook.conversion_review_versions, vec!["v1"]);
+        let service = webhook.client_config.unwrap().service.unwrap();
+        assert_eq!(service.namespace, "foo-system");
+        assert_eq!(service.name, "foo-webhook");
+        assert_eq!(service.path.as_deref(), Some("/v1/convert"));
+        assert_eq!(service.port, Some(8443));
+    }
+}
@@ -0,0 +1,257 @@
+//! Harvesting of schema defaults from `Default` implementations, and applying them like the apiserver
+use super::apiexts::v1::{JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool};
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+/// The fields of `T::default()` that would be set on an object where all fields are absent
+///
+/// This serializes `T::default()`, and keeps every top level field that is not `null`, with the `null` fields of
+/// nested objects removed as well. The result maps serialized field names to the values suitable as schema `default`s,
+/// and takes all serde attributes (renames, `skip_serializing_if`, custom serializers, ..) into account.
+///
+/// Returns an empty map if `T` does not serialize to an object.
+///
+/// ```
+/// # use serde::Serialize;
+/// #[derive(Serialize)]
+/// #[serde(rename_all = "camelCase")]
+/// struct FooSpec {
+///     replica_count: i32,
+///     name: Option<String>,
+/// }
+///
+/// impl Default for FooSpec {
+///     fn default() -> Self {
+///         Self { replica_count: 1, name: None }
+///     }
+/// }
+///
+/// let defaults = kube_core::crd::extract_defaults::<FooSpec>();
+/// assert_eq!(defaults["replicaCount"], 1);
+/// assert!(!defaults.contains_key("name"));
+/// ```
+pub fn extract_defaults<T: Default + Serialize>() -> Map<String, Value> {
+    match serde_json::to_value(T::default()) {
+        Ok(Value::Object(fields)) => fields
+            .into_iter()
+            .filter(|(_, value)| !value.is_null())
+            .map(|(key, value)| (key, strip_nulls(value)))
+            .collect(),
+        _ => Map::new(),
+    }
+}
+
+fn strip_nulls(value: Value) -> Value {
+    match value {
+        Value::Object(fields) => Value::Object(
+            fields
+                .into_iter()
+                .filter(|(_, value)| !value.is_null())
+                .map(|(key, value)| (key, strip_nulls(value)))
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(strip_nulls).collect()),
+        value => value,
+    }
+}
+
+/// Transform an object like the apiserver does before storing it, by filling in schema defaults and pruning fields
+///
+/// `schema` is the `openAPIV3Schema` of the version of the object. Absent fields with a `default` are set to it,
+/// as are `null` fields that are not `nullable`, and defaults are also applied inside of defaulted values. Then
+/// fields that are not in the `properties` of their object are removed, unless the object sets
+/// `x-kubernetes-preserve-unknown-fields` or `additionalProperties`. The `apiVersion`, `kind` and `metadata` of the
+/// object (and of `x-kubernetes-embedded-resource`s) are kept.
+///
+/// This lets tests assert what a controller reads back after creating an object. Like the apiserver, this does not
+/// validate the object (see [`validate_instance`](super::validate_instance) for that).
+pub fn apply_schema_defaults_and_prune(schema: &JSONSchemaProps, instance: &mut Value) {
+    apply_defaults(schema, instance);
+    prune(schema, instance, true);
+}
+
+fn apply_defaults(schema: &JSONSchemaProps, value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (name, prop) in schema.properties.iter().flatten() {
+                if fields.get(name).map_or(false, Value::is_null) && prop.nullable != Some(true) {
+                    fields.remove(name);
+                }
+                if let (false, Some(default)) = (fields.contains_key(name), &prop.default) {
+                    fields.insert(name.clone(), default.0.clone());
+                }
+                if let Some(field) = fields.get_mut(name) {
+                    apply_defaults(prop, field);
+                }
+            }
+            if let Some(JSONSchemaPropsOrBool::Schema(additional)) = &schema.additional_properties {
+                for field in fields.values_mut() {
+                    apply_defaults(additional, field);
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(JSONSchemaPropsOrArray::Schema(item_schema)) = &schema.items {
+                for item in items {
+                    apply_defaults(item_schema, item);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+fn prune(schema: &JSONSchemaProps, value: &mut Value, embedded: bool) {
+    match value {
+        Value::Object(fields) => {
+            let embedded = embedded || schema.x_kubernetes_embedded_resource == Some(true);
+            let keep_unknown = schema.x_kubernetes_preserve_unknown_fields == Some(true)
+                || matches!(
+                    schema.additional_properties,
+                    Some(JSONSchemaPropsOrBool::Bool(true)) | Some(JSONSchemaPropsOrBool::Schema(_))
+                );
+            let properties = schema.properties.as_ref();
+            fields.retain(|name, _| {
+                keep_unknown
+                    || properties.map_or(false, |p| p.contains_key(name))
+                    || embedded && ["apiVersion", "kind", "metadata"].contains(&name.as_str())
+            });
+            for (name, field) in fields.iter_mut() {
+                match (
+                    properties.and_then(|p| p.get(name)),
+                    &schema.additional_properties,
+                ) {
+                    (Some(prop), _) => prune(prop, field, false),
+                    (None, Some(JSONSchemaPropsOrBool::Schema(additional))) => {
+                        prune(additional, field, false)
+                    }
+                    (None, _) => {}
+                }
+            }
+        }
+        Value::Array(items) => {
+            if let Some(JSONSchemaPropsOrArray::Schema(item_schema)) = &schema.items {
+                for item in items {
+                    prune(item_schema, item, false);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{apply_schema_defaults_and_prune, extract_defaults, JSONSchemaProps};
+    use serde::Serialize;
+    use serde_json::json;
+
+    #[derive(Serialize)]
+    struct Limits {
+        cpu: String,
+        memory: Option<String>,
+    }
+
+    #[derive(Serialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Spec {
+        replica_count: u32,
+        paused: bool,
+        #[serde(rename = "imagePullPolicy")]
+        pull_policy: String,
+        selector: Option<String>,
+        #[serde(skip_serializing_if = "Vec::is_empty")]
+        args: Vec<String>,
+        limits: Limits,
+    }
+
+    impl Default for Spec {
+        fn default() -> Self {
+            Spec {
+                replica_count: 3,
+                paused: false,
+                pull_policy: "IfNotPresent".into(),
+                selector: None,
+                args: vec![],
+                limits: Limits {
+                    cpu: "100m".into(),
+                    memory: None,
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn extracts_serialized_defaults() {
+        let defaults = extract_defaults::<Spec>();
+        assert_eq!(
+            serde_json::Value::Object(defaults),
+            json!({
+                "replicaCount": 3,
+                "paused": false,
+                "imagePullPolicy": "IfNotPresent",
+                "limits": { "cpu": "100m" }
+            })
+        );
+        assert!(extract_defaults::<u32>().is_empty());
+    }
+
+    #[test]
+    fn defaults_and_prunes_like_the_apiserver() {
+        let schema: JSONSchemaProps = serde_json::from_value(json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "replicas": { "type": "integer", "default": 1 },
+                        "image": { "type": "string" },
+                        "resources": {
+                            "type": "object",
+                            "default": {},
+                            "properties": { "cpu": { "type": "string", "default": "100m" } }
+                        },
+                        "ports": {
+                            "type": "array",
+                            "items": {
+                                "type": "object",
+                                "properties": { "protocol": { "type": "string", "default": "TCP" } }
+                            }
+                        },
+                        "extra": { "type": "object", "x-kubernetes-preserve-unknown-fields": true }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let mut instance = json!({
+            "apiVersion": "clux.dev/v1",
+            "kind": "Foo",
+            "metadata": { "name": "foo" },
+            "spec": {
+                "image": "nginx",
+                "replicas": null,
+                "unknown": true,
+                "ports": [{ "name": "http" }],
+                "extra": { "anything": 1 }
+            },
+            "status": {}
+        });
+        apply_schema_defaults_and_prune(&schema, &mut instance);
+        assert_eq!(
+            instance,
+            json!({
+                "apiVersion": "clux.dev/v1",
+                "kind": "Foo",
+                "metadata": { "name": "foo" },
+                "spec": {
+                    "image": "nginx",
+                    "replicas": 1,
+                    "resources": { "cpu": "100m" },
+                    "ports": [{ "protocol": "TCP" }],
+                    "extra": { "anything": 1 }
+                }
+            })
+        );
+    }
+}
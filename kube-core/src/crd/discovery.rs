@@ -0,0 +1,40 @@
+//! The discovery entries the apiserver advertises for the resources of a crd
+use super::apiexts::v1::CustomResourceDefinition as Crd;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::APIResource;
+
+/// The verbs the apiserver serves for every custom resource
+const VERBS: &[&str] = &[
+    "delete",
+    "deletecollection",
+    "get",
+    "list",
+    "patch",
+    "create",
+    "update",
+    "watch",
+];
+
+/// The `APIResource` entry of the resources of a crd in the discovery of its versions
+///
+/// This is the entry the apiserver lists under `/apis/<group>/<version>` for the crd: its plural as `name`, its
+/// singular, kind, scope, shortnames and categories, and every verb. It lets a fake discovery endpoint in tests match
+/// the real crd. Entries of subresources (like `foos/status`) are not included.
+pub fn discovery_entry(crd: &Crd) -> APIResource {
+    let names = &crd.spec.names;
+    APIResource {
+        name: names.plural.clone(),
+        singular_name: names
+            .singular
+            .clone()
+            .unwrap_or_else(|| names.kind.to_ascii_lowercase()),
+        namespaced: crd.spec.scope == "Namespaced",
+        kind: names.kind.clone(),
+        verbs: VERBS.iter().map(|verb| verb.to_string()).collect(),
+        short_names: names.short_names.clone().filter(|names| !names.is_empty()),
+        categories: names
+            .categories
+            .clone()
+            .filter(|categories| !categories.is_empty()),
+        ..APIResource::default()
+    }
+}
@@ -0,0 +1,930 @@
+//! Minimal instances of `CustomResourceDefinition`s, and offline validation of instances against their schemas
+use super::{
+    apiexts::v1::{
+        CustomResourceDefinition as Crd, CustomResourceDefinitionVersion, JSONSchemaProps,
+        JSONSchemaPropsOrArray, JSONSchemaPropsOrBool,
+    },
+    compare::version_schema,
+};
+use crate::{discovery::ApiResource, dynamic::DynamicObject};
+use serde::de::DeserializeOwned;
+use serde_json::{Map, Value};
+use std::{
+    fmt,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+use thiserror::Error;
+
+/// Errors from [`CustomResourceExt::try_from_dynamic`](super::CustomResourceExt::try_from_dynamic)
+#[derive(Error, Debug)]
+pub enum ConversionError {
+    /// The object has another `apiVersion` or `kind` than the type
+    #[error("object is a {found}, expected a {expected}")]
+    TypeMismatch {
+        /// The `apiVersion` and `kind` of the type, like `clux.dev/v1 Foo`
+        expected: String,
+        /// The `apiVersion` and `kind` of the object
+        found: String,
+    },
+
+    /// The object does not match the schema of the type
+    #[error("object does not match the schema: {}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join(", "))]
+    InvalidFields(Vec<FieldViolation>),
+
+    /// The object matches the schema, but can not be deserialized into the type (e.g. due to a custom `Deserialize`
+    /// implementation, or to checks the schema leaves out)
+    #[error("failed to deserialize object: {0}")]
+    Deserialize(#[source] serde_json::Error),
+}
+
+/// A field of an object that violates the schema, as reported by [`validate_instance`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldViolation {
+    /// The path of the field, like `spec.replicas`
+    pub path: String,
+    /// Why the value of the field is invalid, like `Required value`
+    pub reason: String,
+}
+
+impl FieldViolation {
+    pub(crate) fn new(path: &str, reason: String) -> Self {
+        Self {
+            path: path.to_string(),
+            reason,
+        }
+    }
+}
+
+impl fmt::Display for FieldViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.path, self.reason)
+    }
+}
+
+/// A minimal instance of (the storage version of) `crd` named `name`, with every required field set
+///
+/// Required fields are set to their schema `default`, or else to the first `enum` value, or else to the zero value of
+/// their type (adjusted to `minimum`, `minLength` and `minItems` bounds). Optional fields are left out.
+/// The result should pass [`validate_instance`], unless the schema has constraints that are not considered here
+/// (like `pattern`s or most `x-kubernetes-validations`).
+pub fn minimal_instance(crd: &Crd, name: &str) -> DynamicObject {
+    instance(crd, name, false)
+}
+
+/// An example instance of (the storage version of) `crd` named `name`, for documentation and quickstarts
+///
+/// Like [`minimal_instance`], but fields are set to their schema `example` in preference to their `default`, and
+/// optional fields are included when they (or fields below them) have an `example` or a `default`. Lists whose items
+/// have examples get at least one item. The `status` is left out, as it is not set by users.
+pub fn example_instance(crd: &Crd, name: &str) -> DynamicObject {
+    let mut instance = instance(crd, name, true);
+    if let Value::Object(fields) = &mut instance.data {
+        fields.remove("status");
+    }
+    instance
+}
+
+fn instance(crd: &Crd, name: &str, examples: bool) -> DynamicObject {
+    let versions = &crd.spec.versions;
+    let version = versions.iter().find(|v| v.storage).or_else(|| versions.first());
+    let version_name = version.map(|v| v.name.clone()).unwrap_or_default();
+    let resource = ApiResource {
+        group: crd.spec.group.clone(),
+        api_version: format!("{}/{}", crd.spec.group, version_name),
+        version: version_name,
+        kind: crd.spec.names.kind.clone(),
+        plural: crd.spec.names.plural.clone(),
+    };
+    let mut data = version
+        .and_then(version_schema)
+        .map(|schema| instance_value(schema, examples))
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    if let Value::Object(fields) = &mut data {
+        // these are set by DynamicObject itself
+        for field in ["apiVersion", "kind", "metadata"] {
+            fields.remove(field);
+        }
+    }
+    DynamicObject::new(name, &resource).data(data)
+}
+
+/// The smallest value that `schema` accepts, or with `examples`, its example value
+fn instance_value(schema: &JSONSchemaProps, examples: bool) -> Value {
+    if let Some(example) = schema.example.as_ref().filter(|_| examples) {
+        return example.0.clone();
+    }
+    if let Some(default) = &schema.default {
+        return default.0.clone();
+    }
+    if let Some(value) = schema.enum_.iter().flatten().next() {
+        return value.0.clone();
+    }
+    match schema.type_.as_deref() {
+        Some("object") => {
+            let mut fields = Map::new();
+            let required = schema.required.as_deref().unwrap_or_default();
+            for (name, prop) in schema.properties.iter().flatten() {
+                if required.contains(name) || (examples && has_example(prop)) {
+                    fields.insert(name.clone(), instance_value(prop, examples));
+                }
+            }
+            Value::Object(fields)
+        }
+        Some("array") => {
+            let (item, example) = match &schema.items {
+                Some(JSONSchemaPropsOrArray::Schema(items)) => {
+                    (instance_value(items, examples), examples && has_example(items))
+                }
+                _ => (Value::Null, false),
+            };
+            let len = schema.min_items.unwrap_or(0).max(if example { 1 } else { 0 }) as usize;
+            Value::Array(vec![item; len])
+        }
+        Some("string") => match schema.format.as_deref() {
+            Some("date-time") => "1970-01-01T00:00:00Z".into(),
+            Some("date") => "1970-01-01".into(),
+            Some("ipv4") => "0.0.0.0".into(),
+            Some("ipv6") => "::".into(),
+            Some("cidr") => "0.0.0.0/0".into(),
+            _ => "a".repeat(schema.min_length.unwrap_or(0).max(0) as usize).into(),
+        },
+        Some("integer") => {
+            let value = bounded(schema, 0.0, 1.0);
+            (value.ceil() as i64).into()
+        }
+        Some("number") => bounded(schema, 0.0, 1.0).into(),
+        Some("boolean") => false.into(),
+        _ if schema.x_kubernetes_int_or_string == Some(true) => 0.into(),
+        _ => Value::Object(Map::new()),
+    }
+}
+
+/// Whether `schema` or the properties below it have an `example` or a `default`
+fn has_example(schema: &JSONSchemaProps) -> bool {
+    schema.example.is_some()
+        || schema.default.is_some()
+        || schema
+            .properties
+            .iter()
+            .flatten()
+            .any(|(_, prop)| has_example(prop))
+}
+
+/// `zero`, moved into the `minimum`/`maximum` bounds of `schema` (by `step` past exclusive bounds)
+fn bounded(schema: &JSONSchemaProps, zero: f64, step: f64) -> f64 {
+    let mut value = zero;
+    if let Some(minimum) = schema.minimum {
+        let exclusive = schema.exclusive_minimum == Some(true);
+        if value < minimum || (exclusive && value <= minimum) {
+            value = if exclusive { minimum + step } else { minimum };
+        }
+    }
+    if let Some(maximum) = schema.maximum {
+        let exclusive = schema.exclusive_maximum == Some(true);
+        if value > maximum || (exclusive && value >= maximum) {
+            value = if exclusive { maximum - step } else { maximum };
+        }
+    }
+    value
+}
+
+/// Validate an instance against the schema of its version of `crd`, like the apiserver does when it is created
+///
+/// The `apiVersion` and `kind` must be those of a served version of `crd`. The object is then checked against the
+/// `openAPIV3Schema` of that version: `type`s, `nullable`, `required` fields, `enum`s, the `minimum`/`maximum`,
+/// `multipleOf`, `minLength`/`maxLength` and `minItems`/`maxItems` bounds. Unknown fields are not reported
+/// (the apiserver prunes them), `pattern`s are not checked, and of the `format`s only `ipv4`, `ipv6`, `cidr` and `uri` (as an absolute uri) are. Of the `x-kubernetes-validations`,
+/// only rules that test for the presence of fields (like those of `ValidationRule::dependent_required` and
+/// `#[kube(dependent_required)]`) or compare numeric fields are evaluated: combinations of `has(self.field)`, comparisons
+/// (`<`, `<=`, `>`, `>=`, `==`, `!=`) of fields like `self.min`, number literals and counts of present fields (like
+/// `[has(self.a), has(self.b)].filter(x, x).size()` of `ValidationRule::one_of`), `!`, `&&` and `||`.
+///
+/// # Errors
+///
+/// Returns every violation, as `"<path>: <reason>"` entries modelled on the apiserver messages (like `spec.replicas: Required value`).
+pub fn validate_instance(crd: &Crd, instance: &DynamicObject) -> Result<(), Vec<String>> {
+    let violations = violations(crd, instance);
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations.iter().map(ToString::to_string).collect())
+    }
+}
+
+/// The violations of `instance` reported by [`validate_instance`]
+fn violations(crd: &Crd, instance: &DynamicObject) -> Vec<FieldViolation> {
+    let types = instance.types.clone().unwrap_or_default();
+    let version = match served_version(crd, &types.api_version) {
+        Some(version) => version,
+        None => {
+            return vec![FieldViolation::new(
+                "apiVersion",
+                format!(
+                    "Invalid value: {:?}: must be a served version of {}",
+                    types.api_version, crd.spec.group
+                ),
+            )]
+        }
+    };
+    let mut reasons = vec![];
+    if types.kind != crd.spec.names.kind {
+        reasons.push(FieldViolation::new(
+            "kind",
+            format!("Invalid value: {:?}: must be {}", types.kind, crd.spec.names.kind),
+        ));
+    }
+    if let Some(schema) = version_schema(version) {
+        let mut object = serde_json::to_value(instance).unwrap_or_default();
+        if let Value::Object(fields) = &mut object {
+            // metadata is validated by the apiserver itself
+            fields.remove("metadata");
+        }
+        validate_value("", schema, &object, &mut reasons);
+    }
+    reasons
+}
+
+/// Deserialize `obj` as an instance of `crd`, after checking its type and validating it
+pub(crate) fn typed_from_dynamic<K: DeserializeOwned>(
+    crd: &Crd,
+    obj: &DynamicObject,
+) -> Result<K, ConversionError> {
+    let types = obj.types.clone().unwrap_or_default();
+    if served_version(crd, &types.api_version).is_none() || types.kind != crd.spec.names.kind {
+        let served = crd.spec.versions.iter().filter(|v| v.served);
+        let expected = served
+            .map(|v| format!("{}/{} {}", crd.spec.group, v.name, crd.spec.names.kind))
+            .collect::<Vec<_>>();
+        return Err(ConversionError::TypeMismatch {
+            expected: expected.join(" or "),
+            found: format!("{} {}", types.api_version, types.kind),
+        });
+    }
+    let violations = violations(crd, obj);
+    if !violations.is_empty() {
+        return Err(ConversionError::InvalidFields(violations));
+    }
+    let value = serde_json::to_value(obj).map_err(ConversionError::Deserialize)?;
+    serde_json::from_value(value).map_err(ConversionError::Deserialize)
+}
+
+fn served_version<'a>(crd: &'a Crd, api_version: &str) -> Option<&'a CustomResourceDefinitionVersion> {
+    let (group, version) = api_version.split_once('/')?;
+    if group != crd.spec.group {
+        return None;
+    }
+    crd.spec.versions.iter().find(|v| v.name == version && v.served)
+}
+
+/// Whether `value` is of the string `format`, for the formats checked by [`validate_instance`] (others always match)
+fn matches_format(format: &str, value: &str) -> bool {
+    match format {
+        "ipv4" => value.parse::<Ipv4Addr>().is_ok(),
+        "ipv6" => value.parse::<Ipv6Addr>().is_ok(),
+        "cidr" => match value.split_once('/') {
+            Some((ip, prefix)) => match (ip.parse::<IpAddr>(), prefix.parse::<u8>()) {
+                (Ok(IpAddr::V4(_)), Ok(prefix)) => prefix <= 32,
+                (Ok(IpAddr::V6(_)), Ok(prefix)) => prefix <= 128,
+                _ => false,
+            },
+            None => false,
+        },
+        "uri" => match value.split_once(':') {
+            Some((scheme, rest)) => {
+                let mut chars = scheme.chars();
+                chars.next().map_or(false, |c| c.is_ascii_alphabetic())
+                    && chars.all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                    && !rest.is_empty()
+                    && !value.contains(char::is_whitespace)
+            }
+            None => false,
+        },
+        _ => true,
+    }
+}
+
+fn join(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}.{}", path, segment)
+    }
+}
+
+pub(crate) fn validate_value(
+    path: &str,
+    schema: &JSONSchemaProps,
+    value: &Value,
+    reasons: &mut Vec<FieldViolation>,
+) {
+    let path_or_root = if path.is_empty() { "<root>" } else { path };
+    if value.is_null() {
+        if schema.nullable != Some(true) {
+            reasons.push(FieldViolation::new(
+                path_or_root,
+                "Invalid value: \"null\": must not be null".to_string(),
+            ));
+        }
+        return;
+    }
+    if let Some(values) = &schema.enum_ {
+        if !values.iter().any(|allowed| allowed.0 == *value) {
+            let supported = values.iter().map(|v| v.0.to_string()).collect::<Vec<_>>();
+            reasons.push(FieldViolation::new(
+                path_or_root,
+                format!(
+                    "Unsupported value: {}: supported values: {}",
+                    value,
+                    supported.join(", ")
+                ),
+            ));
+        }
+    }
+    let int_or_string = schema.x_kubernetes_int_or_string == Some(true);
+    let type_matches = match schema.type_.as_deref() {
+        _ if int_or_string => value.is_i64() || value.is_u64() || value.is_string(),
+        Some("object") => value.is_object(),
+        Some("array") => value.is_array(),
+        Some("string") => value.is_string(),
+        Some("integer") => value.is_i64() || value.is_u64(),
+        Some("number") => value.is_number(),
+        Some("boolean") => value.is_boolean(),
+        _ => true,
+    };
+    if !type_matches {
+        reasons.push(FieldViolation::new(
+            path_or_root,
+            format!(
+                "Invalid value: {}: must be of type {}",
+                value,
+                schema.type_.as_deref().unwrap_or("integer or string")
+            ),
+        ));
+        return;
+    }
+
+    match value {
+        Value::Object(fields) => {
+            for name in schema.required.iter().flatten() {
+                if !fields.contains_key(name) {
+                    reasons.push(FieldViolation::new(
+                        &join(path, name),
+                        "Required value".to_string(),
+                    ));
+                }
+            }
+            for (rule, message) in validation_rules(schema) {
+                if evaluate_rule(rule, fields) == Some(false) {
+                    let message = match message {
+                        Some(message) => message.to_string(),
+                        None => format!("failed rule: {}", rule),
+                    };
+                    reasons.push(FieldViolation::new(
+                        path_or_root,
+                        format!("Invalid value: \"object\": {}", message),
+                    ));
+                }
+            }
+            // in a stable order, regardless of whether serde_json preserves the order of fields
+            let mut fields = fields.iter().collect::<Vec<_>>();
+            fields.sort_by_key(|(name, _)| *name);
+            for (name, field) in fields {
+                let prop = schema.properties.as_ref().and_then(|props| props.get(name));
+                let additional = match &schema.additional_properties {
+                    Some(JSONSchemaPropsOrBool::Schema(additional)) => Some(&**additional),
+                    _ => None,
+                };
+                if let Some(prop) = prop.or(additional) {
+                    validate_value(&join(path, name), prop, field, reasons);
+                }
+            }
+        }
+        Value::Array(items) => {
+            let len = items.len() as i64;
+            if let Some(min) = schema.min_items.filter(|min| len < *min) {
+                reasons.push(FieldViolation::new(
+                    path_or_root,
+                    format!("Invalid value: {}: must have at least {} items", len, min),
+                ));
+            }
+            if let Some(max) = schema.max_items.filter(|max| len > *max) {
+                reasons.push(FieldViolation::new(
+                    path_or_root,
+                    format!("Too many: {}: must have at most {} items", len, max),
+                ));
+            }
+            if let Some(JSONSchemaPropsOrArray::Schema(item_schema)) = &schema.items {
+                for (i, item) in items.iter().enumerate() {
+                    validate_value(&format!("{}[{}]", path, i), item_schema, item, reasons);
+                }
+            }
+        }
+        Value::String(string) if !int_or_string || schema.type_.as_deref() == Some("string") => {
+            let len = string.chars().count() as i64;
+            if let Some(min) = schema.min_length.filter(|min| len < *min) {
+                reasons.push(FieldViolation::new(
+                    path_or_root,
+                    format!("Invalid value: {:?}: must be at least {} chars long", string, min),
+                ));
+            }
+            if let Some(max) = schema.max_length.filter(|max| len > *max) {
+                reasons.push(FieldViolation::new(
+                    path_or_root,
+                    format!("Too long: may not be more than {} chars", max),
+                ));
+            }
+            if let Some(format) = schema.format.as_deref().filter(|f| !matches_format(f, string)) {
+                reasons.push(FieldViolation::new(
+                    path_or_root,
+                    format!(
+                        "Invalid value: {:?}: {} in body must be of type {}: {:?}",
+                        string, path_or_root, format, string
+                    ),
+                ));
+            }
+        }
+        Value::Number(number) => {
+            let number = number.as_f64().unwrap_or_default();
+            if let Some(minimum) = schema.minimum {
+                let exclusive = schema.exclusive_minimum == Some(true);
+                if number < minimum || (exclusive && number <= minimum) {
+                    let relation = if exclusive {
+                        "greater than"
+                    } else {
+                        "greater than or equal to"
+                    };
+                    reasons.push(FieldViolation::new(
+                        path_or_root,
+                        format!("Invalid value: {}: should be {} {}", number, relation, minimum),
+                    ));
+                }
+            }
+            if let Some(maximum) = schema.maximum {
+                let exclusive = schema.exclusive_maximum == Some(true);
+                if number > maximum || (exclusive && number >= maximum) {
+                    let relation = if exclusive {
+                        "less than"
+                    } else {
+                        "less than or equal to"
+                    };
+                    reasons.push(FieldViolation::new(
+                        path_or_root,
+                        format!("Invalid value: {}: should be {} {}", number, relation, maximum),
+                    ));
+                }
+            }
+            if let Some(multiple_of) = schema.multiple_of {
+                if (number / multiple_of).fract() != 0.0 {
+                    reasons.push(FieldViolation::new(
+                        path_or_root,
+                        format!(
+                            "Invalid value: {}: should be a multiple of {}",
+                            number, multiple_of
+                        ),
+                    ));
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// A token of the subset of CEL that [`evaluate_rule`] understands
+#[derive(Debug, PartialEq)]
+enum Token {
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+    Bool(bool),
+    Has(String),
+    /// The number of present fields, like `[has(self.a), has(self.b)].filter(x, x).size()`
+    Present(Vec<String>),
+    Field(String),
+    Number(f64),
+    Compare(&'static str),
+}
+
+/// Comparison operators, longest first so `<=` is not read as `<`
+const COMPARISONS: &[&str] = &["<=", ">=", "==", "!=", "<", ">"];
+
+fn tokenize(mut rule: &str) -> Option<Vec<Token>> {
+    let mut tokens = vec![];
+    let field_end = |rest: &str| rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'));
+    loop {
+        rule = rule.trim_start();
+        let (token, len) = if rule.is_empty() {
+            return Some(tokens);
+        } else if rule.starts_with("&&") {
+            (Token::And, 2)
+        } else if rule.starts_with("||") {
+            (Token::Or, 2)
+        } else if let Some(op) = COMPARISONS.iter().find(|op| rule.starts_with(**op)) {
+            (Token::Compare(op), op.len())
+        } else if rule.starts_with('!') {
+            (Token::Not, 1)
+        } else if rule.starts_with('(') {
+            (Token::Open, 1)
+        } else if rule.starts_with(')') {
+            (Token::Close, 1)
+        } else if rule.starts_with("true") {
+            (Token::Bool(true), 4)
+        } else if rule.starts_with("false") {
+            (Token::Bool(false), 5)
+        } else if let Some(rest) = rule.strip_prefix('[') {
+            let end = rest.find(']')?;
+            let fields = rest[..end]
+                .split(',')
+                .map(|has| {
+                    let field = has.trim().strip_prefix("has(self.")?.strip_suffix(')')?;
+                    (!field.is_empty() && field_end(field).is_none()).then(|| cel_unescape(field))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let count = ".filter(x, x).size()";
+            if !rest[end + 1..].starts_with(count) {
+                return None;
+            }
+            (Token::Present(fields), "[".len() + end + "]".len() + count.len())
+        } else if let Some(rest) = rule.strip_prefix("self.") {
+            let end = field_end(rest).unwrap_or(rest.len());
+            if end == 0 || rest[end..].starts_with(['.', '(', '[']) {
+                return None;
+            }
+            (Token::Field(cel_unescape(&rest[..end])), "self.".len() + end)
+        } else if rule.starts_with(|c: char| c.is_ascii_digit() || c == '-') {
+            let end = rule[1..]
+                .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                .map_or(rule.len(), |end| end + 1);
+            (Token::Number(rule[..end].parse().ok()?), end)
+        } else {
+            let rest = rule.strip_prefix("has(self.")?;
+            let end = field_end(rest)?;
+            if !rest[end..].starts_with(')') {
+                return None;
+            }
+            (
+                Token::Has(cel_unescape(&rest[..end])),
+                "has(self.".len() + end + 1,
+            )
+        };
+        tokens.push(token);
+        rule = &rule[len..];
+    }
+}
+
+/// The rules and messages of the `x-kubernetes-validations` of a schema
+///
+/// Validation rules are only part of crd schemas from Kubernetes 1.23, so older versions have none.
+fn validation_rules(schema: &JSONSchemaProps) -> Vec<(&str, Option<&str>)> {
+    k8s_openapi::k8s_if_le_1_22! {
+        let _ = schema;
+        vec![]
+    }
+    k8s_openapi::k8s_if_ge_1_23! {
+        schema
+            .x_kubernetes_validations
+            .iter()
+            .flatten()
+            .map(|rule| (rule.rule.as_str(), rule.message.as_deref()))
+            .collect()
+    }
+}
+
+/// Evaluate a CEL rule that only tests for the presence of the `fields` of an object, or compares numeric fields
+/// (or the number of present fields)
+///
+/// Returns `None` if the rule uses anything else, or compares fields that are missing or not numbers
+/// (unless `&&` or `||` decide the rule without that comparison, like `has(self.x) && self.x > 3`).
+fn evaluate_rule(rule: &str, fields: &Map<String, Value>) -> Option<bool> {
+    let tokens = tokenize(rule)?;
+    let mut tokens = tokens.iter().peekable();
+    let result = rule_or(&mut tokens, fields)?;
+    match tokens.next() {
+        None => result,
+        Some(_) => None,
+    }
+}
+
+type Tokens<'a> = std::iter::Peekable<std::slice::Iter<'a, Token>>;
+
+/// The value of a part of a rule: `None` if it uses anything unsupported, and `Some(None)` if its value is unknown
+/// (like a comparison of a missing field), which `&&` and `||` can still short-circuit like CEL does
+type Evaluated = Option<Option<bool>>;
+
+fn rule_or(tokens: &mut Tokens, fields: &Map<String, Value>) -> Evaluated {
+    let mut result = rule_and(tokens, fields)?;
+    while tokens.next_if_eq(&&Token::Or).is_some() {
+        let right = rule_and(tokens, fields)?;
+        result = match (result, right) {
+            (Some(true), _) | (_, Some(true)) => Some(true),
+            (Some(false), Some(false)) => Some(false),
+            _ => None,
+        };
+    }
+    Some(result)
+}
+
+fn rule_and(tokens: &mut Tokens, fields: &Map<String, Value>) -> Evaluated {
+    let mut result = rule_unary(tokens, fields)?;
+    while tokens.next_if_eq(&&Token::And).is_some() {
+        let right = rule_unary(tokens, fields)?;
+        result = match (result, right) {
+            (Some(false), _) | (_, Some(false)) => Some(false),
+            (Some(true), Some(true)) => Some(true),
+            _ => None,
+        };
+    }
+    Some(result)
+}
+
+fn rule_unary(tokens: &mut Tokens, fields: &Map<String, Value>) -> Evaluated {
+    match tokens.next()? {
+        Token::Not => rule_unary(tokens, fields).map(|value| value.map(|value| !value)),
+        Token::Open => {
+            let value = rule_or(tokens, fields)?;
+            tokens.next_if_eq(&&Token::Close)?;
+            Some(value)
+        }
+        Token::Bool(value) => Some(Some(*value)),
+        Token::Has(field) => Some(Some(fields.contains_key(field))),
+        left @ (Token::Field(_) | Token::Number(_) | Token::Present(_)) => {
+            let left = operand(left, fields)?;
+            let op = match tokens.next()? {
+                Token::Compare(op) => *op,
+                _ => return None,
+            };
+            let right = operand(tokens.next()?, fields)?;
+            let (left, right) = match (left, right) {
+                (Some(left), Some(right)) => (left, right),
+                _ => return Some(None),
+            };
+            Some(Some(match op {
+                "<=" => left <= right,
+                ">=" => left >= right,
+                "==" => left == right,
+                "!=" => left != right,
+                "<" => left < right,
+                _ => left > right,
+            }))
+        }
+        Token::And | Token::Or | Token::Close | Token::Compare(_) => None,
+    }
+}
+
+/// The value of a numeric operand of a comparison, which is `Some(None)` for fields that are missing or not numbers
+fn operand(token: &Token, fields: &Map<String, Value>) -> Option<Option<f64>> {
+    match token {
+        Token::Field(field) => Some(fields.get(field).and_then(Value::as_f64)),
+        Token::Number(number) => Some(Some(*number)),
+        Token::Present(present) => Some(Some(
+            present.iter().filter(|f| fields.contains_key(*f)).count() as f64
+        )),
+        _ => None,
+    }
+}
+
+/// The property name of a field escaped for CEL (like `__dash__` for `-`, and `__in__` for the reserved `in`)
+fn cel_unescape(field: &str) -> String {
+    if let Some(reserved) = field.strip_prefix("__").and_then(|f| f.strip_suffix("__")) {
+        if !reserved.contains("__") && !["underscores", "dot", "dash", "slash"].contains(&reserved) {
+            return reserved.to_string();
+        }
+    }
+    let mut name = String::new();
+    let mut rest = field;
+    while let Some(start) = rest.find("__") {
+        name.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let escape = [
+            ("__underscores__", "__"),
+            ("__dot__", "."),
+            ("__dash__", "-"),
+            ("__slash__", "/"),
+        ]
+        .into_iter()
+        .find(|(escaped, _)| rest.starts_with(escaped));
+        match escape {
+            Some((escaped, unescaped)) => {
+                name.push_str(unescaped);
+                rest = &rest[escaped.len()..];
+            }
+            None => {
+                name.push_str("__");
+                rest = &rest[2..];
+            }
+        }
+    }
+    name.push_str(rest);
+    name
+}
+
+#[cfg(test)]
+mod test {
+    use super::{example_instance, minimal_instance, validate_instance, Crd};
+    use serde_json::json;
+
+    fn crd() -> Crd {
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [{
+                    "name": "v1",
+                    "served": true,
+                    "storage": true,
+                    "schema": { "openAPIV3Schema": {
+                        "type": "object",
+                        "required": ["spec"],
+                        "properties": { "spec": {
+                            "type": "object",
+                            "required": ["name", "replicas", "ratio", "mode", "tags", "limits", "paused"],
+                            "properties": {
+                                "name": { "type": "string", "minLength": 3 },
+                                "replicas": { "type": "integer", "minimum": 0, "exclusiveMinimum": true },
+                                "ratio": { "type": "number", "maximum": -1.5 },
+                                "mode": { "type": "string", "enum": ["fast", "slow"] },
+                                "tags": { "type": "array", "minItems": 1, "items": { "type": "string" } },
+                                "limits": {
+                                    "type": "object",
+                                    "required": ["cpu"],
+                                    "properties": { "cpu": { "type": "string", "default": "1" } }
+                                },
+                                "paused": { "type": "boolean" },
+                                "note": { "type": "string" }
+                            }
+                        } }
+                    } }
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn minimal_instances_are_valid() {
+        let crd = crd();
+        let instance = minimal_instance(&crd, "foo");
+        assert_eq!(instance.metadata.name.as_deref(), Some("foo"));
+        assert_eq!(
+            instance.data,
+            json!({
+                "spec": {
+                    "name": "aaa",
+                    "replicas": 1,
+                    "ratio": -1.5,
+                    "mode": "fast",
+                    "tags": [""],
+                    "limits": { "cpu": "1" },
+                    "paused": false
+                }
+            })
+        );
+        assert_eq!(validate_instance(&crd, &instance), Ok(()));
+    }
+
+    #[test]
+    fn example_instances_prefer_examples() {
+        let mut crd = crd();
+        let version = &mut crd.spec.versions[0];
+        let mut schema = serde_json::to_value(version.schema.take().unwrap()).unwrap();
+        let props = &mut schema["openAPIV3Schema"]["properties"];
+        props["spec"]["properties"]["name"]["example"] = json!("my-foo");
+        props["spec"]["properties"]["note"]["example"] = json!("optional note");
+        props["spec"]["properties"]["tags"]["items"]["example"] = json!("blue");
+        props["status"] =
+            json!({ "type": "object", "properties": { "ready": { "type": "boolean", "default": false } } });
+        version.schema = Some(serde_json::from_value(schema).unwrap());
+
+        let instance = example_instance(&crd, "foo");
+        assert_eq!(
+            instance.data,
+            json!({
+                "spec": {
+                    "name": "my-foo",
+                    "replicas": 1,
+                    "ratio": -1.5,
+                    "mode": "fast",
+                    "tags": ["blue"],
+                    "limits": { "cpu": "1" },
+                    "paused": false,
+                    "note": "optional note"
+                }
+            })
+        );
+        assert_eq!(validate_instance(&crd, &instance), Ok(()));
+        // minimal instances ignore examples
+        assert_eq!(minimal_instance(&crd, "foo").data["spec"]["name"], "aaa");
+    }
+
+    #[test]
+    fn invalid_instances_are_rejected_with_apiserver_reasons() {
+        let crd = crd();
+        let instance = minimal_instance(&crd, "foo").data(json!({
+            "spec": {
+                "name": "a",
+                "replicas": 0,
+                "ratio": "1",
+                "mode": "medium",
+                "tags": [],
+                "limits": { "cpu": null },
+            }
+        }));
+        assert_eq!(validate_instance(&crd, &instance).unwrap_err(), vec![
+            "spec.paused: Required value",
+            r#"spec.limits.cpu: Invalid value: "null": must not be null"#,
+            r#"spec.mode: Unsupported value: "medium": supported values: "fast", "slow""#,
+            r#"spec.name: Invalid value: "a": must be at least 3 chars long"#,
+            r#"spec.ratio: Invalid value: "1": must be of type number"#,
+            "spec.replicas: Invalid value: 0: should be greater than 0",
+            "spec.tags: Invalid value: 0: must have at least 1 items",
+        ]);
+
+        let mut unserved = instance;
+        unserved.types.as_mut().unwrap().api_version = "clux.dev/v2".into();
+        assert_eq!(validate_instance(&crd, &unserved).unwrap_err(), vec![
+            r#"apiVersion: Invalid value: "clux.dev/v2": must be a served version of clux.dev"#
+        ]);
+    }
+
+    #[test]
+    fn violations_keep_paths_apart_from_reasons() {
+        use super::{violations, FieldViolation};
+        let mut crd = crd();
+        let version = &mut crd.spec.versions[0];
+        let mut schema = serde_json::to_value(version.schema.take().unwrap()).unwrap();
+        let limits = &mut schema["openAPIV3Schema"]["properties"]["spec"]["properties"]["limits"];
+        limits["additionalProperties"] = json!({ "type": "string" });
+        version.schema = Some(serde_json::from_value(schema).unwrap());
+
+        let mut instance = minimal_instance(&crd, "foo");
+        instance.data["spec"]["limits"]["nvidia.com/gpu: a100"] = json!(2);
+        assert_eq!(violations(&crd, &instance), vec![FieldViolation {
+            path: "spec.limits.nvidia.com/gpu: a100".into(),
+            reason: "Invalid value: 2: must be of type string".into(),
+        }]);
+    }
+
+    #[test]
+    fn network_formats_are_checked() {
+        use super::matches_format;
+        assert!(matches_format("ipv4", "10.0.0.1"));
+        assert!(!matches_format("ipv4", "10.0.0.256"));
+        assert!(matches_format("ipv6", "fe80::1"));
+        assert!(matches_format("cidr", "10.0.0.0/8"));
+        assert!(matches_format("cidr", "2001:db8::/32"));
+        assert!(!matches_format("cidr", "10.0.0.0/33"));
+        assert!(!matches_format("cidr", "10.0.0.0"));
+        assert!(matches_format("uri", "https://example.com/hook?x=1"));
+        assert!(!matches_format("uri", "example.com/hook"));
+        assert!(!matches_format("uri", "https://example.com/a hook"));
+        assert!(matches_format("hostname", "not checked"));
+    }
+
+    #[test]
+    fn presence_rules_are_evaluated() {
+        use super::evaluate_rule;
+        let fields = json!({ "tls-cert": "cert", "in": true });
+        let fields = fields.as_object().unwrap();
+        let eval = |rule| evaluate_rule(rule, fields);
+        assert_eq!(eval("!has(self.tls__dash__cert) || has(self.key)"), Some(false));
+        assert_eq!(eval("has(self.__in__) && (has(self.key) || !false)"), Some(true));
+        assert_eq!(eval("self.replicas > 0"), None);
+        assert_eq!(eval("has(self.key) ||"), None);
+        assert_eq!(eval("self.hosts.all(h, h != '')"), None);
+    }
+
+    #[test]
+    fn numeric_comparisons_are_evaluated() {
+        use super::evaluate_rule;
+        let fields = json!({ "min": 2, "max": 1.5, "max-surge": 3 });
+        let fields = fields.as_object().unwrap();
+        let eval = |rule| evaluate_rule(rule, fields);
+        assert_eq!(eval("self.min <= self.max"), Some(false));
+        assert_eq!(eval("self.max < self.min && self.min != 0"), Some(true));
+        assert_eq!(eval("!has(self.max) || self.max >= -1.5"), Some(true));
+        assert_eq!(eval("has(self.limit) && self.limit > 3"), Some(false));
+        assert_eq!(eval("!has(self.limit) || self.limit > 3"), Some(true));
+        assert_eq!(eval("self.limit > 3 && self.min > 3"), Some(false));
+        assert_eq!(eval("self.limit > 3 && self.min < 3"), None);
+        assert_eq!(eval("self.max__dash__surge == 3"), Some(true));
+        assert_eq!(eval("self.min <= self.limit"), None);
+        assert_eq!(eval("self.min <="), None);
+        assert_eq!(
+            eval("[has(self.min), has(self.max)].filter(x, x).size() == 1"),
+            Some(false)
+        );
+        assert_eq!(
+            eval("[has(self.min), has(self.limit)].filter(x, x).size() == 1"),
+            Some(true)
+        );
+        assert_eq!(
+            eval("[has(self.min)].filter(x, x).size() >= 0 && true"),
+            Some(true)
+        );
+        assert_eq!(eval("[has(self.min), self.max].filter(x, x).size() == 1"), None);
+        assert_eq!(eval("[has(self.min)].map(x, x).size() == 1"), None);
+    }
+}
@@ -0,0 +1,2843 @@
+//! Merging of single-version `CustomResourceDefinition`s into one multi-version definition
+use super::{
+    apiexts::v1::{
+        CustomResourceColumnDefinition, CustomResourceConversion, CustomResourceDefinition as Crd,
+        CustomResourceDefinitionVersion, CustomResourceValidation, JSONSchemaProps, ServiceReference,
+        WebhookClientConfig, WebhookConversion,
+    },
+    check_structural,
+    compare::{normalize, version_schema},
+    compat, metaschema, names, requires_conversion, schema_paths,
+    v1::CustomResourceExt,
+};
+use crate::Version;
+use k8s_openapi::{apimachinery::pkg::apis::meta::v1::ObjectMeta, ByteString};
+use std::collections::{BTreeMap, HashSet};
+use thiserror::Error;
+
+/// Errors from [`CrdMerger`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum CrdError {
+    /// No crds were given to the merger
+    #[error("no crds to merge")]
+    Empty,
+
+    /// A crd disagrees with the root crd on a property that must be shared by all versions
+    #[error("crd for version {version} has {property} {found:?}, but the root crd has {expected:?}")]
+    PropertyMismatch {
+        /// The version whose crd disagrees with the root
+        version: String,
+        /// The name of the mismatching property
+        property: &'static str,
+        /// Value of the property in the root crd
+        expected: String,
+        /// Value of the property in the mismatching crd
+        found: String,
+    },
+
+    /// The same version was declared by more than one crd
+    #[error("version {0} is declared more than once")]
+    DuplicateVersion(String),
+
+    /// A version referenced by the merger configuration is not present in any crd
+    #[error("version {0} is not present in any crd")]
+    MissingVersion(String),
+
+    /// [`MergePolicy::ExplicitStorage`] was used without choosing a version with [`CrdMerger::storage`]
+    #[error("merge policy requires an explicit storage version")]
+    MissingStorage,
+
+    /// Several versions of the merged crd are the storage version
+    #[error("versions {} are all marked as the storage version", .0.join(", "))]
+    MultipleStorageVersions(Vec<String>),
+
+    /// No version of the merged crd is the storage version
+    #[error("no version is marked as the storage version")]
+    NoStorageVersion,
+
+    /// A retired version is the storage version, so retiring it would lose stored objects
+    #[error("version {0} cannot be retired while it is the storage version")]
+    RetiredStorageVersion(String),
+
+    /// The merged crd uses a feature that the targeted Kubernetes version does not support
+    #[error("{feature} requires Kubernetes >= {min_version}")]
+    FeatureRequiresNewerKube {
+        /// The unsupported feature
+        feature: &'static str,
+        /// The minimum Kubernetes version supporting the feature
+        min_version: String,
+    },
+
+    /// The version given to [`CrdMerger::target_kube_version`] is not a `major.minor` version
+    #[error("invalid kubernetes version {0:?}")]
+    InvalidKubeVersion(String),
+
+    /// The ca bundle given to [`CrdMerger::conversion_webhook_ca_from_pem`] is not a PEM encoded certificate
+    #[error("conversion webhook ca bundle is not a PEM encoded certificate")]
+    InvalidCaBundle,
+
+    /// The service of the conversion webhook has an invalid namespace, name, path (not starting with `/`),
+    /// or port (outside of `1..=65535`)
+    #[error("conversion webhook service has invalid {field} {value:?}")]
+    InvalidConversionService {
+        /// The invalid field of the service reference (`namespace`, `name`, `path` or `port`)
+        field: &'static str,
+        /// The invalid value
+        value: String,
+    },
+
+    /// [`CrdMerger::cert_manager_ca_injection`] was given an invalid namespace or certificate name
+    #[error("cert-manager certificate {0:?} is not a valid namespace/name reference")]
+    InvalidCertificateReference(String),
+
+    /// A conversion webhook setting was used, but the merged crd has no conversion webhook
+    #[error("crd has no conversion webhook")]
+    MissingConversionWebhook,
+
+    /// The conversion strategy is explicitly `None` (like with [`CrdMerger::conversion_none`]), but several served
+    /// versions have differing schemas
+    #[error("conversion strategy None can not convert between the differing schemas of served versions {}", .0.join(", "))]
+    NoneConversionMultiVersion(Vec<String>),
+
+    /// The schema of a version is not structural
+    #[error("schema of version {version} is not structural: {}", paths.join(", "))]
+    NonStructuralSchema {
+        /// The version with the non-structural schema
+        version: String,
+        /// The violations, as reported by [`check_structural`](super::check_structural)
+        paths: Vec<String>,
+    },
+
+    /// [`CrdMerger::validate_metaschema`] was used, and the schema of a version is not a valid schema
+    #[error("schema of version {version} is not a valid schema: {error}")]
+    InvalidSchemaStructure {
+        /// The version with the invalid schema
+        version: String,
+        /// The invalid nodes of the schema and why they are invalid
+        error: String,
+    },
+
+    /// Crds declare conflicting values for the same label or annotation
+    #[error("crds declare conflicting values for {kind} {key:?}")]
+    MetadataConflict {
+        /// Whether the conflict is in a `label` or an `annotation`
+        kind: &'static str,
+        /// The conflicting key
+        key: String,
+    },
+
+    /// [`CrdMerger::require_uniform_scale`] was used, but served versions disagree on the scale subresource
+    #[error("scale subresource of served versions {} differs from that of served version {first}", versions.join(", "))]
+    InconsistentScaleSubresource {
+        /// The first served version, whose scale subresource (or its absence) the others are compared against
+        first: String,
+        /// The served versions with a different scale subresource
+        versions: Vec<String>,
+    },
+
+    /// [`CrdMerger::validate_scale_paths`] was used, but versions with a scale subresource disagree on its paths
+    #[error("scale subresource paths of versions {} differ from those of version {first}", versions.join(", "))]
+    InconsistentScalePaths {
+        /// The first version with a scale subresource, whose paths the others are compared against
+        first: String,
+        /// The versions with a scale subresource using different paths
+        versions: Vec<String>,
+    },
+
+    /// The schema of a version has a field forbidden with [`CrdMerger::forbid_paths`]
+    #[error("field {0} is forbidden")]
+    ForbiddenField(String),
+
+    /// [`CrdMerger::with_source_annotations`] was used, but no source was recorded for a merged version
+    #[error("no source type recorded for version {0}")]
+    MissingVersionSource(String),
+
+    /// [`CrdMerger::require_contiguous_served`] was used, but versions are unserved between served versions of their tier
+    #[error("versions {} are not served, but served versions of the same maturity come before and after them", .0.join(", "))]
+    NonContiguousServedVersions(Vec<String>),
+
+    /// An extension of [`CrdMerger::set_extension`] could not be set
+    #[error("can not set {key} at {pointer:?} in the schema of version {version}: {reason}")]
+    InvalidSchemaExtension {
+        /// The version whose schema was patched
+        version: String,
+        /// The json pointer to the schema node
+        pointer: String,
+        /// The extension key
+        key: String,
+        /// Why the extension could not be set
+        reason: &'static str,
+    },
+}
+
+/// How [`CrdMerger`] assigns the `served` and `storage` flags of the merged versions
+///
+/// A version chosen with [`CrdMerger::storage`] always becomes the storage version, regardless of policy.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergePolicy {
+    /// Keep the `served` and `storage` flags of the input crds (the default)
+    PreserveInputs,
+    /// Require the storage version to be chosen with [`CrdMerger::storage`], and keep the `served` flags
+    ExplicitStorage,
+    /// Serve all versions, and store the version with the highest [`Version`] priority
+    LatestStorageAllServed,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        MergePolicy::PreserveInputs
+    }
+}
+
+/// The conversion of a merged crd, split off by [`CrdMerger::merge_split`]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConversionConfig {
+    /// The `spec.conversion` of the merged crd
+    pub conversion: CustomResourceConversion,
+}
+
+impl ConversionConfig {
+    /// A merge patch setting the conversion of the crd, like for `Patch::Merge` once the crd is applied
+    pub fn patch(&self) -> serde_json::Value {
+        serde_json::json!({ "spec": { "conversion": self.conversion } })
+    }
+}
+
+/// Builder that merges the crds generated for each version of a kind into a single crd
+///
+/// Every version of a kind is typically generated from its own struct (e.g. via `#[derive(CustomResource)]` on a `v1`
+/// and a `v2` module), yielding one single-version crd per struct. Kubernetes however only accepts one crd per kind,
+/// containing all of its versions.
+///
+/// The merged crd takes its name, `spec.names`, `spec.conversion` and `spec.preserveUnknownFields` from the **root**
+/// crd (the first crd, unless changed with [`CrdMerger::with_root`]), and the versions of all crds in input order.
+/// Labels and annotations from all crds are unioned.
+///
+/// ```
+/// # use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+/// use kube_core::crd::CrdMerger;
+/// # fn wrapper(v1_crd: CustomResourceDefinition, v2_crd: CustomResourceDefinition) -> Result<(), kube_core::crd::CrdError> {
+/// let crd = CrdMerger::new(vec![v1_crd, v2_crd]).storage("v2").merge()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CrdMerger {
+    crds: Vec<Crd>,
+    root: Option<String>,
+    names_from: Option<String>,
+    storage: Option<String>,
+    storage_latest_ga: bool,
+    metadata_template: Option<ObjectMeta>,
+    gitops_instance: Option<String>,
+    retired: Vec<String>,
+    validate_structural: bool,
+    validate_metaschema: bool,
+    require_uniform_scale: bool,
+    validate_scale_paths: bool,
+    require_contiguous_served: bool,
+    validate_conversion: bool,
+    forbidden_paths: Vec<String>,
+    merge_status_columns: bool,
+    schema_overrides: BTreeMap<String, JSONSchemaProps>,
+    preserve_unknown: Vec<String>,
+    extensions: Vec<(String, String, String, serde_json::Value)>,
+    policy: MergePolicy,
+    target_kube_version: Option<String>,
+    lower_to_kube_version: bool,
+    conversion_webhook: Option<WebhookClientConfig>,
+    conversion_ca_bundle: Option<Vec<u8>>,
+    conversion_service_path: Option<String>,
+    conversion_service_port: Option<i32>,
+    conversion_none: bool,
+    helm_placeholders: bool,
+    cert_manager_certificate: Option<(String, String)>,
+    sources: Vec<(String, String)>,
+    source_annotations: bool,
+    version_notes: Vec<(String, String)>,
+}
+
+/// Annotation listing the versions removed with [`CrdMerger::retire`]
+pub const RETIRED_VERSIONS_ANNOTATION: &str = "kube.rs/retired-versions";
+
+/// Annotation listing the rust types of the versions, set by [`CrdMerger::with_source_annotations`]
+pub const VERSION_SOURCES_ANNOTATION: &str = "kube.rs/version-sources";
+
+/// Prefix of the annotations with the notes of versions, set by [`CrdMerger::version_note`]
+pub const VERSION_NOTE_ANNOTATION_PREFIX: &str = "kube.rs/version-note.";
+
+/// Version of the report of [`CrdMerger::merge_with_json_report`]
+pub const MERGE_REPORT_VERSION: u32 = 1;
+
+/// Annotation by which the cert-manager CA injector fills in the `caBundle` of a conversion webhook
+pub const CERT_MANAGER_INJECT_CA_FROM_ANNOTATION: &str = "cert-manager.io/inject-ca-from";
+
+/// Helm template of the conversion webhook `caBundle`, set by [`CrdMerger::helm_placeholders`]
+pub const HELM_CA_BUNDLE_PLACEHOLDER: &str = "{{ .Values.webhook.caBundle }}";
+/// Helm template of the conversion webhook service namespace, set by [`CrdMerger::helm_placeholders`]
+pub const HELM_NAMESPACE_PLACEHOLDER: &str = "{{ .Values.webhook.namespace }}";
+/// Helm template of the conversion webhook service name, set by [`CrdMerger::helm_placeholders`]
+pub const HELM_SERVICE_NAME_PLACEHOLDER: &str = "{{ .Values.webhook.serviceName }}";
+
+impl CrdMerger {
+    /// Create a merger of the given crds
+    #[must_use]
+    pub fn new(crds: Vec<Crd>) -> Self {
+        Self {
+            crds,
+            ..Self::default()
+        }
+    }
+
+    /// Use the crd containing `version` as the root crd
+    ///
+    /// The root crd provides the name, names, and conversion strategy of the merged crd.
+    #[must_use]
+    pub fn with_root(mut self, version: &str) -> Self {
+        self.root = Some(version.to_string());
+        self
+    }
+
+    /// Take the `spec.names` of the merged crd from the crd containing `version`, rather than from the root crd
+    ///
+    /// This picks the canonical shortnames, categories and list kind, while the root crd still provides
+    /// the name and conversion strategy. Merging fails if the version is not present.
+    #[must_use]
+    pub fn names_from(mut self, version: &str) -> Self {
+        self.names_from = Some(version.to_string());
+        self
+    }
+
+    /// Mark `version` as the storage version, and unset the storage flag on all other versions
+    ///
+    /// Without this, the storage flags are assigned according to the [`MergePolicy`].
+    #[must_use]
+    pub fn storage(mut self, version: &str) -> Self {
+        self.storage = Some(version.to_string());
+        self
+    }
+
+    /// Mark the version with the highest [`Version`] priority as the storage version
+    ///
+    /// This is the newest GA version, or the newest beta (and then alpha) version if there is no GA version, so
+    /// adding a version does not require updating [`CrdMerger::storage`]. Retired versions are not considered, and
+    /// the `served` flags are kept. A version chosen with [`CrdMerger::storage`] takes precedence.
+    #[must_use]
+    pub fn storage_latest_ga(mut self) -> Self {
+        self.storage_latest_ga = true;
+        self
+    }
+
+    /// Set how the `served` and `storage` flags of the merged versions are assigned
+    #[must_use]
+    pub fn policy(mut self, policy: MergePolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Convert between versions with the webhook at `client_config`
+    ///
+    /// This replaces the conversion strategy of the root crd with a `Webhook` conversion using `ConversionReview` `v1`.
+    #[must_use]
+    pub fn conversion_webhook(mut self, client_config: WebhookClientConfig) -> Self {
+        self.conversion_webhook = Some(client_config);
+        self.conversion_none = false;
+        self
+    }
+
+    /// Convert between versions with the webhook served by the Service `namespace/name`
+    ///
+    /// This is [`CrdMerger::conversion_webhook`] with a service reference on the default path `/convert` and port `443`,
+    /// which can be changed with [`CrdMerger::conversion_service_path`] and [`CrdMerger::conversion_service_port`].
+    /// Merging fails if the namespace or name are not DNS labels, the path does not start with `/`,
+    /// or the port is not in `1..=65535`.
+    #[must_use]
+    pub fn conversion_service(self, namespace: &str, name: &str) -> Self {
+        self.conversion_webhook(WebhookClientConfig {
+            service: Some(ServiceReference {
+                namespace: namespace.to_string(),
+                name: name.to_string(),
+                path: Some("/convert".into()),
+                port: Some(443),
+            }),
+            ..WebhookClientConfig::default()
+        })
+    }
+
+    /// Set the url path of the conversion webhook service (defaults to `/convert`)
+    #[must_use]
+    pub fn conversion_service_path(mut self, path: &str) -> Self {
+        self.conversion_service_path = Some(path.to_string());
+        self
+    }
+
+    /// Set the port of the conversion webhook service (defaults to `443`)
+    #[must_use]
+    pub fn conversion_service_port(mut self, port: i32) -> Self {
+        self.conversion_service_port = Some(port);
+        self
+    }
+
+    /// Explicitly use the `None` conversion strategy, which only rewrites the `apiVersion` of objects
+    ///
+    /// This replaces the conversion strategy of the root crd (and any earlier [`CrdMerger::conversion_webhook`]).
+    /// Merging fails if more than one version is served and their schemas differ, as objects read through
+    /// another version than they were written through would then not match its schema.
+    #[must_use]
+    pub fn conversion_none(mut self) -> Self {
+        self.conversion_none = true;
+        self.conversion_webhook = None;
+        self
+    }
+
+    /// Set the `caBundle` of the conversion webhook to a PEM encoded CA certificate
+    ///
+    /// The certificate is base64 encoded in the merged crd, like the apiserver expects.
+    #[must_use]
+    pub fn conversion_webhook_ca_from_pem(mut self, pem: &[u8]) -> Self {
+        self.conversion_ca_bundle = Some(pem.to_vec());
+        self
+    }
+
+    /// Have the cert-manager CA injector set the `caBundle` of the conversion webhook
+    ///
+    /// This sets the [`CERT_MANAGER_INJECT_CA_FROM_ANNOTATION`] of the merged crd to `namespace/certificate`, the
+    /// cert-manager `Certificate` of the webhook, and leaves the `caBundle` empty for cert-manager to fill in
+    /// (replacing an earlier [`CrdMerger::conversion_webhook_ca_from_pem`]). Merging fails if the crd has no
+    /// conversion webhook.
+    #[must_use]
+    pub fn cert_manager_ca_injection(mut self, namespace: &str, certificate: &str) -> Self {
+        self.cert_manager_certificate = Some((namespace.to_string(), certificate.to_string()));
+        self.conversion_ca_bundle = None;
+        self
+    }
+
+    /// Leave the conversion webhook service and `caBundle` as Helm templating placeholders
+    ///
+    /// For a `Webhook` conversion, the service namespace and name become [`HELM_NAMESPACE_PLACEHOLDER`] and
+    /// [`HELM_SERVICE_NAME_PLACEHOLDER`], and the `caBundle` [`HELM_CA_BUNDLE_PLACEHOLDER`], to be filled in at install time.
+    /// The service is still checked before it is replaced. Serialize the merged crd with
+    /// [`to_helm_template`](super::to_helm_template), which writes the `caBundle` placeholder verbatim
+    /// instead of base64 encoded, to drop it into the `templates/` directory of a chart.
+    #[must_use]
+    pub fn helm_placeholders(mut self) -> Self {
+        self.helm_placeholders = true;
+        self
+    }
+
+    /// Deep-merge a metadata template onto the merged crd metadata
+    ///
+    /// Labels and annotations of the template are added to the merged metadata, and take precedence over
+    /// values from the input crds. Finalizers of the template are appended.
+    #[must_use]
+    pub fn metadata_template(mut self, meta: ObjectMeta) -> Self {
+        self.metadata_template = Some(meta);
+        self
+    }
+
+    /// Label the merged crd for GitOps tools tracking which of their applications owns it
+    ///
+    /// Sets `app.kubernetes.io/instance` to `instance` (the label by which ArgoCD tracks and prunes the resources of an
+    /// application by default), and `app.kubernetes.io/managed-by` to `kube-rs`. Labels are unioned: labels already
+    /// set by the input crds or [`CrdMerger::metadata_template`] are kept.
+    #[must_use]
+    pub fn gitops_labels(mut self, instance: &str) -> Self {
+        self.gitops_instance = Some(instance.to_string());
+        self
+    }
+
+    /// Remove `version` from the merged crd, recording it as intentionally retired
+    ///
+    /// Retired versions are listed (comma separated) in the [`RETIRED_VERSIONS_ANNOTATION`] annotation of the merged crd,
+    /// so that other tooling can tell them apart from versions that were dropped by accident.
+    /// Retiring the storage version is an error.
+    #[must_use]
+    pub fn retire(mut self, version: &str) -> Self {
+        self.retired.push(version.to_string());
+        self
+    }
+
+    /// Record `type_path` (like `my_crate::v2::Foo`) as the rust type that generated `version`
+    ///
+    /// Sources are only used by [`CrdMerger::with_source_annotations`].
+    #[must_use]
+    pub fn source(mut self, version: &str, type_path: &str) -> Self {
+        self.sources.push((version.to_string(), type_path.to_string()));
+        self
+    }
+
+    /// Record the custom resource `K` as the source of its version, see [`CrdMerger::source`]
+    #[must_use]
+    pub fn source_of<K: CustomResourceExt>(self) -> Self {
+        self.source(&K::api_resource().version, std::any::type_name::<K>())
+    }
+
+    /// Annotate the merged crd with the rust type that generated every version
+    ///
+    /// The [`VERSION_SOURCES_ANNOTATION`] annotation lists `<version>=<type path>` (comma separated, in version
+    /// order) from [`CrdMerger::source`] or [`CrdMerger::source_of`], which helps finding the struct behind a
+    /// version of a large merged crd. This is an annotation rather than an extension of the version schemas, as
+    /// the apiserver drops unknown schema keywords. Merging fails with [`CrdError::MissingVersionSource`] if a
+    /// merged version has no recorded source, and with [`CrdError::MissingVersion`] for sources of absent versions.
+    #[must_use]
+    pub fn with_source_annotations(mut self) -> Self {
+        self.source_annotations = true;
+        self
+    }
+
+    /// Note what changed in `version` from the previous version, like `"added spec.retries"`
+    ///
+    /// This is purely informational, to help reviewing diffs of generated crds. The note is set as the
+    /// `kube.rs/version-note.<version>` annotation (see [`VERSION_NOTE_ANNOTATION_PREFIX`]) of the merged crd, for the
+    /// same reason as in [`CrdMerger::with_source_annotations`].
+    /// Merging fails with [`CrdError::MissingVersion`] if `version` is not merged.
+    #[must_use]
+    pub fn version_note(mut self, version: &str, note: &str) -> Self {
+        self.version_notes.push((version.to_string(), note.to_string()));
+        self
+    }
+
+    /// Replace the `openAPIV3Schema` of `version` with `schema`
+    ///
+    /// This is an escape hatch for schema features that can not be generated (yet), without regenerating the other versions.
+    #[must_use]
+    pub fn override_schema(mut self, version: &str, schema: JSONSchemaProps) -> Self {
+        self.schema_overrides.insert(version.to_string(), schema);
+        self
+    }
+
+    /// Set `x-kubernetes-preserve-unknown-fields: true` at the root of the schema of `version` only
+    ///
+    /// This lets an older version tolerate fields of clients that have not migrated yet, while newer versions
+    /// validate strictly. It applies after [`CrdMerger::override_schema`]. Merging fails if the version is not present.
+    #[must_use]
+    pub fn preserve_unknown_for_version(mut self, version: &str) -> Self {
+        self.preserve_unknown.push(version.to_string());
+        self
+    }
+
+    /// Set the `x-kubernetes-*` extension `key` to `value` on the schema node of `version` at `pointer`
+    ///
+    /// `pointer` is a [json pointer](https://datatracker.ietf.org/doc/html/rfc6901) into the `openAPIV3Schema`
+    /// (e.g. `/properties/spec` for the spec node, or `""` for the root). This is an escape hatch for schema
+    /// extensions the derive does not support (yet). It applies after [`CrdMerger::override_schema`], and merging
+    /// fails if the pointer does not resolve to a schema node, or `value` does not fit the extension.
+    #[must_use]
+    pub fn set_extension(
+        mut self,
+        version: &str,
+        pointer: &str,
+        key: &str,
+        value: serde_json::Value,
+    ) -> Self {
+        self.extensions
+            .push((version.to_string(), pointer.to_string(), key.to_string(), value));
+        self
+    }
+
+    /// Check that the schemas of all merged versions are structural
+    ///
+    /// This catches schemas the apiserver would reject when merging, rather than when applying the crd.
+    /// It is opt-in, as schemas may intentionally be made structural by post-processing the merged crd.
+    #[must_use]
+    pub fn validate_structural(mut self) -> Self {
+        self.validate_structural = true;
+        self
+    }
+
+    /// Check that the schema of every version is a valid openapi v3 schema, as far as crds allow
+    ///
+    /// Merging fails with [`CrdError::InvalidSchemaStructure`] for schemas with keywords of invalid values (like an
+    /// unknown `type`, a negative `maxLength`, an empty `enum` or an array of `items`), or keywords that crds do not
+    /// allow (like `$ref`). Derived schemas do not contain these, but overridden or hand-written schemas might.
+    #[must_use]
+    pub fn validate_metaschema(mut self) -> Self {
+        self.validate_metaschema = true;
+        self
+    }
+
+    /// Give every version the printer columns of `.status` fields that any version declares
+    ///
+    /// Status is server managed, so its columns usually make sense for all versions, and keeping them consistent
+    /// keeps `kubectl get` output consistent across versions. Columns are matched by name, and columns a version
+    /// already declares are kept, even if another version declares a column of that name with a different path.
+    /// Such conflicts are reported by [`CrdMerger::lint_status_columns`].
+    #[must_use]
+    pub fn merge_status_columns(mut self) -> Self {
+        self.merge_status_columns = true;
+        self
+    }
+
+    /// Check that all served versions have the same scale subresource, or none has one
+    ///
+    /// Differing scale subresources are legal, but make autoscalers behave differently depending on the version
+    /// they query. It is opt-in, as served versions do not need to agree on their subresources.
+    #[must_use]
+    pub fn require_uniform_scale(mut self) -> Self {
+        self.require_uniform_scale = true;
+        self
+    }
+
+    /// Check that all versions with a scale subresource use the same paths
+    ///
+    /// Unlike [`CrdMerger::require_uniform_scale`], this covers unserved versions too, and versions without a scale
+    /// subresource are allowed. Merging fails with [`CrdError::InconsistentScalePaths`] if the `specReplicasPath`,
+    /// `statusReplicasPath` or `labelSelectorPath` of a version differs from that of the first version with a scale.
+    #[must_use]
+    pub fn validate_scale_paths(mut self) -> Self {
+        self.validate_scale_paths = true;
+        self
+    }
+
+    /// Check that the served versions of each maturity tier (GA, beta, alpha) are contiguous
+    ///
+    /// Serving `v1` and `v3` while `v2` is unserved confuses clients, so merging fails with
+    /// [`CrdError::NonContiguousServedVersions`] if an unserved version sits between two served versions of its tier
+    /// (by [`Version`] priority). Versions are only compared within a tier, as not serving e.g. a beta
+    /// between GA versions is normal. Nonconformant version names are not checked.
+    #[must_use]
+    pub fn require_contiguous_served(mut self) -> Self {
+        self.require_contiguous_served = true;
+        self
+    }
+
+    /// Check the conversion strategy of the merged crd against its served versions
+    ///
+    /// The explicit `None` strategy (like from [`CrdMerger::conversion_none`]) is always checked, but with this, merging
+    /// also fails with [`CrdError::NoneConversionMultiVersion`] for crds without a conversion, which the apiserver
+    /// defaults to `None`, if served versions have differing schemas. A conversion webhook with a single served version
+    /// is not an error (it may be in place for an upcoming version), but is reported by [`CrdMerger::lint_conversion`].
+    #[must_use]
+    pub fn validate_conversion_sanity(mut self) -> Self {
+        self.validate_conversion = true;
+        self
+    }
+
+    /// Check that the schemas of the versions have none of the fields at `paths`, like `spec.password`
+    ///
+    /// Paths are dotted like those of [`schema_paths`](super::schema_paths), and a trailing `.*` forbids everything
+    /// below a field (`spec.secrets.*` forbids `spec.secrets.token` and `spec.secrets[*]`, but not `spec.secrets`).
+    /// Merging fails with a [`CrdError::ForbiddenField`] per forbidden field in the schema of any version. This lets
+    /// a build step enforce a policy (like keeping secrets out of specs) across all crds. Can be called repeatedly.
+    #[must_use]
+    pub fn forbid_paths(mut self, paths: &[&str]) -> Self {
+        self.forbidden_paths.extend(paths.iter().map(|p| p.to_string()));
+        self
+    }
+
+    /// Check that the merged crd only uses features supported by Kubernetes `version` (e.g. `"1.22"`)
+    ///
+    /// Checked features are the `x-kubernetes-list-type`, `x-kubernetes-list-map-keys`, `x-kubernetes-map-type`
+    /// and `x-kubernetes-validations` (CEL) schema extensions, and version deprecation.
+    /// Use [`CrdMerger::for_kube_version`] to drop unsupported features instead.
+    #[must_use]
+    pub fn target_kube_version(mut self, version: &str) -> Self {
+        self.target_kube_version = Some(version.to_string());
+        self
+    }
+
+    /// Produce a crd for Kubernetes `version` (e.g. `"1.22"`), by dropping the features that it does not support
+    ///
+    /// This removes the features checked by [`CrdMerger::target_kube_version`] from the merged crd, rather than
+    /// failing the merge. Older apiservers would reject or ignore them anyway, so for example CEL validation rules are
+    /// not enforced on clusters before 1.25. The dropped features are listed by [`CrdMerger::lint_kube_version`],
+    /// which should be logged as warnings.
+    #[must_use]
+    pub fn for_kube_version(mut self, version: &str) -> Self {
+        self.target_kube_version = Some(version.to_string());
+        self.lower_to_kube_version = true;
+        self
+    }
+
+    /// Find the features of the input crds that [`CrdMerger::for_kube_version`] drops
+    ///
+    /// Returns one `"version <name>: <path>: <reason>"` entry per dropped feature, without merging
+    /// (and nothing unless `for_kube_version` is used with a valid version).
+    pub fn lint_kube_version(&self) -> Vec<String> {
+        let target = self
+            .target_kube_version
+            .as_deref()
+            .and_then(compat::parse_kube_version);
+        let target = match target {
+            Some(target) if self.lower_to_kube_version => target,
+            _ => return vec![],
+        };
+        let mut dropped: Vec<String> = vec![];
+        for crd in &self.crds {
+            for problem in compat::drop_unsupported_features(&mut crd.clone(), target) {
+                if !dropped.contains(&problem) {
+                    dropped.push(problem);
+                }
+            }
+        }
+        dropped
+    }
+
+    /// Find names of the input crds that the apiserver would reject
+    ///
+    /// Checks that the plural, singular, kind, list kind, shortnames and categories are valid DNS-1035 labels
+    /// (kinds are checked lowercased). Returns one `"<path>: <reason>"` entry per problem, without merging.
+    pub fn lint_names(&self) -> Vec<String> {
+        let mut problems: Vec<String> = vec![];
+        for problem in self.crds.iter().flat_map(names::name_problems) {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        }
+        problems
+    }
+
+    /// Find `.status` printer columns that versions declare with the same name but different json paths
+    ///
+    /// Returns one `"<column>: <reason>"` entry per conflict, without merging.
+    /// See [`CrdMerger::merge_status_columns`].
+    pub fn lint_status_columns(&self) -> Vec<String> {
+        let versions = self.crds.iter().flat_map(|crd| &crd.spec.versions);
+        status_columns(versions).1
+    }
+
+    /// Find conversion webhooks of the merged crd that are never called, as only one version is served
+    ///
+    /// Returns one entry per problem, and nothing if the crds do not merge. See [`CrdMerger::validate_conversion_sanity`].
+    pub fn lint_conversion(&self) -> Vec<String> {
+        let merged = match self.clone().try_merge() {
+            Ok(merged) => merged,
+            Err(_) => return vec![],
+        };
+        let webhook = merged
+            .spec
+            .conversion
+            .as_ref()
+            .map_or(false, |c| c.strategy == "Webhook");
+        let served = merged
+            .spec
+            .versions
+            .iter()
+            .filter(|v| v.served)
+            .collect::<Vec<_>>();
+        match served.as_slice() {
+            [version] if webhook => vec![format!(
+                "conversion webhook is never called, as only version {} is served",
+                version.name
+            )],
+            _ => vec![],
+        }
+    }
+
+    /// Names of the versions the merged crd will contain, in output order
+    ///
+    /// This is a cheap preview of [`CrdMerger::merge`] for logging, and does not validate the crds,
+    /// so versions declared more than once are only listed once here, while `merge` rejects them.
+    pub fn planned_versions(&self) -> Vec<String> {
+        let mut versions: Vec<String> = vec![];
+        for version in self.crds.iter().flat_map(|crd| &crd.spec.versions) {
+            if !versions.contains(&version.name) && !self.retired.contains(&version.name) {
+                versions.push(version.name.clone());
+            }
+        }
+        versions
+    }
+
+    /// Merge the crds into a single crd
+    ///
+    /// # Errors
+    ///
+    /// Fails if no crds were given, if the crds disagree on their name, group, kind, plural, or scope,
+    /// if a version is declared more than once, if the configuration references a version that is not present,
+    /// if the storage version is retired, if not exactly one version ends up as the storage version, or if one of the opt-in checks (like [`CrdMerger::validate_structural`]
+    /// or [`CrdMerger::target_kube_version`]) fails. See [`CrdError`] for the details of every failure.
+    pub fn merge(self) -> Result<Crd, CrdError> {
+        self.try_merge().map_err(|mut errors| errors.remove(0))
+    }
+
+    /// Merge the crds like [`CrdMerger::merge`], but return the conversion of the merged crd separately
+    ///
+    /// The returned crd has no `spec.conversion`, so its schemas can be applied by one actor, while another (like
+    /// the operator owning the conversion webhook, or cert-manager) patches in the conversion with
+    /// [`ConversionConfig::patch`]. The conversion is `None` if the merged crd has none.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of [`CrdMerger::merge`].
+    pub fn merge_split(self) -> Result<(Crd, Option<ConversionConfig>), CrdError> {
+        let mut merged = self.merge()?;
+        let conversion = merged.spec.conversion.take();
+        Ok((
+            merged,
+            conversion.map(|conversion| ConversionConfig { conversion }),
+        ))
+    }
+
+    /// Merge the crds like [`CrdMerger::merge`], and describe the merge in a json report for scripts and build artifacts
+    ///
+    /// The report is an object with the (stable) keys:
+    /// - `reportVersion`: [`MERGE_REPORT_VERSION`], bumped whenever keys are removed or change meaning
+    /// - `name`, `group` and `kind` of the merged crd
+    /// - `storageVersion`: the name of the storage version
+    /// - `conversion`: the conversion `strategy` (`"None"` or `"Webhook"`)
+    /// - `versions`: per merged version (in order) its `name`, `served`, `storage`, `deprecated`, the index of the
+    ///   input crd it came from as `source`, and the type recorded by [`CrdMerger::source`] as `sourceType` (or `null`)
+    /// - `retired`: the versions removed with [`CrdMerger::retire`]
+    /// - `warnings`: the entries of [`CrdMerger::lint_kube_version`], [`CrdMerger::lint_names`],
+    ///   [`CrdMerger::lint_status_columns`] and [`CrdMerger::lint_conversion`]
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of [`CrdMerger::merge`].
+    pub fn merge_with_json_report(self) -> Result<(Crd, serde_json::Value), CrdError> {
+        let mut warnings = self.lint_kube_version();
+        warnings.extend(self.lint_names());
+        warnings.extend(self.lint_status_columns());
+        warnings.extend(self.lint_conversion());
+        let inputs = self.crds.clone();
+        let sources = self.sources.clone();
+        let retired = self.retired.clone();
+        let merged = self.merge()?;
+
+        let versions = merged.spec.versions.iter().map(|version| {
+            let source = inputs
+                .iter()
+                .position(|crd| crd.spec.versions.iter().any(|v| v.name == version.name));
+            let source_type = sources
+                .iter()
+                .find(|(name, _)| *name == version.name)
+                .map(|(_, ty)| ty);
+            serde_json::json!({
+                "name": version.name,
+                "served": version.served,
+                "storage": version.storage,
+                "deprecated": version.deprecated.unwrap_or(false),
+                "source": source,
+                "sourceType": source_type,
+            })
+        });
+        let storage = merged.spec.versions.iter().find(|v| v.storage).map(|v| &v.name);
+        let conversion = merged
+            .spec
+            .conversion
+            .as_ref()
+            .map_or("None", |c| c.strategy.as_str());
+        let report = serde_json::json!({
+            "reportVersion": MERGE_REPORT_VERSION,
+            "name": merged.metadata.name,
+            "group": merged.spec.group,
+            "kind": merged.spec.names.kind,
+            "storageVersion": storage,
+            "conversion": conversion,
+            "versions": versions.collect::<Vec<_>>(),
+            "retired": retired,
+            "warnings": warnings,
+        });
+        Ok((merged, report))
+    }
+
+    /// A json patch updating `live` to the merged crd, touching only what changed, or `None` if nothing changed
+    ///
+    /// Versions are compared after normalizing their schemas (like [`semantic_diff`](super::semantic_diff)), so a
+    /// crd read back from the apiserver compares equal. Changed versions of `live` are replaced by index (guarded by a
+    /// `test` of their name), versions missing from `live` are appended, and versions missing from the merged crd are
+    /// removed. Changed fields of the spec outside of `versions` (like `names` or `conversion`) are replaced whole,
+    /// while metadata is left alone.
+    ///
+    /// The patch is meant for `Patch::Json`, so an operator can skip applying an unchanged crd on every reconcile.
+    ///
+    /// This is also how to update some versions of a crd whose other versions are edited by someone else: the merge
+    /// strategy of `spec.versions` is part of the built-in schema of `CustomResourceDefinition` in the apiserver (it
+    /// can not be changed by the crd itself), and server-side apply treats the list as one atomic field.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error of [`CrdMerger::merge`].
+    pub fn diff_patch(&self, live: &Crd) -> Result<Option<serde_json::Value>, CrdError> {
+        let merged = normalize(&self.clone().merge()?);
+        let live_normalized = normalize(live);
+        let version_value = |versions: &[CustomResourceDefinitionVersion], name: &str| {
+            let version = versions.iter().find(|v| v.name == name)?;
+            serde_json::to_value(version).ok()
+        };
+
+        let mut ops = vec![];
+        let mut removed = vec![];
+        for (idx, version) in live.spec.versions.iter().enumerate() {
+            let name = &version.name;
+            let pointer = format!("/spec/versions/{}", idx);
+            match version_value(&merged.spec.versions, name) {
+                Some(value)
+                    if Some(&value) == version_value(&live_normalized.spec.versions, name).as_ref() => {}
+                Some(value) => {
+                    ops.push(serde_json::json!({ "op": "test", "path": format!("{}/name", pointer), "value": name }));
+                    ops.push(serde_json::json!({ "op": "replace", "path": pointer, "value": value }));
+                }
+                None => removed.push((pointer, name)),
+            }
+        }
+        // remove from the back, so the indices of the other versions stay valid
+        for (pointer, name) in removed.into_iter().rev() {
+            ops.push(serde_json::json!({ "op": "test", "path": format!("{}/name", pointer), "value": name }));
+            ops.push(serde_json::json!({ "op": "remove", "path": pointer }));
+        }
+        for version in &merged.spec.versions {
+            if !live.spec.versions.iter().any(|v| v.name == version.name) {
+                ops.push(serde_json::json!({ "op": "add", "path": "/spec/versions/-", "value": version }));
+            }
+        }
+
+        let spec = serde_json::to_value(&merged.spec).unwrap_or_default();
+        let live_spec = serde_json::to_value(&live_normalized.spec).unwrap_or_default();
+        let fields = spec
+            .as_object()
+            .into_iter()
+            .chain(live_spec.as_object())
+            .flat_map(|f| f.keys());
+        let fields = fields
+            .filter(|f| *f != "versions")
+            .collect::<std::collections::BTreeSet<_>>();
+        for field in fields {
+            let path = format!("/spec/{}", field);
+            match (spec.get(field), live_spec.get(field)) {
+                (Some(value), live) if Some(value) != live => {
+                    ops.push(serde_json::json!({ "op": "add", "path": path, "value": value }))
+                }
+                (None, Some(_)) => ops.push(serde_json::json!({ "op": "remove", "path": path })),
+                _ => {}
+            }
+        }
+        Ok((!ops.is_empty()).then(|| serde_json::Value::Array(ops)))
+    }
+
+    /// Merge the crds into a single crd, reporting every problem rather than only the first
+    ///
+    /// The errors are the same as those of [`CrdMerger::merge`], in the order they were found.
+    /// If no root crd can be found, that is the only error.
+    pub fn try_merge(self) -> Result<Crd, Vec<CrdError>> {
+        let root_idx = self.root_index().map_err(|err| vec![err])?;
+        let root = &self.crds[root_idx];
+        let mut errors = vec![];
+        for crd in &self.crds {
+            check_consistency(root, crd, &mut errors);
+        }
+
+        let mut merged = root.clone();
+        merged.metadata = merge_metadata(root, &self.crds, &mut errors);
+        merged.spec.versions = vec![];
+        for crd in &self.crds {
+            for version in &crd.spec.versions {
+                if merged.spec.versions.iter().any(|v| v.name == version.name) {
+                    errors.push(CrdError::DuplicateVersion(version.name.clone()));
+                } else {
+                    merged.spec.versions.push(version.clone());
+                }
+            }
+        }
+
+        if let Some(names_from) = &self.names_from {
+            match self
+                .crds
+                .iter()
+                .find(|crd| crd.spec.versions.iter().any(|v| &v.name == names_from))
+            {
+                Some(crd) => merged.spec.names = crd.spec.names.clone(),
+                None => errors.push(CrdError::MissingVersion(names_from.clone())),
+            }
+        }
+
+        if self.policy == MergePolicy::LatestStorageAllServed {
+            for version in &mut merged.spec.versions {
+                version.served = true;
+            }
+        }
+        let latest = || {
+            merged
+                .spec
+                .versions
+                .iter()
+                .map(|v| &v.name)
+                .filter(|name| !self.retired.contains(name))
+                .max_by_key(|name| Version::parse(name).priority())
+                .cloned()
+        };
+        let storage = match (&self.storage, self.policy) {
+            (Some(storage), _) => Some(storage.clone()),
+            (None, _) if self.storage_latest_ga => latest(),
+            (None, MergePolicy::PreserveInputs) => None,
+            (None, MergePolicy::ExplicitStorage) => {
+                errors.push(CrdError::MissingStorage);
+                None
+            }
+            (None, MergePolicy::LatestStorageAllServed) => latest(),
+        };
+        if let Some(storage) = &storage {
+            if merged.spec.versions.iter().any(|v| &v.name == storage) {
+                for version in &mut merged.spec.versions {
+                    version.storage = &version.name == storage;
+                }
+            } else {
+                errors.push(CrdError::MissingVersion(storage.clone()));
+            }
+        }
+
+        for retired in &self.retired {
+            match merged.spec.versions.iter().position(|v| &v.name == retired) {
+                None => errors.push(CrdError::MissingVersion(retired.clone())),
+                Some(idx) if merged.spec.versions[idx].storage => {
+                    errors.push(CrdError::RetiredStorageVersion(retired.clone()))
+                }
+                Some(idx) => {
+                    merged.spec.versions.remove(idx);
+                }
+            }
+        }
+        if !self.retired.is_empty() {
+            merged
+                .metadata
+                .annotations
+                .get_or_insert_with(BTreeMap::new)
+                .insert(RETIRED_VERSIONS_ANNOTATION.to_string(), self.retired.join(","));
+        }
+
+        if self.source_annotations {
+            for (name, _) in &self.sources {
+                if !merged.spec.versions.iter().any(|v| &v.name == name) && !self.retired.contains(name) {
+                    errors.push(CrdError::MissingVersion(name.clone()));
+                }
+            }
+            let mut sources = vec![];
+            for version in &merged.spec.versions {
+                match self.sources.iter().find(|(name, _)| name == &version.name) {
+                    Some((name, source)) => sources.push(format!("{}={}", name, source)),
+                    None => errors.push(CrdError::MissingVersionSource(version.name.clone())),
+                }
+            }
+            merged
+                .metadata
+                .annotations
+                .get_or_insert_with(BTreeMap::new)
+                .insert(VERSION_SOURCES_ANNOTATION.to_string(), sources.join(","));
+        }
+
+        for (name, note) in self.version_notes {
+            if merged.spec.versions.iter().any(|v| v.name == name) {
+                merged
+                    .metadata
+                    .annotations
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(format!("{}{}", VERSION_NOTE_ANNOTATION_PREFIX, name), note);
+            } else {
+                errors.push(CrdError::MissingVersion(name));
+            }
+        }
+
+        for (name, schema) in self.schema_overrides {
+            match merged.spec.versions.iter_mut().find(|v| v.name == name) {
+                Some(version) => {
+                    version.schema = Some(CustomResourceValidation {
+                        open_api_v3_schema: Some(schema),
+                    })
+                }
+                None => errors.push(CrdError::MissingVersion(name)),
+            }
+        }
+
+        for name in self.preserve_unknown {
+            match merged.spec.versions.iter_mut().find(|v| v.name == name) {
+                Some(version) => {
+                    let schema = version
+                        .schema
+                        .get_or_insert_with(CustomResourceValidation::default)
+                        .open_api_v3_schema
+                        .get_or_insert_with(|| JSONSchemaProps {
+                            type_: Some("object".into()),
+                            ..JSONSchemaProps::default()
+                        });
+                    schema.x_kubernetes_preserve_unknown_fields = Some(true);
+                }
+                None => errors.push(CrdError::MissingVersion(name)),
+            }
+        }
+
+        for (name, pointer, key, value) in self.extensions {
+            match merged.spec.versions.iter_mut().find(|v| v.name == name) {
+                Some(version) => {
+                    let schema = version
+                        .schema
+                        .as_mut()
+                        .and_then(|s| s.open_api_v3_schema.as_mut());
+                    if let Err(reason) = set_extension(schema, &pointer, &key, value) {
+                        errors.push(CrdError::InvalidSchemaExtension {
+                            version: name,
+                            pointer,
+                            key,
+                            reason,
+                        });
+                    }
+                }
+                None => errors.push(CrdError::MissingVersion(name)),
+            }
+        }
+
+        if let Some(client_config) = self.conversion_webhook {
+            merged.spec.conversion = Some(CustomResourceConversion {
+                strategy: "Webhook".into(),
+                webhook: Some(WebhookConversion {
+                    client_config: Some(client_config),
+                    conversion_review_versions: vec!["v1".into()],
+                }),
+            });
+        }
+        let service = merged
+            .spec
+            .conversion
+            .as_mut()
+            .and_then(|c| c.webhook.as_mut())
+            .and_then(|w| w.client_config.as_mut())
+            .and_then(|c| c.service.as_mut());
+        match service {
+            Some(service) => {
+                if let Some(path) = self.conversion_service_path {
+                    service.path = Some(path);
+                }
+                if let Some(port) = self.conversion_service_port {
+                    service.port = Some(port);
+                }
+                errors.extend(check_service(service));
+            }
+            None if self.conversion_service_path.is_some() || self.conversion_service_port.is_some() => {
+                errors.push(CrdError::MissingConversionWebhook)
+            }
+            None => {}
+        }
+        if self.conversion_none {
+            merged.spec.conversion = Some(CustomResourceConversion {
+                strategy: "None".into(),
+                webhook: None,
+            });
+        }
+        // also check an explicit `None` of the root crd, like from `#[kube(conversion = "none")]`
+        let none = match &merged.spec.conversion {
+            Some(conversion) => conversion.strategy == "None",
+            None => self.validate_conversion,
+        };
+        if none && requires_conversion(&merged) {
+            let served = merged.spec.versions.iter().filter(|v| v.served);
+            errors.push(CrdError::NoneConversionMultiVersion(
+                served.map(|v| v.name.clone()).collect(),
+            ));
+        }
+        if let Some(pem) = self.conversion_ca_bundle {
+            let client_config = merged
+                .spec
+                .conversion
+                .as_mut()
+                .and_then(|c| c.webhook.as_mut())
+                .and_then(|w| w.client_config.as_mut());
+            if !String::from_utf8_lossy(&pem)
+                .trim_start()
+                .starts_with("-----BEGIN CERTIFICATE-----")
+            {
+                errors.push(CrdError::InvalidCaBundle);
+            } else if let Some(client_config) = client_config {
+                client_config.ca_bundle = Some(ByteString(pem));
+            } else {
+                errors.push(CrdError::MissingConversionWebhook);
+            }
+        }
+
+        if let Some((namespace, certificate)) = self.cert_manager_certificate {
+            let client_config = merged
+                .spec
+                .conversion
+                .as_mut()
+                .and_then(|c| c.webhook.as_mut())
+                .and_then(|w| w.client_config.as_mut());
+            let reference = format!("{}/{}", namespace, certificate);
+            if !names::is_dns1123_label(&namespace)
+                || certificate.len() > 253
+                || !certificate.split('.').all(names::is_dns1123_label)
+            {
+                errors.push(CrdError::InvalidCertificateReference(reference));
+            } else if let Some(client_config) = client_config {
+                client_config.ca_bundle = None;
+                merged
+                    .metadata
+                    .annotations
+                    .get_or_insert_with(BTreeMap::new)
+                    .insert(CERT_MANAGER_INJECT_CA_FROM_ANNOTATION.to_string(), reference);
+            } else {
+                errors.push(CrdError::MissingConversionWebhook);
+            }
+        }
+
+        if self.helm_placeholders {
+            let client_config = merged
+                .spec
+                .conversion
+                .as_mut()
+                .filter(|c| c.strategy == "Webhook")
+                .and_then(|c| c.webhook.as_mut())
+                .and_then(|w| w.client_config.as_mut());
+            if let Some(client_config) = client_config {
+                if let Some(service) = client_config.service.as_mut() {
+                    service.namespace = HELM_NAMESPACE_PLACEHOLDER.into();
+                    service.name = HELM_SERVICE_NAME_PLACEHOLDER.into();
+                }
+                client_config.ca_bundle = Some(ByteString(HELM_CA_BUNDLE_PLACEHOLDER.into()));
+            }
+        }
+
+        if let Some(version) = &self.target_kube_version {
+            match compat::parse_kube_version(version) {
+                None => errors.push(CrdError::InvalidKubeVersion(version.clone())),
+                Some(target) => {
+                    if self.lower_to_kube_version {
+                        compat::drop_unsupported_features(&mut merged, target);
+                    }
+                    if let Some((feature, (major, minor))) = compat::unsupported_feature(&merged, target) {
+                        errors.push(CrdError::FeatureRequiresNewerKube {
+                            feature,
+                            min_version: format!("{}.{}", major, minor),
+                        });
+                    }
+                }
+            }
+        }
+
+        if self.validate_structural {
+            for version in &merged.spec.versions {
+                let paths = version_schema(version).map(check_structural).unwrap_or_default();
+                if !paths.is_empty() {
+                    errors.push(CrdError::NonStructuralSchema {
+                        version: version.name.clone(),
+                        paths,
+                    });
+                }
+            }
+        }
+
+        if self.validate_metaschema {
+            for version in &merged.spec.versions {
+                let violations = version_schema(version)
+                    .map(metaschema::metaschema_violations)
+                    .unwrap_or_default();
+                if !violations.is_empty() {
+                    errors.push(CrdError::InvalidSchemaStructure {
+                        version: version.name.clone(),
+                        error: violations.join("; "),
+                    });
+                }
+            }
+        }
+
+        if self.merge_status_columns {
+            let (union, _) = status_columns(&merged.spec.versions);
+            for version in &mut merged.spec.versions {
+                let columns = version.additional_printer_columns.get_or_insert_with(Vec::new);
+                for column in &union {
+                    if !columns.iter().any(|c| c.name == column.name) {
+                        columns.push(column.clone());
+                    }
+                }
+            }
+        }
+
+        if self.require_uniform_scale {
+            let scale =
+                |v: &CustomResourceDefinitionVersion| v.subresources.as_ref().and_then(|s| s.scale.clone());
+            let mut served = merged.spec.versions.iter().filter(|v| v.served);
+            if let Some(first) = served.next() {
+                let versions = served
+                    .filter(|v| scale(v) != scale(first))
+                    .map(|v| v.name.clone())
+                    .collect::<Vec<_>>();
+                if !versions.is_empty() {
+                    errors.push(CrdError::InconsistentScaleSubresource {
+                        first: first.name.clone(),
+                        versions,
+                    });
+                }
+            }
+        }
+
+        if self.validate_scale_paths {
+            let mut scaled = merged.spec.versions.iter().filter_map(|v| {
+                let scale = v.subresources.as_ref()?.scale.as_ref()?;
+                let paths = (
+                    &scale.spec_replicas_path,
+                    &scale.status_replicas_path,
+                    &scale.label_selector_path,
+                );
+                Some((&v.name, paths))
+            });
+            if let Some((first, paths)) = scaled.next() {
+                let versions = scaled
+                    .filter(|(_, p)| *p != paths)
+                    .map(|(name, _)| name.clone())
+                    .collect::<Vec<_>>();
+                if !versions.is_empty() {
+                    errors.push(CrdError::InconsistentScalePaths {
+                        first: first.clone(),
+                        versions,
+                    });
+                }
+            }
+        }
+
+        if !self.forbidden_paths.is_empty() {
+            let mut forbidden: Vec<String> = vec![];
+            for path in merged
+                .spec
+                .versions
+                .iter()
+                .filter_map(version_schema)
+                .flat_map(schema_paths)
+            {
+                if !forbidden.contains(&path) && self.forbidden_paths.iter().any(|f| forbids(f, &path)) {
+                    forbidden.push(path);
+                }
+            }
+            errors.extend(forbidden.into_iter().map(CrdError::ForbiddenField));
+        }
+
+        if self.require_contiguous_served {
+            let gaps = served_gaps(&merged.spec.versions);
+            if !gaps.is_empty() {
+                errors.push(CrdError::NonContiguousServedVersions(gaps));
+            }
+        }
+
+        // the apiserver requires exactly one storage version, however the flags came about
+        let storage = merged.spec.versions.iter().filter(|v| v.storage);
+        match storage.map(|v| v.name.clone()).collect::<Vec<_>>() {
+            storage if storage.is_empty() => errors.push(CrdError::NoStorageVersion),
+            storage if storage.len() > 1 => errors.push(CrdError::MultipleStorageVersions(storage)),
+            _ => {}
+        }
+
+        if let Some(template) = self.metadata_template {
+            apply_metadata_template(&mut merged.metadata, template);
+        }
+        if let Some(instance) = self.gitops_instance {
+            let labels = merged.metadata.labels.get_or_insert_with(BTreeMap::new);
+            for (key, value) in [
+                ("app.kubernetes.io/instance", instance),
+                ("app.kubernetes.io/managed-by", "kube-rs".to_string()),
+            ] {
+                labels.entry(key.to_string()).or_insert(value);
+            }
+        }
+        if errors.is_empty() {
+            Ok(merged)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn root_index(&self) -> Result<usize, CrdError> {
+        if self.crds.is_empty() {
+            return Err(CrdError::Empty);
+        }
+        match &self.root {
+            None => Ok(0),
+            Some(root) => self
+                .crds
+                .iter()
+                .position(|crd| crd.spec.versions.iter().any(|v| &v.name == root))
+                .ok_or_else(|| CrdError::MissingVersion(root.clone())),
+        }
+    }
+}
+
+/// Whether the `forbidden` path (possibly ending in `.*`) matches the schema `path`
+fn forbids(forbidden: &str, path: &str) -> bool {
+    match forbidden.strip_suffix(".*") {
+        Some(prefix) => path
+            .strip_prefix(prefix)
+            .map_or(false, |rest| rest.starts_with('.') || rest.starts_with('[')),
+        None => forbidden == path,
+    }
+}
+
+/// Group crds by their group and kind, keeping the order in which every kind is first seen
+///
+/// Every bucket contains the crds of one resource, ready to be merged with [`CrdMerger`].
+pub fn partition(crds: Vec<Crd>) -> Vec<Vec<Crd>> {
+    let mut buckets: Vec<Vec<Crd>> = vec![];
+    for crd in crds {
+        let bucket = buckets
+            .iter_mut()
+            .find(|b| b[0].spec.group == crd.spec.group && b[0].spec.names.kind == crd.spec.names.kind);
+        match bucket {
+            Some(bucket) => bucket.push(crd),
+            None => buckets.push(vec![crd]),
+        }
+    }
+    buckets
+}
+
+/// Merge crds of any number of resources into one crd per resource (group and kind)
+///
+/// This [`partition`]s the crds, and merges every bucket with the default [`CrdMerger`] settings, except that the
+/// version with the highest priority becomes the storage version (see [`CrdMerger::storage_latest_ga`]), as every
+/// derived crd marks its version as the storage version.
+///
+/// ```
+/// # use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+/// # fn wrapper(crds: Vec<CustomResourceDefinition>) -> Result<(), kube_core::crd::CrdError> {
+/// let manifests = kube_core::crd::merge_all(crds)?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Fails if merging any of the resources fails.
+pub fn merge_all(crds: Vec<Crd>) -> Result<Vec<Crd>, CrdError> {
+    partition(crds)
+        .into_iter()
+        .map(|bucket| CrdMerger::new(bucket).storage_latest_ga().merge())
+        .collect()
+}
+
+/// Check that `crd` agrees with `root` on the properties shared by all versions of a kind
+fn check_consistency(root: &Crd, crd: &Crd, errors: &mut Vec<CrdError>) {
+    let version = crd
+        .spec
+        .versions
+        .iter()
+        .map(|v| v.name.as_str())
+        .collect::<Vec<_>>()
+        .join(",");
+    let name = |crd: &Crd| crd.metadata.name.clone().unwrap_or_default();
+    let properties = [
+        ("name", name(root), name(crd)),
+        ("group", root.spec.group.clone(), crd.spec.group.clone()),
+        ("kind", root.spec.names.kind.clone(), crd.spec.names.kind.clone()),
+        (
+            "plural",
+            root.spec.names.plural.clone(),
+            crd.spec.names.plural.clone(),
+        ),
+        ("scope", root.spec.scope.clone(), crd.spec.scope.clone()),
+    ];
+    for (property, expected, found) in properties {
+        if expected != found {
+            errors.push(CrdError::PropertyMismatch {
+                version: version.clone(),
+                property,
+                expected,
+                found,
+            });
+        }
+    }
+}
+
+/// Set the extension `key` to `value` on the node of `schema` at `pointer`
+fn set_extension(
+    schema: Option<&mut JSONSchemaProps>,
+    pointer: &str,
+    key: &str,
+    value: serde_json::Value,
+) -> Result<(), &'static str> {
+    if !key.starts_with("x-kubernetes-") {
+        return Err("not an x-kubernetes-* extension");
+    }
+    let schema = schema.ok_or("version has no schema")?;
+    let mut json = serde_json::to_value(&*schema).map_err(|_| "schema is not serializable")?;
+    json.pointer_mut(pointer)
+        .and_then(|node| node.as_object_mut())
+        .ok_or("pointer does not resolve to a schema node")?
+        .insert(key.to_string(), value);
+    let patched: JSONSchemaProps =
+        serde_json::from_value(json).map_err(|_| "value does not fit the extension")?;
+    // unknown keys are dropped when deserializing
+    if serde_json::to_value(&patched)
+        .ok()
+        .and_then(|v| v.pointer(pointer)?.get(key).cloned())
+        .is_none()
+    {
+        return Err("unknown extension");
+    }
+    *schema = patched;
+    Ok(())
+}
+
+/// The union of the `.status` printer columns of `versions` (by name, first declaration wins),
+/// and the conflicting declarations
+fn status_columns<'a>(
+    versions: impl IntoIterator<Item = &'a CustomResourceDefinitionVersion>,
+) -> (Vec<CustomResourceColumnDefinition>, Vec<String>) {
+    let mut union: Vec<CustomResourceColumnDefinition> = vec![];
+    let mut conflicts = vec![];
+    for version in versions {
+        let columns = version.additional_printer_columns.iter().flatten();
+        for column in columns.filter(|c| c.json_path.starts_with(".status.")) {
+            match union.iter().find(|c| c.name == column.name) {
+                None => union.push(column.clone()),
+                Some(existing) if existing.json_path != column.json_path => conflicts.push(format!(
+                    "{}: version {} uses {:?}, but an earlier version uses {:?}",
+                    column.name, version.name, column.json_path, existing.json_path
+                )),
+                Some(_) => {}
+            }
+        }
+    }
+    (union, conflicts)
+}
+
+/// Unserved versions that have served versions of the same maturity tier before and after them
+fn served_gaps(versions: &[CustomResourceDefinitionVersion]) -> Vec<String> {
+    let tier = |name: &str| match Version::parse(name) {
+        Version::Stable(_) => Some(0),
+        Version::Beta(..) => Some(1),
+        Version::Alpha(..) => Some(2),
+        Version::Nonconformant(_) => None,
+    };
+    let served_around = |unserved: &str, before: bool| {
+        let priority = Version::parse(unserved).priority();
+        versions
+            .iter()
+            .filter(|v| v.served && tier(&v.name) == tier(unserved))
+            .any(|v| (Version::parse(&v.name).priority() < priority) == before)
+    };
+    versions
+        .iter()
+        .filter(|v| !v.served && tier(&v.name).is_some())
+        .filter(|v| served_around(&v.name, true) && served_around(&v.name, false))
+        .map(|v| v.name.clone())
+        .collect()
+}
+
+/// Problems with the reference to a conversion webhook service
+fn check_service(service: &ServiceReference) -> Vec<CrdError> {
+    let mut errors = vec![];
+    if !names::is_dns1123_label(&service.namespace) {
+        errors.push(CrdError::InvalidConversionService {
+            field: "namespace",
+            value: service.namespace.clone(),
+        });
+    }
+    if !names::is_dns1035_label(&service.name) {
+        errors.push(CrdError::InvalidConversionService {
+            field: "name",
+            value: service.name.clone(),
+        });
+    }
+    if let Some(path) = service.path.as_ref().filter(|p| !p.starts_with('/')) {
+        errors.push(CrdError::InvalidConversionService {
+            field: "path",
+            value: path.clone(),
+        });
+    }
+    if let Some(port) = service.port.filter(|p| !(1..=65535).contains(p)) {
+        errors.push(CrdError::InvalidConversionService {
+            field: "port",
+            value: port.to_string(),
+        });
+    }
+    errors
+}
+
+/// Name of the root crd, with the labels and annotations of all crds unioned
+fn merge_metadata(root: &Crd, crds: &[Crd], errors: &mut Vec<CrdError>) -> ObjectMeta {
+    let mut labels = BTreeMap::new();
+    let mut annotations = BTreeMap::new();
+    for crd in crds {
+        union_into(&mut labels, crd.metadata.labels.as_ref(), "label", errors);
+        union_into(
+            &mut annotations,
+            crd.metadata.annotations.as_ref(),
+            "annotation",
+            errors,
+        );
+    }
+    ObjectMeta {
+        name: root.metadata.name.clone(),
+        labels: Some(labels).filter(|l| !l.is_empty()),
+        annotations: Some(annotations).filter(|a| !a.is_empty()),
+        ..ObjectMeta::default()
+    }
+}
+
+fn union_into(
+    target: &mut BTreeMap<String, String>,
+    source: Option<&BTreeMap<String, String>>,
+    kind: &'static str,
+    errors: &mut Vec<CrdError>,
+) {
+    for (key, value) in source.into_iter().flatten() {
+        match target.get(key) {
+            Some(existing) if existing != value => errors.push(CrdError::MetadataConflict {
+                kind,
+                key: key.clone(),
+            }),
+            _ => {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+fn apply_metadata_template(meta: &mut ObjectMeta, template: ObjectMeta) {
+    if let Some(labels) = template.labels {
+        meta.labels.get_or_insert_with(BTreeMap::new).extend(labels);
+    }
+    if let Some(annotations) = template.annotations {
+        meta.annotations
+            .get_or_insert_with(BTreeMap::new)
+            .extend(annotations);
+    }
+    if let Some(finalizers) = template.finalizers {
+        let existing = meta.finalizers.get_or_insert_with(Vec::new);
+        let mut seen = existing.iter().cloned().collect::<HashSet<_>>();
+        for finalizer in finalizers {
+            if seen.insert(finalizer.clone()) {
+                existing.push(finalizer);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        merge_all, Crd, CrdError, CrdMerger, MergePolicy, CERT_MANAGER_INJECT_CA_FROM_ANNOTATION,
+        HELM_CA_BUNDLE_PLACEHOLDER, HELM_NAMESPACE_PLACEHOLDER, HELM_SERVICE_NAME_PLACEHOLDER,
+        MERGE_REPORT_VERSION, RETIRED_VERSIONS_ANNOTATION, VERSION_NOTE_ANNOTATION_PREFIX,
+        VERSION_SOURCES_ANNOTATION,
+    };
+    use k8s_openapi::{
+        apiextensions_apiserver::pkg::apis::apiextensions::v1::{JSONSchemaProps, WebhookClientConfig},
+        apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    };
+    use serde_json::json;
+
+    fn crd(version: &str) -> Crd {
+        serde_json::from_value(json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos", "singular": "foo" },
+                "scope": "Namespaced",
+                "versions": [{
+                    "name": version,
+                    "served": true,
+                    "storage": true,
+                    "schema": {
+                        "openAPIV3Schema": {
+                            "type": "object",
+                            "properties": { "spec": { "type": "object" } }
+                        }
+                    }
+                }]
+            }
+        }))
+        .unwrap()
+    }
+
+    fn labelled(mut crd: Crd, labels: &[(&str, &str)], annotations: &[(&str, &str)]) -> Crd {
+        let to_map = |pairs: &[(&str, &str)]| {
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect()
+        };
+        crd.metadata.labels = Some(to_map(labels));
+        crd.metadata.annotations = Some(to_map(annotations));
+        crd
+    }
+
+    #[test]
+    fn merges_versions_in_order() {
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .merge()
+            .unwrap();
+        let versions = merged
+            .spec
+            .versions
+            .iter()
+            .map(|v| (v.name.as_str(), v.storage))
+            .collect::<Vec<_>>();
+        assert_eq!(versions, vec![("v1", false), ("v2", true)]);
+    }
+
+    #[test]
+    fn planned_versions_previews_output() {
+        let merger = CrdMerger::new(vec![crd("v1"), crd("v2"), crd("v1")]).with_root("v2");
+        assert_eq!(merger.planned_versions(), vec!["v1", "v2"]);
+        assert!(merger.merge().is_err());
+        let merger = CrdMerger::new(vec![crd("v2"), crd("v1")]).storage("v2");
+        let planned = merger.planned_versions();
+        let merged = merger.merge().unwrap();
+        let versions = merged.spec.versions.iter().map(|v| &v.name).collect::<Vec<_>>();
+        assert_eq!(versions, planned.iter().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn source_annotations_name_the_type_of_every_version() {
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .source("v1", "operator::v1::Foo")
+            .source("v2", "operator::v2::Foo")
+            .with_source_annotations()
+            .merge()
+            .unwrap();
+        assert_eq!(
+            merged.metadata.annotations.unwrap()[VERSION_SOURCES_ANNOTATION],
+            "v1=operator::v1::Foo,v2=operator::v2::Foo"
+        );
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1"), crd("v2")])
+                .storage("v2")
+                .source("v2", "operator::v2::Foo")
+                .source("v3", "operator::v3::Foo")
+                .with_source_annotations()
+                .try_merge()
+                .unwrap_err(),
+            vec![
+                CrdError::MissingVersion("v3".into()),
+                CrdError::MissingVersionSource("v1".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn version_notes_are_annotated() {
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .version_note("v2", "added spec.retries")
+            .merge()
+            .unwrap();
+        let annotations = merged.metadata.annotations.unwrap();
+        assert_eq!(annotations["kube.rs/version-note.v2"], "added spec.retries");
+        assert!(!annotations.contains_key(&format!("{}v1", VERSION_NOTE_ANNOTATION_PREFIX)));
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .version_note("v3", "added spec.retries")
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingVersion("v3".into())
+        );
+    }
+
+    #[test]
+    fn json_report_describes_the_merge() {
+        let (merged, report) = CrdMerger::new(vec![crd("v1alpha1"), crd("v1"), crd("v2")])
+            .storage("v1")
+            .retire("v1alpha1")
+            .source("v2", "my_crate::v2::Foo")
+            .merge_with_json_report()
+            .unwrap();
+        assert_eq!(merged.spec.versions.len(), 2);
+        assert_eq!(
+            report,
+            json!({
+                "reportVersion": MERGE_REPORT_VERSION,
+                "name": "foos.clux.dev",
+                "group": "clux.dev",
+                "kind": "Foo",
+                "storageVersion": "v1",
+                "conversion": "None",
+                "versions": [
+                    {
+                        "name": "v1",
+                        "served": true,
+                        "storage": true,
+                        "deprecated": false,
+                        "source": 1,
+                        "sourceType": null
+                    },
+                    {
+                        "name": "v2",
+                        "served": true,
+                        "storage": false,
+                        "deprecated": false,
+                        "source": 2,
+                        "sourceType": "my_crate::v2::Foo"
+                    }
+                ],
+                "retired": ["v1alpha1"],
+                "warnings": []
+            })
+        );
+    }
+
+    #[test]
+    fn retire_drops_version_and_annotates() {
+        let merger = CrdMerger::new(vec![crd("v1alpha1"), crd("v1")])
+            .storage("v1")
+            .retire("v1alpha1");
+        assert_eq!(merger.planned_versions(), vec!["v1"]);
+        let merged = merger.merge().unwrap();
+        assert_eq!(merged.spec.versions.len(), 1);
+        assert_eq!(
+            merged.metadata.annotations.unwrap()[RETIRED_VERSIONS_ANNOTATION],
+            "v1alpha1"
+        );
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1alpha1"), crd("v1")])
+                .storage("v1")
+                .retire("v1")
+                .merge()
+                .unwrap_err(),
+            CrdError::RetiredStorageVersion("v1".into())
+        );
+    }
+
+    #[test]
+    fn validate_metaschema_reports_malformed_nodes() {
+        let mut v2 = crd("v2");
+        v2.spec.versions[0].schema = serde_json::from_value(json!({
+            "openAPIV3Schema": {
+                "type": "object",
+                "properties": {
+                    "spec": {
+                        "type": "object",
+                        "properties": {
+                            "name": { "type": "text", "maxLength": -1 },
+                            "ports": { "type": "array", "items": [{ "type": "integer" }] }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let merger = CrdMerger::new(vec![crd("v1"), v2]).storage("v2");
+        assert!(merger.clone().merge().is_ok());
+        assert_eq!(
+            merger.validate_metaschema().merge().unwrap_err(),
+            CrdError::InvalidSchemaStructure {
+                version: "v2".into(),
+                error: [
+                    r#"properties[spec].properties[name].type: Unsupported value: "text""#,
+                    "properties[spec].properties[name].maxLength: Invalid value: -1: must not be negative",
+                    "properties[spec].properties[ports].items: Invalid value: must be a single schema, not an array of schemas",
+                ]
+                .join("; ")
+            }
+        );
+    }
+
+    #[test]
+    fn validate_structural_reports_violations() {
+        let mut v2 = crd("v2");
+        v2.spec.versions[0].schema = serde_json::from_value(json!({
+            "openAPIV3Schema": {
+                "oneOf": [{ "type": "object", "properties": { "spec": { "type": "object" } } }]
+            }
+        }))
+        .unwrap();
+        let merger = CrdMerger::new(vec![crd("v1"), v2]).storage("v2");
+        assert!(merger.clone().merge().is_ok());
+        match merger.validate_structural().merge().unwrap_err() {
+            CrdError::NonStructuralSchema { version, paths } => {
+                assert_eq!(version, "v2");
+                assert!(paths.iter().any(|p| p.starts_with("type:")));
+                assert!(paths.iter().any(|p| p.starts_with("oneOf[0].type:")));
+                assert!(paths.iter().any(|p| p.starts_with("oneOf[0].properties[spec]:")));
+            }
+            err => panic!("unexpected error {:?}", err),
+        }
+    }
+
+    #[test]
+    fn override_schema_replaces_only_target_version() {
+        let schema: JSONSchemaProps = serde_json::from_value(json!({
+            "type": "object",
+            "x-kubernetes-preserve-unknown-fields": true
+        }))
+        .unwrap();
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .override_schema("v2", schema.clone())
+            .merge()
+            .unwrap();
+        assert_eq!(merged.spec.versions[0].schema, crd("v1").spec.versions[0].schema);
+        let v2_schema = merged.spec.versions[1].schema.as_ref().unwrap();
+        assert_eq!(v2_schema.open_api_v3_schema.as_ref(), Some(&schema));
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .override_schema("v2", schema)
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingVersion("v2".into())
+        );
+    }
+
+    #[test]
+    fn preserve_unknown_for_version_only_relaxes_that_version() {
+        let merged = CrdMerger::new(vec![crd("v1beta1"), crd("v1")])
+            .storage("v1")
+            .preserve_unknown_for_version("v1beta1")
+            .merge()
+            .unwrap();
+        let preserves = merged
+            .spec
+            .versions
+            .iter()
+            .map(|v| {
+                let schema = v.schema.as_ref().unwrap().open_api_v3_schema.as_ref().unwrap();
+                (v.name.as_str(), schema.x_kubernetes_preserve_unknown_fields)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(preserves, vec![("v1beta1", Some(true)), ("v1", None)]);
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .preserve_unknown_for_version("v1beta1")
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingVersion("v1beta1".into())
+        );
+    }
+
+    #[test]
+    fn set_extension_patches_nested_schema_node() {
+        let mut base = crd("v1");
+        base.spec.versions[0].schema = serde_json::from_value(json!({
+            "openAPIV3Schema": {
+                "type": "object",
+                "properties": { "spec": {
+                    "type": "object",
+                    "properties": { "replicas": { "type": "integer" } }
+                } }
+            }
+        }))
+        .unwrap();
+        let rules = json!([{ "rule": "self >= 0", "message": "must not be negative" }]);
+        let merged = CrdMerger::new(vec![base.clone()])
+            .set_extension(
+                "v1",
+                "/properties/spec/properties/replicas",
+                "x-kubernetes-validations",
+                rules.clone(),
+            )
+            .merge()
+            .unwrap();
+        let schema = serde_json::to_value(&merged.spec.versions[0].schema).unwrap();
+        assert_eq!(
+            schema.pointer("/openAPIV3Schema/properties/spec/properties/replicas/x-kubernetes-validations"),
+            Some(&rules)
+        );
+        assert_eq!(
+            schema.pointer("/openAPIV3Schema/properties/spec/x-kubernetes-validations"),
+            None
+        );
+
+        let err = CrdMerger::new(vec![base.clone()])
+            .set_extension(
+                "v1",
+                "/properties/status",
+                "x-kubernetes-map-type",
+                json!("atomic"),
+            )
+            .merge()
+            .unwrap_err();
+        assert_eq!(err, CrdError::InvalidSchemaExtension {
+            version: "v1".into(),
+            pointer: "/properties/status".into(),
+            key: "x-kubernetes-map-type".into(),
+            reason: "pointer does not resolve to a schema node",
+        });
+        let err = CrdMerger::new(vec![base])
+            .set_extension("v1", "", "x-kubernetes-map-type", json!(3))
+            .merge()
+            .unwrap_err();
+        assert!(matches!(err, CrdError::InvalidSchemaExtension {
+            reason: "value does not fit the extension",
+            ..
+        }));
+    }
+
+    #[test]
+    fn diff_patch_touches_only_changed_versions() {
+        let merger = || CrdMerger::new(vec![crd("v1"), crd("v2")]).storage("v2");
+        let live = merger().merge().unwrap();
+        assert_eq!(merger().diff_patch(&live).unwrap(), None);
+
+        let schema: JSONSchemaProps = serde_json::from_value(json!({
+            "type": "object",
+            "properties": { "spec": { "type": "object", "required": ["size"] } }
+        }))
+        .unwrap();
+        let changed = merger().override_schema("v2", schema.clone());
+        let patch = changed.diff_patch(&live).unwrap().unwrap();
+        let mut expected = live.spec.versions[1].clone();
+        expected.schema.as_mut().unwrap().open_api_v3_schema = Some(schema);
+        assert_eq!(
+            patch,
+            json!([
+                { "op": "test", "path": "/spec/versions/1/name", "value": "v2" },
+                { "op": "replace", "path": "/spec/versions/1", "value": expected },
+            ])
+        );
+
+        let added = CrdMerger::new(vec![crd("v2"), crd("v3")]).storage("v2");
+        let mut v3 = crd("v3").spec.versions.remove(0);
+        v3.storage = false;
+        assert_eq!(
+            added.diff_patch(&live).unwrap().unwrap(),
+            json!([
+                { "op": "test", "path": "/spec/versions/0/name", "value": "v1" },
+                { "op": "remove", "path": "/spec/versions/0" },
+                { "op": "add", "path": "/spec/versions/-", "value": v3 },
+            ])
+        );
+    }
+
+    #[test]
+    fn storage_latest_ga_stores_the_newest_stable_version() {
+        let storage = |versions: &[&str]| {
+            CrdMerger::new(versions.iter().map(|v| crd(v)).collect())
+                .storage_latest_ga()
+                .merge()
+                .unwrap()
+                .spec
+                .versions
+                .into_iter()
+                .filter(|v| v.storage)
+                .map(|v| v.name)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(storage(&["v1", "v2", "v3beta1"]), vec!["v2"]);
+        assert_eq!(storage(&["v1alpha1", "v1beta2", "v1beta1"]), vec!["v1beta2"]);
+        let explicit = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage_latest_ga()
+            .storage("v1")
+            .merge()
+            .unwrap();
+        assert!(explicit.spec.versions[0].storage);
+    }
+
+    #[test]
+    fn policies_assign_served_and_storage() {
+        let inputs = || {
+            let mut v1alpha1 = crd("v1alpha1");
+            v1alpha1.spec.versions[0].served = false;
+            v1alpha1.spec.versions[0].storage = false;
+            let mut v1beta1 = crd("v1beta1");
+            v1beta1.spec.versions[0].storage = false;
+            vec![crd("v1"), v1alpha1, v1beta1]
+        };
+        let flags = |merger: CrdMerger| {
+            merger
+                .merge()
+                .unwrap()
+                .spec
+                .versions
+                .into_iter()
+                .map(|v| (v.name, v.served, v.storage))
+                .collect::<Vec<_>>()
+        };
+        let expected = |flags: [(bool, bool); 3]| {
+            ["v1", "v1alpha1", "v1beta1"]
+                .iter()
+                .zip(flags)
+                .map(|(name, (served, storage))| (name.to_string(), served, storage))
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(
+            flags(CrdMerger::new(inputs()).policy(MergePolicy::PreserveInputs)),
+            expected([(true, true), (false, false), (true, false)])
+        );
+        assert_eq!(
+            flags(CrdMerger::new(inputs()).policy(MergePolicy::LatestStorageAllServed)),
+            expected([(true, true), (true, false), (true, false)])
+        );
+        assert_eq!(
+            flags(
+                CrdMerger::new(inputs())
+                    .policy(MergePolicy::ExplicitStorage)
+                    .storage("v1beta1")
+            ),
+            expected([(true, false), (false, false), (true, true)])
+        );
+        assert_eq!(
+            CrdMerger::new(inputs())
+                .policy(MergePolicy::ExplicitStorage)
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingStorage
+        );
+    }
+
+    #[test]
+    fn merge_status_columns_unions_status_columns() {
+        let with_columns = |version: &str, columns: serde_json::Value| {
+            let mut crd = crd(version);
+            crd.spec.versions[0].additional_printer_columns = serde_json::from_value(columns).unwrap();
+            crd
+        };
+        let inputs = || {
+            vec![
+                with_columns(
+                    "v1",
+                    json!([
+                        { "name": "Phase", "type": "string", "jsonPath": ".status.phase" },
+                        { "name": "Image", "type": "string", "jsonPath": ".spec.image" }
+                    ]),
+                ),
+                with_columns(
+                    "v2",
+                    json!([
+                        { "name": "Ready", "type": "boolean", "jsonPath": ".status.ready" },
+                        { "name": "Phase", "type": "string", "jsonPath": ".status.state" }
+                    ]),
+                ),
+            ]
+        };
+        let names = |merged: Crd| {
+            merged
+                .spec
+                .versions
+                .into_iter()
+                .map(|v| {
+                    let columns = v.additional_printer_columns.unwrap_or_default();
+                    columns.into_iter().map(|c| c.json_path).collect::<Vec<_>>()
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let merged = CrdMerger::new(inputs()).storage("v2").merge().unwrap();
+        assert_eq!(names(merged), vec![vec![".status.phase", ".spec.image"], vec![
+            ".status.ready",
+            ".status.state"
+        ]]);
+        let merger = CrdMerger::new(inputs()).storage("v2").merge_status_columns();
+        assert_eq!(merger.lint_status_columns(), vec![
+            r#"Phase: version v2 uses ".status.state", but an earlier version uses ".status.phase""#
+        ]);
+        assert_eq!(names(merger.merge().unwrap()), vec![
+            vec![".status.phase", ".spec.image", ".status.ready"],
+            vec![".status.ready", ".status.state"]
+        ]);
+    }
+
+    #[test]
+    fn require_contiguous_served_rejects_gaps_within_a_tier() {
+        let unserved = |version: &str| {
+            let mut crd = crd(version);
+            crd.spec.versions[0].served = false;
+            crd.spec.versions[0].storage = false;
+            crd
+        };
+        let crds = vec![crd("v1"), unserved("v2"), crd("v3")];
+        assert!(CrdMerger::new(crds.clone()).storage("v3").merge().is_ok());
+        assert_eq!(
+            CrdMerger::new(crds)
+                .storage("v3")
+                .require_contiguous_served()
+                .merge()
+                .unwrap_err(),
+            CrdError::NonContiguousServedVersions(vec!["v2".into()])
+        );
+
+        // unserved versions of another tier, or at the end of a tier, are fine
+        let crds = vec![
+            crd("v1"),
+            unserved("v2beta1"),
+            crd("v2"),
+            unserved("v3"),
+            crd("v1beta1"),
+        ];
+        assert!(CrdMerger::new(crds)
+            .storage("v2")
+            .require_contiguous_served()
+            .merge()
+            .is_ok());
+    }
+
+    #[test]
+    fn require_uniform_scale_rejects_mixed_scale() {
+        let scaled = |version: &str| {
+            let mut crd = crd(version);
+            crd.spec.versions[0].subresources = serde_json::from_value(json!({
+                "scale": { "specReplicasPath": ".spec.replicas", "statusReplicasPath": ".status.replicas" }
+            }))
+            .unwrap();
+            crd
+        };
+        let inputs = || vec![scaled("v1"), crd("v2"), scaled("v3")];
+        assert!(CrdMerger::new(inputs()).storage("v3").merge().is_ok());
+        assert_eq!(
+            CrdMerger::new(inputs())
+                .storage("v3")
+                .require_uniform_scale()
+                .merge()
+                .unwrap_err(),
+            CrdError::InconsistentScaleSubresource {
+                first: "v1".into(),
+                versions: vec!["v2".into()],
+            }
+        );
+
+        let mut unserved = crd("v2");
+        unserved.spec.versions[0].served = false;
+        assert!(CrdMerger::new(vec![scaled("v1"), unserved, scaled("v3")])
+            .storage("v3")
+            .require_uniform_scale()
+            .merge()
+            .is_ok());
+    }
+
+    #[test]
+    fn validate_scale_paths_covers_all_versions() {
+        let scaled = |version: &str, selector: Option<&str>| {
+            let mut crd = crd(version);
+            crd.spec.versions[0].subresources = serde_json::from_value(json!({
+                "scale": {
+                    "specReplicasPath": ".spec.replicas",
+                    "statusReplicasPath": ".status.replicas",
+                    "labelSelectorPath": selector
+                }
+            }))
+            .unwrap();
+            crd
+        };
+        let mut unserved = scaled("v2", Some(".status.selector"));
+        unserved.spec.versions[0].served = false;
+        let inputs = || vec![scaled("v1", None), unserved.clone(), crd("v3")];
+        assert!(CrdMerger::new(inputs()).storage("v3").merge().is_ok());
+        assert_eq!(
+            CrdMerger::new(inputs())
+                .storage("v3")
+                .validate_scale_paths()
+                .merge()
+                .unwrap_err(),
+            CrdError::InconsistentScalePaths {
+                first: "v1".into(),
+                versions: vec!["v2".into()],
+            }
+        );
+
+        // versions without a scale subresource are not compared
+        assert!(
+            CrdMerger::new(vec![scaled("v1", None), crd("v2"), scaled("v3", None)])
+                .storage("v3")
+                .validate_scale_paths()
+                .merge()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn target_kube_version_rejects_newer_features() {
+        let mut v1 = crd("v1");
+        v1.spec.versions[0].schema = serde_json::from_value(json!({
+            "openAPIV3Schema": {
+                "type": "object",
+                "properties": {
+                    "spec": {
+                        "type": "object",
+                        "x-kubernetes-validations": [{ "rule": "self.replicas >= 0" }]
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        assert_eq!(
+            CrdMerger::new(vec![v1.clone()])
+                .target_kube_version("1.22")
+                .merge()
+                .unwrap_err(),
+            CrdError::FeatureRequiresNewerKube {
+                feature: "x-kubernetes-validations",
+                min_version: "1.25".into(),
+            }
+        );
+        assert!(CrdMerger::new(vec![v1.clone()])
+            .target_kube_version("v1.25.3")
+            .merge()
+            .is_ok());
+        assert_eq!(
+            CrdMerger::new(vec![v1])
+                .target_kube_version("latest")
+                .merge()
+                .unwrap_err(),
+            CrdError::InvalidKubeVersion("latest".into())
+        );
+    }
+
+    #[test]
+    fn for_kube_version_drops_newer_features() {
+        let mut v1 = crd("v1");
+        v1.spec.versions[0].deprecated = Some(true);
+        v1.spec.versions[0].schema = serde_json::from_value(json!({
+            "openAPIV3Schema": {
+                "type": "object",
+                "properties": {
+                    "spec": {
+                        "type": "object",
+                        "x-kubernetes-validations": [{ "rule": "self.replicas >= 0" }],
+                        "properties": {
+                            "ports": { "type": "array", "x-kubernetes-list-type": "set", "items": { "type": "integer" } }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let merger = CrdMerger::new(vec![v1]).for_kube_version("1.22");
+        assert_eq!(merger.lint_kube_version(), vec![
+            "version v1: schema.openAPIV3Schema.properties[spec].x-kubernetes-validations: dropped, requires Kubernetes >= 1.25",
+        ]);
+        let merged = merger.merge().unwrap();
+        let version = &merged.spec.versions[0];
+        assert_eq!(version.deprecated, Some(true));
+        let spec = &version
+            .schema
+            .as_ref()
+            .unwrap()
+            .open_api_v3_schema
+            .as_ref()
+            .unwrap()
+            .properties
+            .as_ref()
+            .unwrap()["spec"];
+        assert_eq!(spec.x_kubernetes_validations, None);
+        assert_eq!(
+            spec.properties.as_ref().unwrap()["ports"]
+                .x_kubernetes_list_type
+                .as_deref(),
+            Some("set")
+        );
+    }
+
+    #[test]
+    fn merge_all_merges_per_kind() {
+        let bar = |version: &str| {
+            let mut bar = crd(version);
+            bar.metadata.name = Some("bars.clux.dev".into());
+            bar.spec.names.kind = "Bar".into();
+            bar.spec.names.plural = "bars".into();
+            bar
+        };
+        let merged = merge_all(vec![crd("v1"), bar("v1"), crd("v2"), bar("v2")]).unwrap();
+        let kinds = merged
+            .iter()
+            .map(|crd| {
+                let versions = crd
+                    .spec
+                    .versions
+                    .iter()
+                    .map(|v| v.name.as_str())
+                    .collect::<Vec<_>>();
+                (crd.spec.names.kind.as_str(), versions)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(kinds, vec![("Foo", vec!["v1", "v2"]), ("Bar", vec!["v1", "v2"])]);
+    }
+
+    #[test]
+    fn conversion_webhook_ca_bundle_is_base64_encoded() {
+        let client_config: WebhookClientConfig = serde_json::from_value(json!({
+            "service": { "name": "foo-conversion", "namespace": "default", "path": "/convert" }
+        }))
+        .unwrap();
+        let pem = b"-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n";
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .conversion_webhook(client_config.clone())
+            .conversion_webhook_ca_from_pem(pem)
+            .merge()
+            .unwrap();
+        let conversion = serde_json::to_value(merged.spec.conversion).unwrap();
+        assert_eq!(conversion["strategy"], "Webhook");
+        assert_eq!(conversion["webhook"]["conversionReviewVersions"], json!(["v1"]));
+        assert_eq!(
+            conversion["webhook"]["clientConfig"]["caBundle"],
+            "LS0tLS1CRUdJTiBDRVJUSUZJQ0FURS0tLS0tCk1JSUIKLS0tLS1FTkQgQ0VSVElGSUNBVEUtLS0tLQo="
+        );
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .conversion_webhook(client_config)
+                .conversion_webhook_ca_from_pem(b"not a certificate")
+                .merge()
+                .unwrap_err(),
+            CrdError::InvalidCaBundle
+        );
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .conversion_webhook_ca_from_pem(pem)
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingConversionWebhook
+        );
+    }
+
+    #[test]
+    fn cert_manager_ca_injection_annotates_and_leaves_ca_bundle_empty() {
+        let pem = b"-----BEGIN CERTIFICATE-----\nMIIB\n-----END CERTIFICATE-----\n";
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .conversion_service("foo-system", "foo-webhook")
+            .conversion_webhook_ca_from_pem(pem)
+            .cert_manager_ca_injection("foo-system", "foo-webhook-cert")
+            .merge()
+            .unwrap();
+        let annotations = merged.metadata.annotations.as_ref().unwrap();
+        assert_eq!(
+            annotations[CERT_MANAGER_INJECT_CA_FROM_ANNOTATION],
+            "foo-system/foo-webhook-cert"
+        );
+        let webhook = merged.spec.conversion.unwrap().webhook.unwrap();
+        assert_eq!(webhook.client_config.unwrap().ca_bundle, None);
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .cert_manager_ca_injection("foo-system", "foo-webhook-cert")
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingConversionWebhook
+        );
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .conversion_service("foo-system", "foo-webhook")
+                .cert_manager_ca_injection("foo-system", "Foo_Cert")
+                .merge()
+                .unwrap_err(),
+            CrdError::InvalidCertificateReference("foo-system/Foo_Cert".into())
+        );
+    }
+
+    #[test]
+    fn merge_split_separates_conversion() {
+        let merger = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .conversion_service("foo-system", "foo-webhook")
+            .conversion_service_path("/convert");
+        let (split, conversion) = merger.clone().merge_split().unwrap();
+        assert_eq!(split.spec.conversion, None);
+        let expected = merger.merge().unwrap().spec.conversion.unwrap();
+        let conversion = conversion.unwrap();
+        assert_eq!(conversion.conversion, expected);
+        assert_eq!(conversion.patch()["spec"]["conversion"]["strategy"], "Webhook");
+        assert_eq!(
+            conversion.patch()["spec"]["conversion"]["webhook"]["clientConfig"]["service"]["path"],
+            "/convert"
+        );
+
+        let (_, conversion) = CrdMerger::new(vec![crd("v1")]).merge_split().unwrap();
+        assert_eq!(conversion, None);
+    }
+
+    #[test]
+    fn helm_placeholders_appear_verbatim_in_template() {
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .conversion_service("default", "foo-conversion")
+            .helm_placeholders()
+            .merge()
+            .unwrap();
+        let service = merged.spec.conversion.as_ref().unwrap().webhook.as_ref().unwrap();
+        let service = service.client_config.as_ref().unwrap().service.as_ref().unwrap();
+        assert_eq!(service.namespace, HELM_NAMESPACE_PLACEHOLDER);
+        assert_eq!(service.name, HELM_SERVICE_NAME_PLACEHOLDER);
+
+        let template = crate::crd::to_helm_template(&[merged]).unwrap();
+        assert!(template.contains(&format!("caBundle: {}\n", HELM_CA_BUNDLE_PLACEHOLDER)));
+        assert!(template.contains(HELM_NAMESPACE_PLACEHOLDER));
+        assert!(template.contains(HELM_SERVICE_NAME_PLACEHOLDER));
+        assert!(template.contains("path: /convert"));
+
+        // invalid services are still rejected
+        let err = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .conversion_service("default", "Foo")
+            .helm_placeholders()
+            .merge()
+            .unwrap_err();
+        assert!(matches!(err, CrdError::InvalidConversionService {
+            field: "name",
+            ..
+        }));
+    }
+
+    #[test]
+    fn conversion_service_references_service_with_defaults() {
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .conversion_service("default", "foo-conversion")
+            .merge()
+            .unwrap();
+        let webhook = merged.spec.conversion.unwrap().webhook.unwrap();
+        assert_eq!(
+            serde_json::to_value(webhook.client_config).unwrap(),
+            json!({
+                "service": { "namespace": "default", "name": "foo-conversion", "path": "/convert", "port": 443 }
+            })
+        );
+
+        let merged = CrdMerger::new(vec![crd("v1")])
+            .conversion_service("default", "foo-conversion")
+            .conversion_service_path("/v1/convert")
+            .conversion_service_port(8443)
+            .merge()
+            .unwrap();
+        let client_config = merged.spec.conversion.unwrap().webhook.unwrap().client_config;
+        let service = client_config.unwrap().service.unwrap();
+        assert_eq!(service.path.as_deref(), Some("/v1/convert"));
+        assert_eq!(service.port, Some(8443));
+
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .conversion_service("", "Foo")
+                .try_merge()
+                .unwrap_err(),
+            vec![
+                CrdError::InvalidConversionService {
+                    field: "namespace",
+                    value: "".into()
+                },
+                CrdError::InvalidConversionService {
+                    field: "name",
+                    value: "Foo".into()
+                },
+            ]
+        );
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .conversion_service_port(8443)
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingConversionWebhook
+        );
+    }
+
+    #[test]
+    fn conversion_service_rejects_relative_paths_and_invalid_ports() {
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .conversion_service("default", "foo-conversion")
+                .conversion_service_path("convert")
+                .conversion_service_port(0)
+                .try_merge()
+                .unwrap_err(),
+            vec![
+                CrdError::InvalidConversionService {
+                    field: "path",
+                    value: "convert".into()
+                },
+                CrdError::InvalidConversionService {
+                    field: "port",
+                    value: "0".into()
+                },
+            ]
+        );
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .conversion_service("default", "foo-conversion")
+                .conversion_service_port(65536)
+                .merge()
+                .unwrap_err()
+                .to_string(),
+            r#"conversion webhook service has invalid port "65536""#
+        );
+    }
+
+    #[test]
+    fn conversion_none_requires_identical_served_schemas() {
+        let schema: JSONSchemaProps = serde_json::from_value(json!({
+            "type": "object",
+            "x-kubernetes-preserve-unknown-fields": true
+        }))
+        .unwrap();
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1"), crd("v2")])
+                .override_schema("v2", schema.clone())
+                .conversion_none()
+                .merge()
+                .unwrap_err(),
+            CrdError::NoneConversionMultiVersion(vec!["v1".into(), "v2".into()])
+        );
+
+        let mut unserved = crd("v2");
+        unserved.spec.versions[0].served = false;
+        let merged = CrdMerger::new(vec![crd("v1"), unserved])
+            .storage("v1")
+            .override_schema("v2", schema)
+            .conversion_none()
+            .merge()
+            .unwrap();
+        assert_eq!(merged.spec.conversion.unwrap().strategy, "None");
+        assert!(CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .conversion_none()
+            .merge()
+            .is_ok());
+    }
+
+    #[test]
+    fn validate_conversion_sanity_checks_strategy_against_served_versions() {
+        let schema: JSONSchemaProps = serde_json::from_value(json!({
+            "type": "object",
+            "x-kubernetes-preserve-unknown-fields": true
+        }))
+        .unwrap();
+        let differing = || {
+            CrdMerger::new(vec![crd("v1"), crd("v2")])
+                .storage("v2")
+                .override_schema("v2", schema.clone())
+                .validate_conversion_sanity()
+        };
+        let single = || CrdMerger::new(vec![crd("v1")]).validate_conversion_sanity();
+        let webhook = WebhookClientConfig {
+            url: Some("https://conversion.example.com".into()),
+            ..WebhookClientConfig::default()
+        };
+
+        // None with differing served versions, whether explicit or defaulted
+        assert_eq!(
+            differing().merge().unwrap_err(),
+            CrdError::NoneConversionMultiVersion(vec!["v1".into(), "v2".into()])
+        );
+        assert_eq!(differing().conversion_none().try_merge().unwrap_err(), vec![
+            CrdError::NoneConversionMultiVersion(vec!["v1".into(), "v2".into()])
+        ]);
+        assert!(CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .override_schema("v2", schema.clone())
+            .merge()
+            .is_ok());
+        // None with a single version
+        assert!(single().merge().is_ok());
+        assert_eq!(single().lint_conversion(), Vec::<String>::new());
+        // webhook with a single version
+        let merger = single().conversion_webhook(webhook.clone());
+        assert_eq!(merger.lint_conversion(), vec![
+            "conversion webhook is never called, as only version v1 is served"
+        ]);
+        assert!(merger.merge().is_ok());
+        // webhook with several versions
+        let merger = differing().conversion_webhook(webhook);
+        assert_eq!(merger.lint_conversion(), Vec::<String>::new());
+        assert!(merger.merge().is_ok());
+    }
+
+    #[test]
+    fn forbid_paths_reports_forbidden_fields_of_any_version() {
+        let mut v2 = crd("v2");
+        v2.spec.versions[0].schema = serde_json::from_value(json!({
+            "openAPIV3Schema": {
+                "type": "object",
+                "properties": {
+                    "spec": {
+                        "type": "object",
+                        "properties": {
+                            "password": { "type": "string" },
+                            "secrets": {
+                                "type": "object",
+                                "properties": { "token": { "type": "string" } }
+                            }
+                        }
+                    }
+                }
+            }
+        }))
+        .unwrap();
+        let merger = CrdMerger::new(vec![crd("v1"), v2]).storage("v2");
+        assert_eq!(
+            merger
+                .clone()
+                .forbid_paths(&["spec.password", "spec.token"])
+                .merge()
+                .unwrap_err(),
+            CrdError::ForbiddenField("spec.password".into())
+        );
+        assert_eq!(
+            merger
+                .clone()
+                .forbid_paths(&["spec.secrets.*"])
+                .try_merge()
+                .unwrap_err(),
+            vec![CrdError::ForbiddenField("spec.secrets.token".into())]
+        );
+        assert!(merger
+            .forbid_paths(&["spec.token", "spec.password.*"])
+            .merge()
+            .is_ok());
+    }
+
+    #[test]
+    fn merged_crd_has_exactly_one_storage_version() {
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1"), crd("v2")]).merge().unwrap_err(),
+            CrdError::MultipleStorageVersions(vec!["v1".into(), "v2".into()])
+        );
+        let mut v1 = crd("v1");
+        v1.spec.versions[0].storage = false;
+        assert_eq!(
+            CrdMerger::new(vec![v1.clone()]).merge().unwrap_err(),
+            CrdError::NoStorageVersion
+        );
+        assert!(CrdMerger::new(vec![v1, crd("v2")]).merge().is_ok());
+    }
+
+    #[test]
+    fn try_merge_reports_every_problem() {
+        let mut other = crd("v2");
+        other.spec.scope = "Cluster".into();
+        let errors = CrdMerger::new(vec![crd("v1"), other, crd("v1")])
+            .try_merge()
+            .unwrap_err();
+        assert_eq!(errors, vec![
+            CrdError::PropertyMismatch {
+                version: "v2".into(),
+                property: "scope",
+                expected: "Namespaced".into(),
+                found: "Cluster".into(),
+            },
+            CrdError::DuplicateVersion("v1".into()),
+            CrdError::MultipleStorageVersions(vec!["v1".into(), "v2".into()]),
+        ]);
+        assert_eq!(CrdMerger::new(vec![]).try_merge().unwrap_err(), vec![
+            CrdError::Empty
+        ]);
+    }
+
+    #[test]
+    fn lint_names_flags_invalid_shortnames() {
+        let mut v2 = crd("v2");
+        v2.spec.names.short_names = Some(vec!["f".into(), "Foo!".into()]);
+        let merger = CrdMerger::new(vec![crd("v1"), v2.clone(), v2]);
+        assert_eq!(merger.lint_names(), vec![
+            r#"spec.names.shortNames[1]: "Foo!" must be a lowercase DNS-1035 label (alphanumerics or '-', starting with a letter)"#
+        ]);
+        assert!(CrdMerger::new(vec![crd("v1")]).lint_names().is_empty());
+    }
+
+    #[test]
+    fn rejects_inconsistent_crds() {
+        let mut other = crd("v2");
+        other.spec.group = "other.dev".into();
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1"), other]).merge().unwrap_err(),
+            CrdError::PropertyMismatch {
+                version: "v2".into(),
+                property: "group",
+                expected: "clux.dev".into(),
+                found: "other.dev".into(),
+            }
+        );
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1"), crd("v1")]).merge().unwrap_err(),
+            CrdError::DuplicateVersion("v1".into())
+        );
+        assert_eq!(CrdMerger::new(vec![]).merge().unwrap_err(), CrdError::Empty);
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")]).storage("v3").merge().unwrap_err(),
+            CrdError::MissingVersion("v3".into())
+        );
+    }
+
+    #[test]
+    fn with_root_selects_names() {
+        let mut v2 = crd("v2");
+        v2.spec.names.short_names = Some(vec!["f".into()]);
+        let merged = CrdMerger::new(vec![crd("v1"), v2])
+            .with_root("v2")
+            .storage("v2")
+            .merge()
+            .unwrap();
+        assert_eq!(merged.spec.names.short_names, Some(vec!["f".into()]));
+    }
+
+    #[test]
+    fn names_from_selects_names_independently_of_root() {
+        let mut v2 = crd("v2");
+        v2.spec.names.short_names = Some(vec!["f".into()]);
+        v2.spec.names.categories = Some(vec!["all".into()]);
+        let merged = CrdMerger::new(vec![crd("v1"), v2, crd("v3")])
+            .with_root("v3")
+            .names_from("v2")
+            .storage("v3")
+            .merge()
+            .unwrap();
+        assert_eq!(merged.spec.names.short_names, Some(vec!["f".into()]));
+        assert_eq!(merged.spec.names.categories, Some(vec!["all".into()]));
+
+        let merged = CrdMerger::new(vec![crd("v1"), crd("v2")])
+            .storage("v2")
+            .merge()
+            .unwrap();
+        assert_eq!(merged.spec.names.short_names, None);
+        assert_eq!(
+            CrdMerger::new(vec![crd("v1")])
+                .names_from("v2")
+                .merge()
+                .unwrap_err(),
+            CrdError::MissingVersion("v2".into())
+        );
+    }
+
+    #[test]
+    fn unions_input_metadata() {
+        let v1 = labelled(crd("v1"), &[("app", "foo")], &[("a", "1")]);
+        let v2 = labelled(crd("v2"), &[("tier", "api")], &[("a", "2")]);
+        assert_eq!(
+            CrdMerger::new(vec![v1.clone(), v2])
+                .storage("v2")
+                .merge()
+                .unwrap_err(),
+            CrdError::MetadataConflict {
+                kind: "annotation",
+                key: "a".into()
+            }
+        );
+        let v2 = labelled(crd("v2"), &[("tier", "api")], &[("b", "2")]);
+        let merged = CrdMerger::new(vec![v1, v2]).storage("v2").merge().unwrap();
+        assert_eq!(merged.metadata.labels.unwrap().len(), 2);
+        assert_eq!(merged.metadata.annotations.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn metadata_template_overrides_annotations_and_unions_labels() {
+        let v1 = labelled(crd("v1"), &[("app", "foo")], &[("owner", "code")]);
+        let template = labelled(crd("v1"), &[("team", "infra")], &[("owner", "ops")]).metadata;
+        let merged = CrdMerger::new(vec![v1])
+            .metadata_template(ObjectMeta {
+                finalizers: Some(vec!["kube.rs/cleanup".into()]),
+                ..template
+            })
+            .merge()
+            .unwrap();
+        let labels = merged.metadata.labels.unwrap();
+        assert_eq!(labels["app"], "foo");
+        assert_eq!(labels["team"], "infra");
+        assert_eq!(merged.metadata.annotations.unwrap()["owner"], "ops");
+        assert_eq!(merged.metadata.finalizers, Some(vec!["kube.rs/cleanup".into()]));
+        assert_eq!(merged.metadata.name.as_deref(), Some("foos.clux.dev"));
+    }
+
+    #[test]
+    fn gitops_labels_are_unioned() {
+        let v1 = labelled(crd("v1"), &[("app", "foo")], &[]);
+        let labels = CrdMerger::new(vec![v1])
+            .gitops_labels("foo-operator")
+            .merge()
+            .unwrap()
+            .metadata
+            .labels
+            .unwrap();
+        assert_eq!(labels["app"], "foo");
+        assert_eq!(labels["app.kubernetes.io/instance"], "foo-operator");
+        assert_eq!(labels["app.kubernetes.io/managed-by"], "kube-rs");
+
+        let managed = labelled(crd("v1"), &[("app.kubernetes.io/managed-by", "Helm")], &[]);
+        let labels = CrdMerger::new(vec![managed])
+            .gitops_labels("foo-operator")
+            .merge()
+            .unwrap()
+            .metadata
+            .labels
+            .unwrap();
+        assert_eq!(labels["app.kubernetes.io/managed-by"], "Helm");
+        assert_eq!(labels["app.kubernetes.io/instance"], "foo-operator");
+    }
+}
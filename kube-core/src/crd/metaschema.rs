@@ -0,0 +1,135 @@
+//! Checks of crd schemas against the openapi v3 schema meta-schema, as restricted by the apiserver
+use super::apiexts::v1::{JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool};
+
+/// Types the openapi v3 meta-schema allows for `type`
+const TYPES: &[&str] = &["array", "boolean", "integer", "number", "object", "string"];
+
+/// Find the nodes of a schema that are not valid schemas
+///
+/// Returns one `"<path>: <reason>"` entry per problem, with paths like those of
+/// [`check_structural`](super::check_structural). Checked are the keywords whose values the meta-schema restricts
+/// (`type`, lengths and counts, `multipleOf`, `enum`, `required`, `items`, the `x-kubernetes-*` extensions), and
+/// the keywords crds do not allow (`$ref`, `$schema`, `id`, `definitions`, `dependencies`, `additionalItems`,
+/// `patternProperties`).
+pub(crate) fn metaschema_violations(schema: &JSONSchemaProps) -> Vec<String> {
+    let mut violations = vec![];
+    check_node("", schema, &mut violations);
+    violations
+}
+
+fn join(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}.{}", path, segment)
+    }
+}
+
+fn check_node(path: &str, node: &JSONSchemaProps, violations: &mut Vec<String>) {
+    let mut report =
+        |keyword: &str, reason: String| violations.push(format!("{}: {}", join(path, keyword), reason));
+
+    if let Some(type_) = node.type_.as_deref().filter(|t| !TYPES.contains(t)) {
+        report("type", format!("Unsupported value: {:?}", type_));
+    }
+    let forbidden = [
+        ("$ref", node.ref_path.is_some()),
+        ("$schema", node.schema.is_some()),
+        ("id", node.id.is_some()),
+        ("definitions", node.definitions.is_some()),
+        ("dependencies", node.dependencies.is_some()),
+        ("additionalItems", node.additional_items.is_some()),
+        ("patternProperties", node.pattern_properties.is_some()),
+    ];
+    for (keyword, set) in forbidden {
+        if set {
+            report(keyword, "Forbidden: not allowed in crd schemas".into());
+        }
+    }
+    let counts = [
+        ("minLength", node.min_length),
+        ("maxLength", node.max_length),
+        ("minItems", node.min_items),
+        ("maxItems", node.max_items),
+        ("minProperties", node.min_properties),
+        ("maxProperties", node.max_properties),
+    ];
+    for (keyword, count) in counts {
+        if let Some(count) = count.filter(|c| *c < 0) {
+            report(keyword, format!("Invalid value: {}: must not be negative", count));
+        }
+    }
+    if let Some(multiple_of) = node.multiple_of.filter(|m| *m <= 0.0) {
+        report(
+            "multipleOf",
+            format!("Invalid value: {}: must be greater than zero", multiple_of),
+        );
+    }
+    if node.enum_.as_ref().map_or(false, Vec::is_empty) {
+        report("enum", "Invalid value: must have at least one value".into());
+    }
+    if let Some(required) = &node.required {
+        if required.is_empty() {
+            report("required", "Invalid value: must have at least one field".into());
+        }
+        for (i, field) in required.iter().enumerate() {
+            if required[..i].contains(field) {
+                report("required", format!("Duplicate value: {:?}", field));
+            }
+        }
+    }
+    if let Some(JSONSchemaPropsOrArray::Schemas(_)) = &node.items {
+        report(
+            "items",
+            "Invalid value: must be a single schema, not an array of schemas".into(),
+        );
+    }
+    if let Some(list_type) = node
+        .x_kubernetes_list_type
+        .as_deref()
+        .filter(|t| !["atomic", "set", "map"].contains(t))
+    {
+        report(
+            "x-kubernetes-list-type",
+            format!("Unsupported value: {:?}", list_type),
+        );
+    }
+    if let Some(map_type) = node
+        .x_kubernetes_map_type
+        .as_deref()
+        .filter(|t| !["granular", "atomic"].contains(t))
+    {
+        report(
+            "x-kubernetes-map-type",
+            format!("Unsupported value: {:?}", map_type),
+        );
+    }
+
+    for (name, prop) in node.properties.iter().flatten() {
+        check_node(&join(path, &format!("properties[{}]", name)), prop, violations);
+    }
+    match &node.items {
+        Some(JSONSchemaPropsOrArray::Schema(items)) => check_node(&join(path, "items"), items, violations),
+        Some(JSONSchemaPropsOrArray::Schemas(items)) => {
+            for (i, item) in items.iter().enumerate() {
+                check_node(&join(path, &format!("items[{}]", i)), item, violations);
+            }
+        }
+        None => {}
+    }
+    if let Some(JSONSchemaPropsOrBool::Schema(additional)) = &node.additional_properties {
+        check_node(&join(path, "additionalProperties"), additional, violations);
+    }
+    for (name, schemas) in [
+        ("allOf", &node.all_of),
+        ("anyOf", &node.any_of),
+        ("oneOf", &node.one_of),
+    ] {
+        for (i, schema) in schemas.iter().flatten().enumerate() {
+            check_node(&join(path, &format!("{}[{}]", name, i)), schema, violations);
+        }
+    }
+    if let Some(not) = &node.not {
+        check_node(&join(path, "not"), not, violations);
+    }
+}
@@ -0,0 +1,78 @@
+//! `StorageVersionMigration` manifests rewriting the stored objects of `CustomResourceDefinition`s
+use super::apiexts::v1::CustomResourceDefinition as Crd;
+use crate::{discovery::ApiResource, dynamic::DynamicObject, gvk::GroupVersionKind};
+use serde_json::json;
+
+/// The api version of `StorageVersionMigration`s (Kubernetes >= 1.30, with the `StorageVersionMigrator` feature gate)
+pub const STORAGE_VERSION_MIGRATION_API_VERSION: &str = "storagemigration.k8s.io/v1alpha1";
+
+/// A `StorageVersionMigration` rewriting the objects of `crd` in its storage version
+///
+/// After changing the storage version of a crd, objects stay stored in the version they were written in until they
+/// are written again. Applying this migration makes the apiserver rewrite all of them in the storage version
+/// (or the first version, if none is marked as storage), after which the old versions can be dropped from the
+/// `storedVersions` of the crd. The migration is named `<crd name>-<version>`.
+///
+/// `k8s-openapi` has no `StorageVersionMigration` type yet, so this is a [`DynamicObject`], which can be applied
+/// through an `Api<DynamicObject>` of [`storage_version_migration_resource`].
+pub fn storage_version_migration(crd: &Crd) -> DynamicObject {
+    let versions = &crd.spec.versions;
+    let version = versions.iter().find(|v| v.storage).or_else(|| versions.first());
+    let version = version.map(|v| v.name.clone()).unwrap_or_default();
+    let name = format!("{}-{}", crd.metadata.name.as_deref().unwrap_or_default(), version);
+    DynamicObject::new(&name, &storage_version_migration_resource()).data(json!({
+        "spec": {
+            "resource": {
+                "group": crd.spec.group,
+                "version": version,
+                "resource": crd.spec.names.plural,
+            }
+        }
+    }))
+}
+
+/// The [`ApiResource`] of (cluster scoped) `StorageVersionMigration`s
+pub fn storage_version_migration_resource() -> ApiResource {
+    let gvk = GroupVersionKind::gvk("storagemigration.k8s.io", "v1alpha1", "StorageVersionMigration");
+    ApiResource::from_gvk_with_plural(&gvk, "storageversionmigrations")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        storage_version_migration, storage_version_migration_resource, Crd,
+        STORAGE_VERSION_MIGRATION_API_VERSION,
+    };
+    use serde_json::json;
+
+    #[test]
+    fn migration_targets_storage_version() {
+        let crd: Crd = serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [
+                    { "name": "v1", "served": true, "storage": false },
+                    { "name": "v2", "served": true, "storage": true }
+                ]
+            }
+        }))
+        .unwrap();
+        let migration = storage_version_migration(&crd);
+        let types = migration.types.clone().unwrap();
+        assert_eq!(types.api_version, STORAGE_VERSION_MIGRATION_API_VERSION);
+        assert_eq!(types.kind, "StorageVersionMigration");
+        assert_eq!(
+            storage_version_migration_resource().api_version,
+            STORAGE_VERSION_MIGRATION_API_VERSION
+        );
+        assert_eq!(migration.metadata.name.as_deref(), Some("foos.clux.dev-v2"));
+        assert_eq!(migration.metadata.namespace, None);
+        assert_eq!(
+            migration.data,
+            json!({ "spec": { "resource": { "group": "clux.dev", "version": "v2", "resource": "foos" } } })
+        );
+    }
+}
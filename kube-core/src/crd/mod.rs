@@ -0,0 +1,371 @@
+//! Traits and tyes for CustomResources
+
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions as apiexts;
+
+/// Used by the `crd_cached` and `schema_hash` generated by kube-derive
+#[doc(hidden)]
+pub use once_cell;
+
+mod apiserver;
+pub use apiserver::FakeApiserver;
+mod apiservice;
+pub use apiservice::conversion_apiservice;
+mod breaking;
+pub use breaking::{breaking_changes, change_requires_new_version, BreakingChange};
+mod cel;
+pub use cel::{estimate_cel_cost, CostEstimate, CEL_COST_LIMIT};
+mod columns;
+pub use columns::{printer_columns_for_version, PrinterColumn};
+mod compare;
+mod compat;
+mod conversion;
+pub use compare::{
+    annotate_schema_hash, normalize_schema, requires_conversion, roundtrip_check, schema_hash, semantic_diff,
+    semantic_eq, CrdFieldDiff, RoundtripDiff, SCHEMA_HASH_ANNOTATION,
+};
+pub use conversion::ConversionWebhook;
+mod defaults;
+pub use defaults::{apply_schema_defaults_and_prune, extract_defaults};
+mod discovery;
+pub use discovery::discovery_entry;
+mod instance;
+#[cfg(feature = "schema")] pub(crate) use instance::validate_value;
+pub use instance::{example_instance, minimal_instance, validate_instance, ConversionError, FieldViolation};
+mod merge;
+pub use merge::{
+    merge_all, partition, ConversionConfig, CrdError, CrdMerger, MergePolicy,
+    CERT_MANAGER_INJECT_CA_FROM_ANNOTATION, HELM_CA_BUNDLE_PLACEHOLDER, HELM_NAMESPACE_PLACEHOLDER,
+    HELM_SERVICE_NAME_PLACEHOLDER, MERGE_REPORT_VERSION, RETIRED_VERSIONS_ANNOTATION,
+    VERSION_NOTE_ANNOTATION_PREFIX, VERSION_SOURCES_ANNOTATION,
+};
+mod metaschema;
+mod migration;
+pub use migration::{
+    storage_version_migration, storage_version_migration_resource, STORAGE_VERSION_MIGRATION_API_VERSION,
+};
+mod names;
+/// Used by the shortname checks generated by kube-derive
+#[doc(hidden)]
+pub use names::is_dns1035_label;
+pub use names::{validate_object_name, NameError};
+mod owner;
+pub use owner::{OwnerReferenceBuilder, OwnerReferenceExt};
+mod paths;
+pub use paths::schema_paths;
+mod rbac;
+pub use rbac::{controller_rules, rbac_rules};
+#[cfg(feature = "register")] mod registry;
+#[cfg(feature = "register")]
+#[doc(hidden)]
+pub use inventory;
+#[cfg(feature = "register")]
+#[cfg_attr(docsrs, doc(cfg(feature = "register")))]
+pub use registry::{registered_crds, RegisteredCrd};
+mod selector;
+pub use selector::{FieldSelectorError, SelectableFieldSelector};
+mod structural;
+pub use structural::check_structural;
+mod versions;
+pub use versions::{
+    gvks, gvks_all, is_served_version, orphaned_stored_versions, preferred_version, storage_migration_needed,
+    StorageMigration,
+};
+mod yaml;
+pub use yaml::{
+    generated_header, print_crds, to_helm_template, to_yaml_documents, to_yaml_documents_with_header,
+    write_crds, OutputFormat,
+};
+
+/// The scope of a custom resource as a [`k8s_openapi::ResourceScope`] type
+///
+/// This is implemented by `#[derive(CustomResource)]`, with [`k8s_openapi::NamespaceResourceScope`] for
+/// `#[kube(namespaced)]` resources and [`k8s_openapi::ClusterResourceScope`] otherwise, so that functions which
+/// only make sense for one scope can require it at compile time.
+pub trait CustomResourceScope {
+    /// Either [`k8s_openapi::NamespaceResourceScope`] or [`k8s_openapi::ClusterResourceScope`]
+    type Scope: k8s_openapi::ResourceScope;
+}
+
+/// Types for v1 CustomResourceDefinitions
+pub mod v1 {
+    /// Extension trait that is implemented by kube-derive
+    ///
+    /// This trait variant is implemented by default (or when `#[kube(apiextensions = "v1")]`)
+    pub trait CustomResourceExt {
+        /// Helper to generate the CRD including the JsonSchema
+        ///
+        /// This is using the stable v1::CustomResourceDefinitions (present in kubernetes >= 1.16)
+        fn crd() -> super::apiexts::v1::CustomResourceDefinition;
+        /// The [`crd`](Self::crd), generated on the first call and shared by all later calls
+        ///
+        /// This saves regenerating large schemas when several parts of a program need the crd.
+        /// It is safe to call from multiple threads, and every call returns the same crd.
+        ///
+        /// kube-derive keeps the crd in a static of the type. Otherwise, the crds of every type are kept in a shared
+        /// map (and never freed), and the crd may be generated more than once when first called from several threads.
+        fn crd_cached() -> &'static super::apiexts::v1::CustomResourceDefinition
+        where
+            Self: Sized + 'static,
+        {
+            use std::{any::TypeId, collections::HashMap, sync::Mutex};
+            type Crd = super::apiexts::v1::CustomResourceDefinition;
+            static CRDS: once_cell::sync::Lazy<Mutex<HashMap<TypeId, &'static Crd>>> =
+                once_cell::sync::Lazy::new(Default::default);
+
+            let cached = CRDS
+                .lock()
+                .expect("crd cache")
+                .get(&TypeId::of::<Self>())
+                .copied();
+            // generate the crd without holding the lock, as `crd` may use the cache of other types
+            cached.unwrap_or_else(|| {
+                let crd = Self::crd();
+                let mut crds = CRDS.lock().expect("crd cache");
+                *crds
+                    .entry(TypeId::of::<Self>())
+                    .or_insert_with(|| Box::leak(Box::new(crd)))
+            })
+        }
+        /// Helper to generate the CRD with the given `scope`, regardless of `#[kube(namespaced)]`
+        ///
+        /// This ships the same resource as both a cluster-scoped and a namespaced crd, for different deployments.
+        /// The [`api_resource`](Self::api_resource) does not depend on the scope, so use it with the dynamic `Api`
+        /// constructors matching the deployed crd (`Api::namespaced_with` or `Api::all_with`), as the typed `Api`
+        /// constructors follow the scope declared by `#[kube(namespaced)]`.
+        fn crd_scoped(scope: crate::discovery::Scope) -> super::apiexts::v1::CustomResourceDefinition {
+            let mut crd = Self::crd();
+            crd.spec.scope = match scope {
+                crate::discovery::Scope::Cluster => "Cluster".into(),
+                crate::discovery::Scope::Namespaced => "Namespaced".into(),
+            };
+            crd
+        }
+        /// Helper to return the name of this `CustomResourceDefinition` in kubernetes.
+        ///
+        /// This is not the name of an _instance_ of this custom resource but the `CustomResourceDefinition` object itself.
+        fn crd_name() -> &'static str;
+        /// The kind of this resource, as declared by `#[kube(kind = "...")]`
+        ///
+        /// This is the same as `spec.names.kind` of [`crd`](Self::crd), without having to generate the crd.
+        fn crd_kind() -> &'static str;
+        /// Helper to generate the api information type for use with the dynamic `Api`
+        fn api_resource() -> crate::discovery::ApiResource;
+        /// Shortnames of this resource type.
+        ///
+        /// For example: [`Pod`] has the shortname alias `po`.
+        ///
+        /// NOTE: This function returns *declared* short names (at compile-time, using the `#[kube(shortname = "foo")]`), not the
+        /// shortnames registered with the Kubernetes API (which is what tools such as `kubectl` look at).
+        ///
+        /// [`Pod`]: `k8s_openapi::api::core::v1::Pod`
+        fn shortnames() -> &'static [&'static str];
+        /// Json paths of the fields declared selectable with `#[kube(selectable = ".spec.field")]`
+        ///
+        /// Use [`SelectableFieldSelector::for_resource`](super::SelectableFieldSelector::for_resource)
+        /// to build field selectors that only use these fields.
+        fn selectable_fields() -> &'static [&'static str] {
+            &[]
+        }
+        /// A stable hash of the schemas of [`crd`](Self::crd), as computed by [`schema_hash`](super::schema_hash)
+        ///
+        /// Comparing this to a hash recorded on the live crd (e.g. in an annotation) tells whether the schema
+        /// compiled into this binary changed since the crd was applied. The hash is computed once, from
+        /// [`crd_cached`](Self::crd_cached).
+        ///
+        /// kube-derive keeps the hash in a static of the type. Otherwise, the hashes of every type are kept in a
+        /// shared map (and never freed), like the crds of [`crd_cached`](Self::crd_cached).
+        fn schema_hash() -> &'static str
+        where
+            Self: Sized + 'static,
+        {
+            use std::{any::TypeId, collections::HashMap, sync::Mutex};
+            static HASHES: once_cell::sync::Lazy<Mutex<HashMap<TypeId, &'static str>>> =
+                once_cell::sync::Lazy::new(Default::default);
+
+            let cached = HASHES
+                .lock()
+                .expect("hash cache")
+                .get(&TypeId::of::<Self>())
+                .copied();
+            cached.unwrap_or_else(|| {
+                let hash = super::schema_hash(Self::crd_cached());
+                let mut hashes = HASHES.lock().expect("hash cache");
+                *hashes
+                    .entry(TypeId::of::<Self>())
+                    .or_insert_with(|| Box::leak(hash.into_boxed_str()))
+            })
+        }
+        /// A minimal instance named `name`, with every required field of the spec set (see [`minimal_instance`](super::minimal_instance))
+        ///
+        /// This is a quick valid object for smoke tests and examples, which can be checked with
+        /// [`validate_instance`](super::validate_instance).
+        fn minimal(name: &str) -> crate::dynamic::DynamicObject {
+            super::minimal_instance(&Self::crd(), name)
+        }
+        /// Check that `name` is a valid name for an object of this resource, before trying to create it
+        ///
+        /// See [`validate_object_name`](super::validate_object_name) for the DNS-1123 subdomain rules checked.
+        fn validate_name(name: &str) -> Result<(), super::NameError> {
+            super::validate_object_name(name)
+        }
+        /// An example manifest named `name`, with the `example`s and `default`s of the spec (see [`example_instance`](super::example_instance))
+        ///
+        /// This is a copy-pasteable starting point for documentation and quickstarts, with `apiVersion`, `kind`,
+        /// `metadata` and the spec. Use `serde_yaml::to_string` to render it.
+        fn example_manifest(name: &str) -> serde_yaml::Value {
+            serde_yaml::to_value(super::example_instance(&Self::crd(), name)).expect("valid manifest")
+        }
+        /// Convert a dynamic object into this type, with errors pinpointing what does not match
+        ///
+        /// The object must have the `apiVersion` and `kind` of this type, and is validated against the schema of
+        /// [`crd`](Self::crd) (see [`validate_instance`](super::validate_instance)) before it is deserialized, so that
+        /// objects that do not match (e.g. of another version during a migration) fail with the offending fields
+        /// rather than with the first error of `serde`.
+        fn try_from_dynamic(obj: &crate::dynamic::DynamicObject) -> Result<Self, super::ConversionError>
+        where
+            Self: Sized + serde::de::DeserializeOwned,
+        {
+            super::instance::typed_from_dynamic(&Self::crd(), obj)
+        }
+        /// The RBAC rules a controller of this resource needs, generated from [`crd`](Self::crd)
+        ///
+        /// See [`controller_rules`](super::controller_rules) for the granted verbs.
+        fn controller_policy_rules() -> Vec<k8s_openapi::api::rbac::v1::PolicyRule> {
+            super::controller_rules(&Self::crd())
+        }
+        /// The entry of this resource in the discovery of its group version, generated from [`crd`](Self::crd)
+        ///
+        /// See [`discovery_entry`](super::discovery_entry), e.g. for serving a fake discovery endpoint in tests.
+        fn api_resource_discovery_entry() -> k8s_openapi::apimachinery::pkg::apis::meta::v1::APIResource {
+            super::discovery_entry(&Self::crd())
+        }
+        /// Whether the `live` crd (e.g. as read from the cluster) matches [`crd`](Self::crd)
+        ///
+        /// When `live` has a [`SCHEMA_HASH_ANNOTATION`](super::SCHEMA_HASH_ANNOTATION) (see
+        /// [`annotate_schema_hash`](super::annotate_schema_hash)), only that is compared to [`schema_hash`](Self::schema_hash),
+        /// so changes outside of the schemas (like names or printer columns) are not noticed.
+        /// Otherwise the crds are compared with [`semantic_eq`](super::semantic_eq).
+        /// Both use [`crd_cached`](Self::crd_cached), so the crd is not regenerated on every call.
+        fn matches_live(live: &super::apiexts::v1::CustomResourceDefinition) -> bool
+        where
+            Self: Sized + 'static,
+        {
+            let recorded = live
+                .metadata
+                .annotations
+                .as_ref()
+                .and_then(|annotations| annotations.get(super::SCHEMA_HASH_ANNOTATION));
+            match recorded {
+                Some(hash) => hash == Self::schema_hash(),
+                None => super::semantic_eq(Self::crd_cached(), live),
+            }
+        }
+        /// The schema of the `spec` of this resource, taken from the `openAPIV3Schema` of [`crd`](Self::crd)
+        ///
+        /// This lets e.g. validating webhooks check the user supplied spec independently of the server managed status.
+        /// Returns `None` when the crd has no schema (with `#[kube(schema = "disabled")]`).
+        fn spec_schema() -> Option<super::apiexts::v1::JSONSchemaProps> {
+            let crd = Self::crd();
+            let version = crd.spec.versions.into_iter().next()?;
+            version.schema?.open_api_v3_schema?.properties?.remove("spec")
+        }
+        /// The schemars schema of this resource, before it is converted into the crd schema
+        ///
+        /// This can be run through further schemars visitors before building a custom crd.
+        /// Returns `None` when schema generation is disabled (with `#[kube(schema = "disabled")]`).
+        #[cfg(feature = "schema")]
+        fn root_schema() -> Option<schemars::schema::RootSchema> {
+            None
+        }
+    }
+}
+
+/// Types for legacy v1beta1 CustomResourceDefinitions
+#[cfg(feature = "deprecated-crd-v1beta1")]
+pub mod v1beta1 {
+    /// Extension trait that is implemented by kube-derive for legacy v1beta1::CustomResourceDefinitions
+    ///
+    /// This trait variant is only implemented with `#[kube(apiextensions = "v1beta1")]`
+    pub trait CustomResourceExt {
+        /// Helper to generate the legacy CRD without a JsonSchema
+        ///
+        /// This is using v1beta1::CustomResourceDefinitions (which will be removed in kubernetes 1.22)
+        fn crd() -> super::apiexts::v1beta1::CustomResourceDefinition;
+        /// Helper to return the name of this `CustomResourceDefinition` in kubernetes.
+        ///
+        /// This is not the name of an _instance_ of this custom resource but the `CustomResourceDefinition` object itself.
+        fn crd_name() -> &'static str;
+        /// The kind of this resource, as declared by `#[kube(kind = "...")]`
+        ///
+        /// This is the same as `spec.names.kind` of [`crd`](Self::crd), without having to generate the crd.
+        fn crd_kind() -> &'static str;
+        /// Helper to generate the api information type for use with the dynamic `Api`
+        fn api_resource() -> crate::discovery::ApiResource;
+        /// Shortnames of this resource type.
+        ///
+        /// For example: [`Pod`] has the shortname alias `po`.
+        ///
+        /// NOTE: This function returns *declared* short names (at compile-time, using the `#[kube(shortname = "foo")]`), not the
+        /// shortnames registered with the Kubernetes API (which is what tools such as `kubectl` look at).
+        ///
+        /// [`Pod`]: `k8s_openapi::api::core::v1::Pod`
+        fn shortnames() -> &'static [&'static str];
+    }
+}
+
+/// re-export the current latest version until a newer one is available in cloud providers
+pub use v1::CustomResourceExt;
+
+#[cfg(test)]
+mod test {
+    use super::{apiexts::v1::CustomResourceDefinition, v1::CustomResourceExt};
+    use crate::discovery::ApiResource;
+
+    struct Manual;
+
+    impl CustomResourceExt for Manual {
+        fn crd() -> CustomResourceDefinition {
+            serde_json::from_value(serde_json::json!({
+                "metadata": { "name": "manuals.clux.dev" },
+                "spec": {
+                    "group": "clux.dev",
+                    "names": { "kind": "Manual", "plural": "manuals" },
+                    "scope": "Namespaced",
+                    "versions": [{ "name": "v1", "served": true, "storage": true }]
+                }
+            }))
+            .unwrap()
+        }
+
+        fn crd_name() -> &'static str {
+            "manuals.clux.dev"
+        }
+
+        fn crd_kind() -> &'static str {
+            "Manual"
+        }
+
+        fn api_resource() -> ApiResource {
+            ApiResource::from_gvk_with_plural(
+                &crate::GroupVersionKind::gvk("clux.dev", "v1", "Manual"),
+                "manuals",
+            )
+        }
+
+        fn shortnames() -> &'static [&'static str] {
+            &[]
+        }
+    }
+
+    #[test]
+    fn crd_cached_defaults_to_a_shared_cache() {
+        assert!(std::ptr::eq(Manual::crd_cached(), Manual::crd_cached()));
+        assert_eq!(Manual::crd_cached(), &Manual::crd());
+    }
+
+    #[test]
+    fn schema_hash_defaults_to_a_shared_cache() {
+        assert!(std::ptr::eq(Manual::schema_hash(), Manual::schema_hash()));
+        assert_eq!(Manual::schema_hash(), super::schema_hash(&Manual::crd()));
+    }
+}
@@ -0,0 +1,161 @@
+//! Validation of the `spec.names` of `CustomResourceDefinition`s, and of the names of their objects
+use super::apiexts::v1::CustomResourceDefinition as Crd;
+use thiserror::Error;
+
+/// The maximum length of a DNS-1123 subdomain
+const DNS1123_SUBDOMAIN_MAX_LENGTH: usize = 253;
+
+/// Errors from [`validate_object_name`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum NameError {
+    /// The name is empty
+    #[error("name must not be empty")]
+    Empty,
+    /// The name is longer than a DNS-1123 subdomain can be
+    #[error("name {name:?} must be no more than 253 characters, found {len}")]
+    TooLong {
+        /// The rejected name
+        name: String,
+        /// Its length
+        len: usize,
+    },
+    /// The name has characters or a shape a DNS-1123 subdomain can not have
+    #[error("name {0:?} must consist of lowercase alphanumerics, '-' or '.', and must start and end with an alphanumeric")]
+    Invalid(String),
+}
+
+/// Check that `name` is a valid name for a custom resource object, before trying to create it
+///
+/// Like the apiserver, this requires names to be DNS-1123 subdomains: at most 253 lowercase alphanumeric characters,
+/// `-` or `.`, with every `.` separated part starting and ending with an alphanumeric (like `my-app.v2`).
+pub fn validate_object_name(name: &str) -> Result<(), NameError> {
+    if name.is_empty() {
+        return Err(NameError::Empty);
+    }
+    if name.len() > DNS1123_SUBDOMAIN_MAX_LENGTH {
+        return Err(NameError::TooLong {
+            name: name.to_string(),
+            len: name.len(),
+        });
+    }
+    let is_label = |label: &str| {
+        let bytes = label.as_bytes();
+        let alphanumeric = |b: &u8| b.is_ascii_lowercase() || b.is_ascii_digit();
+        match (bytes.first(), bytes.last()) {
+            (Some(first), Some(last)) => {
+                alphanumeric(first)
+                    && alphanumeric(last)
+                    && bytes.iter().all(|b| alphanumeric(b) || *b == b'-')
+            }
+            _ => false,
+        }
+    };
+    if name.split('.').all(is_label) {
+        Ok(())
+    } else {
+        Err(NameError::Invalid(name.to_string()))
+    }
+}
+
+/// Whether `name` is a DNS-1035 label (like the apiserver requires of crd names)
+///
+/// That is at most 63 lowercase alphanumeric characters or `-`, starting with a letter and ending with an alphanumeric.
+/// This is a `const fn`, so that `kube::derive` can check `#[kube(shortname)]`s at compile time.
+#[doc(hidden)]
+pub const fn is_dns1035_label(name: &str) -> bool {
+    let bytes = name.as_bytes();
+    if bytes.is_empty() || bytes.len() > 63 || !bytes[0].is_ascii_lowercase() {
+        return false;
+    }
+    let last = bytes[bytes.len() - 1];
+    if !(last.is_ascii_lowercase() || last.is_ascii_digit()) {
+        return false;
+    }
+    let mut i = 0;
+    while i < bytes.len() {
+        let b = bytes[i];
+        if !(b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-') {
+            return false;
+        }
+        i += 1;
+    }
+    true
+}
+
+/// Whether `name` is a DNS-1123 label (like the apiserver requires of namespaces)
+///
+/// This is a DNS-1035 label that may also start with a digit.
+pub(crate) fn is_dns1123_label(name: &str) -> bool {
+    match name.as_bytes().first() {
+        Some(first) if first.is_ascii_digit() => is_dns1035_label(&format!("a{}", &name[1..])),
+        _ => is_dns1035_label(name),
+    }
+}
+
+/// The names of `crd` that the apiserver would reject, as `"<path>: <reason>"` entries
+pub(crate) fn name_problems(crd: &Crd) -> Vec<String> {
+    let names = &crd.spec.names;
+    let mut checked = vec![
+        ("spec.names.plural".to_string(), names.plural.clone()),
+        ("spec.names.kind".to_string(), names.kind.to_ascii_lowercase()),
+    ];
+    if let Some(singular) = &names.singular {
+        checked.push(("spec.names.singular".into(), singular.clone()));
+    }
+    if let Some(list_kind) = &names.list_kind {
+        checked.push(("spec.names.listKind".into(), list_kind.to_ascii_lowercase()));
+    }
+    for (field, list) in [
+        ("shortNames", &names.short_names),
+        ("categories", &names.categories),
+    ] {
+        for (i, name) in list.iter().flatten().enumerate() {
+            checked.push((format!("spec.names.{}[{}]", field, i), name.clone()));
+        }
+    }
+    checked
+        .into_iter()
+        .filter(|(_, name)| !is_dns1035_label(name))
+        .map(|(path, name)| {
+            format!(
+                "{}: {:?} must be a lowercase DNS-1035 label (alphanumerics or '-', starting with a letter)",
+                path, name
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{is_dns1035_label, validate_object_name, NameError};
+
+    #[test]
+    fn dns1035_labels() {
+        for valid in ["foo", "f", "foo-bar", "f00"] {
+            assert!(is_dns1035_label(valid), "{}", valid);
+        }
+        for invalid in ["", "Foo", "foo!", "1foo", "foo-", "foo_bar", &"f".repeat(64)] {
+            assert!(!is_dns1035_label(invalid), "{}", invalid);
+        }
+    }
+
+    #[test]
+    fn object_names_are_dns1123_subdomains() {
+        for valid in ["foo", "0foo", "foo-bar.v2", "a.b.c", &"f".repeat(253)] {
+            assert_eq!(validate_object_name(valid), Ok(()), "{}", valid);
+        }
+        for invalid in ["Foo", "foo_bar", "-foo", "foo.", "foo..bar", "foo.-bar"] {
+            assert_eq!(
+                validate_object_name(invalid),
+                Err(NameError::Invalid(invalid.into())),
+                "{}",
+                invalid
+            );
+        }
+        assert_eq!(validate_object_name(""), Err(NameError::Empty));
+        assert!(matches!(
+            validate_object_name(&"f".repeat(254)),
+            Err(NameError::TooLong { len: 254, .. })
+        ));
+    }
+}
@@ -0,0 +1,82 @@
+//! Owner references to instances of custom resources
+use super::v1::CustomResourceExt;
+use crate::Resource;
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::OwnerReference;
+
+/// Extension trait to create owner references pointing at a custom resource instance
+///
+/// This is implemented for every custom resource implementing [`CustomResourceExt`],
+/// and is typically used by controllers to make child objects owned by (and garbage collected with) their parent.
+///
+/// ```
+/// use kube_core::crd::OwnerReferenceExt;
+/// # fn wrapper<K: OwnerReferenceExt>(foo: &K) {
+/// let owner = foo.owner_ref().expect("foo was fetched from the apiserver");
+/// let weak_owner = foo.owner_ref_builder().controller(false).block_owner_deletion(false).build();
+/// # }
+/// ```
+pub trait OwnerReferenceExt: Resource<DynamicType = ()> + CustomResourceExt {
+    /// An owner reference marking this resource as the controller, which blocks deletion of the owner
+    ///
+    /// This sets `controller` and `blockOwnerDeletion` to `true`.
+    /// Returns `None` if the resource has no name or uid (like before it is created).
+    fn owner_ref(&self) -> Option<OwnerReference> {
+        self.owner_ref_builder().build()
+    }
+
+    /// A builder for an owner reference to this resource, to override the defaults of [`owner_ref`](Self::owner_ref)
+    fn owner_ref_builder(&self) -> OwnerReferenceBuilder {
+        let meta = self.meta();
+        OwnerReferenceBuilder {
+            api_version: <Self as Resource>::api_version(&()).to_string(),
+            kind: <Self as Resource>::kind(&()).to_string(),
+            name: meta.name.clone(),
+            uid: meta.uid.clone(),
+            controller: true,
+            block_owner_deletion: true,
+        }
+    }
+}
+
+impl<K: Resource<DynamicType = ()> + CustomResourceExt> OwnerReferenceExt for K {}
+
+/// Builder for an [`OwnerReference`], created by [`OwnerReferenceExt::owner_ref_builder`]
+#[derive(Clone, Debug)]
+pub struct OwnerReferenceBuilder {
+    api_version: String,
+    kind: String,
+    name: Option<String>,
+    uid: Option<String>,
+    controller: bool,
+    block_owner_deletion: bool,
+}
+
+impl OwnerReferenceBuilder {
+    /// Set whether the owner is the managing controller (defaults to `true`)
+    #[must_use]
+    pub fn controller(mut self, controller: bool) -> Self {
+        self.controller = controller;
+        self
+    }
+
+    /// Set whether the owner can only be deleted after the owned object (defaults to `true`)
+    #[must_use]
+    pub fn block_owner_deletion(mut self, block_owner_deletion: bool) -> Self {
+        self.block_owner_deletion = block_owner_deletion;
+        self
+    }
+
+    /// Build the owner reference
+    ///
+    /// Returns `None` if the owner has no name or uid.
+    pub fn build(self) -> Option<OwnerReference> {
+        Some(OwnerReference {
+            api_version: self.api_version,
+            kind: self.kind,
+            name: self.name?,
+            uid: self.uid?,
+            controller: Some(self.controller),
+            block_owner_deletion: Some(self.block_owner_deletion),
+        })
+    }
+}
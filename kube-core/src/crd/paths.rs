@@ -0,0 +1,87 @@
+//! Addressable field paths of crd schemas
+use super::apiexts::v1::{JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool};
+
+/// Every addressable dotted path in a schema, like `spec.foo`, `spec.bar.baz` and `status.phase`
+///
+/// Paths are relative to the schema root (so the root itself is not included), and listed in depth-first order
+/// with the properties of a node sorted by name. Items of arrays are addressed as `spec.ports[*]` (and
+/// `spec.ports[*].name`), values of maps (`additionalProperties`) as `spec.labels.*`.
+///
+/// This is the primitive behind checking printer column and selectable field paths against a schema:
+///
+/// ```
+/// # use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaProps;
+/// # fn wrapper(schema: JSONSchemaProps) {
+/// let paths = kube_core::crd::schema_paths(&schema);
+/// assert!(paths.iter().any(|p| p == "status.phase"));
+/// # }
+/// ```
+pub fn schema_paths(schema: &JSONSchemaProps) -> Vec<String> {
+    let mut paths = vec![];
+    collect("", schema, &mut paths);
+    paths
+}
+
+fn collect(path: &str, node: &JSONSchemaProps, paths: &mut Vec<String>) {
+    for (name, prop) in node.properties.iter().flatten() {
+        let prop_path = if path.is_empty() {
+            name.clone()
+        } else {
+            format!("{}.{}", path, name)
+        };
+        paths.push(prop_path.clone());
+        collect(&prop_path, prop, paths);
+    }
+    if let Some(JSONSchemaPropsOrArray::Schema(items)) = &node.items {
+        let items_path = format!("{}[*]", path);
+        paths.push(items_path.clone());
+        collect(&items_path, items, paths);
+    }
+    if let Some(JSONSchemaPropsOrBool::Schema(values)) = &node.additional_properties {
+        let values_path = format!("{}.*", path);
+        paths.push(values_path.clone());
+        collect(&values_path, values, paths);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{schema_paths, JSONSchemaProps};
+    use serde_json::json;
+
+    #[test]
+    fn enumerates_nested_paths() {
+        let schema: JSONSchemaProps = serde_json::from_value(json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "foo": { "type": "string" },
+                        "bar": { "type": "object", "properties": { "baz": { "type": "integer" } } },
+                        "ports": {
+                            "type": "array",
+                            "items": { "type": "object", "properties": { "name": { "type": "string" } } }
+                        },
+                        "labels": { "type": "object", "additionalProperties": { "type": "string" } }
+                    }
+                },
+                "status": { "type": "object", "properties": { "phase": { "type": "string" } } }
+            }
+        }))
+        .unwrap();
+        assert_eq!(schema_paths(&schema), vec![
+            "spec",
+            "spec.bar",
+            "spec.bar.baz",
+            "spec.foo",
+            "spec.labels",
+            "spec.labels.*",
+            "spec.ports",
+            "spec.ports[*]",
+            "spec.ports[*].name",
+            "status",
+            "status.phase",
+        ]);
+    }
+}
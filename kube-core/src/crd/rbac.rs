@@ -0,0 +1,117 @@
+//! RBAC rules for managing the resources of a `CustomResourceDefinition`
+use super::apiexts::v1::CustomResourceDefinition as Crd;
+use k8s_openapi::api::rbac::v1::PolicyRule;
+
+/// A `PolicyRule` granting `verbs` on the resources of a crd
+///
+/// The rule is for the group and plural of the crd, and for its `/status` and `/scale` subresources
+/// if any version of the crd has them. This is the rule an operator needs in its `ClusterRole` (or
+/// `Role`) to manage the custom resources, and it stays in sync with the crd when it is renamed.
+pub fn rbac_rules(crd: &Crd, verbs: &[&str]) -> PolicyRule {
+    let plural = &crd.spec.names.plural;
+    let (status, scale) = subresources(crd);
+    let mut resources = vec![plural.clone()];
+    if status {
+        resources.push(format!("{}/status", plural));
+    }
+    if scale {
+        resources.push(format!("{}/scale", plural));
+    }
+    PolicyRule {
+        api_groups: Some(vec![crd.spec.group.clone()]),
+        resources: Some(resources),
+        verbs: verbs.iter().map(|v| v.to_string()).collect(),
+        ..PolicyRule::default()
+    }
+}
+
+/// The `PolicyRule`s a controller of the resources of a crd needs
+///
+/// These are `get`, `list`, `watch`, `patch` and `update` of the resources, and `get`, `patch` and `update` of
+/// their `/status` subresource if any version of the crd has one. Rules for other resources the controller
+/// manages (like owned `Deployment`s or `Event`s) need to be added.
+pub fn controller_rules(crd: &Crd) -> Vec<PolicyRule> {
+    let plural = &crd.spec.names.plural;
+    let rule = |resource: String, verbs: &[&str]| PolicyRule {
+        api_groups: Some(vec![crd.spec.group.clone()]),
+        resources: Some(vec![resource]),
+        verbs: verbs.iter().map(|v| v.to_string()).collect(),
+        ..PolicyRule::default()
+    };
+    let mut rules = vec![rule(plural.clone(), &["get", "list", "watch", "patch", "update"])];
+    if subresources(crd).0 {
+        rules.push(rule(format!("{}/status", plural), &["get", "patch", "update"]));
+    }
+    rules
+}
+
+/// Whether any version of a crd has a status and a scale subresource
+fn subresources(crd: &Crd) -> (bool, bool) {
+    let subresources = crd.spec.versions.iter().filter_map(|v| v.subresources.as_ref());
+    subresources.fold((false, false), |(status, scale), s| {
+        (status || s.status.is_some(), scale || s.scale.is_some())
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{controller_rules, rbac_rules, Crd};
+    use serde_json::json;
+
+    fn crd(subresources: serde_json::Value) -> Crd {
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [
+                    { "name": "v1", "served": true, "storage": false },
+                    { "name": "v2", "served": true, "storage": true, "subresources": subresources }
+                ]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn rule_covers_resources_and_subresources_of_the_crd() {
+        let rule = rbac_rules(&crd(json!({ "status": {} })), &["get", "list", "watch", "patch"]);
+        assert_eq!(rule.api_groups, Some(vec!["clux.dev".to_string()]));
+        assert_eq!(
+            rule.resources,
+            Some(vec!["foos".to_string(), "foos/status".to_string()])
+        );
+        assert_eq!(rule.verbs, vec!["get", "list", "watch", "patch"]);
+
+        let scaled = crd(json!({
+            "status": {},
+            "scale": { "specReplicasPath": ".spec.replicas", "statusReplicasPath": ".status.replicas" }
+        }));
+        assert_eq!(
+            rbac_rules(&scaled, &["*"]).resources,
+            Some(vec![
+                "foos".to_string(),
+                "foos/status".to_string(),
+                "foos/scale".to_string()
+            ])
+        );
+        assert_eq!(
+            rbac_rules(&crd(json!(null)), &["get"]).resources,
+            Some(vec!["foos".to_string()])
+        );
+    }
+
+    #[test]
+    fn controller_rules_cover_status_subresource() {
+        let rules = controller_rules(&crd(json!({ "status": {} })));
+        assert_eq!(rules.len(), 2);
+        assert_eq!(rules[0].api_groups, Some(vec!["clux.dev".to_string()]));
+        assert_eq!(rules[0].resources, Some(vec!["foos".to_string()]));
+        assert_eq!(rules[0].verbs, vec!["get", "list", "watch", "patch", "update"]);
+        assert_eq!(rules[1].resources, Some(vec!["foos/status".to_string()]));
+        assert_eq!(rules[1].verbs, vec!["get", "patch", "update"]);
+
+        assert_eq!(controller_rules(&crd(json!(null))).len(), 1);
+    }
+}
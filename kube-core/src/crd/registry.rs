@@ -0,0 +1,41 @@
+//! Link time registry of the crds of `#[kube(register)]` types
+use super::apiexts::v1::CustomResourceDefinition as Crd;
+use crate::Version;
+
+/// A crd registered with `#[kube(register)]`, collected by [`registered_crds`]
+pub struct RegisteredCrd {
+    crd: fn() -> Crd,
+}
+
+impl RegisteredCrd {
+    /// Register the crd generated by `crd` (used by kube-derive)
+    #[doc(hidden)]
+    pub const fn new(crd: fn() -> Crd) -> Self {
+        Self { crd }
+    }
+}
+
+inventory::collect!(RegisteredCrd);
+
+/// The crds of every `#[kube(register)]` type linked into the binary, sorted by name and then version
+///
+/// Versions of a kind are sorted from least to most stable (like `v1alpha1`, `v1beta1`, `v1`).
+///
+/// This lets a crd generating binary output every crd without listing the types, e.g. with
+/// `merge_all(registered_crds())` to merge the versions of every kind. Only types in crates that are
+/// linked into the binary are registered, so they need to be used (e.g. with `use my_crate::*`).
+pub fn registered_crds() -> Vec<Crd> {
+    let mut crds = inventory::iter::<RegisteredCrd>
+        .into_iter()
+        .map(|registered| (registered.crd)())
+        .collect::<Vec<_>>();
+    crds.sort_by_cached_key(|crd| {
+        let version = crd
+            .spec
+            .versions
+            .first()
+            .map(|v| Version::parse(&v.name).priority());
+        (crd.metadata.name.clone(), version)
+    });
+    crds
+}
@@ -0,0 +1,151 @@
+//! Field selectors restricted to the selectable fields of a custom resource
+use super::v1::CustomResourceExt;
+use crate::params::ListParams;
+use thiserror::Error;
+
+/// Fields that the apiserver can select on for every custom resource
+const ALWAYS_SELECTABLE: &[&str] = &["metadata.name", "metadata.namespace"];
+
+/// Errors from building a [`SelectableFieldSelector`]
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FieldSelectorError {
+    /// The field is not declared selectable, so the apiserver would reject the selector
+    #[error("field {path:?} is not selectable, selectable fields are: {selectable:?}")]
+    NotSelectable {
+        /// The requested field path
+        path: String,
+        /// The fields that can be selected on
+        selectable: Vec<String>,
+    },
+}
+
+/// Builder for field selectors that only use the selectable fields of a custom resource
+///
+/// Custom resources can only be filtered on `metadata.name`, `metadata.namespace`,
+/// and the fields declared with `#[kube(selectable = ".spec.field")]`.
+/// Selecting on any other field is rejected here rather than by the apiserver.
+///
+/// ```
+/// use kube::core::crd::SelectableFieldSelector;
+/// let lp = SelectableFieldSelector::new(&[".spec.phase"])
+///     .eq("spec.phase", "Running")?
+///     .ne("metadata.name", "ignored")?
+///     .list_params();
+/// assert_eq!(lp.field_selector.unwrap(), "spec.phase=Running,metadata.name!=ignored");
+/// # Ok::<(), kube::core::crd::FieldSelectorError>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct SelectableFieldSelector {
+    selectable: Vec<String>,
+    requirements: Vec<String>,
+}
+
+impl SelectableFieldSelector {
+    /// Selector for a resource with the given selectable json paths (with or without the leading `.`)
+    pub fn new(selectable: &[&str]) -> Self {
+        let selectable = ALWAYS_SELECTABLE
+            .iter()
+            .chain(selectable)
+            .map(|path| normalize(path).to_string())
+            .collect();
+        Self {
+            selectable,
+            requirements: vec![],
+        }
+    }
+
+    /// Selector for the selectable fields of a derived custom resource
+    pub fn for_resource<K: CustomResourceExt>() -> Self {
+        Self::new(K::selectable_fields())
+    }
+
+    /// Require the field at `path` to equal `value`
+    ///
+    /// # Errors
+    ///
+    /// Fails if `path` is not a selectable field.
+    pub fn eq(self, path: &str, value: &str) -> Result<Self, FieldSelectorError> {
+        self.require(path, "=", value)
+    }
+
+    /// Require the field at `path` to not equal `value`
+    ///
+    /// # Errors
+    ///
+    /// Fails if `path` is not a selectable field.
+    pub fn ne(self, path: &str, value: &str) -> Result<Self, FieldSelectorError> {
+        self.require(path, "!=", value)
+    }
+
+    fn require(mut self, path: &str, op: &str, value: &str) -> Result<Self, FieldSelectorError> {
+        let path = normalize(path);
+        if !self.selectable.iter().any(|s| s == path) {
+            return Err(FieldSelectorError::NotSelectable {
+                path: path.to_string(),
+                selectable: self.selectable,
+            });
+        }
+        self.requirements
+            .push(format!("{}{}{}", path, op, escape_value(value)));
+        Ok(self)
+    }
+
+    /// The field selector string, like `spec.phase=Running,metadata.name!=foo`
+    pub fn to_field_selector(&self) -> String {
+        self.requirements.join(",")
+    }
+
+    /// `ListParams` (for list and watch calls) using this field selector
+    pub fn list_params(&self) -> ListParams {
+        ListParams::default().fields(&self.to_field_selector())
+    }
+}
+
+fn normalize(path: &str) -> &str {
+    path.strip_prefix('.').unwrap_or(path)
+}
+
+/// Escape the characters that are special in field selector values, like `fields.EscapeValue` does
+fn escape_value(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '\\' | ',' | '=') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod test {
+    use super::{FieldSelectorError, SelectableFieldSelector};
+
+    #[test]
+    fn builds_selector_from_selectable_fields() {
+        let selector = SelectableFieldSelector::new(&[".spec.phase"])
+            .eq(".spec.phase", "a,b=c")
+            .unwrap()
+            .ne("metadata.namespace", "kube-system")
+            .unwrap();
+        assert_eq!(
+            selector.to_field_selector(),
+            r"spec.phase=a\,b\=c,metadata.namespace!=kube-system"
+        );
+    }
+
+    #[test]
+    fn rejects_non_selectable_fields() {
+        let err = SelectableFieldSelector::new(&[".spec.phase"])
+            .eq("spec.replicas", "1")
+            .unwrap_err();
+        assert_eq!(err, FieldSelectorError::NotSelectable {
+            path: "spec.replicas".into(),
+            selectable: vec![
+                "metadata.name".into(),
+                "metadata.namespace".into(),
+                "spec.phase".into()
+            ],
+        });
+    }
+}
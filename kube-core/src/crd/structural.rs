@@ -0,0 +1,216 @@
+//! Checks for [structural schemas](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#specifying-a-structural-schema)
+use super::apiexts::v1::{JSONSchemaProps, JSONSchemaPropsOrArray, JSONSchemaPropsOrBool};
+
+/// Find the reasons why a schema is not structural
+///
+/// Returns one `"<path>: <reason>"` entry per violation (empty if the schema is structural), with paths relative to
+/// the schema root (e.g. `properties[spec].type`). The checked rules are those the apiserver rejects crds for:
+///
+/// - the root is of type `object`, and every other node specifies a `type`
+///   (unless it sets `x-kubernetes-int-or-string` or `x-kubernetes-preserve-unknown-fields`)
+/// - `properties` and `additionalProperties` are not set on the same node
+/// - inside `allOf`, `anyOf`, `oneOf` and `not`, no `type`, `description`, `default`, `nullable`
+///   or `additionalProperties` is set, and every property and item is also specified outside of them
+///   (except for the `anyOf: [{type: integer}, {type: string}]` of `x-kubernetes-int-or-string` nodes)
+pub fn check_structural(schema: &JSONSchemaProps) -> Vec<String> {
+    let mut violations = vec![];
+    if schema.type_.as_deref() != Some("object") {
+        violations.push(format!(
+            "type: must be object at the root, found {:?}",
+            schema.type_
+        ));
+    }
+    check_node("", schema, &mut violations);
+    violations
+}
+
+fn join(path: &str, segment: &str) -> String {
+    if path.is_empty() {
+        segment.to_string()
+    } else {
+        format!("{}.{}", path, segment)
+    }
+}
+
+fn check_node(path: &str, node: &JSONSchemaProps, violations: &mut Vec<String>) {
+    let untyped = node.x_kubernetes_int_or_string == Some(true)
+        || node.x_kubernetes_preserve_unknown_fields == Some(true);
+    if !path.is_empty() && node.type_.is_none() && !untyped {
+        violations.push(format!(
+            "{}: Required value: must not be empty",
+            join(path, "type")
+        ));
+    }
+    if node.properties.is_some() && node.additional_properties.is_some() {
+        violations.push(format!(
+            "{}: Forbidden: additionalProperties and properties are mutually exclusive",
+            join(path, "additionalProperties")
+        ));
+    }
+    for (name, prop) in node.properties.iter().flatten() {
+        check_node(&join(path, &format!("properties[{}]", name)), prop, violations);
+    }
+    for (items_path, items) in items(path, node) {
+        check_node(&items_path, items, violations);
+    }
+    if let Some(JSONSchemaPropsOrBool::Schema(additional)) = &node.additional_properties {
+        check_node(&join(path, "additionalProperties"), additional, violations);
+    }
+    // the apiserver allows int-or-string nodes to spell out their types
+    let int_or_string_any_of = node.x_kubernetes_int_or_string == Some(true)
+        && node.any_of.as_deref().map_or(false, |any_of| {
+            let types = any_of.iter().map(|s| s.type_.as_deref()).collect::<Vec<_>>();
+            types == [Some("integer"), Some("string")]
+        });
+    for (junctor_path, junctor) in junctors(path, node) {
+        if int_or_string_any_of && junctor_path.starts_with(&join(path, "anyOf[")) {
+            continue;
+        }
+        check_junctor(&junctor_path, junctor, Some(node), violations);
+    }
+}
+
+/// Check a schema inside of a logical junctor against the `outer` node it applies to
+fn check_junctor(
+    path: &str,
+    node: &JSONSchemaProps,
+    outer: Option<&JSONSchemaProps>,
+    violations: &mut Vec<String>,
+) {
+    let forbidden = [
+        ("type", node.type_.is_some()),
+        ("description", node.description.is_some()),
+        ("default", node.default.is_some()),
+        ("nullable", node.nullable.is_some()),
+        ("additionalProperties", node.additional_properties.is_some()),
+    ];
+    for (field, set) in forbidden {
+        if set {
+            violations.push(format!(
+                "{}: Forbidden: must not be used inside of logical junctors",
+                join(path, field)
+            ));
+        }
+    }
+    for (name, prop) in node.properties.iter().flatten() {
+        let prop_path = join(path, &format!("properties[{}]", name));
+        let outer_prop = outer
+            .and_then(|o| o.properties.as_ref())
+            .and_then(|p| p.get(name));
+        if outer_prop.is_none() {
+            violations.push(format!(
+                "{}: Required value: must be specified outside of logical junctors",
+                prop_path
+            ));
+        }
+        check_junctor(&prop_path, prop, outer_prop, violations);
+    }
+    let outer_items = outer.and_then(|o| match &o.items {
+        Some(JSONSchemaPropsOrArray::Schema(items)) => Some(&**items),
+        _ => None,
+    });
+    for (items_path, items) in items(path, node) {
+        if outer_items.is_none() {
+            violations.push(format!(
+                "{}: Required value: must be specified outside of logical junctors",
+                items_path
+            ));
+        }
+        check_junctor(&items_path, items, outer_items, violations);
+    }
+    for (junctor_path, junctor) in junctors(path, node) {
+        check_junctor(&junctor_path, junctor, outer, violations);
+    }
+}
+
+fn items<'a>(path: &str, node: &'a JSONSchemaProps) -> Vec<(String, &'a JSONSchemaProps)> {
+    match &node.items {
+        None => vec![],
+        Some(JSONSchemaPropsOrArray::Schema(items)) => vec![(join(path, "items"), &**items)],
+        Some(JSONSchemaPropsOrArray::Schemas(items)) => items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| (join(path, &format!("items[{}]", i)), item))
+            .collect(),
+    }
+}
+
+fn junctors<'a>(path: &str, node: &'a JSONSchemaProps) -> Vec<(String, &'a JSONSchemaProps)> {
+    let mut junctors = vec![];
+    for (name, schemas) in [
+        ("allOf", &node.all_of),
+        ("anyOf", &node.any_of),
+        ("oneOf", &node.one_of),
+    ] {
+        for (i, schema) in schemas.iter().flatten().enumerate() {
+            junctors.push((join(path, &format!("{}[{}]", name, i)), schema));
+        }
+    }
+    if let Some(not) = &node.not {
+        junctors.push((join(path, "not"), &**not));
+    }
+    junctors
+}
+
+#[cfg(test)]
+mod test {
+    use super::check_structural;
+    use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaProps;
+    use serde_json::json;
+
+    fn schema(value: serde_json::Value) -> JSONSchemaProps {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn accepts_structural_schema() {
+        let schema = schema(json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "type": "object",
+                    "properties": {
+                        "port": { "x-kubernetes-int-or-string": true },
+                        "a": { "type": "string" },
+                        "b": { "type": "string" },
+                    },
+                    "oneOf": [{ "required": ["a"] }, { "required": ["b"] }]
+                }
+            }
+        }));
+        assert_eq!(check_structural(&schema), Vec::<String>::new());
+    }
+
+    #[test]
+    fn accepts_int_or_string_any_of() {
+        let int_or_string = json!({
+            "anyOf": [{ "type": "integer" }, { "type": "string" }],
+            "x-kubernetes-int-or-string": true
+        });
+        let valid = schema(json!({ "type": "object", "properties": { "port": int_or_string } }));
+        assert!(check_structural(&valid).is_empty());
+
+        let without_extension = json!({ "anyOf": [{ "type": "integer" }, { "type": "string" }] });
+        let invalid = schema(json!({ "type": "object", "properties": { "port": without_extension } }));
+        assert_eq!(check_structural(&invalid).len(), 3);
+    }
+
+    #[test]
+    fn reports_untyped_nodes_and_junctor_violations() {
+        let schema = schema(json!({
+            "type": "object",
+            "properties": {
+                "spec": {
+                    "properties": { "a": { "type": "string" } },
+                    "anyOf": [{ "type": "object", "properties": { "b": { "type": "string" } } }]
+                }
+            }
+        }));
+        assert_eq!(check_structural(&schema), vec![
+            "properties[spec].type: Required value: must not be empty",
+            "properties[spec].anyOf[0].type: Forbidden: must not be used inside of logical junctors",
+            "properties[spec].anyOf[0].properties[b]: Required value: must be specified outside of logical junctors",
+            "properties[spec].anyOf[0].properties[b].type: Forbidden: must not be used inside of logical junctors",
+        ]);
+    }
+}
@@ -0,0 +1,239 @@
+//! Accessors for the versions of a `CustomResourceDefinition`
+use super::{apiexts::v1::CustomResourceDefinition as Crd, compare::version_schema};
+use crate::{
+    gvk::{GroupVersion, GroupVersionKind},
+    Version,
+};
+
+/// The [`GroupVersionKind`] of every served version of a crd, in declaration order
+///
+/// Useful to watch all served versions of a kind, e.g. during a storage version migration.
+pub fn gvks(crd: &Crd) -> Vec<GroupVersionKind> {
+    crd_gvks(crd, false)
+}
+
+/// The [`GroupVersionKind`] of every version of a crd (including unserved ones), in declaration order
+pub fn gvks_all(crd: &Crd) -> Vec<GroupVersionKind> {
+    crd_gvks(crd, true)
+}
+
+/// Whether `api_version` (like `clux.dev/v1`) names a version served by `crd`
+///
+/// Group-less (core group) api versions like `v1` never match, as crds always have a group.
+/// Malformed api versions never match either.
+pub fn is_served_version(crd: &Crd, api_version: &str) -> bool {
+    match api_version.parse::<GroupVersion>() {
+        Ok(gv) => {
+            gv.group == crd.spec.group && crd.spec.versions.iter().any(|v| v.served && v.name == gv.version)
+        }
+        Err(_) => false,
+    }
+}
+
+/// The served version of a crd that discovery prefers, or `None` if no version is served
+///
+/// Like the apiserver, this is the served version with the highest [`Version`] priority: GA before beta before alpha,
+/// and higher version numbers first. Clients holding the crd can use this instead of a discovery call.
+pub fn preferred_version(crd: &Crd) -> Option<&str> {
+    crd.spec
+        .versions
+        .iter()
+        .filter(|v| v.served)
+        .map(|v| v.name.as_str())
+        .max_by_key(|name| Version::parse(name).priority())
+}
+
+fn crd_gvks(crd: &Crd, include_unserved: bool) -> Vec<GroupVersionKind> {
+    crd.spec
+        .versions
+        .iter()
+        .filter(|v| include_unserved || v.served)
+        .map(|v| GroupVersionKind::gvk(&crd.spec.group, &v.name, &crd.spec.names.kind))
+        .collect()
+}
+
+/// The objects to rewrite after changing the storage version of a crd, found by [`storage_migration_needed`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StorageMigration {
+    /// The previous storage version
+    pub from: String,
+    /// The new storage version
+    pub to: String,
+    /// The versions objects may still be stored as, and that need rewriting to `to`
+    ///
+    /// These are the `status.storedVersions` of the old crd (if known), otherwise just `from`.
+    pub rewrite: Vec<String>,
+    /// Whether reading objects stored as `from` through `to` needs a conversion webhook, as their schemas differ
+    pub requires_webhook: bool,
+}
+
+/// Whether updating `old` to `new` changes the storage version, and which stored objects need rewriting if so
+///
+/// Rewriting is typically done by a `StorageVersionMigration`, or by reading and writing back every object.
+/// Returns `None` if the storage version is unchanged, or either crd has no storage version.
+pub fn storage_migration_needed(old: &Crd, new: &Crd) -> Option<StorageMigration> {
+    let storage = |crd: &Crd| crd.spec.versions.iter().find(|v| v.storage).cloned();
+    let (from, to) = (storage(old)?, storage(new)?);
+    if from.name == to.name {
+        return None;
+    }
+    let stored = old.status.as_ref().and_then(|s| s.stored_versions.clone());
+    let rewrite = match stored {
+        Some(stored) if !stored.is_empty() => stored.into_iter().filter(|v| v != &to.name).collect(),
+        _ => vec![from.name.clone()],
+    };
+    Some(StorageMigration {
+        requires_webhook: version_schema(&from) != version_schema(&to),
+        from: from.name,
+        to: to.name,
+        rewrite,
+    })
+}
+
+/// The `status.storedVersions` of a live crd that are no longer in its `spec.versions`
+///
+/// Objects may still be stored as these versions, and the apiserver refuses to remove a version from
+/// `status.storedVersions` while it is not in `spec`, so they need a storage migration to the storage version
+/// (and then removing from `status.storedVersions`) before they are really gone. Returns them in `status` order.
+pub fn orphaned_stored_versions(live: &Crd) -> Vec<String> {
+    let stored = live.status.as_ref().and_then(|s| s.stored_versions.as_ref());
+    stored
+        .into_iter()
+        .flatten()
+        .filter(|stored| !live.spec.versions.iter().any(|v| &&v.name == stored))
+        .cloned()
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        gvks, gvks_all, is_served_version, orphaned_stored_versions, preferred_version,
+        storage_migration_needed, Crd, StorageMigration,
+    };
+    use crate::gvk::GroupVersionKind;
+    use serde_json::json;
+
+    #[test]
+    fn gvks_of_served_versions() {
+        let crd: Crd = serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [
+                    { "name": "v1alpha1", "served": false, "storage": false },
+                    { "name": "v1beta1", "served": true, "storage": false },
+                    { "name": "v1", "served": true, "storage": true }
+                ]
+            }
+        }))
+        .unwrap();
+        assert_eq!(gvks(&crd), vec![
+            GroupVersionKind::gvk("clux.dev", "v1beta1", "Foo"),
+            GroupVersionKind::gvk("clux.dev", "v1", "Foo"),
+        ]);
+        assert_eq!(gvks_all(&crd).len(), 3);
+        assert_eq!(gvks_all(&crd)[0].version, "v1alpha1");
+
+        assert!(is_served_version(&crd, "clux.dev/v1"));
+        assert!(is_served_version(&crd, "clux.dev/v1beta1"));
+        assert!(!is_served_version(&crd, "clux.dev/v1alpha1"));
+        assert!(!is_served_version(&crd, "clux.dev/v2"));
+        assert!(!is_served_version(&crd, "other.dev/v1"));
+        assert!(!is_served_version(&crd, "v1"));
+        assert!(!is_served_version(&crd, "clux.dev/v1/extra"));
+    }
+
+    #[test]
+    fn preferred_version_is_highest_priority_served_version() {
+        let crd = |versions: &[(&str, bool)]| -> Crd {
+            let versions = versions
+                .iter()
+                .map(|(name, served)| json!({ "name": name, "served": served, "storage": false }))
+                .collect::<Vec<_>>();
+            serde_json::from_value(json!({
+                "metadata": { "name": "foos.clux.dev" },
+                "spec": {
+                    "group": "clux.dev",
+                    "names": { "kind": "Foo", "plural": "foos" },
+                    "scope": "Namespaced",
+                    "versions": versions
+                }
+            }))
+            .unwrap()
+        };
+        let all_served = crd(&[("v1", true), ("v2", true), ("v1beta1", true)]);
+        assert_eq!(preferred_version(&all_served), Some("v2"));
+        let beta = crd(&[("v1", false), ("v2beta1", true), ("v2alpha1", true)]);
+        assert_eq!(preferred_version(&beta), Some("v2beta1"));
+        assert_eq!(preferred_version(&crd(&[("v1", false)])), None);
+    }
+
+    fn crd(storage: &str, stored_versions: &[&str], v2_props: serde_json::Value) -> Crd {
+        let version = |name: &str, props: serde_json::Value| {
+            json!({
+                "name": name,
+                "served": true,
+                "storage": name == storage,
+                "schema": { "openAPIV3Schema": { "type": "object", "properties": props } }
+            })
+        };
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [
+                    version("v1", json!({ "spec": { "type": "object" } })),
+                    version("v2", v2_props),
+                ]
+            },
+            "status": { "storedVersions": stored_versions }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn storage_version_change_needs_migration() {
+        let v1_props = json!({ "spec": { "type": "object" } });
+        let v2_props = json!({ "spec": { "type": "object", "properties": { "x": { "type": "string" } } } });
+        let old = crd("v1", &["v1"], v2_props.clone());
+        assert_eq!(storage_migration_needed(&old, &old), None);
+
+        let new = crd("v2", &[], v2_props.clone());
+        assert_eq!(
+            storage_migration_needed(&old, &new),
+            Some(StorageMigration {
+                from: "v1".into(),
+                to: "v2".into(),
+                rewrite: vec!["v1".into()],
+                requires_webhook: true,
+            })
+        );
+
+        let identical = crd("v2", &[], v1_props.clone());
+        let migration = storage_migration_needed(&crd("v1", &["v1"], v1_props), &identical).unwrap();
+        assert!(!migration.requires_webhook);
+
+        // objects still stored as the new version do not need rewriting
+        let partially_migrated = crd("v1", &["v2", "v1"], v2_props);
+        let migration = storage_migration_needed(&partially_migrated, &new).unwrap();
+        assert_eq!(migration.rewrite, vec!["v1".to_string()]);
+    }
+
+    #[test]
+    fn stored_versions_missing_from_spec_are_orphaned() {
+        let props = json!({ "spec": { "type": "object" } });
+        assert!(orphaned_stored_versions(&crd("v2", &["v1", "v2"], props.clone())).is_empty());
+        assert_eq!(
+            orphaned_stored_versions(&crd("v2", &["v1beta1", "v1", "v2", "v0"], props.clone())),
+            vec!["v1beta1".to_string(), "v0".to_string()]
+        );
+        let mut no_status = crd("v2", &[], props);
+        no_status.status = None;
+        assert!(orphaned_stored_versions(&no_status).is_empty());
+    }
+}
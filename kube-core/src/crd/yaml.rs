@@ -0,0 +1,216 @@
+//! Serialization of `CustomResourceDefinition`s for manifest files
+use super::{apiexts::v1::CustomResourceDefinition as Crd, HELM_CA_BUNDLE_PLACEHOLDER};
+use chrono::{DateTime, SecondsFormat, Utc};
+use k8s_openapi::ByteString;
+use std::io::{self, Write};
+
+/// Output format of [`print_crds`] and [`write_crds`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// A multi-document yaml stream, like [`to_yaml_documents`]
+    Yaml,
+    /// A pretty printed json array
+    Json,
+}
+
+/// Serialize crds into a single multi-document yaml stream
+///
+/// Every crd becomes one `---` prefixed document, in the order given. Keys are always emitted in the same order
+/// (struct fields in declaration order, maps sorted), so regenerated manifests only change when the crds do.
+///
+/// ```
+/// # use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+/// # fn wrapper(foo_crd: CustomResourceDefinition, bar_crd: CustomResourceDefinition) -> serde_yaml::Result<()> {
+/// let manifest = kube_core::crd::to_yaml_documents(&[foo_crd, bar_crd])?;
+/// std::fs::write("crds.yaml", manifest).unwrap();
+/// # Ok(())
+/// # }
+/// ```
+pub fn to_yaml_documents(crds: &[Crd]) -> serde_yaml::Result<String> {
+    let mut out = String::new();
+    for crd in crds {
+        let doc = serde_yaml::to_string(crd)?;
+        if !doc.starts_with("---") {
+            out.push_str("---\n");
+        }
+        out.push_str(&doc);
+        if !out.ends_with('\n') {
+            out.push('\n');
+        }
+    }
+    Ok(out)
+}
+
+/// Serialize crds like [`to_yaml_documents`], preceded by `header` as yaml comments
+///
+/// Every line of `header` becomes a `# ` prefixed comment line before the first document,
+/// e.g. to warn against editing a generated manifest. See [`generated_header`] for a typical header.
+///
+/// ```
+/// # use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+/// # fn wrapper(crds: Vec<CustomResourceDefinition>) -> serde_yaml::Result<()> {
+/// use kube_core::crd::{generated_header, to_yaml_documents_with_header};
+/// let header = generated_header(concat!("crdgen ", env!("CARGO_PKG_VERSION")), None);
+/// std::fs::write("crds.yaml", to_yaml_documents_with_header(&crds, &header)?).unwrap();
+/// # Ok(())
+/// # }
+/// ```
+pub fn to_yaml_documents_with_header(crds: &[Crd], header: &str) -> serde_yaml::Result<String> {
+    let mut out = String::new();
+    for line in header.lines() {
+        out.push('#');
+        if !line.is_empty() {
+            out.push(' ');
+            out.push_str(line);
+        }
+        out.push('\n');
+    }
+    out.push_str(&to_yaml_documents(crds)?);
+    Ok(out)
+}
+
+/// Serialize crds merged with [`CrdMerger::helm_placeholders`](super::CrdMerger::helm_placeholders) for a Helm chart
+///
+/// This is [`to_yaml_documents`], except that the [`HELM_CA_BUNDLE_PLACEHOLDER`] is written verbatim,
+/// rather than base64 encoded like any other `caBundle`, so Helm fills it in when rendering the chart.
+pub fn to_helm_template(crds: &[Crd]) -> serde_yaml::Result<String> {
+    let yaml = to_yaml_documents(crds)?;
+    let encoded = serde_json::to_value(ByteString(HELM_CA_BUNDLE_PLACEHOLDER.into()))
+        .ok()
+        .and_then(|v| v.as_str().map(String::from))
+        .unwrap_or_default();
+    Ok(yaml.replace(&encoded, HELM_CA_BUNDLE_PLACEHOLDER))
+}
+
+/// A header for [`to_yaml_documents_with_header`] marking a manifest as generated by `generator`
+///
+/// The header records `generated_at` when given. Leave it out to only change the manifest when the crds do,
+/// rather than on every regeneration.
+pub fn generated_header(generator: &str, generated_at: Option<DateTime<Utc>>) -> String {
+    let mut header = format!("DO NOT EDIT - generated by {}", generator);
+    if let Some(time) = generated_at {
+        header.push_str(&format!(
+            "\ngenerated at {}",
+            time.to_rfc3339_opts(SecondsFormat::Secs, true)
+        ));
+    }
+    header
+}
+
+/// Write crds to `writer` in the given format
+///
+/// # Errors
+///
+/// Fails if the crds can not be serialized, or if writing fails.
+pub fn write_crds<W: Write>(mut writer: W, crds: &[Crd], format: OutputFormat) -> io::Result<()> {
+    let out = match format {
+        OutputFormat::Yaml => {
+            to_yaml_documents(crds).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+        }
+        OutputFormat::Json => serde_json::to_string_pretty(crds)? + "\n",
+    };
+    writer.write_all(out.as_bytes())?;
+    writer.flush()
+}
+
+/// Print crds to stdout, for use as the `main` of a `crdgen` binary
+///
+/// On failure, the error is printed to stderr and the process exits with status 1,
+/// except when stdout was closed early (like when piped into `head`), which exits successfully.
+///
+/// ```no_run
+/// # use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+/// use kube_core::crd::{print_crds, OutputFormat};
+/// # fn wrapper(foo_crd: CustomResourceDefinition, bar_crd: CustomResourceDefinition) {
+/// print_crds(&[foo_crd, bar_crd], OutputFormat::Yaml);
+/// # }
+/// ```
+pub fn print_crds(crds: &[Crd], format: OutputFormat) {
+    match write_crds(io::stdout().lock(), crds, format) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(err) => {
+            eprintln!("failed to print crds: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        generated_header, to_yaml_documents, to_yaml_documents_with_header, write_crds, Crd, OutputFormat,
+    };
+    use chrono::{DateTime, Utc};
+    use serde_json::json;
+
+    fn crd(plural: &str) -> Crd {
+        serde_json::from_value(json!({
+            "metadata": {
+                "name": format!("{}.clux.dev", plural),
+                "labels": { "b": "2", "a": "1" }
+            },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": plural, "plural": plural },
+                "scope": "Namespaced",
+                "versions": [{ "name": "v1", "served": true, "storage": true }]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn emits_one_stable_document_per_crd() {
+        let crds = vec![crd("foos"), crd("bars")];
+        let yaml = to_yaml_documents(&crds).unwrap();
+        assert_eq!(yaml.matches("---\n").count(), 2);
+        assert!(yaml.find("foos.clux.dev").unwrap() < yaml.find("bars.clux.dev").unwrap());
+        assert!(yaml.find("a: \"1\"").unwrap() < yaml.find("b: \"2\"").unwrap());
+        assert_eq!(yaml, to_yaml_documents(&crds).unwrap());
+
+        let docs = serde_yaml::Deserializer::from_str(&yaml)
+            .map(|doc| serde::Deserialize::deserialize(doc).unwrap())
+            .collect::<Vec<Crd>>();
+        assert_eq!(docs, crds);
+    }
+
+    #[test]
+    fn written_crds_round_trip() {
+        let crds = vec![crd("foos"), crd("bars")];
+        let mut yaml = vec![];
+        write_crds(&mut yaml, &crds, OutputFormat::Yaml).unwrap();
+        let docs = serde_yaml::Deserializer::from_slice(&yaml)
+            .map(|doc| serde::Deserialize::deserialize(doc).unwrap())
+            .collect::<Vec<Crd>>();
+        assert_eq!(docs, crds);
+
+        let mut json = vec![];
+        write_crds(&mut json, &crds, OutputFormat::Json).unwrap();
+        assert!(json.ends_with(b"]\n"));
+        assert_eq!(serde_json::from_slice::<Vec<Crd>>(&json).unwrap(), crds);
+    }
+
+    #[test]
+    fn header_precedes_first_document_once() {
+        let crds = vec![crd("foos"), crd("bars")];
+        let header = generated_header("crdgen 0.1.0", None);
+        let yaml = to_yaml_documents_with_header(&crds, &header).unwrap();
+        assert!(yaml.starts_with("# DO NOT EDIT - generated by crdgen 0.1.0\n---\n"));
+        assert_eq!(yaml.matches("DO NOT EDIT").count(), 1);
+        assert_eq!(yaml, to_yaml_documents_with_header(&crds, &header).unwrap());
+
+        let docs = serde_yaml::Deserializer::from_str(&yaml)
+            .map(|doc| serde::Deserialize::deserialize(doc).unwrap())
+            .collect::<Vec<Crd>>();
+        assert_eq!(docs, crds);
+
+        let timestamped = generated_header(
+            "crdgen",
+            Some("2022-03-01T12:00:00Z".parse::<DateTime<Utc>>().unwrap()),
+        );
+        let yaml = to_yaml_documents_with_header(&crds, &timestamped).unwrap();
+        assert!(yaml
+            .starts_with("# DO NOT EDIT - generated by crdgen\n# generated at 2022-03-01T12:00:00Z\n---\n"));
+    }
+}
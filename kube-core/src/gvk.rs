@@ -118,3 +118,26 @@ impl GroupVersionResource {
         }
     }
 }
+
+/// The group, version, and kind of a resource type as associated consts
+///
+/// This is implemented by `#[derive(CustomResource)]`, and lets hot paths match the type information of objects
+/// (e.g. of every watch event) against a type without allocating, or use the parts in const contexts.
+pub trait StaticGroupVersionKind {
+    /// API group
+    const GROUP: &'static str;
+    /// Version
+    const VERSION: &'static str;
+    /// Kind
+    const KIND: &'static str;
+
+    /// The [`GroupVersionKind`] built from the consts
+    fn gvk() -> GroupVersionKind {
+        GroupVersionKind::gvk(Self::GROUP, Self::VERSION, Self::KIND)
+    }
+
+    /// Whether `gvk` is the group, version, and kind of this type
+    fn matches_gvk(gvk: &GroupVersionKind) -> bool {
+        gvk.group == Self::GROUP && gvk.version == Self::VERSION && gvk.kind == Self::KIND
+    }
+}
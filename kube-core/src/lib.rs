@@ -14,6 +14,8 @@
 #[cfg(feature = "admission")]
 pub mod admission;
 
+pub mod conversion;
+
 pub mod discovery;
 
 pub mod dynamic;
@@ -23,7 +25,7 @@ pub mod crd;
 pub use crd::CustomResourceExt;
 
 pub mod gvk;
-pub use gvk::{GroupVersion, GroupVersionKind, GroupVersionResource};
+pub use gvk::{GroupVersion, GroupVersionKind, GroupVersionResource, StaticGroupVersionKind};
 
 pub mod metadata;
 pub use metadata::{ListMeta, ObjectMeta, TypeMeta};
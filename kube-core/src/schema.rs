@@ -4,19 +4,37 @@
 
 use std::collections::btree_map::Entry;
 
+use crate::crd::FieldViolation;
+use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaProps;
+
 // Used in docs
 #[allow(unused_imports)] use schemars::gen::SchemaSettings;
 
 use schemars::{
-    schema::{Metadata, ObjectValidation, Schema, SchemaObject},
+    schema::{InstanceType, Metadata, ObjectValidation, RootSchema, Schema, SchemaObject, SingleOrVec},
     visit::Visitor,
+    Map,
 };
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 /// schemars [`Visitor`] that rewrites a [`Schema`] to conform to Kubernetes' "structural schema" rules
 ///
-/// The following two transformations are applied
+/// The following transformations are applied
 ///  * Rewrite enums from `oneOf` to `object`s with multiple variants ([schemars#84](https://github.com/GREsau/schemars/issues/84))
+///  * Rewrite untagged enums (`anyOf`) by hoisting the properties (or common `type`) of the variants out of the `anyOf`,
+///    using `x-kubernetes-int-or-string` for integer or string variants, and falling back to
+///    `x-kubernetes-preserve-unknown-fields` (noted in the `description`) for variants of different types
+///    (or struct variants defining the same property differently)
+///  * Collapse documented string enums from `oneOf` into a single `enum`, listing the doc comment of each variant
+///    in the `description` (so they show up in `kubectl explain`)
+///  * Merge the tag property of internally tagged enums into a single `enum` listing every variant,
+///    and guard it with an `x-kubernetes-validations` rule so unknown tags are rejected. Per variant, a rule requires
+///    its required fields and forbids the fields of other variants when the tag selects it. Fields that are identical
+///    in several variants are shared, in which case the `oneOf` is dropped (as several branches would match)
 ///  * Rewrite `additionalProperties` from `#[serde(flatten)]` to `x-kubernetes-preserve-unknown-fields` ([kube-rs#844](https://github.com/kube-rs/kube-rs/issues/844))
+///  * Rewrite the schemas of arbitrary JSON (`serde_json::Value` and `serde_json::Map<String, Value>`), which schemars
+///    leaves empty or as an object with `additionalProperties: true`, to `x-kubernetes-preserve-unknown-fields`
 ///
 /// This is used automatically by `kube::derive`'s `#[derive(CustomResource)]`,
 /// but it can also be used manually with [`SchemaSettings::with_visitor`].
@@ -24,13 +42,17 @@ use schemars::{
 /// # Panics
 ///
 /// The [`Visitor`] functions may panic if the transform could not be applied. For example,
-/// there must not be any overlapping properties between `oneOf` branches.
+/// there must not be any differently defined overlapping properties between `oneOf` branches (other than the tag of an
+/// internally tagged enum).
 #[derive(Debug, Clone)]
 pub struct StructuralSchemaRewriter;
 
 impl Visitor for StructuralSchemaRewriter {
     fn visit_schema_object(&mut self, schema: &mut schemars::schema::SchemaObject) {
         schemars::visit::visit_schema_object(self, schema);
+        collapse_documented_string_enum(schema);
+        rewrite_untagged_enum(schema);
+        let mut tag_rules = Vec::new();
         if let Some(one_of) = schema
             .subschemas
             .as_mut()
@@ -39,7 +61,10 @@ impl Visitor for StructuralSchemaRewriter {
             let common_obj = schema
                 .object
                 .get_or_insert_with(|| Box::new(ObjectValidation::default()));
-            for variant in one_of {
+            let mut tags = Vec::new();
+            let mut shared = false;
+            let mut variants = Vec::new();
+            for variant in one_of.iter_mut() {
                 if let Schema::Object(SchemaObject {
                     instance_type: variant_type,
                     object: Some(variant_obj),
@@ -47,6 +72,7 @@ impl Visitor for StructuralSchemaRewriter {
                     ..
                 }) = variant
                 {
+                    variants.push(VariantFields::of(variant_obj));
                     if let Some(variant_metadata) = variant_metadata {
                         // Move enum variant description from oneOf clause to its corresponding property
                         if let Some(description) = std::mem::take(&mut variant_metadata.description) {
@@ -65,10 +91,21 @@ impl Visitor for StructuralSchemaRewriter {
                     let variant_properties = std::mem::take(&mut variant_obj.properties);
                     for (property_name, property) in variant_properties {
                         match common_obj.properties.entry(property_name) {
-                            Entry::Occupied(entry) => panic!(
-                                "property {:?} is already defined in another enum variant",
-                                entry.key()
-                            ),
+                            Entry::Occupied(mut entry) => {
+                                if merge_enum_values(entry.get_mut(), &property) {
+                                    if !tags.contains(entry.key()) {
+                                        tags.push(entry.key().clone());
+                                    }
+                                } else if *entry.get() == property {
+                                    // identical fields of several variants form a common superset
+                                    shared = true;
+                                } else {
+                                    panic!(
+                                        "property {:?} is already defined differently in another enum variant",
+                                        entry.key()
+                                    )
+                                }
+                            }
                             Entry::Vacant(entry) => {
                                 entry.insert(property);
                             }
@@ -95,6 +132,31 @@ impl Visitor for StructuralSchemaRewriter {
                     }
                 }
             }
+            for tag in &tags {
+                if let Some(Schema::Object(SchemaObject {
+                    enum_values: Some(values),
+                    ..
+                })) = common_obj.properties.get(tag)
+                {
+                    tag_rules.push(enum_rule(tag, values));
+                }
+                tag_rules.extend(variants.iter().filter_map(|variant| variant.rule(tag, &variants)));
+            }
+            // Variants sharing fields would all match the `oneOf`, so only the rules enforce the variant fields
+            if shared && !tags.is_empty() {
+                for tag in tags {
+                    common_obj.required.insert(tag);
+                }
+                if let Some(subschemas) = &mut schema.subschemas {
+                    subschemas.one_of = None;
+                }
+            }
+        }
+        // validation rules are only part of crd schemas from Kubernetes 1.23
+        k8s_openapi::k8s_if_ge_1_23! {
+            for rule in tag_rules {
+                push_validation(schema, rule);
+            }
         }
         // check for maps without with properties (i.e. flattnened maps)
         // and allow these to persist dynamically
@@ -108,9 +170,935 @@ impl Visitor for StructuralSchemaRewriter {
                 }
             }
         }
+        preserve_arbitrary_json(schema);
+    }
+}
+
+/// Let the apiserver store arbitrary JSON verbatim, rather than rejecting its schema for having no `type`
+fn preserve_arbitrary_json(schema: &mut SchemaObject) {
+    let any = SchemaObject {
+        metadata: schema.metadata.clone(),
+        extensions: schema.extensions.clone(),
+        ..SchemaObject::default()
+    };
+    let any_map = schema.instance_type == Some(InstanceType::Object.into())
+        && schema.object.as_deref().map_or(false, |object| {
+            object.properties.is_empty()
+                && object.pattern_properties.is_empty()
+                && object.additional_properties.as_deref() == Some(&Schema::Bool(true))
+        });
+    if any_map {
+        schema.object = None;
+    } else if *schema != any || schema.extensions.contains_key("x-kubernetes-int-or-string") {
+        return;
+    }
+    schema
+        .extensions
+        .insert("x-kubernetes-preserve-unknown-fields".into(), true.into());
+}
+
+/// Rewrite a `oneOf` of single valued string enums (what schemars generates for unit enums with documented variants)
+/// into one string enum, appending the variant docs to the `description`
+fn collapse_documented_string_enum(schema: &mut SchemaObject) {
+    let one_of = match schema.subschemas.as_ref().and_then(|s| s.one_of.as_ref()) {
+        Some(one_of) => one_of,
+        None => return,
+    };
+    let mut values = Vec::new();
+    let mut docs = Vec::new();
+    for variant in one_of {
+        match variant {
+            Schema::Object(SchemaObject {
+                instance_type: Some(instance_type),
+                enum_values: Some(variant_values),
+                metadata,
+                object: None,
+                ..
+            }) if *instance_type == InstanceType::String.into()
+                && variant_values.iter().all(Value::is_string) =>
+            {
+                // schemars groups the undocumented variants into one branch
+                let description = metadata.as_ref().and_then(|m| m.description.as_ref());
+                if let (Some(description), [value]) = (description, variant_values.as_slice()) {
+                    docs.push(format!(
+                        "- `{}`: {}",
+                        value.as_str().unwrap_or_default(),
+                        description.replace('\n', " ")
+                    ));
+                }
+                values.extend(variant_values.iter().cloned());
+            }
+            _ => return,
+        }
+    }
+
+    if !docs.is_empty() {
+        let metadata = schema
+            .metadata
+            .get_or_insert_with(|| Box::new(Metadata::default()));
+        let description = match metadata.description.take() {
+            Some(description) => format!("{}\n\nPossible values:\n{}", description, docs.join("\n")),
+            None => format!("Possible values:\n{}", docs.join("\n")),
+        };
+        metadata.description = Some(description);
+    }
+    schema.instance_type = Some(InstanceType::String.into());
+    schema.enum_values = Some(values);
+    if let Some(subschemas) = schema.subschemas.as_mut() {
+        subschemas.one_of = None;
+    }
+    if schema.subschemas.as_deref() == Some(&Default::default()) {
+        schema.subschemas = None;
+    }
+}
+
+/// Note added to the `description` of untagged enums that can only be represented as `x-kubernetes-preserve-unknown-fields`
+const UNTAGGED_ENUM_NOTE: &str =
+    "The variants of this field have different types, so it is not validated by the apiserver.";
+
+/// Make the `anyOf` that schemars generates for untagged enums structural
+fn rewrite_untagged_enum(schema: &mut SchemaObject) {
+    let any_of = match schema.subschemas.as_mut().and_then(|s| s.any_of.take()) {
+        Some(any_of) => any_of,
+        None => return,
+    };
+    // Unit variants are `null`
+    let mut variants = Vec::new();
+    for variant in any_of {
+        match variant {
+            Schema::Object(variant) if variant.instance_type == Some(InstanceType::Null.into()) => {
+                schema.extensions.insert("nullable".into(), true.into());
+            }
+            variant => variants.push(variant),
+        }
+    }
+    let types = variants
+        .iter()
+        .map(|variant| match variant {
+            Schema::Object(SchemaObject {
+                instance_type: Some(SingleOrVec::Single(instance_type)),
+                ..
+            }) => Some(**instance_type),
+            _ => None,
+        })
+        .collect::<Option<Vec<_>>>()
+        .unwrap_or_default();
+    let common_type = types
+        .first()
+        .copied()
+        .filter(|first| types.iter().all(|t| t == first));
+    let int_or_string = !types.is_empty()
+        && types
+            .iter()
+            .all(|t| *t == InstanceType::Integer || *t == InstanceType::String);
+
+    let mut branches = Vec::new();
+    match common_type {
+        Some(InstanceType::Object) => {
+            // A property defined differently by several variants can not be hoisted out of the `anyOf`
+            let mut properties = schema
+                .object
+                .iter()
+                .flat_map(|obj| obj.properties.iter())
+                .collect::<Map<_, _>>();
+            let conflicting = variants
+                .iter()
+                .filter_map(|variant| match variant {
+                    Schema::Object(variant) => variant.object.as_deref(),
+                    Schema::Bool(_) => None,
+                })
+                .flat_map(|obj| obj.properties.iter())
+                .any(|(name, property)| *properties.entry(name).or_insert(property) != property);
+            if conflicting {
+                return preserve_unknown_variants(schema);
+            }
+            let common_obj = schema
+                .object
+                .get_or_insert_with(|| Box::new(ObjectValidation::default()));
+            for variant in variants {
+                let mut variant = match variant {
+                    Schema::Object(variant) => variant,
+                    Schema::Bool(_) => continue,
+                };
+                let variant_obj = variant.object.take().unwrap_or_default();
+                for (property_name, property) in variant_obj.properties {
+                    common_obj.properties.entry(property_name).or_insert(property);
+                }
+                // Only `required` may be left in the variants, everything else has to be specified outside of `anyOf`
+                branches.push(Schema::Object(SchemaObject {
+                    object: Some(Box::new(ObjectValidation {
+                        required: variant_obj.required,
+                        ..ObjectValidation::default()
+                    })),
+                    ..SchemaObject::default()
+                }));
+            }
+            schema.instance_type = Some(InstanceType::Object.into());
+        }
+        Some(InstanceType::Array) => {
+            let items = variants
+                .iter()
+                .map(|variant| match variant {
+                    Schema::Object(variant) => variant.array.as_ref().and_then(|a| a.items.clone()),
+                    Schema::Bool(_) => None,
+                })
+                .collect::<Vec<_>>();
+            if !items.iter().all(|i| i.is_some() && *i == items[0]) {
+                return preserve_unknown_variants(schema);
+            }
+            schema.instance_type = Some(InstanceType::Array.into());
+            schema.array.get_or_insert_with(Default::default).items = items[0].clone();
+            branches = variants
+                .into_iter()
+                .map(|variant| strip_structural_metadata(variant, true))
+                .collect();
+        }
+        Some(common_type) => {
+            schema.instance_type = Some(common_type.into());
+            branches = variants
+                .into_iter()
+                .map(|variant| strip_structural_metadata(variant, false))
+                .collect();
+        }
+        None if int_or_string => {
+            schema
+                .extensions
+                .insert("x-kubernetes-int-or-string".into(), true.into());
+        }
+        None => return preserve_unknown_variants(schema),
+    }
+    // Variants without any constraints left make the `anyOf` trivially true
+    let trivial = Schema::Object(SchemaObject::default());
+    if !branches.is_empty() && !branches.contains(&trivial) {
+        schema.subschemas.get_or_insert_with(Default::default).any_of = Some(branches);
+    }
+    if schema.subschemas.as_deref() == Some(&Default::default()) {
+        schema.subschemas = None;
+    }
+}
+
+/// Strip what structural schemas forbid inside of `anyOf` from an untagged enum variant
+fn strip_structural_metadata(variant: Schema, strip_items: bool) -> Schema {
+    match variant {
+        Schema::Object(mut variant) => {
+            variant.instance_type = None;
+            variant.metadata = None;
+            variant.extensions.remove("nullable");
+            if strip_items {
+                if let Some(array) = variant.array.as_mut() {
+                    array.items = None;
+                }
+            }
+            Schema::Object(variant)
+        }
+        variant => variant,
     }
 }
 
+/// Accept any value for an untagged enum whose variants can not be represented structurally
+fn preserve_unknown_variants(schema: &mut SchemaObject) {
+    schema.instance_type = None;
+    schema.subschemas = None;
+    schema
+        .extensions
+        .insert("x-kubernetes-preserve-unknown-fields".into(), true.into());
+    let metadata = schema
+        .metadata
+        .get_or_insert_with(|| Box::new(Metadata::default()));
+    metadata.description = Some(match metadata.description.take() {
+        Some(description) => format!("{}\n\n{}", description, UNTAGGED_ENUM_NOTE),
+        None => UNTAGGED_ENUM_NOTE.to_string(),
+    });
+}
+
+/// Merge the `enum` values of two string enum schemas (such as the tag of an internally tagged enum)
+///
+/// Returns `false` (leaving `existing` unchanged) if either schema is not a string enum.
+fn merge_enum_values(existing: &mut Schema, new: &Schema) -> bool {
+    let string_enum = |s: &SchemaObject| {
+        s.instance_type == Some(InstanceType::String.into())
+            && s.enum_values
+                .as_ref()
+                .map_or(false, |values| values.iter().all(Value::is_string))
+    };
+    match (existing, new) {
+        (Schema::Object(existing), Schema::Object(new)) if string_enum(existing) && string_enum(new) => {
+            let values = existing.enum_values.get_or_insert_with(Vec::new);
+            for value in new.enum_values.iter().flatten() {
+                if !values.contains(value) {
+                    values.push(value.clone());
+                }
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The fields of a variant of an internally tagged enum
+struct VariantFields {
+    properties: Vec<String>,
+    required: Vec<String>,
+    /// values of single valued string enum properties (the tag is one of them)
+    constants: Vec<(String, String)>,
+}
+
+impl VariantFields {
+    fn of(variant: &ObjectValidation) -> Self {
+        let constants = variant
+            .properties
+            .iter()
+            .filter_map(|(name, property)| match property {
+                Schema::Object(SchemaObject {
+                    enum_values: Some(values),
+                    ..
+                }) if values.len() == 1 => Some((name.clone(), values[0].as_str()?.to_string())),
+                _ => None,
+            })
+            .collect();
+        Self {
+            properties: variant.properties.keys().cloned().collect(),
+            required: variant.required.iter().cloned().collect(),
+            constants,
+        }
+    }
+
+    /// CEL rule requiring the required fields of this variant, and forbidding the fields of other `variants`,
+    /// when the `tag` selects this variant
+    fn rule(&self, tag: &str, variants: &[VariantFields]) -> Option<Value> {
+        let value = &self.constants.iter().find(|(name, _)| name == tag)?.1;
+        let required = self.required.iter().filter(|f| *f != tag).collect::<Vec<_>>();
+        let mut forbidden = variants
+            .iter()
+            .flat_map(|v| &v.properties)
+            .filter(|f| *f != tag && !self.properties.contains(f))
+            .collect::<Vec<_>>();
+        forbidden.sort();
+        forbidden.dedup();
+        if required.is_empty() && forbidden.is_empty() {
+            return None;
+        }
+        let conditions = required
+            .iter()
+            .map(|f| format!("has(self.{})", cel_field(f)))
+            .chain(forbidden.iter().map(|f| format!("!has(self.{})", cel_field(f))))
+            .collect::<Vec<_>>();
+        let list = |fields: &[&String]| fields.iter().map(|f| f.as_str()).collect::<Vec<_>>().join(", ");
+        let mut parts = vec![];
+        match required.len() {
+            0 => {}
+            1 => parts.push(format!("{} is required", required[0])),
+            _ => parts.push(format!("{} are required", list(&required))),
+        }
+        match forbidden.len() {
+            0 => {}
+            1 => parts.push(format!("{} is not allowed", forbidden[0])),
+            _ => parts.push(format!("{} are not allowed", list(&forbidden))),
+        }
+        let field = cel_field(tag);
+        Some(serde_json::json!({
+            "rule": format!(
+                "!has(self.{field}) || self.{field} != {value} || ({conditions})",
+                field = field,
+                value = cel_string(value),
+                conditions = conditions.join(" && "),
+            ),
+            "message": format!("when {} is {}, {}", tag, value, parts.join(" and ")),
+        }))
+    }
+}
+
+/// CEL rule restricting the tag property `tag` to the given `values`
+fn enum_rule(tag: &str, values: &[Value]) -> Value {
+    let field = cel_field(tag);
+    let allowed = values
+        .iter()
+        .filter_map(Value::as_str)
+        .map(cel_string)
+        .collect::<Vec<_>>()
+        .join(", ");
+    let names = values
+        .iter()
+        .filter_map(Value::as_str)
+        .collect::<Vec<_>>()
+        .join(", ");
+    serde_json::json!({
+        "rule": format!("!has(self.{field}) || self.{field} in [{allowed}]", field = field, allowed = allowed),
+        "message": format!("{} must be one of: {}", tag, names),
+    })
+}
+
+/// A [CEL validation rule](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#validation-rules)
+/// in the `x-kubernetes-validations` of a schema
+///
+/// This is what `#[kube(validation(...))]` field attributes of `#[derive(CustomResource)]` emit,
+/// and it can be added to hand written schemas with [`push_validation_rule`].
+///
+/// The `reason` and `field_path` are not modelled by `k8s-openapi` yet, so they are lost when a schema is converted
+/// into a `CustomResourceDefinition` (and thus by the `crd()` of `#[derive(CustomResource)]`, which rejects them).
+///
+/// ```
+/// use kube_core::schema::ValidationRule;
+/// let rule = ValidationRule::new("self.min <= self.max")
+///     .message("min must not exceed max")
+///     .reason("FieldValueInvalid")
+///     .field_path(".min");
+/// assert_eq!(serde_json::to_value(&rule).unwrap(), serde_json::json!({
+///     "rule": "self.min <= self.max",
+///     "message": "min must not exceed max",
+///     "reason": "FieldValueInvalid",
+///     "fieldPath": ".min",
+/// }));
+/// ```
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct ValidationRule {
+    /// The CEL expression, with `self` bound to the value of the schema node
+    pub rule: String,
+    /// Message returned when the rule fails
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    /// Machine readable reason of the failure, like `FieldValueInvalid` or `FieldValueForbidden` (Kubernetes >= 1.28)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Json path (relative to the schema node) of the field the failure is reported for (Kubernetes >= 1.28)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub field_path: Option<String>,
+}
+
+impl ValidationRule {
+    /// A rule with the CEL expression `rule`
+    pub fn new(rule: impl Into<String>) -> Self {
+        Self {
+            rule: rule.into(),
+            ..Self::default()
+        }
+    }
+
+    /// A rule requiring the `required` fields whenever `field` is set, like `dependentRequired` of json schema
+    ///
+    /// The rule is for the object containing the fields, and refers to them by their serialized names.
+    ///
+    /// ```
+    /// use kube_core::schema::ValidationRule;
+    /// let rule = ValidationRule::dependent_required("tlsCert", &["tlsKey"]);
+    /// assert_eq!(rule.rule, "!has(self.tlsCert) || has(self.tlsKey)");
+    /// assert_eq!(rule.message.as_deref(), Some("tlsKey is required when tlsCert is set"));
+    /// ```
+    pub fn dependent_required(field: &str, required: &[&str]) -> Self {
+        let has = required
+            .iter()
+            .map(|name| format!("has(self.{})", cel_field(name)))
+            .collect::<Vec<_>>();
+        let has = match has.as_slice() {
+            [single] => single.clone(),
+            _ => format!("({})", has.join(" && ")),
+        };
+        Self::new(format!("!has(self.{}) || {}", cel_field(field), has)).message(format!(
+            "{} {} required when {} is set",
+            required.join(", "),
+            if required.len() == 1 { "is" } else { "are" },
+            field
+        ))
+    }
+
+    /// A rule requiring exactly one of `fields` to be set, like a `oneOf` of `required` fields in json schema
+    ///
+    /// The rule is for the object containing the fields, and refers to them by their serialized names. Unlike a
+    /// `oneOf`, which crd schemas only allow in restricted forms, it counts the present fields.
+    ///
+    /// ```
+    /// use kube_core::schema::ValidationRule;
+    /// let rule = ValidationRule::one_of(&["s3", "gcs"]);
+    /// assert_eq!(rule.rule, "[has(self.s3), has(self.gcs)].filter(x, x).size() == 1");
+    /// assert_eq!(rule.message.as_deref(), Some("exactly one of s3, gcs must be set"));
+    /// ```
+    pub fn one_of(fields: &[&str]) -> Self {
+        let has = fields
+            .iter()
+            .map(|name| format!("has(self.{})", cel_field(name)))
+            .collect::<Vec<_>>();
+        Self::new(format!("[{}].filter(x, x).size() == 1", has.join(", ")))
+            .message(format!("exactly one of {} must be set", fields.join(", ")))
+    }
+
+    /// Set the message returned when the rule fails
+    #[must_use]
+    pub fn message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    /// Set the reason returned when the rule fails
+    #[must_use]
+    pub fn reason(mut self, reason: impl Into<String>) -> Self {
+        self.reason = Some(reason.into());
+        self
+    }
+
+    /// Set the path of the field that failures are reported for
+    #[must_use]
+    pub fn field_path(mut self, field_path: impl Into<String>) -> Self {
+        self.field_path = Some(field_path.into());
+        self
+    }
+}
+
+/// Inline the `$ref`s that schemars emits for recursive types, expanding them at most `max_depth` levels deep
+///
+/// Crd schemas can not contain `$ref`s, but a self-referential type (like a tree node with child nodes) can not be
+/// inlined completely as it is infinitely deep. This expands every reference to a recursive type `max_depth` times
+/// below its first occurrence, and replaces the references at that depth with an object with
+/// `x-kubernetes-preserve-unknown-fields: true`, which the apiserver does not validate further.
+/// The `definitions` of the root schema are removed.
+///
+/// This is used automatically by `kube::derive`'s `#[derive(CustomResource)]`, with the depth set by
+/// `#[kube(max_recursion_depth = 3)]`.
+pub fn inline_recursive_refs(root: &mut RootSchema, max_depth: usize) {
+    let definitions = std::mem::take(&mut root.definitions);
+    RefInliner {
+        definitions: &definitions,
+        depth: max_depth,
+    }
+    .visit_schema_object(&mut root.schema);
+}
+
+struct RefInliner<'a> {
+    definitions: &'a Map<String, Schema>,
+    /// how many more times references may be expanded on the current path
+    depth: usize,
+}
+
+impl Visitor for RefInliner<'_> {
+    fn visit_schema_object(&mut self, schema: &mut SchemaObject) {
+        let definition = match referenced(schema).and_then(|name| self.definitions.get(name)) {
+            Some(definition) => definition.clone().into_object(),
+            None => return schemars::visit::visit_schema_object(self, schema),
+        };
+        let outer = std::mem::take(schema);
+        if self.depth == 0 {
+            *schema = SchemaObject {
+                instance_type: Some(InstanceType::Object.into()),
+                ..SchemaObject::default()
+            };
+            schema
+                .extensions
+                .insert("x-kubernetes-preserve-unknown-fields".into(), true.into());
+        } else {
+            *schema = definition;
+        }
+        // a referencing field keeps its own description and `nullable`
+        if outer.metadata.as_ref().map_or(false, |m| m.description.is_some()) {
+            schema.metadata().description = outer.metadata.and_then(|m| m.description);
+        }
+        schema.extensions.extend(outer.extensions);
+        if self.depth > 0 {
+            self.depth -= 1;
+            schemars::visit::visit_schema_object(self, schema);
+            self.depth += 1;
+        }
+    }
+}
+
+/// The name of the definition a schema refers to, directly or as the only schema of an `allOf`
+fn referenced(schema: &SchemaObject) -> Option<&str> {
+    let reference = match (&schema.reference, &schema.subschemas) {
+        (Some(reference), _) => reference,
+        (None, Some(subschemas)) => match subschemas.all_of.as_deref() {
+            Some(
+                [Schema::Object(SchemaObject {
+                    reference: Some(reference),
+                    ..
+                })],
+            ) => reference,
+            _ => return None,
+        },
+        (None, None) => return None,
+    };
+    reference.rsplit('/').next()
+}
+
+/// Append a [`ValidationRule`] to the `x-kubernetes-validations` of a schema
+pub fn push_validation_rule(schema: &mut SchemaObject, rule: &ValidationRule) {
+    push_validation(schema, serde_json::to_value(rule).expect("valid validation rule"));
+}
+
+/// Append a rule to the `x-kubernetes-validations` of a schema
+pub(crate) fn push_validation(schema: &mut SchemaObject, rule: Value) {
+    let rules = schema
+        .extensions
+        .entry("x-kubernetes-validations".into())
+        .or_insert_with(|| Value::Array(vec![]));
+    if let Value::Array(rules) = rules {
+        rules.push(rule);
+    }
+}
+
+/// Schema of a list of [`Condition`]s, for `#[schemars(schema_with = "kube::core::schema::conditions_schema")]`
+///
+/// `k8s-openapi` types do not implement `JsonSchema`, so status fields of type `Vec<Condition>` need this schema.
+/// Conditions are a map list keyed by their `type`, so server-side apply merges them by type.
+/// This is used by the status type generated by `kube::derive`'s `#[kube(auto_status)]`.
+///
+/// [`Condition`]: k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition
+pub fn conditions_schema(_: &mut schemars::gen::SchemaGenerator) -> Schema {
+    serde_json::from_value(serde_json::json!({
+        "type": "array",
+        "x-kubernetes-list-type": "map",
+        "x-kubernetes-list-map-keys": ["type"],
+        "items": {
+            "type": "object",
+            "properties": {
+                "lastTransitionTime": { "type": "string", "format": "date-time" },
+                "message": { "type": "string" },
+                "observedGeneration": { "type": "integer", "format": "int64" },
+                "reason": { "type": "string" },
+                "status": { "type": "string" },
+                "type": { "type": "string" }
+            },
+            "required": ["lastTransitionTime", "message", "reason", "status", "type"]
+        }
+    }))
+    .expect("valid conditions schema")
+}
+
+/// The anchored regex of quantities like `"500m"` or `"1Gi"`, as the apiserver uses for `resource.Quantity`
+pub const QUANTITY_PATTERN: &str = r"^(\+|-)?(([0-9]+(\.[0-9]*)?)|(\.[0-9]+))(([KMGTPE]i)|[numkMGTPE]|([eE](\+|-)?(([0-9]+(\.[0-9]*)?)|(\.[0-9]+))))?$";
+
+/// Schema of a [`Quantity`], for `#[schemars(schema_with = "kube::core::schema::quantity_schema")]`
+///
+/// `k8s-openapi` types do not implement `JsonSchema`, so fields of type `Quantity` need this schema.
+/// Like the apiserver schemas of quantities, it accepts integers and strings like `"500m"` or `"1Gi"`.
+/// `kube::derive`'s `#[derive(CustomResource)]` also sets it on `Quantity` fields of the spec.
+///
+/// [`Quantity`]: k8s_openapi::apimachinery::pkg::api::resource::Quantity
+pub fn quantity_schema(_: &mut schemars::gen::SchemaGenerator) -> Schema {
+    serde_json::from_value(serde_json::json!({
+        "anyOf": [{ "type": "integer" }, { "type": "string" }],
+        "pattern": QUANTITY_PATTERN,
+        "x-kubernetes-int-or-string": true
+    }))
+    .expect("valid quantity schema")
+}
+
+/// Rewrite numeric `exclusiveMinimum` and `exclusiveMaximum` bounds into the form crds expect
+///
+/// Json schema (and thus schemars) states exclusive bounds as numbers (`exclusiveMinimum: 0`), while the openapi v3.0
+/// dialect of crds uses flags on the inclusive bounds (`minimum: 0, exclusiveMinimum: true`). Where the node already has
+/// a tighter inclusive bound (like the `minimum: 0` of unsigned integers, with `exclusiveMinimum: -5`), that is kept
+/// and the exclusive bound dropped. This rewrites every schema node of a serialized schema (literal values like `default`s and `enum`s are left alone).
+///
+/// This is used automatically by `kube::derive`'s `#[derive(CustomResource)]` when generating the crd.
+pub fn exclusive_bounds_to_openapi_v3(schema: &mut Value) {
+    let node = match schema {
+        Value::Object(node) => node,
+        Value::Array(items) => {
+            items.iter_mut().for_each(exclusive_bounds_to_openapi_v3);
+            return;
+        }
+        _ => return,
+    };
+    for (exclusive, inclusive, sign) in [
+        ("exclusiveMinimum", "minimum", 1.0),
+        ("exclusiveMaximum", "maximum", -1.0),
+    ] {
+        if let Some(bound) = node.get(exclusive).and_then(Value::as_f64) {
+            // an inclusive bound past the exclusive one (like the `minimum: 0` of unsigned integers) is tighter
+            let tighter = node
+                .get(inclusive)
+                .and_then(Value::as_f64)
+                .filter(|b| sign * (b - bound) > 0.0);
+            if tighter.is_some() {
+                node.remove(exclusive);
+            } else {
+                let bound = node[exclusive].clone();
+                node.insert(inclusive.into(), bound);
+                node.insert(exclusive.into(), Value::Bool(true));
+            }
+        }
+    }
+    for (key, value) in node.iter_mut() {
+        match key.as_str() {
+            "default" | "enum" | "example" | "x-kubernetes-validations" => {}
+            "properties" | "patternProperties" | "definitions" => {
+                if let Value::Object(schemas) = value {
+                    schemas.values_mut().for_each(exclusive_bounds_to_openapi_v3);
+                }
+            }
+            _ => exclusive_bounds_to_openapi_v3(value),
+        }
+    }
+}
+
+/// Rewrite the integer `format`s of a serialized schema to the `int32` and `int64` formats of openapi
+///
+/// schemars names formats after the Rust types (`uint32` for `u32`, `uint` for `usize`, ..), which openapi tooling does
+/// not know and may treat as `int64`. This rewrites the formats of 8 and 16 bit integers and of `i32` to `int32`, and those of
+/// `u32`, 64 bit and pointer sized integers to `int64` (`int32` is signed, so it can not hold every `u32`).
+/// The `minimum: 0` of unsigned integers is kept, and other formats are left alone.
+///
+/// This is used automatically by `kube::derive`'s `#[derive(CustomResource)]` when generating the crd.
+pub fn integer_formats_to_openapi_v3(schema: &mut Value) {
+    let node = match schema {
+        Value::Object(node) => node,
+        Value::Array(items) => {
+            items.iter_mut().for_each(integer_formats_to_openapi_v3);
+            return;
+        }
+        _ => return,
+    };
+    if node.get("type").and_then(Value::as_str) == Some("integer") {
+        let format = match node.get("format").and_then(Value::as_str) {
+            Some("int8" | "int16" | "int32" | "uint8" | "uint16") => Some("int32"),
+            Some("uint32" | "int64" | "uint64" | "int" | "uint") => Some("int64"),
+            _ => None,
+        };
+        if let Some(format) = format {
+            node.insert("format".into(), format.into());
+        }
+    }
+    for (key, value) in node.iter_mut() {
+        match key.as_str() {
+            "default" | "enum" | "example" | "x-kubernetes-validations" => {}
+            "properties" | "patternProperties" | "definitions" => {
+                if let Value::Object(schemas) = value {
+                    schemas.values_mut().for_each(integer_formats_to_openapi_v3);
+                }
+            }
+            _ => integer_formats_to_openapi_v3(value),
+        }
+    }
+}
+
+/// Set `additionalProperties: false` on the object nodes of a serialized schema, rejecting unknown fields
+///
+/// This is what `#[serde(deny_unknown_fields)]` generates, for every object with `properties` below `schema` (including
+/// `schema` itself). Objects without `properties` (like maps), with `additionalProperties` already set, or with
+/// `x-kubernetes-preserve-unknown-fields` are left alone.
+///
+/// crds do not allow `additionalProperties` next to `properties`, so this strictness only applies to the schema of
+/// [`CustomResourceExt::root_schema`](crate::CustomResourceExt::root_schema), and is removed from the crd by
+/// [`closed_objects_to_pruning`]. This is used by `kube::derive`'s `#[kube(deny_unknown_fields)]`.
+pub fn deny_unknown_fields(schema: &mut Value) {
+    let node = match schema {
+        Value::Object(node) => node,
+        _ => return,
+    };
+    let closable = node.get("properties").map_or(false, Value::is_object)
+        && !node.contains_key("additionalProperties")
+        && node.get("x-kubernetes-preserve-unknown-fields") != Some(&Value::Bool(true));
+    if closable {
+        node.insert("additionalProperties".into(), Value::Bool(false));
+    }
+    for (key, value) in node.iter_mut() {
+        match (key.as_str(), value) {
+            ("properties", Value::Object(schemas)) => schemas.values_mut().for_each(deny_unknown_fields),
+            ("allOf" | "anyOf" | "oneOf", Value::Array(schemas)) => {
+                schemas.iter_mut().for_each(deny_unknown_fields)
+            }
+            ("items" | "additionalProperties", value) => deny_unknown_fields(value),
+            _ => {}
+        }
+    }
+}
+
+/// Set the `description` of the nodes of a serialized schema from `(path, description)` pairs
+///
+/// Paths are dotted like those of [`schema_paths`](crate::crd::schema_paths), like `spec.replicas`, `spec.ports[*].name`
+/// (for the items of a list) or `spec.labels.*` (for the values of a map), and replace the existing descriptions
+/// (like those from doc comments). This is used by `kube::derive`'s `#[kube(docs = "DOCS")]`.
+///
+/// Returns the paths that are not in the schema, whose descriptions are skipped.
+pub fn apply_descriptions<'a>(schema: &mut Value, docs: &[(&'a str, &str)]) -> Vec<&'a str> {
+    let mut unknown = vec![];
+    for (path, description) in docs {
+        match description_node(schema, path) {
+            Some(Value::Object(node)) => {
+                node.insert("description".into(), (*description).into());
+            }
+            _ => unknown.push(*path),
+        }
+    }
+    unknown
+}
+
+/// The node of a serialized schema at a dotted `path` of [`apply_descriptions`]
+fn description_node<'a>(schema: &'a mut Value, path: &str) -> Option<&'a mut Value> {
+    let mut node = schema;
+    for segment in path.split('.') {
+        let (name, items) = match segment.strip_suffix("[*]") {
+            Some(name) => (name, true),
+            None => (segment, false),
+        };
+        node = match name {
+            "*" => node.get_mut("additionalProperties"),
+            name => node.get_mut("properties").and_then(|props| props.get_mut(name)),
+        }
+        .filter(|node| node.is_object())?;
+        if items {
+            node = node.get_mut("items").filter(|node| node.is_object())?;
+        }
+    }
+    Some(node)
+}
+
+/// Remove `additionalProperties: false` from the object nodes of a serialized schema that have `properties`
+///
+/// Json schema rejects unknown fields of such objects (this is what `#[serde(deny_unknown_fields)]` generates), but
+/// crds forbid setting `additionalProperties` next to `properties`. Instead, the apiserver prunes the fields of objects
+/// that are not in the schema (unless they set `x-kubernetes-preserve-unknown-fields`), and with the
+/// `fieldValidation=Strict` query parameter (`kubectl --validate=strict`), rejects requests setting them.
+///
+/// This is used automatically by `kube::derive`'s `#[derive(CustomResource)]` when generating the crd.
+pub fn closed_objects_to_pruning(schema: &mut Value) {
+    let node = match schema {
+        Value::Object(node) => node,
+        Value::Array(items) => {
+            items.iter_mut().for_each(closed_objects_to_pruning);
+            return;
+        }
+        _ => return,
+    };
+    if node.contains_key("properties") && node.get("additionalProperties") == Some(&Value::Bool(false)) {
+        node.remove("additionalProperties");
+    }
+    for (key, value) in node.iter_mut() {
+        match key.as_str() {
+            "default" | "enum" | "example" | "x-kubernetes-validations" => {}
+            "properties" | "patternProperties" | "definitions" => {
+                if let Value::Object(schemas) = value {
+                    schemas.values_mut().for_each(closed_objects_to_pruning);
+                }
+            }
+            _ => closed_objects_to_pruning(value),
+        }
+    }
+}
+
+/// Replace `uniqueItems: true` in a serialized schema with `x-kubernetes-list-type: set`
+///
+/// schemars sets `uniqueItems` on the schemas of sets (like `BTreeSet` and `HashSet`), but the apiserver rejects it in
+/// crds, as checking it is quadratic. Lists of scalars get `x-kubernetes-list-type: set` instead (unless they have a
+/// list type), which has the apiserver reject duplicate items. Other lists can not be sets, so only lose `uniqueItems`.
+///
+/// This is used automatically by `kube::derive`'s `#[derive(CustomResource)]` when generating the crd.
+pub fn unique_items_to_list_sets(schema: &mut Value) {
+    let node = match schema {
+        Value::Object(node) => node,
+        Value::Array(items) => {
+            items.iter_mut().for_each(unique_items_to_list_sets);
+            return;
+        }
+        _ => return,
+    };
+    if node.remove("uniqueItems") == Some(Value::Bool(true)) {
+        let scalar_items = node
+            .get("items")
+            .and_then(|items| items.get("type"))
+            .and_then(Value::as_str)
+            .map_or(false, |ty| {
+                ["string", "integer", "number", "boolean"].contains(&ty)
+            });
+        if scalar_items && !node.contains_key("x-kubernetes-list-type") {
+            node.insert("x-kubernetes-list-type".into(), "set".into());
+        }
+    }
+    for (key, value) in node.iter_mut() {
+        match key.as_str() {
+            "default" | "enum" | "example" | "x-kubernetes-validations" => {}
+            "properties" | "patternProperties" | "definitions" => {
+                if let Value::Object(schemas) = value {
+                    schemas.values_mut().for_each(unique_items_to_list_sets);
+                }
+            }
+            _ => unique_items_to_list_sets(value),
+        }
+    }
+}
+
+/// Check the fields of a serialized spec against the schema keywords constraining them, like the apiserver does
+///
+/// `fields` pairs the serialized names of fields with their constraints, like
+/// `("replicas", json!({"exclusiveMinimum": 0}))` (exclusive bounds can take either form). Absent and `null` fields are
+/// not checked. Besides the bounds, lengths, `multipleOf`s, `enum`s and `format`s checked by
+/// [`validate_instance`](crate::crd::validate_instance), the `pattern`s of strings are checked.
+///
+/// This is used by the `validate()` that `kube::derive`'s `#[kube(validate)]` generates on specs.
+///
+/// # Errors
+///
+/// Returns every violation, with the names of the fields as paths.
+pub fn validate_fields(spec: &Value, fields: &[(&str, Value)]) -> Result<(), Vec<FieldViolation>> {
+    let mut violations = vec![];
+    for (name, constraints) in fields {
+        let value = match spec.get(name) {
+            Some(value) if !value.is_null() => value,
+            _ => continue,
+        };
+        let mut constraints = constraints.clone();
+        exclusive_bounds_to_openapi_v3(&mut constraints);
+        let schema: JSONSchemaProps = serde_json::from_value(constraints).expect("valid constraints");
+        crate::crd::validate_value(name, &schema, value, &mut violations);
+        if let (Some(pattern), Value::String(string)) = (&schema.pattern, value) {
+            if !regex::Regex::new(pattern).map_or(true, |re| re.is_match(string)) {
+                violations.push(FieldViolation::new(
+                    name,
+                    format!(
+                        "Invalid value: {:?}: {} in body should match '{}'",
+                        string, name, pattern
+                    ),
+                ));
+            }
+        }
+    }
+    if violations.is_empty() {
+        Ok(())
+    } else {
+        Err(violations)
+    }
+}
+
+/// Escape a property name for use in a CEL expression
+///
+/// Follows the [Kubernetes escaping rules](https://kubernetes.io/docs/reference/using-api/cel/#escaping)
+pub(crate) fn cel_field(name: &str) -> String {
+    const RESERVED: &[&str] = &[
+        "true",
+        "false",
+        "null",
+        "in",
+        "as",
+        "break",
+        "const",
+        "continue",
+        "else",
+        "for",
+        "function",
+        "if",
+        "import",
+        "let",
+        "loop",
+        "package",
+        "namespace",
+        "return",
+        "var",
+        "void",
+        "while",
+    ];
+    if RESERVED.contains(&name) {
+        return format!("__{}__", name);
+    }
+    name.replace("__", "__underscores__")
+        .replace('.', "__dot__")
+        .replace('-', "__dash__")
+        .replace('/', "__slash__")
+}
+
+/// Quote a string as a CEL string literal
+pub(crate) fn cel_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
 fn only_item<I: Iterator>(mut i: I) -> Option<I::Item> {
     let item = i.next()?;
     if i.next().is_some() {
@@ -0,0 +1,146 @@
+//! The `#[kube(builder)]` of a spec struct
+use crate::field_attrs::{is_option, option_inner, serde_attr, serde_flag, serialized_name};
+use proc_macro2::{Ident, Span, TokenStream};
+use syn::{Data, DeriveInput, Fields, Path};
+
+/// Generate the `<Spec>Builder` of a spec struct, whose `build()` fills in the schema defaults of unset fields
+///
+/// `root` is the generated resource type, whose (cached) v1 crd provides the defaults.
+pub(crate) fn generate(
+    input: &DeriveInput,
+    root: &Ident,
+    kube_core: &Path,
+    serde: &Path,
+    serde_json: &Path,
+) -> Result<TokenStream, TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(error(
+                    input,
+                    "#[kube(builder)] requires a spec struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(error(
+                input,
+                "#[kube(builder)] requires a spec struct with named fields",
+            ))
+        }
+    };
+    if !input.generics.params.is_empty() {
+        return Err(error(input, "#[kube(builder)] can not be used on generic specs"));
+    }
+
+    let spec = &input.ident;
+    let vis = &input.vis;
+    let builder = Ident::new(&format!("{}Builder", spec), Span::call_site());
+    let rename_all = serde_attr(&input.attrs, "rename_all");
+    let mut builder_fields = vec![];
+    let mut setters = vec![];
+    let mut values = vec![];
+    for field in fields {
+        let name = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        if serde_flag(&field.attrs, "skip") {
+            values.push(quote! { #name: ::std::default::Default::default(), });
+            continue;
+        }
+        builder_fields.push(quote! { #name: ::std::option::Option<#ty>, });
+
+        let serialized = serialized_name(field, rename_all.as_deref());
+        let doc = format!("Set the `{}` field", serialized);
+        let setter = match option_inner(ty) {
+            Some(inner) => quote! {
+                #[doc = #doc]
+                #[must_use]
+                pub fn #name(mut self, value: impl ::std::convert::Into<#inner>) -> Self {
+                    self.#name = ::std::option::Option::Some(::std::option::Option::Some(value.into()));
+                    self
+                }
+            },
+            None => quote! {
+                #[doc = #doc]
+                #[must_use]
+                pub fn #name(mut self, value: impl ::std::convert::Into<#ty>) -> Self {
+                    self.#name = ::std::option::Option::Some(value.into());
+                    self
+                }
+            },
+        };
+        setters.push(setter);
+
+        // flattened fields have no schema of their own, so they are deserialized from an empty object
+        let missing = if serde_flag(&field.attrs, "flatten") {
+            quote! { #serde_json::from_value(#serde_json::Value::Object(#serde_json::Map::new()))? }
+        } else if is_option(ty) {
+            quote! { ::std::option::Option::None }
+        } else {
+            quote! {
+                return ::std::result::Result::Err(
+                    <#serde_json::Error as #serde::de::Error>::missing_field(#serialized)
+                )
+            }
+        };
+        values.push(quote! {
+            #name: match self.#name {
+                ::std::option::Option::Some(value) => value,
+                ::std::option::Option::None => match default(#serialized) {
+                    ::std::option::Option::Some(default) => #serde_json::from_value(default)?,
+                    ::std::option::Option::None => #missing,
+                },
+            },
+        });
+    }
+
+    let builder_doc = format!("Builder of a [`{}`], created with `{}::builder()`", spec, spec);
+    Ok(quote! {
+        #[doc = #builder_doc]
+        #[derive(Default)]
+        #vis struct #builder {
+            #(#builder_fields)*
+        }
+
+        impl #builder {
+            #(#setters)*
+
+            /// Build the spec, setting unset fields to their schema `default` (like the apiserver would)
+            ///
+            /// Unset optional fields without a default are `None`.
+            ///
+            /// # Errors
+            ///
+            /// Fails if a required field without a default is unset, or if a default does not deserialize.
+            pub fn build(self) -> ::std::result::Result<#spec, #serde_json::Error> {
+                let crd = <#root as #kube_core::crd::v1::CustomResourceExt>::crd_cached();
+                let schema = crd
+                    .spec
+                    .versions
+                    .first()
+                    .and_then(|version| version.schema.as_ref()?.open_api_v3_schema.as_ref()?.properties.as_ref()?.get("spec"));
+                let default = |name: &str| {
+                    let prop = schema?.properties.as_ref()?.get(name)?;
+                    let mut value = prop.default.as_ref()?.0.clone();
+                    #kube_core::crd::apply_schema_defaults_and_prune(prop, &mut value);
+                    ::std::option::Option::Some(value)
+                };
+                ::std::result::Result::Ok(#spec {
+                    #(#values)*
+                })
+            }
+        }
+
+        impl #spec {
+            /// A builder of this spec, filling in schema defaults of unset fields
+            #vis fn builder() -> #builder {
+                ::std::default::Default::default()
+            }
+        }
+    })
+}
+
+fn error(input: &DeriveInput, message: &str) -> TokenStream {
+    syn::Error::new_spanned(&input.ident, message).to_compile_error()
+}
@@ -1,4 +1,8 @@
-use darling::{FromDeriveInput, FromMeta};
+use crate::field_attrs;
+use darling::{
+    util::{Override, SpannedValue},
+    FromDeriveInput, FromMeta,
+};
 use proc_macro2::{Ident, Span, TokenStream};
 use syn::{parse_quote, Data, DeriveInput, Path, Visibility};
 
@@ -23,17 +27,152 @@ struct KubeAttrs {
     derives: Vec<String>,
     schema: Option<SchemaMode>,
     status: Option<String>,
+    #[darling(default)]
+    auto_status: bool,
     #[darling(multiple, rename = "category")]
     categories: Vec<String>,
     #[darling(multiple, rename = "shortname")]
-    shortnames: Vec<String>,
+    shortnames: Vec<SpannedValue<String>>,
     #[darling(multiple, rename = "printcolumn")]
     printcolums: Vec<String>,
+    #[darling(default)]
+    age_column: bool,
+    status_ready_column: Option<Override<ReadyColumn>>,
+    #[darling(multiple, rename = "selectable")]
+    selectable: Vec<String>,
     scale: Option<String>,
+    #[darling(default = "default_max_recursion_depth")]
+    max_recursion_depth: usize,
+    #[darling(default)]
+    deny_unknown_fields: bool,
+    #[darling(multiple, rename = "validation")]
+    validations: Vec<Validation>,
+    #[darling(multiple)]
+    dependent_required: Vec<SpecFields>,
+    #[darling(multiple)]
+    one_of: Vec<SpecFields>,
+    #[darling(multiple, rename = "crd_annotation")]
+    crd_annotations: Vec<KVTuple>,
+    /// `"none"` or `"webhook"` (with the service in `conversion_webhook`)
+    conversion: Option<String>,
+    /// path to a `ConversionWebhook` const
+    conversion_webhook: Option<Path>,
+    /// path to a `&[(&str, &str)]` const of field paths and their descriptions
+    docs: Option<Path>,
+    #[darling(default)]
+    aggregated: bool,
+    #[darling(default)]
+    register: bool,
+    #[darling(default)]
+    builder: bool,
+    #[darling(default)]
+    validate: bool,
     #[darling(default)]
     crates: Crates,
 }
 
+/// A `("key", "value")` pair
+#[derive(Debug)]
+struct KVTuple(String, String);
+
+impl FromMeta for KVTuple {
+    fn from_list(items: &[syn::NestedMeta]) -> darling::Result<Self> {
+        if let [syn::NestedMeta::Lit(syn::Lit::Str(key)), syn::NestedMeta::Lit(syn::Lit::Str(value))] = items
+        {
+            Ok(KVTuple(key.value(), value.value()))
+        } else {
+            Err(darling::Error::unsupported_format(
+                r#"expected `("key", "value")` with string literals"#,
+            ))
+        }
+    }
+}
+
+/// A `status_ready_column` or `status_ready_column(jsonPath = "...")`
+#[derive(Debug, Default, FromMeta)]
+struct ReadyColumn {
+    #[darling(rename = "jsonPath")]
+    json_path: Option<String>,
+}
+
+/// The `jsonPath` of the `Ready` column, the status of the `Ready` condition
+const READY_CONDITION_PATH: &str = ".status.conditions[?(@.type=='Ready')].status";
+
+/// A `validation = "rule"` on the spec, or a `validation(rule = "...", field = "spec.count", message = "...")`
+/// on the spec or one of its (nested) fields, which can also be given as `path = "spec.range"`
+#[derive(Debug)]
+struct Validation {
+    rule: String,
+    field: Option<String>,
+    message: Option<String>,
+    /// the versions the rule is emitted for (all if empty)
+    versions: Vec<String>,
+}
+
+impl FromMeta for Validation {
+    fn from_string(rule: &str) -> darling::Result<Self> {
+        Ok(Validation {
+            rule: rule.to_string(),
+            field: None,
+            message: None,
+            versions: vec![],
+        })
+    }
+
+    fn from_list(items: &[syn::NestedMeta]) -> darling::Result<Self> {
+        #[derive(FromMeta)]
+        struct List {
+            rule: String,
+            field: Option<String>,
+            /// alias of `field`, reading better for rules on objects
+            path: Option<String>,
+            message: Option<String>,
+            #[darling(multiple, rename = "version")]
+            versions: Vec<String>,
+        }
+        let List {
+            rule,
+            field,
+            path,
+            message,
+            versions,
+        } = List::from_list(items)?;
+        if field.is_some() && path.is_some() {
+            return Err(darling::Error::custom(
+                "`field` and `path` are the same, only one of them can be set",
+            ));
+        }
+        Ok(Validation {
+            rule,
+            field: field.or(path),
+            message,
+            versions,
+        })
+    }
+}
+
+/// A `(field, field, ..)` list of at least two spec field names
+#[derive(Debug)]
+struct SpecFields(Vec<syn::Ident>);
+
+impl FromMeta for SpecFields {
+    fn from_list(items: &[syn::NestedMeta]) -> darling::Result<Self> {
+        let fields = items
+            .iter()
+            .map(|item| match item {
+                syn::NestedMeta::Meta(syn::Meta::Path(path)) => path.get_ident().cloned(),
+                _ => None,
+            })
+            .collect::<Option<Vec<_>>>();
+        match fields {
+            Some(fields) if fields.len() >= 2 => Ok(SpecFields(fields)),
+            _ => Err(darling::Error::unsupported_format(
+                "expected `(field, field, ..)` with at least two spec field names",
+            )),
+        }
+    }
+}
+
 #[derive(Debug, FromMeta)]
 struct Crates {
     #[darling(default = "Self::default_kube_core")]
@@ -88,6 +227,10 @@ fn default_apiext() -> String {
     "v1".to_owned()
 }
 
+fn default_max_recursion_depth() -> usize {
+    3
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum SchemaMode {
     Disabled,
@@ -124,6 +267,21 @@ impl FromMeta for SchemaMode {
     }
 }
 
+pub(crate) fn crd_merger(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let parser = syn::punctuated::Punctuated::<syn::Type, syn::Token![,]>::parse_terminated;
+    let types = match syn::parse::Parser::parse2(parser, input) {
+        Err(err) => return err.to_compile_error(),
+        Ok(types) => types,
+    };
+    let kube_core = Crates::default_kube_core();
+    let crds = types
+        .iter()
+        .map(|ty| quote! { <#ty as #kube_core::CustomResourceExt>::crd() });
+    quote! {
+        #kube_core::crd::CrdMerger::new(::std::vec![#(#crds),*])
+    }
+}
+
 pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
     let derive_input: DeriveInput = match syn::parse2(input) {
         Err(err) => return err.to_compile_error(),
@@ -144,6 +302,10 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         Err(err) => return err.write_errors(),
         Ok(attrs) => attrs,
     };
+    let field_schemas = match field_attrs::parse(&derive_input) {
+        Err(err) => return err,
+        Ok(schemas) => schemas,
+    };
 
     let KubeAttrs {
         group,
@@ -154,13 +316,30 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         derives,
         schema: schema_mode,
         status,
+        auto_status,
         plural,
         singular,
         categories,
         shortnames,
-        printcolums,
+        mut printcolums,
+        age_column,
+        status_ready_column,
+        selectable,
         apiextensions,
         scale,
+        max_recursion_depth,
+        deny_unknown_fields,
+        validations,
+        dependent_required,
+        one_of,
+        crd_annotations,
+        conversion,
+        conversion_webhook,
+        docs,
+        aggregated,
+        register,
+        builder,
+        validate,
         crates:
             Crates {
                 kube_core,
@@ -172,6 +351,221 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
             },
     } = kube_attrs;
 
+    // The apiserver requires shortnames to be DNS-1035 labels, which kube-core checks at compile time
+    let check_shortnames = shortnames.iter().map(|shortname| {
+        let name = shortname.as_str();
+        quote::quote_spanned! {shortname.span()=>
+            const _: () = {
+                struct ShortnameMustBeLowercaseAlphanumericsOrDashesStartingWithALetter;
+                trait IsDns1035Label {}
+                impl IsDns1035Label for [ShortnameMustBeLowercaseAlphanumericsOrDashesStartingWithALetter; 0] {}
+                fn is_dns1035_label<T: IsDns1035Label>() {}
+                fn check() {
+                    is_dns1035_label::<[ShortnameMustBeLowercaseAlphanumericsOrDashesStartingWithALetter;
+                        !#kube_core::crd::is_dns1035_label(#name) as usize]>();
+                }
+            };
+        }
+    });
+    let check_shortnames = quote! { #(#check_shortnames)* };
+    let shortnames = shortnames.iter().map(|s| s.to_string()).collect::<Vec<_>>();
+
+    for path in &selectable {
+        if !(path.starts_with(".spec.") || path.starts_with(".status.")) {
+            return syn::Error::new_spanned(
+                &derive_input.ident,
+                format!(
+                    r#"#[kube(selectable = {:?})] is invalid: selectable fields must be json paths under .spec or .status"#,
+                    path
+                ),
+            )
+            .to_compile_error();
+        }
+    }
+
+    // Paths must use the serialized names of spec fields, which differ under `#[serde(rename_all)]`
+    let renamed = field_attrs::renamed_fields(&derive_input);
+    let column_paths = printcolums.iter().filter_map(|column| {
+        let column: serde_json::Value = serde_json::from_str(column).ok()?;
+        Some(("printcolumn", column.get("jsonPath")?.as_str()?.to_string()))
+    });
+    let selectable_paths = selectable.iter().map(|path| ("selectable", path.clone()));
+    for (attr, path) in column_paths.chain(selectable_paths) {
+        let field = match path.strip_prefix(".spec.") {
+            Some(rest) => rest.split(|c| c == '.' || c == '[').next().unwrap_or_default(),
+            None => continue,
+        };
+        if renamed.iter().any(|(_, serialized)| serialized == field) {
+            continue;
+        }
+        if let Some((_, serialized)) = renamed.iter().find(|(rust, _)| rust == field) {
+            return syn::Error::new_spanned(
+                &derive_input.ident,
+                format!(
+                    r#"#[kube({})] path {:?} uses the rust name of the field, but it is serialized as {:?}"#,
+                    attr, path, serialized
+                ),
+            )
+            .to_compile_error();
+        }
+    }
+
+    if register && (aggregated || apiextensions != "v1") {
+        return syn::Error::new_spanned(
+            &derive_input.ident,
+            r#"#[kube(register)] requires a v1 crd, and can not be used with #[kube(aggregated)] or #[kube(apiextensions = "v1beta1")]"#,
+        )
+        .to_compile_error();
+    }
+
+    match (conversion.as_deref(), &conversion_webhook) {
+        (None, None) | (Some("none"), None) | (Some("webhook"), Some(_)) => {}
+        _ => {
+            return syn::Error::new_spanned(
+                &derive_input.ident,
+                r#"#[kube(conversion)] must be "none", or "webhook" with the service in #[kube(conversion_webhook = "CONST")]"#,
+            )
+            .to_compile_error()
+        }
+    }
+    if conversion.is_some() && (aggregated || apiextensions != "v1") {
+        return syn::Error::new_spanned(
+            &derive_input.ident,
+            r#"#[kube(conversion)] requires a v1 crd, and can not be used with #[kube(aggregated)] or #[kube(apiextensions = "v1beta1")]"#,
+        )
+        .to_compile_error();
+    }
+
+    if builder && (aggregated || apiextensions != "v1") {
+        return syn::Error::new_spanned(
+            &derive_input.ident,
+            r#"#[kube(builder)] requires a v1 crd, and can not be used with #[kube(aggregated)] or #[kube(apiextensions = "v1beta1")]"#,
+        )
+        .to_compile_error();
+    }
+
+    if validations.iter().any(|v| v.rule.trim().is_empty()) {
+        return syn::Error::new_spanned(
+            &derive_input.ident,
+            r#"#[kube(validation = "...")] is invalid: the CEL rule must not be empty"#,
+        )
+        .to_compile_error();
+    }
+    // Field rules are placed on the schema of the field, where `self` is the value of the field
+    let field_names = field_attrs::field_names(&derive_input);
+    let fields_known = field_attrs::fields_are_known(&derive_input);
+    for validation in &validations {
+        let field = match &validation.field {
+            Some(field) => field,
+            None => continue,
+        };
+        let path = match field.strip_prefix("spec.") {
+            Some(path) if path.split('.').all(|segment| !segment.is_empty()) => path,
+            _ => {
+                return syn::Error::new_spanned(
+                    &derive_input.ident,
+                    format!(
+                        r#"#[kube(validation(field = {:?}))] is invalid: fields must be dotted paths under spec, like "spec.count""#,
+                        field
+                    ),
+                )
+                .to_compile_error()
+            }
+        };
+        let top = path.split('.').next().unwrap_or_default();
+        if let Some((_, serialized)) = renamed.iter().find(|(rust, _)| rust == top) {
+            return syn::Error::new_spanned(
+                &derive_input.ident,
+                format!(
+                    r#"#[kube(validation(field = {:?}))] uses the rust name of the field, but it is serialized as {:?}"#,
+                    field, serialized
+                ),
+            )
+            .to_compile_error();
+        }
+        if fields_known && !field_names.iter().any(|(_, serialized)| serialized == top) {
+            return syn::Error::new_spanned(
+                &derive_input.ident,
+                format!(
+                    r#"#[kube(validation(field = {:?}))] is invalid: {:?} is not a field of {}"#,
+                    field, top, derive_input.ident
+                ),
+            )
+            .to_compile_error();
+        }
+    }
+    // Rules scoped to other versions are checked above, but only emitted by the derives of their versions
+    let validations = validations
+        .into_iter()
+        .filter(|v| v.versions.is_empty() || v.versions.contains(&version));
+    let (field_validations, validations): (Vec<_>, Vec<_>) = validations.partition(|v| v.field.is_some());
+
+    // Dependencies and `one_of` groups are given as rust field names, and checked against their serialized names
+    let serialized = |attr: &str, fields: &[syn::Ident]| {
+        let mut serialized = vec![];
+        for field in fields {
+            let name = field.to_string();
+            match field_names
+                .iter()
+                .find(|(rust, _)| *rust == name.trim_start_matches("r#"))
+            {
+                Some((_, name)) => serialized.push(name.clone()),
+                None => {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        format!(
+                            "#[kube({})] field {:?} is not a field of {}",
+                            attr, name, derive_input.ident
+                        ),
+                    )
+                    .to_compile_error())
+                }
+            }
+        }
+        Ok(serialized)
+    };
+    let mut dependent_rules = vec![];
+    for SpecFields(fields) in &dependent_required {
+        let serialized = match serialized("dependent_required", fields) {
+            Ok(serialized) => serialized,
+            Err(err) => return err,
+        };
+        let (field, required) = serialized.split_first().expect("at least two fields");
+        dependent_rules.push(quote! {
+            #kube_core::schema::ValidationRule::dependent_required(#field, &[#(#required),*])
+        });
+    }
+    for SpecFields(fields) in &one_of {
+        let serialized = match serialized("one_of", fields) {
+            Ok(serialized) => serialized,
+            Err(err) => return err,
+        };
+        dependent_rules.push(quote! {
+            #kube_core::schema::ValidationRule::one_of(&[#(#serialized),*])
+        });
+    }
+
+    if auto_status && status.is_some() {
+        return syn::Error::new_spanned(
+            &derive_input.ident,
+            r#"#[kube(auto_status)] generates the status type, so it can not be used with #[kube(status = "...")]"#,
+        )
+        .to_compile_error();
+    }
+    let status = match status.as_deref().map(syn::parse_str::<Path>).transpose() {
+        Ok(status) => status,
+        Err(_) => {
+            return syn::Error::new_spanned(
+                &derive_input.ident,
+                format!(
+                    r#"#[kube(status = {:?})] is invalid: expected the path of the status type"#,
+                    status.unwrap_or_default()
+                ),
+            )
+            .to_compile_error()
+        }
+    };
+
     let struct_name = kind_struct.unwrap_or_else(|| kind.clone());
     if derive_input.ident == struct_name {
         return syn::Error::new_spanned(
@@ -180,13 +574,45 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         )
         .to_compile_error();
     }
+    // The root type refers to the spec type without generic arguments, so every parameter needs a default
+    let free_param = derive_input.generics.params.iter().find(|param| match param {
+        syn::GenericParam::Type(param) => param.default.is_none(),
+        syn::GenericParam::Const(param) => param.default.is_none(),
+        syn::GenericParam::Lifetime(_) => true,
+    });
+    if let Some(param) = free_param {
+        return syn::Error::new_spanned(
+            param,
+            "#[derive(CustomResource)] requires generic parameters of the spec to have defaults (like `T = Prod`), or a concrete newtype spec (like `struct ProdSpec(Config<Prod>);`)",
+        )
+        .to_compile_error();
+    }
+    let impl_builder = if builder {
+        let root = Ident::new(&struct_name, Span::call_site());
+        match crate::builder::generate(&derive_input, &root, &kube_core, &serde, &serde_json) {
+            Ok(tokens) => tokens,
+            Err(err) => return err,
+        }
+    } else {
+        quote! {}
+    };
+    let impl_validate = if validate {
+        field_attrs::validate_fn(&derive_input, &field_schemas, &kube_core, &serde, &serde_json)
+    } else {
+        quote! {}
+    };
     let visibility = derive_input.vis;
     let ident = derive_input.ident;
+    let auto_status = auto_status.then(|| Ident::new(&format!("{}Status", struct_name), Span::call_site()));
+    let status = match &auto_status {
+        Some(ident) => Some(syn::parse_quote! { #ident }),
+        None => status,
+    };
 
     // 1. Create root object Foo and truncate name from FooSpec
 
     // Default visibility is `pub(crate)`
-    // Default generics is no generics (makes little sense to re-use CRD kind?)
+    // Generic specs are instantiated with their defaults (makes little sense to re-use CRD kind?)
     // We enforce metadata + spec's existence (always there)
     // => No default impl
     let rootident = Ident::new(&struct_name, Span::call_site());
@@ -232,11 +658,19 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
     }
 
     // Enable schema generation by default for v1 because it's mandatory.
-    let schema_mode = schema_mode.unwrap_or(if apiextensions == "v1" {
+    // Aggregated resources have no crd, so they do not need a schema.
+    let schema_mode = schema_mode.unwrap_or(if apiextensions == "v1" && !aggregated {
         SchemaMode::Derived
     } else {
         SchemaMode::Disabled
     });
+    if docs.is_some() && !(schema_mode.use_in_crd() && apiextensions == "v1") {
+        return syn::Error::new_spanned(
+            &ident,
+            r#"#[kube(docs)] requires a v1 crd with a schema, and can not be used with #[kube(schema = "disabled")], #[kube(aggregated)] or #[kube(apiextensions = "v1beta1")]"#,
+        )
+        .to_compile_error();
+    }
     // We exclude fields `apiVersion`, `kind`, and `metadata` from our schema because
     // these are validated by the API server implicitly. Also, we can't generate the
     // schema for `metadata` (`ObjectMeta`) because it doesn't implement `JsonSchema`.
@@ -249,6 +683,39 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         derive_paths.push(syn::parse_quote! { #schemars::JsonSchema });
     }
 
+    let auto_status_obj = match &auto_status {
+        Some(status_ident) => {
+            let (schema_derive, conditions_schema) = if schema_mode.derive() {
+                let schema_with = quote! { #kube_core::schema::conditions_schema }.to_string();
+                (
+                    quote! { , #schemars::JsonSchema },
+                    quote! { #[schemars(schema_with = #schema_with)] },
+                )
+            } else {
+                (quote! {}, quote! {})
+            };
+            let docstr = format!(
+                " Auto-generated status type for {} via `#[kube(auto_status)]`",
+                rootident_str
+            );
+            quote! {
+                #[doc = #docstr]
+                #[derive(#serde::Serialize, #serde::Deserialize, Clone, Debug, Default, PartialEq #schema_derive)]
+                #[serde(rename_all = "camelCase")]
+                #visibility struct #status_ident {
+                    /// The latest observations of the state of the resource, by condition type
+                    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+                    #conditions_schema
+                    #visibility conditions: Vec<#k8s_openapi::apimachinery::pkg::apis::meta::v1::Condition>,
+                    /// The `metadata.generation` of the resource that this status was observed for
+                    #[serde(default, skip_serializing_if = "Option::is_none")]
+                    #visibility observed_generation: Option<i64>,
+                }
+            }
+        }
+        None => quote! {},
+    };
+
     let docstr = format!(" Auto-generated derived type for {} via `CustomResource`", ident);
     let root_obj = quote! {
         #[doc = #docstr]
@@ -326,6 +793,23 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         }
     };
 
+    let scope_type = if namespaced {
+        quote! { #k8s_openapi::NamespaceResourceScope }
+    } else {
+        quote! { #k8s_openapi::ClusterResourceScope }
+    };
+    let impl_static_gvk = quote! {
+        impl #kube_core::gvk::StaticGroupVersionKind for #rootident {
+            const GROUP: &'static str = #group;
+            const VERSION: &'static str = #version;
+            const KIND: &'static str = #kind;
+        }
+
+        impl #kube_core::crd::CustomResourceScope for #rootident {
+            type Scope = #scope_type;
+        }
+    };
+
     // 3. Implement Default if requested
     let impl_default = if has_default {
         quote! {
@@ -346,6 +830,35 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
     // 4. Implement CustomResource
 
     // Compute a bunch of crd props
+    if let Some(ready) = status_ready_column {
+        if status.is_none() {
+            return syn::Error::new_spanned(
+                &ident,
+                r#"#[kube(status_ready_column)] requires a status, like #[kube(status = "...")] or #[kube(auto_status)]"#,
+            )
+            .to_compile_error();
+        }
+        let json_path = ready
+            .unwrap_or_default()
+            .json_path
+            .unwrap_or_else(|| READY_CONDITION_PATH.to_string());
+        let has_ready_column = printcolums.iter().any(|column| {
+            let column = serde_json::from_str::<serde_json::Value>(column).unwrap_or_default();
+            column.get("name").and_then(|name| name.as_str()) == Some("Ready")
+        });
+        if !has_ready_column {
+            let column = serde_json::json!({ "name": "Ready", "type": "string", "jsonPath": json_path });
+            printcolums.push(column.to_string());
+        }
+    }
+    let has_age_column = printcolums.iter().any(|column| {
+        let column = serde_json::from_str::<serde_json::Value>(column).unwrap_or_default();
+        column.get("name").and_then(|name| name.as_str()) == Some("Age")
+    });
+    if age_column && !has_age_column {
+        printcolums
+            .push(r#"{"name":"Age","type":"date","jsonPath":".metadata.creationTimestamp"}"#.to_string());
+    }
     let mut printers = format!("[ {} ]", printcolums.join(",")); // hacksss
     if apiextensions == "v1beta1" {
         // only major api inconsistency..
@@ -370,22 +883,151 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         quote! { &[#names] }
     };
 
+    let impl_cached = if apiextensions == "v1" {
+        quote! {
+            fn crd_cached() -> &'static #apiext::CustomResourceDefinition {
+                static CRD: #kube_core::crd::once_cell::sync::OnceCell<#apiext::CustomResourceDefinition> =
+                    #kube_core::crd::once_cell::sync::OnceCell::new();
+                CRD.get_or_init(<Self as #extver::CustomResourceExt>::crd)
+            }
+
+            fn schema_hash() -> &'static str {
+                static HASH: #kube_core::crd::once_cell::sync::OnceCell<String> =
+                    #kube_core::crd::once_cell::sync::OnceCell::new();
+                HASH.get_or_init(|| {
+                    #kube_core::crd::schema_hash(<Self as #extver::CustomResourceExt>::crd_cached())
+                })
+            }
+        }
+    } else {
+        quote! {}
+    };
+
+    let impl_selectable_fields = if apiextensions == "v1" && !selectable.is_empty() {
+        quote! {
+            fn selectable_fields() -> &'static [&'static str] {
+                &[#(#selectable),*]
+            }
+        }
+    } else {
+        quote! {}
+    };
+
     let categories_json = serde_json::to_string(&categories).unwrap();
     let short_json = serde_json::to_string(&shortnames).unwrap();
     let crd_meta_name = format!("{}.{}", plural, group);
-    let crd_meta = quote! { { "name": #crd_meta_name } };
+    let crd_meta = if crd_annotations.is_empty() {
+        quote! { { "name": #crd_meta_name } }
+    } else {
+        let annotations = crd_annotations.iter().map(|KVTuple(k, v)| quote! { #k: #v });
+        quote! { { "name": #crd_meta_name, "annotations": { #(#annotations),* } } }
+    };
+
+    let impl_root_schema = if schema_mode.use_in_crd() && apiextensions == "v1" {
+        let schema_patches = if field_schemas.is_empty()
+            && validations.is_empty()
+            && field_validations.is_empty()
+            && dependent_rules.is_empty()
+            && !deny_unknown_fields
+            && docs.is_none()
+        {
+            quote! {}
+        } else {
+            let patches = field_attrs::schema_patches(&field_schemas, &kube_core, &serde_json);
+            let rule = |v: &Validation| {
+                let rule = &v.rule;
+                let message = v.message.iter();
+                quote! { #kube_core::schema::ValidationRule::new(#rule)#(.message(#message))* }
+            };
+            let field_rules = field_validations.iter().map(|v| {
+                let field = v.field.as_deref().unwrap_or_default();
+                let pointer = format!("/properties/{}", field.replace('.', "/properties/"));
+                let rule = rule(v);
+                quote! {
+                    let field = schema
+                        .pointer_mut(#pointer)
+                        .and_then(|s| s.as_object_mut())
+                        .expect(concat!("validation field ", #field, " is in the schema"));
+                    let rules = field
+                        .entry("x-kubernetes-validations")
+                        .or_insert_with(|| #serde_json::Value::Array(vec![]));
+                    if let Some(rules) = rules.as_array_mut() {
+                        rules.push(#serde_json::to_value(#rule).expect("valid validation rule"));
+                    }
+                }
+            });
+            let validations = validations.iter().map(rule).collect::<Vec<_>>();
+            let spec_rules = if validations.is_empty() && dependent_rules.is_empty() {
+                quote! {}
+            } else {
+                quote! {
+                    if let Some(spec) = schema.pointer_mut("/properties/spec").and_then(|s| s.as_object_mut()) {
+                        let rules = spec
+                            .entry("x-kubernetes-validations")
+                            .or_insert_with(|| #serde_json::Value::Array(vec![]));
+                        if let Some(rules) = rules.as_array_mut() {
+                            #(rules.push(#serde_json::to_value(#validations).expect("valid validation rule"));)*
+                            #(rules.push(#serde_json::to_value(#dependent_rules).expect("valid validation rule"));)*
+                        }
+                    }
+                }
+            };
+            let closed = if deny_unknown_fields {
+                quote! {
+                    if let Some(spec) = schema.pointer_mut("/properties/spec") {
+                        #kube_core::schema::deny_unknown_fields(spec);
+                    }
+                }
+            } else {
+                quote! {}
+            };
+            // misspelled (or outdated) paths fail every crd generation, so they are caught by any test
+            let descriptions = docs.iter().map(|docs| {
+                quote! {
+                    let unknown = #kube_core::schema::apply_descriptions(&mut schema, #docs);
+                    assert!(unknown.is_empty(), "#[kube(docs)] paths are not in the schema of {}: {:?}", #kind, unknown);
+                }
+            });
+            quote! {
+                let mut schema = #serde_json::to_value(&schema).expect("valid schema");
+                #patches
+                #closed
+                #spec_rules
+                #(#field_rules)*
+                #(#descriptions)*
+                let schema: #schemars::schema::RootSchema = #serde_json::from_value(schema).expect("valid schema");
+            }
+        };
+        quote! {
+            fn root_schema() -> Option<#schemars::schema::RootSchema> {
+                // Don't use definitions and don't include `$schema` because these are not allowed.
+                let gen = #schemars::gen::SchemaSettings::openapi3()
+                    .with(|s| {
+                        s.inline_subschemas = true;
+                        s.meta_schema = None;
+                    })
+                    .with_visitor(#kube_core::schema::StructuralSchemaRewriter)
+                    .into_generator();
+                let mut schema = gen.into_root_schema_for::<Self>();
+                #kube_core::schema::inline_recursive_refs(&mut schema, #max_recursion_depth);
+                #schema_patches
+                Some(schema)
+            }
+        }
+    } else {
+        quote! {}
+    };
 
     let schemagen = if schema_mode.use_in_crd() {
         quote! {
-            // Don't use definitions and don't include `$schema` because these are not allowed.
-            let gen = #schemars::gen::SchemaSettings::openapi3()
-                .with(|s| {
-                    s.inline_subschemas = true;
-                    s.meta_schema = None;
-                })
-                .with_visitor(#kube_core::schema::StructuralSchemaRewriter)
-                .into_generator();
-            let schema = gen.into_root_schema_for::<Self>();
+            let schema = <Self as #extver::CustomResourceExt>::root_schema().map(|schema| {
+                let mut schema = #serde_json::to_value(&schema).expect("valid schema");
+                #kube_core::schema::exclusive_bounds_to_openapi_v3(&mut schema);
+                #kube_core::schema::integer_formats_to_openapi_v3(&mut schema);
+                #kube_core::schema::closed_objects_to_pruning(&mut schema);
+                #kube_core::schema::unique_items_to_list_sets(&mut schema);
+                schema
+            });
         }
     } else {
         // we could issue a compile time warning for this, but it would hit EVERY compile, which would be noisy
@@ -453,59 +1095,120 @@ pub(crate) fn derive(input: proc_macro2::TokenStream) -> proc_macro2::TokenStrea
         }
     };
 
+    let set_conversion = match (conversion.as_deref(), conversion_webhook) {
+        (Some("none"), _) => quote! {
+            crd.spec.conversion = Some(#apiext::CustomResourceConversion {
+                strategy: "None".into(),
+                webhook: None,
+            });
+        },
+        (_, Some(webhook)) => quote! {
+            crd.spec.conversion = Some(#kube_core::crd::ConversionWebhook::conversion(&#webhook));
+        },
+        _ => quote! {},
+    };
+
     // Implement the CustomResourceExt trait to allow users writing generic logic on top of them
-    let impl_crd = quote! {
-        impl #extver::CustomResourceExt for #rootident {
-
-            fn crd() -> #apiext::CustomResourceDefinition {
-                let columns : Vec<#apiext::CustomResourceColumnDefinition> = #serde_json::from_str(#printers).expect("valid printer column json");
-                let scale: Option<#apiext::CustomResourceSubresourceScale> = if #scale_code.is_empty() {
-                    None
-                } else {
-                    #serde_json::from_str(#scale_code).expect("valid scale subresource json")
-                };
-                let categories: Vec<String> = #serde_json::from_str(#categories_json).expect("valid categories");
-                let shorts : Vec<String> = #serde_json::from_str(#short_json).expect("valid shortnames");
-                let subres = if #has_status {
-                    if let Some(s) = &scale {
-                        #serde_json::json!({
-                            "status": {},
-                            "scale": scale
-                        })
-                    } else {
-                        #serde_json::json!({"status": {} })
-                    }
-                } else {
-                    #serde_json::json!({})
-                };
+    // Aggregated resources are served by an APIService rather than a crd, so only keep the discovery helpers
+    let impl_crd = if aggregated {
+        quote! {
+            impl #rootident {
+                /// Helper to generate the api information type for use with the dynamic `Api`
+                pub fn api_resource() -> #kube_core::dynamic::ApiResource {
+                    #kube_core::dynamic::ApiResource::erase::<Self>(&())
+                }
 
-                #jsondata
-                #serde_json::from_value(jsondata)
-                    .expect("valid custom resource from #[kube(attrs..)]")
+                /// Shortnames of this resource type, as declared by `#[kube(shortname = "...")]`
+                pub fn shortnames() -> &'static [&'static str] {
+                    #shortnames_slice
+                }
             }
+        }
+    } else {
+        quote! {
+            impl #extver::CustomResourceExt for #rootident {
 
-            fn crd_name() -> &'static str {
-                #crd_meta_name
-            }
+                fn crd() -> #apiext::CustomResourceDefinition {
+                    let columns : Vec<#apiext::CustomResourceColumnDefinition> = #serde_json::from_str(#printers).expect("valid printer column json");
+                    let scale: Option<#apiext::CustomResourceSubresourceScale> = if #scale_code.is_empty() {
+                        None
+                    } else {
+                        #serde_json::from_str(#scale_code).expect("valid scale subresource json")
+                    };
+                    let categories: Vec<String> = #serde_json::from_str(#categories_json).expect("valid categories");
+                    let shorts : Vec<String> = #serde_json::from_str(#short_json).expect("valid shortnames");
+                    let subres = if #has_status {
+                        if let Some(s) = &scale {
+                            #serde_json::json!({
+                                "status": {},
+                                "scale": scale
+                            })
+                        } else {
+                            #serde_json::json!({"status": {} })
+                        }
+                    } else {
+                        #serde_json::json!({})
+                    };
+
+                    #jsondata
+                    #[allow(unused_mut)]
+                    let mut crd: #apiext::CustomResourceDefinition = #serde_json::from_value(jsondata)
+                        .expect("valid custom resource from #[kube(attrs..)]");
+                    #set_conversion
+                    crd
+                }
+
+                #impl_cached
 
-            fn api_resource() -> #kube_core::dynamic::ApiResource {
-                #kube_core::dynamic::ApiResource::erase::<Self>(&())
+                fn crd_name() -> &'static str {
+                    #crd_meta_name
+                }
+
+                fn crd_kind() -> &'static str {
+                    #kind
+                }
+
+                fn api_resource() -> #kube_core::dynamic::ApiResource {
+                    #kube_core::dynamic::ApiResource::erase::<Self>(&())
+                }
+
+                fn shortnames() -> &'static [&'static str] {
+                    #shortnames_slice
+                }
+
+                #impl_selectable_fields
+                #impl_root_schema
             }
+        }
+    };
 
-            fn shortnames() -> &'static [&'static str] {
-                #shortnames_slice
+    let impl_register = if register {
+        quote! {
+            #kube_core::crd::inventory::submit! {
+                #kube_core::crd::RegisteredCrd::new(<#rootident as #extver::CustomResourceExt>::crd)
             }
         }
+    } else {
+        quote! {}
     };
 
+    let field_checks = field_attrs::compile_checks(&field_schemas);
+
     let impl_hasspec = generate_hasspec(&ident, &rootident, &kube_core);
 
     // Concat output
     quote! {
         #root_obj
+        #auto_status_obj
         #impl_resource
+        #impl_static_gvk
         #impl_default
         #impl_crd
+        #impl_register
+        #impl_builder
+        #impl_validate
+        #check_shortnames
+        #field_checks
         #impl_hasspec
         #impl_hasstatus
     }
@@ -559,12 +1262,11 @@ struct StatusInformation {
 /// returns: A `StatusInformation` struct
 fn process_status(
     root_ident: &Ident,
-    status: &Option<String>,
+    status: &Option<Path>,
     visibility: &Visibility,
     kube_core: &Path,
 ) -> StatusInformation {
-    if let Some(status_name) = &status {
-        let ident = format_ident!("{}", status_name);
+    if let Some(ident) = &status {
         StatusInformation {
             field: quote! {
                 #[serde(skip_serializing_if = "Option::is_none")]
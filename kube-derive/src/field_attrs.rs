@@ -0,0 +1,1053 @@
+//! Field level `#[kube(attrs)]` that customize the generated schema of spec fields
+use darling::{util::Override, FromField, FromMeta};
+use proc_macro2::{Ident, TokenStream};
+use syn::{
+    spanned::Spanned, Attribute, Data, DeriveInput, Field, Fields, GenericArgument, Lit, Meta, NestedMeta,
+    Path, PathArguments, Type,
+};
+
+/// Values we can parse from #[kube(attrs)] on spec fields
+#[derive(Debug, FromField)]
+#[darling(attributes(kube))]
+struct KubeFieldAttrs {
+    /// replacement schema of the field (`"byte"` for base64 encoded binary data, `"duration"` for duration strings,
+    /// `"quantity"` for quantities, which is the default of `Quantity` fields, `"date"` for calendar dates, which is
+    /// the default of `NaiveDate` fields, and `"date-time"` for timestamps)
+    schema: Option<String>,
+    /// function returning the `JSONSchemaProps` replacing the schema of the field
+    schema_with: Option<Path>,
+    /// `format` of an integer field (`"int32"` or `"int64"`), overriding the one derived from its type
+    schema_format: Option<String>,
+    /// `title` of the field, for tools rendering the schema
+    title: Option<String>,
+    /// regex the string value must match
+    pattern: Option<String>,
+    /// name of a common format whose anchored regex is emitted as the `pattern` (see [`FORMAT_PATTERNS`])
+    format_pattern: Option<String>,
+    /// name of a format the apiserver validates, emitted as the `format` and the anchored regex `pattern`
+    format: Option<String>,
+    /// `minLength` of a string field
+    min_length: Option<u64>,
+    /// `maxLength` of a string field
+    max_length: Option<u64>,
+    /// `minItems` of an array field
+    min_items: Option<u64>,
+    /// `maxItems` of an array field
+    max_items: Option<u64>,
+    /// `minItems: 1` on an optional array field, so an empty array can not be sent in place of an absent one
+    #[darling(default)]
+    treat_empty_as_absent: bool,
+    /// exclusive lower bound of a numeric field
+    exclusive_minimum: Option<JsonNumber>,
+    /// exclusive upper bound of a numeric field
+    exclusive_maximum: Option<JsonNumber>,
+    /// `nullable: true` (or no `nullable` with `nullable = false`), regardless of whether the field is an `Option`
+    nullable: Option<bool>,
+    /// `multipleOf` of a numeric field
+    multiple_of: Option<JsonNumber>,
+    /// `x-kubernetes-map-type` of an object field
+    map_type: Option<String>,
+    /// json literal (or plain string) emitted as the schema `default`
+    default: Option<String>,
+    /// json literal (or plain string) emitted as the schema `example`
+    example: Option<String>,
+    /// json literal (or plain string) the field must equal, emitted as a single value `enum`
+    const_value: Option<String>,
+    /// `uniqueItems: true` on an array field
+    #[darling(default)]
+    unique_items: bool,
+    /// `x-kubernetes-list-type` of an array field
+    list_type: Option<String>,
+    /// `x-kubernetes-list-map-keys` of an array field with `list_type = "map"`
+    #[darling(multiple)]
+    list_map_key: Vec<String>,
+    /// marks the field deprecated in its `description`, with an optional message
+    deprecated_field: Option<Override<String>>,
+    /// CEL rules appended to the `x-kubernetes-validations` of the field
+    #[darling(multiple, rename = "validation")]
+    validations: Vec<ValidationAttr>,
+    /// cargo feature of the crate deriving the resource without which the field is removed from the schema
+    feature: Option<String>,
+}
+
+/// A `#[kube(validation(rule = "...", message = "..."))]` field attribute
+#[derive(Debug, FromMeta)]
+struct ValidationAttr {
+    rule: String,
+    message: Option<String>,
+    /// rejected, as k8s-openapi can not hold it
+    reason: Option<String>,
+    /// rejected, as k8s-openapi can not hold it
+    field_path: Option<String>,
+}
+
+/// A number literal (or a string containing one, for negative numbers)
+#[derive(Debug)]
+struct JsonNumber(serde_json::Number);
+
+impl FromMeta for JsonNumber {
+    fn from_string(value: &str) -> darling::Result<Self> {
+        match serde_json::from_str(value) {
+            Ok(serde_json::Value::Number(n)) => Ok(JsonNumber(n)),
+            _ => Err(darling::Error::unknown_value(value)),
+        }
+    }
+
+    fn from_value(value: &Lit) -> darling::Result<Self> {
+        match value {
+            Lit::Str(s) => Self::from_string(&s.value()),
+            Lit::Int(i) => Self::from_string(i.base10_digits()),
+            Lit::Float(f) => Self::from_string(f.base10_digits()),
+            _ => Err(darling::Error::unexpected_lit_type(value)),
+        }
+        .map_err(|err| err.with_span(value))
+    }
+}
+
+/// The anchored regex of go duration strings like `"1h30m"`, as parsed by `time.ParseDuration`
+const DURATION_PATTERN: &str = r"^(0|-?([0-9]+(\.[0-9]+)?(ns|us|µs|ms|s|m|h))+)$";
+
+/// The anchored regexes of the `format_pattern`s
+const FORMAT_PATTERNS: &[(&str, &str)] = &[
+    ("dns1123-label", "^[a-z0-9]([-a-z0-9]*[a-z0-9])?$"),
+    (
+        "dns1123-subdomain",
+        r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?(\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*$",
+    ),
+    (
+        "uuid",
+        "^[0-9a-fA-F]{8}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{4}-[0-9a-fA-F]{12}$",
+    ),
+    (
+        "ipv4",
+        r"^((25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])\.){3}(25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])$",
+    ),
+    (
+        "ipv6",
+        r"^(([0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|(([0-9a-fA-F]{1,4}:){0,6}[0-9a-fA-F]{1,4})?::(([0-9a-fA-F]{1,4}:){0,6}[0-9a-fA-F]{1,4})?)$",
+    ),
+    (
+        "cidr",
+        r"^(((25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])\.){3}(25[0-5]|2[0-4][0-9]|1[0-9][0-9]|[1-9]?[0-9])/(3[0-2]|[12]?[0-9])|(([0-9a-fA-F]{1,4}:){7}[0-9a-fA-F]{1,4}|(([0-9a-fA-F]{1,4}:){0,6}[0-9a-fA-F]{1,4})?::(([0-9a-fA-F]{1,4}:){0,6}[0-9a-fA-F]{1,4})?)/(12[0-8]|1[01][0-9]|[1-9]?[0-9]))$",
+    ),
+    ("url", r"^https?://[^\s/?#]+([/?#]\S*)?$"),
+    (
+        "semver",
+        r"^(0|[1-9][0-9]*)\.(0|[1-9][0-9]*)\.(0|[1-9][0-9]*)(-[0-9A-Za-z-]+(\.[0-9A-Za-z-]+)*)?(\+[0-9A-Za-z-]+(\.[0-9A-Za-z-]+)*)?$",
+    ),
+];
+
+/// The `format_pattern`s that are also `format`s the apiserver validates
+const FORMATS: &[&str] = &["ipv4", "ipv6", "cidr", "uuid", "url"];
+
+/// Schema keywords to set on the schema of a named (serialized) spec field
+pub(crate) struct FieldSchema {
+    name: String,
+    /// keywords to remove before setting `keywords`
+    removed: Vec<&'static str>,
+    keywords: Vec<(&'static str, serde_json::Value)>,
+    /// keywords set to the value of a const of `kube_core::schema`, by name
+    const_keywords: Vec<(&'static str, &'static str)>,
+    validations: Vec<ValidationAttr>,
+    /// function returning the `JSONSchemaProps` replacing the schema of the field
+    schema_fn: Option<Path>,
+    /// the `Deprecated: ...` marker to prefix the `description` with
+    deprecated: Option<String>,
+    /// whether to remove the field from `required`, as it is not always serialized
+    optional: bool,
+    /// the cargo feature without which the field is removed from the schema
+    feature: Option<String>,
+    /// code that fails to compile if a field the schema depends on does not exist
+    checks: TokenStream,
+}
+
+/// Parse the field level `#[kube(attrs)]` of a spec struct
+pub(crate) fn parse(input: &DeriveInput) -> Result<Vec<FieldSchema>, TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return Ok(vec![]),
+        },
+        _ => return Ok(vec![]),
+    };
+    let rename_all = serde_attr(&input.attrs, "rename_all");
+    let mut schemas = vec![];
+    for field in fields {
+        let attrs = KubeFieldAttrs::from_field(field).map_err(|err| err.write_errors())?;
+        let mut keywords = vec![];
+        let mut const_keywords = vec![];
+        let mut removed = vec![];
+        if attrs.schema.is_some() && attrs.schema_with.is_some() {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`schema` and `schema_with` can not be used together",
+            )
+            .to_compile_error());
+        }
+        // the whole schema is replaced when generating the crd
+        let schema_fn = attrs.schema_with;
+        let schema = attrs.schema.or_else(|| {
+            if schema_fn.is_some() {
+                None
+            } else if is_quantity(&field.ty) {
+                Some("quantity".to_string())
+            } else if is_date(&field.ty) {
+                Some("date".to_string())
+            } else {
+                None
+            }
+        });
+        if let Some(schema) = schema {
+            let replacement = match schema.as_str() {
+                "byte" => vec![("type", "string".into()), ("format", "byte".into())],
+                "duration" => vec![("type", "string".into()), ("pattern", DURATION_PATTERN.into())],
+                "date" => vec![("type", "string".into()), ("format", "date".into())],
+                "date-time" => vec![("type", "string".into()), ("format", "date-time".into())],
+                "quantity" => {
+                    const_keywords.push(("pattern", "QUANTITY_PATTERN"));
+                    vec![
+                        (
+                            "anyOf",
+                            serde_json::json!([{ "type": "integer" }, { "type": "string" }]),
+                        ),
+                        ("x-kubernetes-int-or-string", true.into()),
+                    ]
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        format!(
+                            r#"`schema` must be "byte", "duration", "quantity", "date" or "date-time", found {:?}"#,
+                            schema
+                        ),
+                    )
+                    .to_compile_error())
+                }
+            };
+            // keep `nullable` of optional fields
+            removed.extend([
+                "type",
+                "items",
+                "format",
+                "pattern",
+                "minimum",
+                "maximum",
+                "minItems",
+                "maxItems",
+                "properties",
+                "required",
+                "additionalProperties",
+                "allOf",
+                "anyOf",
+                "oneOf",
+                "$ref",
+            ]);
+            keywords.extend(replacement);
+        }
+        if let Some(format) = attrs.schema_format {
+            if json_type(&field.ty) != Some("integer") {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`schema_format` can only be used on integer fields",
+                )
+                .to_compile_error());
+            }
+            if format != "int32" && format != "int64" {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    format!(
+                        r#"`schema_format` must be "int32" or "int64", found {:?}"#,
+                        format
+                    ),
+                )
+                .to_compile_error());
+            }
+            keywords.push(("format", format.into()));
+        }
+        if let Some(title) = attrs.title {
+            keywords.push(("title", title.into()));
+        }
+        if let Some(format) = attrs.format_pattern {
+            if attrs.pattern.is_some() || json_type(&field.ty) != Some("string") {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`format_pattern` can only be used on string fields without a `pattern`",
+                )
+                .to_compile_error());
+            }
+            let names = FORMAT_PATTERNS.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            keywords.push((
+                "pattern",
+                format_pattern(field, "format_pattern", &format, &names)?.into(),
+            ));
+        }
+        if let Some(format) = attrs.format {
+            if attrs.pattern.is_some() || json_type(&field.ty) != Some("string") {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`format` can only be used on string fields without a `pattern`",
+                )
+                .to_compile_error());
+            }
+            keywords.push((
+                "pattern",
+                format_pattern(field, "format", &format, FORMATS)?.into(),
+            ));
+            // the apiserver calls the format of urls `uri`
+            let format = if format == "url" { "uri".into() } else { format };
+            keywords.push(("format", format.into()));
+        }
+        if let Some(pattern) = attrs.pattern {
+            if let Err(err) = regex::Regex::new(&pattern) {
+                return Err(
+                    syn::Error::new_spanned(field, format!("invalid `pattern` regex: {}", err))
+                        .to_compile_error(),
+                );
+            }
+            keywords.push(("pattern", pattern.into()));
+        }
+        if attrs.min_length.is_some() || attrs.max_length.is_some() {
+            if json_type(&field.ty) != Some("string") {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`min_length` and `max_length` can only be used on string fields",
+                )
+                .to_compile_error());
+            }
+            if let (Some(min), Some(max)) = (attrs.min_length, attrs.max_length) {
+                if min > max {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        format!("`min_length` {} is greater than `max_length` {}", min, max),
+                    )
+                    .to_compile_error());
+                }
+            }
+        }
+        if let Some(min_length) = attrs.min_length {
+            keywords.push(("minLength", min_length.into()));
+        }
+        if let Some(max_length) = attrs.max_length {
+            keywords.push(("maxLength", max_length.into()));
+        }
+        if attrs.min_items.is_some() || attrs.max_items.is_some() {
+            if json_type(&field.ty) != Some("array") {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`min_items` and `max_items` can only be used on array fields (like `Vec<T>`)",
+                )
+                .to_compile_error());
+            }
+            if let (Some(min), Some(max)) = (attrs.min_items, attrs.max_items) {
+                if min > max {
+                    return Err(syn::Error::new_spanned(
+                        field,
+                        format!("`min_items` {} is greater than `max_items` {}", min, max),
+                    )
+                    .to_compile_error());
+                }
+            }
+        }
+        if let Some(min_items) = attrs.min_items {
+            keywords.push(("minItems", min_items.into()));
+        }
+        if let Some(max_items) = attrs.max_items {
+            keywords.push(("maxItems", max_items.into()));
+        }
+        if attrs.treat_empty_as_absent {
+            if !is_option(&field.ty) || json_type(&field.ty) != Some("array") || attrs.min_items.is_some() {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`treat_empty_as_absent` can only be used on optional array fields (like `Option<Vec<T>>`) without `min_items`",
+                )
+                .to_compile_error());
+            }
+            keywords.push(("minItems", 1.into()));
+        }
+        if (attrs.exclusive_minimum.is_some() || attrs.exclusive_maximum.is_some())
+            && !matches!(json_type(&field.ty), Some("integer" | "number"))
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`exclusive_minimum` and `exclusive_maximum` can only be used on numeric fields",
+            )
+            .to_compile_error());
+        }
+        // These use the json schema form, which `crd()` rewrites to the openapi v3.0 form
+        if let Some(JsonNumber(min)) = attrs.exclusive_minimum {
+            keywords.push(("exclusiveMinimum", min.into()));
+        }
+        if let Some(JsonNumber(max)) = attrs.exclusive_maximum {
+            keywords.push(("exclusiveMaximum", max.into()));
+        }
+        if let Some(JsonNumber(multiple_of)) = attrs.multiple_of {
+            if !matches!(json_type(&field.ty), Some("integer" | "number")) {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`multiple_of` can only be used on numeric fields",
+                )
+                .to_compile_error());
+            }
+            if multiple_of.as_f64().map_or(true, |m| m <= 0.0) {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    format!("`multiple_of` must be positive, found {}", multiple_of),
+                )
+                .to_compile_error());
+            }
+            keywords.push(("multipleOf", multiple_of.into()));
+        }
+        match attrs.nullable {
+            Some(true) => keywords.push(("nullable", true.into())),
+            Some(false) => removed.push("nullable"),
+            None => {}
+        }
+        if let Some(map_type) = attrs.map_type {
+            if map_type != "granular" && map_type != "atomic" {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    format!(
+                        r#"`map_type` must be "granular" or "atomic", found {:?}"#,
+                        map_type
+                    ),
+                )
+                .to_compile_error());
+            }
+            keywords.push(("x-kubernetes-map-type", map_type.into()));
+        }
+        if let Some(default) = attrs.default {
+            keywords.push(("default", json_literal(field, "default", default)?));
+        }
+        if let Some(example) = attrs.example {
+            keywords.push(("example", json_literal(field, "example", example)?));
+        }
+        // crds do not support `const`, so pin the value with an `enum`
+        if let Some(const_value) = attrs.const_value {
+            let value = json_literal(field, "const_value", const_value)?;
+            keywords.push(("enum", vec![value].into()));
+        }
+        if (attrs.unique_items || attrs.list_type.is_some() || !attrs.list_map_key.is_empty())
+            && json_type(&field.ty) != Some("array")
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`unique_items`, `list_type` and `list_map_key` can only be used on array fields (like `Vec<T>`)",
+            )
+            .to_compile_error());
+        }
+        if (attrs.list_type.as_deref() == Some("map")) == attrs.list_map_key.is_empty() {
+            return Err(syn::Error::new_spanned(
+                field,
+                r#"`list_type = "map"` requires at least one `list_map_key`, which can only be used with it"#,
+            )
+            .to_compile_error());
+        }
+        if attrs.unique_items {
+            keywords.push(("uniqueItems", true.into()));
+        }
+        if let Some(list_type) = attrs.list_type {
+            if !["atomic", "set", "map"].contains(&list_type.as_str()) {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    format!(
+                        r#"`list_type` must be "atomic", "set" or "map", found {:?}"#,
+                        list_type
+                    ),
+                )
+                .to_compile_error());
+            }
+            keywords.push(("x-kubernetes-list-type", list_type.into()));
+        }
+        // The item type is only known by name here, so have the compiler check that it has the key fields
+        let mut checks = TokenStream::new();
+        if let Some(item) = item_type(&field.ty) {
+            for key in &attrs.list_map_key {
+                let ident = syn::parse_str::<Ident>(&to_snake_case(key)).map_err(|_| {
+                    syn::Error::new_spanned(field, format!("invalid `list_map_key` {:?}", key))
+                        .to_compile_error()
+                })?;
+                let ident = Ident::new(&ident.to_string(), field.span());
+                checks.extend(quote_spanned! {field.span()=>
+                    const _: fn(&#item) = |item| {
+                        let _ = &item.#ident;
+                    };
+                });
+            }
+        }
+        if !attrs.list_map_key.is_empty() {
+            keywords.push(("x-kubernetes-list-map-keys", attrs.list_map_key.clone().into()));
+        }
+        // k8s-openapi does not model them yet, so the crd would silently lose them
+        if attrs
+            .validations
+            .iter()
+            .any(|v| v.reason.is_some() || v.field_path.is_some())
+        {
+            return Err(syn::Error::new_spanned(
+                field,
+                "`reason` and `field_path` of `validation` are not supported, as k8s-openapi does not model them yet and `crd()` would drop them",
+            )
+            .to_compile_error());
+        }
+        let deprecated = attrs.deprecated_field.map(|message| {
+            let message = message.unwrap_or_else(|| "this field may be removed in a future version".into());
+            format!("Deprecated: {}", message)
+        });
+        // schemars requires these fields even though serializing omits them, so the apiserver would reject the object
+        let optional = serde_attr(&field.attrs, "skip_serializing_if").is_some()
+            || serde_flag(&field.attrs, "skip_serializing");
+        if serde_flag(&field.attrs, "skip") {
+            if !keywords.is_empty()
+                || !removed.is_empty()
+                || schema_fn.is_some()
+                || !attrs.validations.is_empty()
+                || deprecated.is_some()
+                || !checks.is_empty()
+                || attrs.feature.is_some()
+            {
+                return Err(syn::Error::new_spanned(
+                    field,
+                    "`#[serde(skip)]` fields are not part of the schema, so they cannot have schema attributes",
+                )
+                .to_compile_error());
+            }
+            continue;
+        }
+        if keywords.is_empty()
+            && removed.is_empty()
+            && schema_fn.is_none()
+            && attrs.validations.is_empty()
+            && deprecated.is_none()
+            && checks.is_empty()
+            && !optional
+            && attrs.feature.is_none()
+        {
+            continue;
+        }
+        let name = serialized_name(field, rename_all.as_deref());
+        schemas.push(FieldSchema {
+            name,
+            removed,
+            keywords,
+            const_keywords,
+            validations: attrs.validations,
+            schema_fn,
+            deprecated,
+            optional,
+            feature: attrs.feature,
+            checks,
+        });
+    }
+    Ok(schemas)
+}
+
+/// The rust and serialized names of the fields of a spec struct whose serialized name differs
+pub(crate) fn renamed_fields(input: &DeriveInput) -> Vec<(String, String)> {
+    field_names(input)
+        .into_iter()
+        .filter(|(rust, serialized)| rust != serialized)
+        .collect()
+}
+
+/// The rust and serialized names of all named fields of a spec struct, except `#[serde(skip)]` fields
+pub(crate) fn field_names(input: &DeriveInput) -> Vec<(String, String)> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => return vec![],
+        },
+        _ => return vec![],
+    };
+    let rename_all = serde_attr(&input.attrs, "rename_all");
+    fields
+        .iter()
+        .filter(|field| !serde_flag(&field.attrs, "skip"))
+        .map(|field| (rust_name(field), serialized_name(field, rename_all.as_deref())))
+        .collect()
+}
+
+/// Whether the serialized fields of a spec are all in [`field_names`]: it is a struct with named fields, none flattened
+pub(crate) fn fields_are_known(input: &DeriveInput) -> bool {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => !fields
+                .named
+                .iter()
+                .any(|field| serde_flag(&field.attrs, "flatten")),
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+fn rust_name(field: &Field) -> String {
+    let ident = field.ident.as_ref().expect("named field").to_string();
+    ident.trim_start_matches("r#").to_string()
+}
+
+/// The name of `field` after `#[serde(rename)]` or the container's `#[serde(rename_all)]`
+pub(crate) fn serialized_name(field: &Field, rename_all: Option<&str>) -> String {
+    serde_attr(&field.attrs, "rename").unwrap_or_else(|| apply_rename_all(&rust_name(field), rename_all))
+}
+
+/// The json type of the schema of `ty`, if it is a (possibly optional) primitive or collection that is known by name
+fn json_type(ty: &Type) -> Option<&'static str> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    if segment.ident == "Option" {
+        if let PathArguments::AngleBracketed(args) = &segment.arguments {
+            if let Some(GenericArgument::Type(inner)) = args.args.first() {
+                return json_type(inner);
+            }
+        }
+        return None;
+    }
+    match segment.ident.to_string().as_str() {
+        "String" => Some("string"),
+        "bool" => Some("boolean"),
+        "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => Some("integer"),
+        "f32" | "f64" => Some("number"),
+        "Vec" | "VecDeque" | "HashSet" | "BTreeSet" => Some("array"),
+        _ => None,
+    }
+}
+
+/// Whether the type is an `Option`
+pub(crate) fn is_option(ty: &Type) -> bool {
+    option_inner(ty).is_some()
+}
+
+/// The `T` of an `Option<T>`
+pub(crate) fn option_inner(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) if segment.ident == "Option" => match args.args.first()? {
+            GenericArgument::Type(inner) => Some(inner),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Whether the type is a (possibly optional) `k8s_openapi::apimachinery::pkg::api::resource::Quantity`
+fn is_quantity(ty: &Type) -> bool {
+    is_path(ty, "k8s_openapi::apimachinery::pkg::api::resource::Quantity")
+}
+
+/// Whether the type is a (possibly optional) `chrono::NaiveDate`
+///
+/// Unlike `DateTime`s, these serialize without a time, so they need `format: date` rather than `date-time`.
+fn is_date(ty: &Type) -> bool {
+    is_path(ty, "chrono::NaiveDate")
+}
+
+/// Whether the (possibly optional) type is named by a trailing part of `path`
+///
+/// Imports can not be resolved here, so a bare `Quantity` is taken to be the one of `path`, while an `other::Quantity`
+/// is not.
+fn is_path(ty: &Type, path: &str) -> bool {
+    if let Some(inner) = option_inner(ty) {
+        return is_path(inner, path);
+    }
+    let segments = match ty {
+        Type::Path(ty) if ty.qself.is_none() => &ty.path.segments,
+        _ => return false,
+    };
+    let path = path.split("::").collect::<Vec<_>>();
+    !segments.is_empty()
+        && segments.len() <= path.len()
+        && segments
+            .iter()
+            .rev()
+            .zip(path.iter().rev())
+            .all(|(segment, name)| segment.ident == name && segment.arguments.is_empty())
+}
+
+/// The item type of a (possibly optional) `Vec` or `VecDeque`
+fn item_type(ty: &Type) -> Option<&Type> {
+    let segment = match ty {
+        Type::Path(path) if path.qself.is_none() => path.path.segments.last()?,
+        _ => return None,
+    };
+    let inner = match &segment.arguments {
+        PathArguments::AngleBracketed(args) => match args.args.first()? {
+            GenericArgument::Type(inner) => inner,
+            _ => return None,
+        },
+        _ => return None,
+    };
+    match segment.ident.to_string().as_str() {
+        "Option" => item_type(inner),
+        "Vec" | "VecDeque" => Some(inner),
+        _ => None,
+    }
+}
+
+/// Convert a camelCase (serialized) name to the snake_case name of the rust field
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for c in name.chars() {
+        if c.is_ascii_uppercase() {
+            if !snake.is_empty() {
+                snake.push('_');
+            }
+            snake.push(c.to_ascii_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
+
+/// The value of a json literal attribute (falling back to a plain string), checked against the type of the field
+fn json_literal(field: &Field, attr: &str, literal: String) -> Result<serde_json::Value, TokenStream> {
+    let json_type = json_type(&field.ty);
+    let value = match json_type {
+        Some("string") => serde_json::Value::String(literal),
+        _ => serde_json::from_str(&literal).unwrap_or(serde_json::Value::String(literal)),
+    };
+    if let Some(expected) = json_type {
+        let found = value_type(&value);
+        if found != expected && !(expected == "number" && found == "integer") {
+            return Err(syn::Error::new_spanned(
+                field,
+                format!("`{}` must be a json {}, found {}", attr, expected, value),
+            )
+            .to_compile_error());
+        }
+    }
+    Ok(value)
+}
+
+/// The json schema type of a json value
+fn value_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(n) if n.is_f64() => "number",
+        serde_json::Value::Number(_) => "integer",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// Items checking at compile time that the fields `schemas` refer to exist
+pub(crate) fn compile_checks(schemas: &[FieldSchema]) -> TokenStream {
+    schemas.iter().map(|schema| schema.checks.clone()).collect()
+}
+
+/// The keywords of a [`FieldSchema`] that constrain values, which the `validate()` of `#[kube(validate)]` checks
+const CONSTRAINTS: &[&str] = &[
+    "enum",
+    "format",
+    "pattern",
+    "minLength",
+    "maxLength",
+    "minItems",
+    "maxItems",
+    "exclusiveMinimum",
+    "exclusiveMaximum",
+    "multipleOf",
+];
+
+/// The `validate()` of `#[kube(validate)]`, checking the fields of the spec against the constraints of their schemas
+pub(crate) fn validate_fn(
+    input: &DeriveInput,
+    schemas: &[FieldSchema],
+    kube_core: &Path,
+    serde: &Path,
+    serde_json: &Path,
+) -> TokenStream {
+    let fields = schemas.iter().filter_map(|schema| {
+        let constraints = schema
+            .keywords
+            .iter()
+            .filter(|(keyword, _)| CONSTRAINTS.contains(keyword))
+            .map(|(keyword, value)| (keyword.to_string(), value.clone()))
+            .collect::<serde_json::Map<_, _>>();
+        let const_constraints = schema
+            .const_keywords
+            .iter()
+            .filter(|(keyword, _)| CONSTRAINTS.contains(keyword))
+            .map(|(keyword, name)| {
+                let name = format_ident!("{}", name);
+                quote! { constraints.insert(#keyword.to_string(), #kube_core::schema::#name.into()); }
+            })
+            .collect::<Vec<_>>();
+        if constraints.is_empty() && const_constraints.is_empty() {
+            return None;
+        }
+        let name = &schema.name;
+        let constraints = serde_json::Value::Object(constraints).to_string();
+        Some(quote! {
+            (#name, {
+                let mut constraints: #serde_json::Map<String, #serde_json::Value> =
+                    #serde_json::from_str(#constraints).expect("valid constraints");
+                #(#const_constraints)*
+                #serde_json::Value::Object(constraints)
+            })
+        })
+    });
+    let ident = &input.ident;
+    let mut generics = input.generics.clone();
+    generics
+        .make_where_clause()
+        .predicates
+        .push(syn::parse_quote! { Self: #serde::Serialize });
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Check the fields against the constraints of their `#[kube]` attributes, like the apiserver does
+            ///
+            /// These are the `pattern`s, `format`s, lengths, item counts, exclusive bounds, `multiple_of`s and
+            /// `const_value`s of the fields. Constraints from `#[schemars]` or `#[validate]` attributes are not checked.
+            pub fn validate(&self) -> ::std::result::Result<(), ::std::vec::Vec<#kube_core::crd::FieldViolation>> {
+                let spec = #serde_json::to_value(self).expect("valid spec");
+                #kube_core::schema::validate_fields(&spec, &[#(#fields),*])
+            }
+        }
+    }
+}
+
+/// Code setting the keywords of `schemas` on the spec properties of the `schema` json value in scope
+pub(crate) fn schema_patches(schemas: &[FieldSchema], kube_core: &Path, serde_json: &Path) -> TokenStream {
+    schemas
+        .iter()
+        .map(|schema| field_patch(schema, kube_core, serde_json))
+        .collect()
+}
+
+fn field_patch(schema: &FieldSchema, kube_core: &Path, serde_json: &Path) -> TokenStream {
+    let pointer = format!(
+        "/properties/spec/properties/{}",
+        schema.name.replace('~', "~0").replace('/', "~1")
+    );
+    let removed = &schema.removed;
+    // the `description` (from doc comments) and `nullable` (of optional fields) are kept unless the function sets them
+    let replaced = schema.schema_fn.iter().map(|schema_fn| {
+        quote! {
+            if let #serde_json::Value::Object(replacement) = #serde_json::to_value(#schema_fn()).expect("valid schema") {
+                prop.retain(|keyword, _| keyword == "description" || keyword == "nullable");
+                prop.extend(replacement);
+            }
+        }
+    });
+    let inserts = schema.keywords.iter().map(|(keyword, value)| {
+        let value = value.to_string();
+        quote! {
+            prop.insert(#keyword.to_string(), #serde_json::from_str(#value).expect("valid schema keyword"));
+        }
+    });
+    let const_inserts = schema.const_keywords.iter().map(|(keyword, name)| {
+        let name = format_ident!("{}", name);
+        quote! {
+            prop.insert(#keyword.to_string(), #kube_core::schema::#name.into());
+        }
+    });
+    let rules = schema.validations.iter().map(|validation| {
+        let rule = &validation.rule;
+        let message = validation.message.iter().map(|m| quote! { .message(#m) });
+        quote! {
+            #kube_core::schema::ValidationRule::new(#rule) #(#message)*
+        }
+    });
+    let validations = if schema.validations.is_empty() {
+        quote! {}
+    } else {
+        quote! {
+            let rules = prop
+                .entry("x-kubernetes-validations")
+                .or_insert_with(|| #serde_json::Value::Array(vec![]));
+            if let Some(rules) = rules.as_array_mut() {
+                #(rules.push(#serde_json::to_value(#rules).expect("valid validation rule"));)*
+            }
+        }
+    };
+    // `kubectl explain` shows the description, as the apiserver has no notion of deprecated fields
+    let deprecated = schema.deprecated.iter().map(|marker| {
+        quote! {
+            let description = match prop.get("description").and_then(|d| d.as_str()) {
+                Some(description) => format!("{}\n\n{}", #marker, description),
+                None => #marker.to_string(),
+            };
+            prop.insert("description".to_string(), #serde_json::Value::String(description));
+        }
+    });
+    let optional = if schema.optional {
+        let name = &schema.name;
+        quote! {
+            if let Some(spec) = schema.pointer_mut("/properties/spec").and_then(|s| s.as_object_mut()) {
+                if let Some(required) = spec.get_mut("required").and_then(|r| r.as_array_mut()) {
+                    required.retain(|r| r != #name);
+                    if required.is_empty() {
+                        spec.remove("required");
+                    }
+                }
+            }
+        }
+    } else {
+        quote! {}
+    };
+    // the cfg is evaluated in the crate deriving the resource, so this follows its features
+    let gated = schema.feature.iter().map(|feature| {
+        let name = &schema.name;
+        quote! {
+            #[cfg(not(feature = #feature))]
+            {
+                if let Some(spec) = schema.pointer_mut("/properties/spec").and_then(|s| s.as_object_mut()) {
+                    if let Some(props) = spec.get_mut("properties").and_then(|p| p.as_object_mut()) {
+                        props.remove(#name);
+                    }
+                    if let Some(required) = spec.get_mut("required").and_then(|r| r.as_array_mut()) {
+                        required.retain(|r| r != #name);
+                        if required.is_empty() {
+                            spec.remove("required");
+                        }
+                    }
+                }
+            }
+        }
+    });
+    quote! {
+        if let Some(prop) = schema.pointer_mut(#pointer).and_then(|p| p.as_object_mut()) {
+            #(#replaced)*
+            #(prop.remove(#removed);)*
+            #(#inserts)*
+            #(#const_inserts)*
+            #validations
+            #(#deprecated)*
+        }
+        #optional
+        #(#gated)*
+    }
+}
+
+/// The anchored regex of the format `name` given to the attribute `attr`, which accepts the formats `names`
+fn format_pattern(
+    field: &Field,
+    attr: &str,
+    name: &str,
+    names: &[&str],
+) -> Result<&'static str, TokenStream> {
+    match FORMAT_PATTERNS
+        .iter()
+        .find(|(format, _)| *format == name && names.contains(format))
+    {
+        Some((_, pattern)) => Ok(pattern),
+        None => Err(syn::Error::new_spanned(
+            field,
+            format!(
+                "unknown `{}` {:?}, expected one of {}",
+                attr,
+                name,
+                names.join(", ")
+            ),
+        )
+        .to_compile_error()),
+    }
+}
+
+/// The value of a `#[serde(key = "value")]` (or serialize half of a `#[serde(key(serialize = "value"))]`) attribute
+pub(crate) fn serde_attr(attrs: &[Attribute], key: &str) -> Option<String> {
+    let lit_str = |lit: &Lit| match lit {
+        Lit::Str(s) => Some(s.value()),
+        _ => None,
+    };
+    for attr in attrs.iter().filter(|a| a.path.is_ident("serde")) {
+        let list = match attr.parse_meta() {
+            Ok(Meta::List(list)) => list,
+            _ => continue,
+        };
+        for nested in list.nested {
+            match nested {
+                NestedMeta::Meta(Meta::NameValue(nv)) if nv.path.is_ident(key) => return lit_str(&nv.lit),
+                NestedMeta::Meta(Meta::List(inner)) if inner.path.is_ident(key) => {
+                    for nested in inner.nested {
+                        if let NestedMeta::Meta(Meta::NameValue(nv)) = nested {
+                            if nv.path.is_ident("serialize") {
+                                return lit_str(&nv.lit);
+                            }
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    None
+}
+
+/// Whether a `#[serde(key)]` flag (like `skip`) is set
+pub(crate) fn serde_flag(attrs: &[Attribute], key: &str) -> bool {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("serde"))
+        .filter_map(|attr| match attr.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .any(|nested| matches!(nested, NestedMeta::Meta(Meta::Path(path)) if path.is_ident(key)))
+}
+
+/// Rename a snake_case field like `#[serde(rename_all = "...")]` does
+fn apply_rename_all(field: &str, rule: Option<&str>) -> String {
+    let pascal = || {
+        field
+            .split('_')
+            .map(|part| {
+                let mut chars = part.chars();
+                chars
+                    .next()
+                    .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                    .unwrap_or_default()
+            })
+            .collect::<String>()
+    };
+    match rule {
+        Some("UPPERCASE") | Some("SCREAMING_SNAKE_CASE") => field.to_ascii_uppercase(),
+        Some("PascalCase") => pascal(),
+        Some("camelCase") => {
+            let pascal = pascal();
+            let mut chars = pascal.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_lowercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        }
+        Some("kebab-case") => field.replace('_', "-"),
+        Some("SCREAMING-KEBAB-CASE") => field.to_ascii_uppercase().replace('_', "-"),
+        _ => field.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply_rename_all, to_snake_case};
+
+    #[test]
+    fn snake_cases_camel_case_keys() {
+        assert_eq!(to_snake_case("name"), "name");
+        assert_eq!(to_snake_case("containerPort"), "container_port");
+        assert_eq!(to_snake_case("PortName"), "port_name");
+    }
+
+    #[test]
+    fn renames_like_serde() {
+        assert_eq!(apply_rename_all("foo_bar", None), "foo_bar");
+        assert_eq!(apply_rename_all("foo_bar", Some("camelCase")), "fooBar");
+        assert_eq!(apply_rename_all("foo_bar", Some("PascalCase")), "FooBar");
+        assert_eq!(apply_rename_all("foo_bar", Some("kebab-case")), "foo-bar");
+        assert_eq!(
+            apply_rename_all("foo_bar", Some("SCREAMING-KEBAB-CASE")),
+            "FOO-BAR"
+        );
+        assert_eq!(apply_rename_all("foo_bar", Some("UPPERCASE")), "FOO_BAR");
+    }
+}
@@ -5,7 +5,9 @@
 extern crate proc_macro;
 #[macro_use] extern crate quote;
 
+mod builder;
 mod custom_resource;
+mod field_attrs;
 
 /// A custom derive for kubernetes custom resource definitions.
 ///
@@ -106,6 +108,13 @@ mod custom_resource;
 /// ## `#[kube(status = "StatusStructName")]`
 /// Adds a status struct to the top level generated type and enables the status
 /// subresource in your crd.
+/// The status can be a path to a type in another module (like `status = "crate::status::FooStatus"`),
+/// whose schema is embedded under `properties.status`.
+///
+/// ## `#[kube(auto_status)]`
+/// Generates a `{Kind}Status` type (like `FooStatus`), and uses it as the status like `#[kube(status = "FooStatus")]`.
+/// The generated status has the `conditions` (a `Vec<Condition>`, with the schema of `kube::core::schema::conditions_schema`)
+/// and `observedGeneration` most statuses consist of, and derives `Default` and `PartialEq`. Can not be used with `status`.
 ///
 /// ## `#[kube(derive = "Trait")]`
 /// Adding `#[kube(derive = "PartialEq")]` is required if you want your generated
@@ -130,11 +139,134 @@ mod custom_resource;
 /// ## `#[kube(scale = r#"json"#)]`
 /// Allow customizing the scale struct for the [scale subresource](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#subresources).
 ///
+/// ## `#[kube(max_recursion_depth = 3)]`
+/// How many levels deep recursive types (like a tree node with child nodes) are expanded in the schema, below their
+/// first occurrence. Crd schemas can not refer to themselves, so at this depth the recursive type is replaced with an
+/// object with `x-kubernetes-preserve-unknown-fields: true`, which the apiserver does not validate. Defaults to `3`.
+///
+/// ## `#[kube(deny_unknown_fields)]`
+/// Like `#[serde(deny_unknown_fields)]` on every struct of the spec, set `additionalProperties: false` on the objects
+/// of the spec in `CustomResourceExt::root_schema`, documenting that unknown fields are invalid.
+/// Objects with `x-kubernetes-preserve-unknown-fields` (like flattened maps) are left open.
+///
+/// Crds do not allow `additionalProperties` next to `properties`, so this is removed from the generated crd (as is the
+/// one `#[serde(deny_unknown_fields)]` generates). In the crd, unknown fields are expressed by pruning instead: the
+/// apiserver drops fields that are not in the schema, and rejects requests setting them with `fieldValidation=Strict`
+/// (`kubectl apply --validate=strict`, the default of `kubectl` since 1.27). This does not make the generated
+/// struct reject unknown fields when deserializing.
+///
 /// ## `#[kube(printcolumn = r#"json"#)]`
 /// Allows adding straight json to [printcolumns](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#additional-printer-columns).
+/// The `jsonPath` refers to serialized field names (after `#[serde(rename_all)]` and `#[serde(rename)]`);
+/// paths using the rust name of a renamed spec field are rejected at compile time.
+///
+/// ## `#[kube(age_column)]`
+/// Adds the `Age` printcolumn that built-in resources show (`{"name":"Age", "type":"date", "jsonPath":".metadata.creationTimestamp"}`),
+/// after the declared printcolumns. Nothing is added if a printcolumn named `Age` is already declared.
+///
+/// ## `#[kube(status_ready_column)]`
+/// Adds a `Ready` printcolumn (`{"name":"Ready", "type":"string", "jsonPath":".status.conditions[?(@.type=='Ready')].status"}`)
+/// showing the status of the `Ready` condition, after the declared printcolumns. Use
+/// `#[kube(status_ready_column(jsonPath = "..."))]` for another path. Requires a status (like `#[kube(auto_status)]`),
+/// and nothing is added if a printcolumn named `Ready` is already declared.
 ///
 /// ## `#[kube(shortname = "sn")]`
 /// Add a single shortname to the generated crd.
+/// Shortnames must be lowercase alphanumerics or `-`, start with a letter and end with an alphanumeric (checked at compile time).
+///
+/// ## `#[kube(selectable = ".spec.field")]`
+/// Declare a field (a json path under `.spec` or `.status`) as selectable in field selectors.
+/// The declared paths are returned by `CustomResourceExt::selectable_fields`, and checked by
+/// `kube::core::crd::SelectableFieldSelector` when building field selectors.
+/// Like printcolumn paths, these use serialized field names.
+///
+/// NOTE: `selectableFields` (Kubernetes >= 1.30) is not modelled by `k8s-openapi` yet, so the paths are not emitted in `crd()`.
+///
+/// ## `#[kube(validation = "has(self.config) || self.mode != 'custom'")]`
+/// Appends a [CEL validation rule](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#validation-rules)
+/// to the `x-kubernetes-validations` of the spec, where `self` is the spec object. Can be repeated.
+/// This expresses constraints across fields, like fields that are only required when another field has a certain value.
+/// Rules must not be empty, but are otherwise not checked at compile time.
+///
+/// ## `#[kube(validation(field = "spec.count", rule = "self > 0", message = "must be positive"))]`
+/// Appends the rule (with an optional `message`) to the `x-kubernetes-validations` of a field instead of the spec,
+/// where `self` is the value of the field. The field is a dotted path of serialized field names under `spec`.
+/// The first field of the path must be a field of the spec (unless the spec has flattened fields).
+/// A field rule can not refer to its sibling fields, so put rules across fields on the spec instead.
+///
+/// ## `#[kube(validation(path = "spec.range", rule = "self.min <= self.max"))]`
+/// The same as `field`, for rules on nested objects, where `self` is the object, so the rule can relate its fields
+/// (here `spec.range.min` and `spec.range.max`). Only one of `field` and `path` can be set.
+///
+/// ## `#[kube(validation(rule = "self.replicas <= 100", version = "v1"))]`
+/// Only appends the rule when deriving the listed versions (`version` can be repeated), for specs shared between
+/// versions (e.g. declared by a `macro_rules!` per version) that validate stable versions more strictly than
+/// alpha ones. The rule is still checked at compile time for every version, and can also be scoped to a `field`.
+///
+/// ## `#[kube(dependent_required(tls_cert, tls_key))]`
+/// Requires the other spec fields (here `tls_key`) whenever the first one (`tls_cert`) is set, like `dependentRequired`
+/// of json schema (which crds do not support). This appends the CEL validation rule `!has(self.tlsCert) || has(self.tlsKey)`
+/// to the spec, using the serialized field names. The fields are rust field names of the spec, checked at compile time. Can be repeated.
+///
+/// ## `#[kube(one_of(s3, gcs, azure))]`
+/// Requires exactly one of the spec fields to be set. A `oneOf` of `required` fields is not structural, so this appends the
+/// CEL validation rule `[has(self.s3), has(self.gcs), has(self.azure)].filter(x, x).size() == 1` to the spec instead,
+/// using the serialized field names. The fields are rust field names of the spec, checked at compile time. Can be repeated.
+///
+/// ## `#[kube(crd_annotation("api-approved.kubernetes.io", "https://github.com/kubernetes/enhancements/pull/1111"))]`
+/// Add an annotation to the metadata of the generated crd (not to the custom resources).
+/// Crds in `*.k8s.io` and `*.kubernetes.io` groups need an `api-approved.kubernetes.io` annotation to be accepted.
+///
+/// ## `#[kube(conversion = "webhook", conversion_webhook = "FOO_CONVERSION")]`
+/// Sets the conversion strategy of the generated crd, so `crd()` needs no post-processing. `"webhook"` converts with the
+/// service of a `kube::core::crd::ConversionWebhook` const (given by path), and `"none"` explicitly uses the `None` strategy,
+/// in which case merging versions with differing schemas fails. The root crd given to `CrdMerger` decides the
+/// conversion of the merged crd.
+///
+/// Each derived type only knows its own version, so the schemas of `"none"` versions are only checked to be identical
+/// when they are combined through `CrdMerger::merge` (or `try_merge`). A multi-version crd assembled by hand from several
+/// `crd()`s is not checked.
+///
+/// ```ignore
+/// const FOO_CONVERSION: ConversionWebhook = ConversionWebhook::service("foo-system", "foo-webhook");
+/// ```
+///
+/// ## `#[kube(docs = "DOCS")]`
+/// Sets the `description`s of the schema from a `&[(&str, &str)]` const (given by path) of field paths and descriptions,
+/// replacing those from doc comments, so docs can be maintained (or translated) apart from the structs. Paths are dotted
+/// from the root, like `spec.replicas`, `spec.ports[*].name` for the items of a list, or `spec.labels.*` for the values of
+/// a map. Generating the crd panics on paths that are not in the schema (like those of renamed fields), so any test
+/// generating the crd catches outdated docs. As the descriptions are set on the generated schema, this can not be used
+/// with `#[kube(schema = "disabled")]`.
+///
+/// ```ignore
+/// const FOO_DOCS: &[(&str, &str)] = &[("spec.replicas", "Number of pods"), ("status.phase", "Current phase")];
+/// ```
+///
+/// ## `#[kube(aggregated)]`
+/// Marks the resource as served by an [aggregated apiserver](https://kubernetes.io/docs/concepts/extend-kubernetes/api-extension/apiserver-aggregation/)
+/// (through an `APIService`) rather than by a `CustomResourceDefinition`.
+///
+/// The generated type does not implement `CustomResourceExt`, so no `crd()` can be applied by accident,
+/// but it still gets inherent `api_resource()` and `shortnames()` helpers. Schema generation defaults to `"disabled"`.
+///
+/// ## `#[kube(register)]`
+/// Registers the crd in the link time registry read by `kube::core::crd::registered_crds()`, so a crd generating
+/// binary can output the crds of every registered type without listing them. Requires the `register` feature of `kube`,
+/// and can not be combined with `aggregated`.
+///
+/// ## `#[kube(builder)]`
+/// Generates a `FooSpecBuilder` (created with `FooSpec::builder()`) with a setter per field, named after the rust field.
+/// Setters of `Option<T>` fields take the `T`. Its `build()` sets unset fields to the `default` of their schema, like the
+/// apiserver would, leaves unset optional fields without a default as `None`, and fails with a `serde_json::Error` for
+/// unset required fields. Requires a v1 crd and a spec with named fields and no generic parameters.
+///
+/// ## `#[kube(validate)]`
+/// Generates a `validate()` on the spec, checking its fields against the constraints of their `#[kube]` field attributes
+/// (like `pattern`, `min_length` or `exclusive_minimum`), like the apiserver would, so code that does not go through the
+/// apiserver (like webhooks and tests) enforces the same rules. It returns the violations as
+/// `Vec<kube::core::crd::FieldViolation>`. Constraints from `#[schemars]` and `#[validate]` attributes are not checked,
+/// and the generated method shadows the `validate()` of `#[derive(Validate)]`, so do not combine the two.
 ///
 /// ## Example with all properties
 ///
@@ -172,16 +304,189 @@ mod custom_resource;
 /// }
 /// ```
 ///
+/// # Optional `#[kube]` field attributes
+///
+/// These customize the schema of the (serialized) fields of the spec struct, and take `#[serde(rename)]`
+/// and `#[serde(rename_all)]` into account. They only apply to direct fields of the spec struct.
+///
+/// ## `#[kube(schema = "byte")]`
+/// Replaces the schema of the field with `type: string, format: byte`, like Kubernetes uses for binary data.
+/// Use it on base64 encoded fields (e.g. a `Vec<u8>` with `#[serde(serialize_with = "...", deserialize_with = "...")]`),
+/// whose derived schema would otherwise be an array of integers. Note that `schemars` takes `#[serde(with = "...")]`
+/// to name the type of the schema, so that needs a `#[schemars(with = "Vec<u8>")]` alongside.
+///
+/// ## `#[kube(schema = "duration")]`
+/// Replaces the schema of the field with `type: string` and a `pattern` matching go duration strings like `"30s"`
+/// or `"1h30m"`, like Kubernetes uses for durations. The derived schema of a `std::time::Duration` is an object of
+/// `secs` and `nanos`, so use this on fields serialized as duration strings
+/// (e.g. with `#[serde(serialize_with = "...", deserialize_with = "...")]`), or on plain `String` fields.
+///
+/// ## `#[kube(schema = "quantity")]`
+/// Replaces the schema of the field with the schema the apiserver uses for quantities like `"500m"` or `"1Gi"`:
+/// an `x-kubernetes-int-or-string` with the quantity `pattern`. This is the default for fields of type
+/// `Quantity` (from `k8s_openapi::apimachinery::pkg::api::resource`), so the attribute is only needed on custom
+/// quantity-like types. As `Quantity` does not implement `JsonSchema`, such fields also need
+/// `#[schemars(schema_with = "kube::core::schema::quantity_schema")]`.
+///
+/// ## `#[kube(schema = "date")]`
+/// Replaces the schema of the field with `type: string, format: date`, for calendar dates like `"2024-05-01"`.
+/// This is the default for fields of type `NaiveDate` (from `chrono`), so they are not mistaken for timestamps.
+/// Use `#[kube(schema = "date-time")]` for `type: string, format: date-time` on custom timestamp types.
+///
+/// These defaults go by the written type, as imports can not be resolved: a bare `Quantity` or `NaiveDate` is
+/// taken to be the one of `k8s_openapi` or `chrono`, while a differently qualified one (like `units::Quantity`) is not.
+///
+/// ## `#[kube(schema_with = "path::to::schema_fn")]`
+/// Replaces the schema of the field with the `JSONSchemaProps` returned by the function (`fn() -> JSONSchemaProps`),
+/// for fields whose wire format differs from their type (like with `#[serde(serialize_with = "...", deserialize_with = "...")]`).
+/// The `description` of the doc comment and `nullable` of optional fields are kept unless the function sets them,
+/// and the other field attributes apply on top of the replaced schema. This can not be combined with `schema`.
+///
+/// ## `#[kube(title = "Replica count")]`
+/// Sets the `title` of the field, which tools rendering schemas (like dashboards) can show as a label.
+/// This overrides a `#[schemars(title = "...")]`, which is also kept in the crd schema.
+///
+/// ## `#[kube(pattern = "^v[0-9]+$")]`
+/// Sets the `pattern` a string field must match. The regex is checked at compile time.
+///
+/// ## `#[kube(format_pattern = "dns1123-label")]`
+/// Sets the `pattern` of a string field to the anchored regex of a common format, instead of a hand-written `pattern`:
+/// `"dns1123-label"` (like names of most resources), `"dns1123-subdomain"`, `"uuid"`, `"ipv4"`, `"ipv6"`, `"cidr"`, `"url"`
+/// (of `http` or `https`) or `"semver"`.
+/// Unknown formats are a compile error.
+///
+/// ## `#[kube(format = "cidr")]`
+/// Sets the `format` of a string field to one the apiserver validates, together with the anchored regex of the format
+/// as its `pattern` (for tools that only check patterns): `"ipv4"`, `"ipv6"`, `"cidr"` (of either), `"uuid"` or `"url"`.
+/// A `"url"` becomes `format: uri`, which the apiserver accepts for almost any string, so its `pattern` (of `http` and
+/// `https` urls) is what rejects invalid urls.
+/// These can also be used as `format_pattern`s, to only set the `pattern`. Unknown formats are a compile error.
+///
+/// ## `#[kube(min_length = 1, max_length = 63)]`
+/// Sets the `minLength` and `maxLength` of a string field. Either can be omitted.
+///
+/// ## `#[kube(min_items = 1, max_items = 10)]`
+/// Sets the `minItems` and `maxItems` of an array field (like `Vec<T>`). Either can be omitted.
+///
+/// ## `#[kube(treat_empty_as_absent)]`
+/// Sets `minItems: 1` on an optional array field (like `Option<Vec<T>>`), so the apiserver rejects an empty array,
+/// and an absent field is the only way to express that there are no items. Without it, an `Option<Vec<T>>` is an
+/// optional, `nullable` array that can be absent, `null` or empty, which the controller needs to treat alike.
+/// Serializing `None` as an absent field needs `#[serde(skip_serializing_if = "Option::is_none")]`.
+///
+/// ## `#[kube(exclusive_minimum = 0, exclusive_maximum = 100)]`
+/// Sets an exclusive lower or upper bound of a numeric field. Use a string (`exclusive_minimum = "-1"`) for negative bounds.
+/// `root_schema()` uses the json schema form (`exclusiveMinimum: 0`), while `crd()` uses the openapi v3.0 form
+/// crds expect (`minimum: 0` with `exclusiveMinimum: true`).
+///
+/// ## `#[kube(multiple_of = 64)]`
+/// Sets the `multipleOf` of a numeric field, which must be positive (checked at compile time).
+///
+/// ## `#[kube(schema_format = "int64")]`
+/// Sets the `format` of an integer field to `"int32"` or `"int64"`. Without it, 8 and 16 bit integers and `i32`
+/// get `format: int32`, and `u32`, 64 bit and pointer sized ones get `format: int64` (`int32` can not hold every `u32`).
+///
+/// ## `#[kube(nullable)]`
+/// Sets `nullable: true` on the field, regardless of whether it is an `Option`, so the apiserver keeps explicit `null` values
+/// rather than rejecting or pruning them. `#[kube(nullable = false)]` removes `nullable` instead.
+///
+/// Whether a field may be absent is separate from this: `Option` fields (and fields with a `#[serde(default)]`)
+/// are left out of `required`. `Option` fields are `nullable` by default, as they serialize as `null` unless they have
+/// `#[serde(skip_serializing_if = "Option::is_none")]`, so only use `nullable = false` on such skipped fields.
+///
+/// ## `#[kube(map_type = "granular")]`
+/// Sets the [`x-kubernetes-map-type`](https://kubernetes.io/docs/reference/using-api/server-side-apply/#merge-strategy)
+/// of an object field to `"granular"` (fields are owned separately by server-side apply managers) or `"atomic"`.
+///
+/// ## `#[kube(default = "Running")]`
+/// Sets the schema `default` of the field. The literal is parsed as json (so `default = "3"` is the integer `3`),
+/// falling back to a plain string, and must match the type of a string, integer, number or bool field (checked at compile time).
+/// Defaults are static: no Kubernetes version supports conditional (e.g. CEL) defaulting in crd schemas, so a default
+/// depending on other fields needs a mutating admission webhook (or a `MutatingAdmissionPolicy`).
+///
+/// ## `#[kube(example = r#"["10.0.0.0/8"]"#)]`
+/// Sets the schema `example` of the field, shown by documentation tools and UIs rendering the crd (and kept by the apiserver).
+/// The literal is parsed and type checked like `default`, and may also be a json array for collection fields.
+///
+/// ## `#[kube(const_value = "Foo")]`
+/// Requires the field to equal the value, like a pinned discriminator. Crds do not support the json schema `const`, so
+/// this emits the single value `enum: ["Foo"]`. The literal is parsed and type checked like `default`.
+///
+/// ## `#[kube(deprecated_field = "use replicas instead")]`
+/// Prefixes the `description` of the field with `Deprecated: use replicas instead` (or a generic message for a plain
+/// `#[kube(deprecated_field)]`), which `kubectl explain` shows. The apiserver has no per-field deprecation (only per
+/// version), and drops unknown `x-kubernetes-*` schema extensions, so the description is the only marker that survives.
+///
+/// ## `#[kube(list_type = "set")]`
+/// Sets the [`x-kubernetes-list-type`](https://kubernetes.io/docs/reference/using-api/server-side-apply/#merge-strategy)
+/// of an array field to `"set"` (unique scalar items, merged by server-side apply), `"map"` or `"atomic"`.
+///
+/// ## `#[kube(list_type = "map", list_map_key = "name", list_map_key = "protocol")]`
+/// Sets the `x-kubernetes-list-map-keys` of a `list_type = "map"` array field, whose items are merged by
+/// server-side apply by the (serialized) values of these keys. Can be repeated, and is required for `"map"` lists.
+/// It is checked at compile time that the item type has the fields of these keys (converted from camelCase to snake_case),
+/// so the fields need to be visible from the spec struct. The apiserver also requires keys to be required or defaulted.
+///
+/// ## `#[kube(unique_items)]`
+/// Sets `uniqueItems: true` on an array field, like schemars does for `BTreeSet` and `HashSet` fields.
+///
+/// NOTE: the apiserver rejects `uniqueItems: true` in crds, as checking it is quadratic, so it is only kept in
+/// `root_schema()`. In the crd, arrays of scalars get `x-kubernetes-list-type: set` instead (unless they set a
+/// `list_type`), which has the apiserver reject duplicate items, and other arrays drop it.
+///
+/// ## `#[kube(validation(rule = "self.min <= self.max", message = "..."))]`
+/// Appends a [CEL validation rule](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#validation-rules)
+/// (a `kube::core::schema::ValidationRule`) to the `x-kubernetes-validations` of the field. Can be repeated.
+/// `rule` is required, and `message` optional.
+///
+/// NOTE: the `reason` and `field_path` of rules (Kubernetes >= 1.28) are rejected at compile time, as `k8s-openapi`
+/// does not model them yet, so `crd()` would drop them.
+///
+/// ## `#[kube(feature = "experimental")]`
+/// Removes the field from the schema unless the cargo feature `experimental` of the crate deriving the resource is
+/// enabled, so a stable crd is generated by default and one with the experimental fields with the feature.
+/// The field is still part of the struct, and without the feature the apiserver prunes it, so it should be an `Option`
+/// (with `#[serde(skip_serializing_if = "Option::is_none")]`) or have a `#[serde(default)]`.
+/// To remove the field from the struct too, use `#[cfg(feature = "experimental")]` on it instead.
+///
+/// # Generic specs
+///
+/// The generated type is not generic, so the spec must not have free generic parameters. A generic spec
+/// can be used either through defaults for all of its parameters (`struct ConfigSpec<T = Prod>`), or by wrapping
+/// a concrete instantiation in a newtype (`struct ProdConfigSpec(Config<Prod>);`), whose schema is that of the instantiation.
+///
 /// # Enums
 ///
 /// Kubernetes requires that the generated [schema is "structural"](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#specifying-a-structural-schema).
 /// This means that the structure of the schema must not depend on the particular values. For enums this imposes a few limitations:
 ///
-/// - Only [externally tagged enums](https://serde.rs/enum-representations.html#externally-tagged) are supported
+/// - Only [externally tagged](https://serde.rs/enum-representations.html#externally-tagged),
+///   [internally tagged](https://serde.rs/enum-representations.html#internally-tagged) and
+///   [untagged](https://serde.rs/enum-representations.html#untagged) enums are supported
+/// - Struct variants of untagged enums may not define the same field with different types
 /// - Unit variants may not be mixed with struct or tuple variants (`enum Foo { Bar, Baz {}, Qux() }` is invalid, for example)
 ///
 /// If these restrictions are not followed then `YourCrd::crd()` may panic, or the Kubernetes API may reject the CRD definition.
 ///
+/// Unit-only enums become a string schema whose `enum` lists every variant, also as the `items` of lists of them
+/// (like `protocols: Vec<Protocol>`). The tag of an internally tagged enum
+/// gets the same `enum`, alongside an `x-kubernetes-validations` rule restricting it to the known variants
+/// (CEL validation rules require Kubernetes >= 1.23 with the `CustomResourceValidationExpressions` feature gate, or >= 1.25).
+///
+/// The fields of the variants of an internally tagged enum are merged into one object, with a rule per variant requiring
+/// its required fields and forbidding the fields of the other variants when the tag selects it. Variants may share
+/// identically defined fields (like a `bucket` in both `S3 { bucket, region }` and `Gcs { bucket, project }`), in which
+/// case the `oneOf` of the variants is dropped, and only the rules enforce which fields belong to which variant.
+///
+/// Untagged enums of struct variants become an object with the fields of all variants, and an `anyOf` of the `required`
+/// fields of each variant. Integer or string variants become `x-kubernetes-int-or-string`, and variants of one other
+/// type share that type. Variants of different types (or struct variants defining the same field differently) can not
+/// be described structurally, so the field becomes `x-kubernetes-preserve-unknown-fields` (with a note in its
+/// description) and is not validated by the apiserver.
+///
+/// Fields of arbitrary JSON (`serde_json::Value`, or a `serde_json::Map<String, Value>`) also become
+/// `x-kubernetes-preserve-unknown-fields`, so the apiserver stores them verbatim.
+///
 /// # Generated code
 ///
 /// The example above will roughly generate:
@@ -196,6 +501,14 @@ mod custom_resource;
 ///     status: Option<FooStatus>,
 /// }
 /// impl kube::Resource for FooCrd {...}
+/// impl kube::core::StaticGroupVersionKind for FooCrd {
+///     const GROUP: &'static str = "clux.dev";
+///     const VERSION: &'static str = "v1";
+///     const KIND: &'static str = "Foo";
+/// }
+/// impl kube::core::crd::CustomResourceScope for FooCrd {
+///     type Scope = k8s_openapi::NamespaceResourceScope;
+/// }
 ///
 /// impl FooCrd {
 ///     pub fn new(name: &str, spec: FooSpec) -> Self { ... }
@@ -210,6 +523,24 @@ mod custom_resource;
 /// - `impl JsonSchema` on a type / newtype around external type. See [#129](https://github.com/kube-rs/kube-rs/issues/129#issuecomment-750852916)
 /// - [`#[validate(...)]` field attributes with validator](https://github.com/Keats/validator) for kubebuilder style validation rules (see [`crd_api` example](https://github.com/kube-rs/kube-rs/blob/master/examples/crd_api.rs)))
 ///
+/// Defaults from `#[serde(default)]` are emitted as schema `default`s wherever the field appears,
+/// including inside the `items` of arrays (e.g. a `Vec<Listener>` where `Listener` has defaulted fields),
+/// so the apiserver will also default fields of list entries.
+///
+/// Newtype structs (like `struct Port(u16)`) deriving `JsonSchema` get the schema of the type they wrap,
+/// including its `format` and bounds, so domain newtypes can be used in specs without weakening the schema.
+/// A doc comment on the newtype becomes the `description` of every field using it.
+/// This includes `#[serde(transparent)]` structs, and id types like `struct ClusterId(String)`, which are plain
+/// `type: string` nodes. Constraints on the wrapped field (like `#[schemars(length(max = 63), regex(pattern = "..."))]`)
+/// are kept.
+///
+/// `#[serde(skip)]` fields are left out of the schema. Spec fields with `#[serde(skip_serializing_if = "...")]` or
+/// `#[serde(skip_serializing)]` are not `required`, as serializing omits them. This only applies to the fields of the
+/// spec struct itself; add `#[serde(default)]` to such fields of nested structs.
+///
+/// Fields of `#[serde(flatten)]` structs are merged into the `properties` of the parent, like serde does when (de)serializing.
+/// The derive can not see the fields of other types, so make sure flattened structs do not reuse the names of parent fields.
+///
 /// You might need to override parts of the schemas (for fields in question) when you are:
 /// - **using complex enums**: enums do not currently generate [structural schemas](https://kubernetes.io/docs/tasks/extend-kubernetes/custom-resources/custom-resource-definitions/#specifying-a-structural-schema), so kubernetes won't support them by default
 /// - **customizing [merge-strategies](https://kubernetes.io/docs/reference/using-api/server-side-apply/#merge-strategy)** (e.g. like in the [`crd_derive_schema` example](https://github.com/kube-rs/kube-rs/blob/master/examples/crd_derive_schema.rs))
@@ -227,6 +558,7 @@ mod custom_resource;
 /// ## Validation Caveats
 /// The supported **`#[validate]` attrs also exist as `#[schemars]` attrs** so you can use those directly if you do not require the validation to run client-side (in your code).
 /// Otherwise, you should `#[derive(Validate)]` on your struct to have both server-side (kubernetes) and client-side validation.
+/// To check the constraints of `#[kube]` field attributes client-side instead, see `#[kube(validate)]`.
 ///
 /// When using `validator` directly, you must add it to your dependencies (with the `derive` feature).
 ///
@@ -271,3 +603,32 @@ mod custom_resource;
 pub fn derive_custom_resource(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     custom_resource::derive(proc_macro2::TokenStream::from(input)).into()
 }
+
+/// A `CrdMerger` of the crds of the given `CustomResourceExt` types
+///
+/// `crd_merger![v1::Foo, v2::Foo]` expands to `CrdMerger::new(vec![v1::Foo::crd(), v2::Foo::crd()])`,
+/// using the `kube::core` paths.
+///
+/// ```rust
+/// use kube::crd_merger;
+///
+/// mod v1 {
+/// #   use kube::CustomResource; use schemars::JsonSchema; use serde::{Deserialize, Serialize};
+///     #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+///     #[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+///     pub struct FooSpec { pub name: String }
+/// }
+/// mod v2 {
+/// #   use kube::CustomResource; use schemars::JsonSchema; use serde::{Deserialize, Serialize};
+///     #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+///     #[kube(group = "clux.dev", version = "v2", kind = "Foo")]
+///     pub struct FooSpec { pub name: String, pub replicas: i32 }
+/// }
+///
+/// let crd = crd_merger![v1::Foo, v2::Foo].storage("v2").merge().unwrap();
+/// assert_eq!(crd.spec.versions.len(), 2);
+/// ```
+#[proc_macro]
+pub fn crd_merger(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    custom_resource::crd_merger(proc_macro2::TokenStream::from(input)).into()
+}
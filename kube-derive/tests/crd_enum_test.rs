@@ -153,3 +153,261 @@ fn test_crd_schema_matches_expected() {
         .unwrap()
     );
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+enum Protocol {
+    Tcp,
+    Udp,
+    #[serde(rename = "sctp")]
+    Sctp,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(tag = "type")]
+enum Shape {
+    Circle { radius: f64 },
+    Square { side: f64 },
+}
+
+/// How often to take backups
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+enum Schedule {
+    /// Every hour, on the hour
+    Hourly,
+    /// Every day at midnight
+    Daily,
+    Weekly,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Endpoint")]
+struct EndpointSpec {
+    protocol: Protocol,
+    shape: Shape,
+    schedule: Schedule,
+}
+
+#[test]
+fn test_string_enum_lists_all_variants() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Endpoint::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(
+        spec["properties"]["protocol"],
+        serde_json::json!({ "type": "string", "enum": ["Tcp", "Udp", "sctp"] })
+    );
+}
+
+#[test]
+fn test_internally_tagged_enum_validates_tag() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Endpoint::crd()).unwrap();
+    let shape =
+        &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"]["shape"];
+    assert_eq!(
+        shape["properties"]["type"],
+        serde_json::json!({ "type": "string", "enum": ["Circle", "Square"] })
+    );
+    assert_eq!(
+        shape["x-kubernetes-validations"],
+        serde_json::json!([
+            {
+                "rule": "!has(self.type) || self.type in ['Circle', 'Square']",
+                "message": "type must be one of: Circle, Square",
+            },
+            {
+                "rule": "!has(self.type) || self.type != 'Circle' || (has(self.radius) && !has(self.side))",
+                "message": "when type is Circle, radius is required and side is not allowed",
+            },
+            {
+                "rule": "!has(self.type) || self.type != 'Square' || (has(self.side) && !has(self.radius))",
+                "message": "when type is Square, side is required and radius is not allowed",
+            },
+        ])
+    );
+}
+
+#[test]
+fn test_string_enum_keeps_variant_docs() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Endpoint::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(
+        spec["properties"]["schedule"],
+        serde_json::json!({
+            "description": "How often to take backups\n\nPossible values:\n- `hourly`: Every hour, on the hour\n- `daily`: Every day at midnight",
+            "type": "string",
+            "enum": ["weekly", "hourly", "daily"],
+        })
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Allowlist")]
+struct AllowlistSpec {
+    protocols: Vec<Protocol>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    schedules: Option<Vec<Schedule>>,
+}
+
+#[test]
+fn test_arrays_of_string_enums_keep_enum_in_items() {
+    use kube::core::{crd::FakeApiserver, CustomResourceExt};
+    let crd = Allowlist::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let spec = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(
+        spec["properties"]["protocols"],
+        serde_json::json!({
+            "type": "array",
+            "items": { "type": "string", "enum": ["Tcp", "Udp", "sctp"] }
+        })
+    );
+    let schedules = &spec["properties"]["schedules"];
+    assert_eq!(schedules["type"], "array");
+    assert_eq!(schedules["nullable"], true);
+    assert_eq!(
+        schedules["items"]["enum"],
+        serde_json::json!(["weekly", "hourly", "daily"])
+    );
+    assert_eq!(FakeApiserver::accept(&crd), Ok(()));
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(untagged)]
+enum Port {
+    Number(u16),
+    Name(String),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(untagged)]
+enum Source {
+    /// Fetched over http
+    Url {
+        url: String,
+        insecure: Option<bool>,
+    },
+    Bucket {
+        bucket: String,
+        key: String,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(untagged)]
+enum Selector {
+    All(bool),
+    Names(Vec<String>),
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(untagged)]
+enum Timeout {
+    Seconds { value: u32 },
+    Duration { value: String, jitter: Option<bool> },
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Mirror")]
+struct MirrorSpec {
+    port: Port,
+    source: Source,
+    selector: Option<Selector>,
+    timeout: Timeout,
+}
+
+#[test]
+fn test_untagged_enums_are_structural() {
+    use kube::core::{crd::check_structural, CustomResourceExt};
+    let schema = Mirror::crd().spec.versions[0].schema.clone().unwrap();
+    assert_eq!(
+        check_structural(schema.open_api_v3_schema.as_ref().unwrap()),
+        Vec::<String>::new()
+    );
+
+    let crd = serde_json::to_value(Mirror::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+
+    assert_eq!(
+        spec["properties"]["port"],
+        serde_json::json!({ "x-kubernetes-int-or-string": true })
+    );
+    assert_eq!(
+        spec["properties"]["source"]["anyOf"],
+        serde_json::json!([{ "required": ["url"] }, { "required": ["bucket", "key"] }])
+    );
+    assert_eq!(spec["properties"]["source"]["type"], "object");
+    assert_eq!(
+        spec["properties"]["source"]["properties"]["key"],
+        serde_json::json!({ "type": "string" })
+    );
+    let selector = &spec["properties"]["selector"];
+    assert_eq!(selector["x-kubernetes-preserve-unknown-fields"], true);
+    assert_eq!(selector["nullable"], true);
+    assert!(selector.get("anyOf").is_none());
+    // `value` is defined differently by the variants, so it can not be hoisted
+    let timeout = &spec["properties"]["timeout"];
+    assert_eq!(timeout["x-kubernetes-preserve-unknown-fields"], true);
+    assert!(timeout.get("properties").is_none());
+    assert!(timeout.get("anyOf").is_none());
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Store {
+    S3 { bucket: String, region: Option<String> },
+    Gcs { bucket: String, project: String },
+    Memory,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Backend")]
+struct BackendSpec {
+    store: Store,
+}
+
+#[test]
+fn test_tagged_enum_with_shared_fields_is_structural() {
+    use kube::core::{crd::check_structural, CustomResourceExt};
+    let schema = Backend::crd().spec.versions[0].schema.clone().unwrap();
+    assert_eq!(
+        check_structural(schema.open_api_v3_schema.as_ref().unwrap()),
+        Vec::<String>::new()
+    );
+
+    let crd = serde_json::to_value(Backend::crd()).unwrap();
+    let store =
+        &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"]["store"];
+    // `bucket` is shared by two variants, so the `oneOf` would match both
+    assert!(store.get("oneOf").is_none());
+    assert_eq!(store["required"], serde_json::json!(["type"]));
+    let properties = store["properties"]
+        .as_object()
+        .unwrap()
+        .keys()
+        .collect::<Vec<_>>();
+    assert_eq!(properties, ["bucket", "project", "region", "type"]);
+    assert_eq!(
+        store["x-kubernetes-validations"],
+        serde_json::json!([
+            {
+                "rule": "!has(self.type) || self.type in ['s3', 'gcs', 'memory']",
+                "message": "type must be one of: s3, gcs, memory",
+            },
+            {
+                "rule": "!has(self.type) || self.type != 's3' || (has(self.bucket) && !has(self.project))",
+                "message": "when type is s3, bucket is required and project is not allowed",
+            },
+            {
+                "rule": "!has(self.type) || self.type != 'gcs' || (has(self.bucket) && has(self.project) && !has(self.region))",
+                "message": "when type is gcs, bucket, project are required and region is not allowed",
+            },
+            {
+                "rule": "!has(self.type) || self.type != 'memory' || (!has(self.bucket) && !has(self.project) && !has(self.region))",
+                "message": "when type is memory, bucket, project, region are not allowed",
+            },
+        ])
+    );
+}
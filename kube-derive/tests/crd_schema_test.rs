@@ -2,7 +2,7 @@ use chrono::{DateTime, NaiveDateTime, Utc};
 use kube_derive::CustomResource;
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 // See `crd_derive_schema` example for how the schema generated from this struct affects defaulting and validation.
 #[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
@@ -76,6 +76,45 @@ fn test_crd_name() {
     assert_eq!("foos.clux.dev", Foo::crd_name());
 }
 
+#[test]
+fn test_crd_kind() {
+    use kube::core::CustomResourceExt;
+    assert_eq!(Foo::crd_kind(), "Foo");
+    assert_eq!(Foo::crd_kind(), Foo::crd().spec.names.kind);
+}
+
+#[test]
+fn test_crd_schema_is_structural() {
+    use kube::core::{crd::check_structural, CustomResourceExt};
+    let crd = Foo::crd();
+    let schema = crd.spec.versions[0].schema.as_ref().unwrap();
+    assert_eq!(
+        check_structural(schema.open_api_v3_schema.as_ref().unwrap()),
+        Vec::<String>::new()
+    );
+}
+
+#[test]
+fn test_root_schema_matches_crd_schema() {
+    use kube::core::CustomResourceExt;
+    let root_schema = serde_json::to_value(Foo::root_schema().unwrap()).unwrap();
+    let crd_schema = serde_json::to_value(
+        &Foo::crd().spec.versions[0]
+            .schema
+            .as_ref()
+            .unwrap()
+            .open_api_v3_schema,
+    )
+    .unwrap();
+    assert_eq!(root_schema, crd_schema);
+    assert!(Flattening::root_schema().is_some());
+    assert_eq!(
+        serde_json::to_value(Release::root_schema().unwrap()).unwrap()["properties"]["spec"]["properties"]
+            ["apiVersion"]["pattern"],
+        "^v[0-9]+$"
+    );
+}
+
 #[test]
 fn test_shortnames() {
     use kube::core::CustomResourceExt;
@@ -262,3 +301,2506 @@ fn flattening() {
     assert_eq!(spec.x_kubernetes_preserve_unknown_fields, Some(true));
     assert_eq!(spec.additional_properties, None);
 }
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Listener {
+    #[serde(default = "default_protocol")]
+    protocol: String,
+    port: u16,
+}
+
+fn default_protocol() -> String {
+    "TCP".into()
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Gateway")]
+struct GatewaySpec {
+    listeners: Vec<Listener>,
+}
+
+#[test]
+fn defaults_propagate_into_array_items() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Gateway::crd()).unwrap();
+    let listeners = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]
+        ["properties"]["listeners"];
+    assert_eq!(listeners["type"], "array");
+    assert_eq!(listeners["items"]["properties"]["protocol"]["default"], "TCP");
+    assert_eq!(listeners["items"]["required"], serde_json::json!(["port"]));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema, validator::Validate)]
+#[kube(group = "clux.dev", version = "v1", kind = "Pool")]
+struct PoolSpec {
+    #[validate(range(min = 1, max = 10))]
+    replicas: i32,
+    #[validate(length(min = 3, max = 32))]
+    name: String,
+}
+
+#[test]
+fn validation_attrs_drive_schema_and_validate() {
+    use kube::core::CustomResourceExt;
+    use validator::Validate;
+    let crd = serde_json::to_value(Pool::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["replicas"]["minimum"], 1.0);
+    assert_eq!(props["replicas"]["maximum"], 10.0);
+    assert_eq!(props["name"]["minLength"], 3);
+    assert_eq!(props["name"]["maxLength"], 32);
+
+    let valid = PoolSpec {
+        replicas: 3,
+        name: "pool".into(),
+    };
+    assert!(valid.validate().is_ok());
+    let errors = PoolSpec {
+        replicas: 11,
+        name: "p".into(),
+    }
+    .validate()
+    .unwrap_err();
+    let fields = errors.field_errors();
+    assert!(fields.contains_key("replicas"));
+    assert!(fields.contains_key("name"));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Depot", validate)]
+#[serde(rename_all = "camelCase")]
+struct DepotSpec {
+    #[kube(min_length = 3, max_length = 63, pattern = "^[a-z0-9-]+$")]
+    name: String,
+    #[kube(exclusive_minimum = 0, multiple_of = 1024)]
+    quota_bytes: u64,
+    #[kube(min_items = 1, max_items = 2)]
+    regions: Vec<String>,
+    #[kube(max_length = 8)]
+    owner: Option<String>,
+}
+
+#[test]
+fn validate_checks_kube_constraints() {
+    use kube::core::crd::FieldViolation;
+    let depot = |name: &str, quota_bytes, regions: &[&str], owner: Option<&str>| DepotSpec {
+        name: name.into(),
+        quota_bytes,
+        regions: regions.iter().map(|r| r.to_string()).collect(),
+        owner: owner.map(Into::into),
+    };
+    assert_eq!(depot("logs", 2048, &["eu"], None).validate(), Ok(()));
+    assert_eq!(depot("logs", 2048, &["eu", "us"], Some("ops")).validate(), Ok(()));
+
+    let violation = |path: &str, reason: &str| FieldViolation {
+        path: path.into(),
+        reason: reason.into(),
+    };
+    assert_eq!(
+        depot("Logs", 0, &[], Some("platform-team")).validate(),
+        Err(vec![
+            violation(
+                "name",
+                r#"Invalid value: "Logs": name in body should match '^[a-z0-9-]+$'"#
+            ),
+            violation("quotaBytes", "Invalid value: 0: should be greater than 0"),
+            violation("regions", "Invalid value: 0: must have at least 1 items"),
+            violation("owner", "Too long: may not be more than 8 chars"),
+        ])
+    );
+    assert_eq!(
+        depot("l", 1000, &["eu", "us", "ap"], None).validate(),
+        Err(vec![
+            violation("name", r#"Invalid value: "l": must be at least 3 chars long"#),
+            violation("quotaBytes", "Invalid value: 1000: should be a multiple of 1024"),
+            violation("regions", "Too many: 3: must have at most 2 items"),
+        ])
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Release")]
+#[serde(rename_all = "camelCase")]
+struct ReleaseSpec {
+    #[kube(pattern = "^v[0-9]+$")]
+    api_version: String,
+    #[kube(pattern = "^[a-z]+$")]
+    #[serde(rename = "channel")]
+    release_channel: Option<String>,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Host")]
+struct HostSpec {
+    #[kube(min_length = 1, max_length = 63)]
+    hostname: String,
+    #[kube(max_length = 253)]
+    domain: Option<String>,
+}
+
+#[test]
+fn length_attrs_set_string_bounds() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Host::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["hostname"]["minLength"], 1);
+    assert_eq!(props["hostname"]["maxLength"], 63);
+    assert_eq!(props["domain"].get("minLength"), None);
+    assert_eq!(props["domain"]["maxLength"], 253);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Throttle")]
+struct ThrottleSpec {
+    #[kube(exclusive_minimum = 0, exclusive_maximum = 1.5)]
+    ratio: f64,
+    #[kube(exclusive_minimum = "-1")]
+    offset: Option<i32>,
+    #[kube(exclusive_minimum = "-5")]
+    burst: u32,
+    #[kube(exclusive_minimum = 0)]
+    queue: u32,
+}
+
+#[test]
+fn exclusive_bound_attrs_use_openapi_v3_form_in_crd() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Throttle::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["ratio"]["minimum"], 0.0);
+    assert_eq!(props["ratio"]["exclusiveMinimum"], true);
+    assert_eq!(props["ratio"]["maximum"], 1.5);
+    assert_eq!(props["ratio"]["exclusiveMaximum"], true);
+    assert_eq!(props["offset"]["minimum"], -1.0);
+    assert_eq!(props["offset"]["exclusiveMinimum"], true);
+    assert_eq!(props["offset"].get("exclusiveMaximum"), None);
+    // the `minimum: 0` of unsigned integers is tighter than a negative exclusive bound
+    assert_eq!(props["burst"]["minimum"], 0.0);
+    assert_eq!(props["burst"].get("exclusiveMinimum"), None);
+    assert_eq!(props["queue"]["minimum"], 0.0);
+    assert_eq!(props["queue"]["exclusiveMinimum"], true);
+
+    let schema = serde_json::to_value(Throttle::root_schema().unwrap()).unwrap();
+    let props = &schema["properties"]["spec"]["properties"];
+    assert_eq!(props["ratio"]["exclusiveMinimum"], 0.0);
+    assert_eq!(props["ratio"]["exclusiveMaximum"], 1.5);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Cluster")]
+struct ClusterSpec {
+    #[kube(min_items = 1, max_items = 10)]
+    nodes: Vec<String>,
+    #[kube(max_items = 3)]
+    zones: Option<Vec<String>>,
+}
+
+#[test]
+fn items_attrs_set_array_bounds() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Cluster::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["nodes"]["minItems"], 1);
+    assert_eq!(props["nodes"]["maxItems"], 10);
+    assert_eq!(props["zones"].get("minItems"), None);
+    assert_eq!(props["zones"]["maxItems"], 3);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Allocation")]
+struct AllocationSpec {
+    #[kube(treat_empty_as_absent)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nodes: Option<Vec<String>>,
+    zones: Option<Vec<String>>,
+}
+
+#[test]
+fn treat_empty_as_absent_rejects_empty_arrays() {
+    use kube::core::{crd::validate_instance, CustomResourceExt};
+    let crd = Allocation::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let spec = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert!(spec.get("required").is_none());
+    for field in ["nodes", "zones"] {
+        assert_eq!(spec["properties"][field]["type"], "array");
+        assert_eq!(spec["properties"][field]["items"]["type"], "string");
+        assert_eq!(spec["properties"][field]["nullable"], true);
+    }
+    assert_eq!(spec["properties"]["nodes"]["minItems"], 1);
+    assert!(spec["properties"]["zones"].get("minItems").is_none());
+
+    let allocation = |nodes: Option<Vec<String>>| {
+        let allocation = Allocation::new("a", AllocationSpec {
+            nodes,
+            zones: Some(vec![]),
+        });
+        serde_json::from_value(serde_json::to_value(allocation).unwrap()).unwrap()
+    };
+    assert_eq!(validate_instance(&crd, &allocation(None)), Ok(()));
+    assert_eq!(
+        validate_instance(&crd, &allocation(Some(vec!["a".into()]))),
+        Ok(())
+    );
+    assert!(validate_instance(&crd, &allocation(Some(vec![]))).is_err());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Volume")]
+#[kube(printcolumn = r#"{"name":"Size", "type":"integer", "jsonPath":".spec.sizeGib"}"#)]
+#[kube(selectable = ".spec.storageClass", selectable = ".spec.mount_path")]
+#[serde(rename_all = "camelCase")]
+struct VolumeSpec {
+    size_gib: u32,
+    storage_class: Option<String>,
+    #[serde(rename = "mount_path")]
+    #[kube(pattern = "^/")]
+    mount_path: String,
+}
+
+#[test]
+fn schema_and_paths_use_serialized_names() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Volume::crd()).unwrap();
+    let version = &crd["spec"]["versions"][0];
+    let spec = &version["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    let mut keys = spec["properties"].as_object().unwrap().keys().collect::<Vec<_>>();
+    keys.sort();
+    assert_eq!(keys, ["mount_path", "sizeGib", "storageClass"]);
+    assert_eq!(spec["required"], serde_json::json!(["mount_path", "sizeGib"]));
+    assert_eq!(spec["properties"]["mount_path"]["pattern"], "^/");
+    assert_eq!(
+        version["additionalPrinterColumns"][0]["jsonPath"],
+        ".spec.sizeGib"
+    );
+    assert_eq!(Volume::selectable_fields(), [
+        ".spec.storageClass",
+        ".spec.mount_path"
+    ]);
+
+    let volume = Volume::new("v", VolumeSpec {
+        size_gib: 1,
+        storage_class: None,
+        mount_path: "/data".into(),
+    });
+    let json = serde_json::to_value(&volume).unwrap();
+    assert_eq!(json["spec"]["sizeGib"], 1);
+    assert_eq!(json["spec"]["mount_path"], "/data");
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Lease")]
+#[serde(rename_all = "camelCase")]
+struct LeaseSpec {
+    #[kube(nullable)]
+    #[serde(default)]
+    holders: Vec<String>,
+    renew_time: Option<String>,
+    #[kube(nullable = false)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    transitions: Option<u32>,
+}
+
+#[test]
+fn nullable_attr_is_independent_of_optional() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Lease::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(spec.get("required"), None);
+    assert_eq!(spec["properties"]["holders"]["nullable"], true);
+    assert_eq!(spec["properties"]["renewTime"]["nullable"], true);
+    assert_eq!(spec["properties"]["transitions"].get("nullable"), None);
+    assert_eq!(spec["properties"]["transitions"]["type"], "integer");
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Config<T> {
+    replicas: u32,
+    profile: T,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Prod {
+    tier: String,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "ProdConfig")]
+struct ProdConfigSpec(Config<Prod>);
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Profiled")]
+struct ProfiledSpec<T = Prod> {
+    profile: T,
+}
+
+#[test]
+fn generic_specs_use_their_instantiation() {
+    use kube::core::CustomResourceExt;
+    let prod_tier = serde_json::json!({
+        "type": "object",
+        "properties": { "tier": { "type": "string" } },
+        "required": ["tier"]
+    });
+
+    let crd = serde_json::to_value(ProdConfig::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(spec["properties"]["replicas"]["type"], "integer");
+    assert_eq!(spec["properties"]["profile"], prod_tier);
+    assert_eq!(spec["required"], serde_json::json!(["profile", "replicas"]));
+
+    let crd = serde_json::to_value(Profiled::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(spec["properties"]["profile"], prod_tier);
+
+    let config = ProdConfig::new(
+        "p",
+        ProdConfigSpec(Config {
+            replicas: 2,
+            profile: Prod { tier: "gold".into() },
+        }),
+    );
+    assert_eq!(
+        serde_json::to_value(&config).unwrap()["spec"]["profile"]["tier"],
+        "gold"
+    );
+}
+
+mod base64 {
+    use k8s_openapi::ByteString;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        ByteString(bytes.to_vec()).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        ByteString::deserialize(deserializer).map(|b| b.0)
+    }
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Blob")]
+struct BlobSpec {
+    #[kube(schema = "byte")]
+    #[serde(
+        serialize_with = "base64::serialize",
+        deserialize_with = "base64::deserialize"
+    )]
+    data: Vec<u8>,
+    #[kube(schema = "byte")]
+    #[serde(
+        default,
+        serialize_with = "base64::serialize",
+        deserialize_with = "base64::deserialize"
+    )]
+    checksum: Vec<u8>,
+}
+
+#[test]
+fn byte_schema_attr_emits_base64_string() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Blob::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(
+        props["data"],
+        serde_json::json!({ "type": "string", "format": "byte" })
+    );
+    assert_eq!(props["checksum"]["type"], "string");
+    assert_eq!(props["checksum"]["format"], "byte");
+
+    let blob = Blob::new("b", BlobSpec {
+        data: b"kube".to_vec(),
+        checksum: vec![],
+    });
+    assert_eq!(serde_json::to_value(&blob).unwrap()["spec"]["data"], "a3ViZQ==");
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Sandbox")]
+#[serde(rename_all = "camelCase")]
+struct SandboxSpec {
+    #[kube(multiple_of = 64)]
+    memory_mib: u32,
+    #[kube(multiple_of = 0.5)]
+    cpus: Option<f64>,
+}
+
+#[test]
+fn multiple_of_attr_sets_multiple_of() {
+    use kube::core::{crd::FakeApiserver, CustomResourceExt};
+    let crd = Sandbox::crd();
+    let schema = crd.spec.versions[0]
+        .schema
+        .as_ref()
+        .unwrap()
+        .open_api_v3_schema
+        .as_ref()
+        .unwrap();
+    let props = schema.properties.as_ref().unwrap()["spec"]
+        .properties
+        .clone()
+        .unwrap();
+    assert_eq!(props["memoryMib"].multiple_of, Some(64.0));
+    assert_eq!(props["cpus"].multiple_of, Some(0.5));
+    assert_eq!(FakeApiserver::accept(&crd), Ok(()));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Scaler")]
+struct ScalerSpec {
+    /// Number of pods to run
+    #[kube(title = "Replica count")]
+    replicas: u32,
+    #[schemars(title = "Target")]
+    target: String,
+    #[schemars(title = "Ignored")]
+    #[kube(title = "Cooldown")]
+    cooldown: u32,
+}
+
+#[test]
+fn title_attr_sets_schema_title() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Scaler::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["replicas"]["title"], "Replica count");
+    assert_eq!(props["replicas"]["description"], "Number of pods to run");
+    assert_eq!(props["target"]["title"], "Target");
+    assert_eq!(props["cooldown"]["title"], "Cooldown");
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+struct ServicePort {
+    name: String,
+    container_port: u16,
+    protocol: Option<String>,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "PortSet")]
+struct PortSetSpec {
+    #[kube(list_type = "map", list_map_key = "name", list_map_key = "containerPort")]
+    ports: Vec<ServicePort>,
+}
+
+#[test]
+fn list_map_keys_are_emitted() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(PortSet::crd()).unwrap();
+    let ports =
+        &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"]["ports"];
+    assert_eq!(ports["x-kubernetes-list-type"], "map");
+    assert_eq!(
+        ports["x-kubernetes-list-map-keys"],
+        serde_json::json!(["name", "containerPort"])
+    );
+    assert!(ports["items"]["properties"].get("containerPort").is_some());
+}
+
+#[test]
+fn pattern_attr_sets_schema_pattern() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Release::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["apiVersion"]["pattern"], "^v[0-9]+$");
+    assert_eq!(props["channel"]["pattern"], "^[a-z]+$");
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone)]
+#[kube(
+    group = "metrics.clux.dev",
+    version = "v1beta1",
+    kind = "NodeMetric",
+    shortname = "nm",
+    aggregated
+)]
+struct NodeMetricSpec {
+    usage: String,
+}
+
+#[test]
+fn aggregated_keeps_discovery_helpers() {
+    let ar = NodeMetric::api_resource();
+    assert_eq!(ar.api_version, "metrics.clux.dev/v1beta1");
+    assert_eq!(ar.kind, "NodeMetric");
+    assert_eq!(ar.plural, "nodemetrics");
+    assert_eq!(NodeMetric::shortnames(), &["nm"]);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct CommonSpec {
+    replicas: i32,
+    paused: Option<bool>,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Worker")]
+struct WorkerSpec {
+    image: String,
+    #[serde(flatten)]
+    common: CommonSpec,
+}
+
+#[test]
+fn flattened_struct_fields_are_merged_into_parent() {
+    use kube::core::{crd::check_structural, CustomResourceExt};
+    let crd = Worker::crd();
+    let schema = crd.spec.versions[0]
+        .schema
+        .as_ref()
+        .and_then(|s| s.open_api_v3_schema.as_ref())
+        .unwrap();
+    assert!(check_structural(schema).is_empty());
+    let spec = serde_json::to_value(&schema.properties.as_ref().unwrap()["spec"]).unwrap();
+    assert_eq!(spec["properties"]["replicas"]["type"], "integer");
+    assert_eq!(spec["properties"]["paused"]["type"], "boolean");
+    assert_eq!(spec["required"], serde_json::json!(["image", "replicas"]));
+    assert!(spec.get("allOf").is_none());
+    assert!(spec.get("additionalProperties").is_none());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "policy.x-k8s.io",
+    version = "v1alpha1",
+    kind = "Policy",
+    crd_annotation(
+        "api-approved.kubernetes.io",
+        "https://github.com/kubernetes/enhancements/pull/1111"
+    ),
+    crd_annotation("clux.dev/owner", "infra")
+)]
+struct PolicySpec {
+    rules: Vec<String>,
+}
+
+#[test]
+fn crd_annotations_are_set_on_crd_metadata() {
+    use kube::core::CustomResourceExt;
+    let annotations = Policy::crd().metadata.annotations.unwrap();
+    assert_eq!(
+        annotations["api-approved.kubernetes.io"],
+        "https://github.com/kubernetes/enhancements/pull/1111"
+    );
+    assert_eq!(annotations["clux.dev/owner"], "infra");
+    assert_eq!(annotations.len(), 2);
+    assert_eq!(Foo::crd().metadata.annotations, None);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Backend {
+    host: String,
+    weight: Option<i32>,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Router")]
+struct RouterSpec {
+    backends: BTreeMap<String, Backend>,
+    fallbacks: Option<HashMap<String, Backend>>,
+}
+
+#[test]
+fn typed_maps_have_value_schemas() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Router::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    for map in ["backends", "fallbacks"] {
+        let values = &props[map]["additionalProperties"];
+        assert_eq!(props[map]["type"], "object");
+        assert_eq!(values["type"], "object");
+        assert_eq!(values["properties"]["host"]["type"], "string");
+        assert_eq!(values["required"], serde_json::json!(["host"]));
+        assert!(props[map].get("x-kubernetes-preserve-unknown-fields").is_none());
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Tuning {
+    cpu: Option<String>,
+    memory: Option<String>,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Tuned")]
+struct TunedSpec {
+    #[kube(map_type = "granular")]
+    tuning: Tuning,
+    #[kube(map_type = "atomic")]
+    selector: Option<BTreeMap<String, String>>,
+}
+
+#[test]
+fn map_type_attr_sets_extension_on_object_node() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Tuned::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["tuning"]["x-kubernetes-map-type"], "granular");
+    assert_eq!(
+        props["tuning"]["properties"]["cpu"].get("x-kubernetes-map-type"),
+        None
+    );
+    assert_eq!(props["selector"]["x-kubernetes-map-type"], "atomic");
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Runner")]
+struct RunnerSpec {
+    #[kube(default = "Running")]
+    phase: String,
+    #[kube(default = "3")]
+    replicas: Option<i32>,
+    #[kube(default = "true")]
+    enabled: bool,
+    #[kube(default = "0.5")]
+    ratio: f64,
+}
+
+#[test]
+fn default_attr_sets_schema_default() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Runner::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["phase"]["default"], "Running");
+    assert_eq!(props["replicas"]["default"], 3);
+    assert_eq!(props["enabled"]["default"], true);
+    assert_eq!(props["ratio"]["default"], 0.5);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "clux.dev",
+    version = "v1",
+    kind = "Plugin",
+    validation = "has(self.config) || self.mode != 'custom'",
+    validation = "self.mode != 'disabled' || !has(self.config)"
+)]
+struct PluginSpec {
+    mode: String,
+    config: Option<String>,
+}
+
+#[test]
+fn struct_validation_attr_appends_rules_to_spec() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Plugin::crd()).unwrap();
+    let schema = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"];
+    assert_eq!(
+        schema["properties"]["spec"]["x-kubernetes-validations"],
+        serde_json::json!([
+            { "rule": "has(self.config) || self.mode != 'custom'" },
+            { "rule": "self.mode != 'disabled' || !has(self.config)" },
+        ])
+    );
+    assert_eq!(schema.get("x-kubernetes-validations"), None);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Team")]
+struct TeamSpec {
+    #[kube(unique_items, list_type = "set")]
+    members: Vec<String>,
+    #[kube(unique_items)]
+    tags: Option<Vec<String>>,
+    history: Vec<String>,
+}
+
+#[test]
+fn unique_items_attr_sets_array_keywords() {
+    use kube::core::CustomResourceExt;
+    let schema = Team::root_schema().unwrap();
+    let props = &serde_json::to_value(&schema).unwrap()["properties"]["spec"]["properties"];
+    assert_eq!(props["members"]["uniqueItems"], true);
+    assert_eq!(props["members"]["x-kubernetes-list-type"], "set");
+    assert_eq!(props["tags"]["uniqueItems"], true);
+    assert_eq!(props["tags"].get("x-kubernetes-list-type"), None);
+    assert_eq!(props["history"].get("uniqueItems"), None);
+    assert_eq!(props["history"].get("x-kubernetes-list-type"), None);
+}
+
+#[test]
+fn spec_schema_is_spec_node_of_crd_schema() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Deployment::crd()).unwrap();
+    let schema = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"];
+    let spec_schema = Deployment::spec_schema().unwrap();
+    assert_eq!(
+        serde_json::to_value(&spec_schema).unwrap(),
+        schema["properties"]["spec"]
+    );
+    assert!(spec_schema.properties.unwrap().contains_key("image"));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Job", selectable = ".spec.phase")]
+struct JobSpec {
+    phase: String,
+    retries: u32,
+}
+
+#[test]
+fn selectable_fields_restrict_field_selectors() {
+    use kube::core::{crd::SelectableFieldSelector, CustomResourceExt};
+    assert_eq!(Job::selectable_fields(), &[".spec.phase"]);
+    let selector = SelectableFieldSelector::for_resource::<Job>()
+        .eq("spec.phase", "Running")
+        .unwrap();
+    assert_eq!(
+        selector.list_params().field_selector.unwrap(),
+        "spec.phase=Running"
+    );
+    assert!(SelectableFieldSelector::for_resource::<Job>()
+        .eq("spec.retries", "1")
+        .is_err());
+}
+
+// A domain newtype, whose schema should be that of the wrapped integer (a doc comment would add a description)
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Port(u16);
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Service")]
+struct ServiceSpec {
+    port: Port,
+    raw_port: u16,
+    fallback: Option<Port>,
+}
+
+#[test]
+fn newtype_fields_use_inner_schema() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Service::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["port"], props["raw_port"]);
+    assert_eq!(props["port"]["type"], "integer");
+    assert_eq!(props["port"]["format"], "int32");
+    assert_eq!(props["port"]["minimum"], 0.0);
+}
+
+// Domain id types, which should be plain strings in the schema like the `String` they wrap
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct ClusterId(String);
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(transparent)]
+struct Uid {
+    value: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct RegionId(#[schemars(length(min = 1, max = 63), regex(pattern = r"^[a-z0-9-]+$"))] String);
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Membership")]
+struct MembershipSpec {
+    cluster: ClusterId,
+    raw_cluster: String,
+    uid: Uid,
+    region: RegionId,
+    peers: Vec<ClusterId>,
+}
+
+#[test]
+fn string_newtypes_are_plain_strings() {
+    use kube::core::{crd::FakeApiserver, CustomResourceExt};
+    let crd = Membership::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let props = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["cluster"], serde_json::json!({ "type": "string" }));
+    assert_eq!(props["cluster"], props["raw_cluster"]);
+    assert_eq!(props["uid"], props["raw_cluster"]);
+    assert_eq!(props["peers"]["items"], props["raw_cluster"]);
+    assert_eq!(
+        props["region"],
+        serde_json::json!({ "type": "string", "minLength": 1, "maxLength": 63, "pattern": "^[a-z0-9-]+$" })
+    );
+    assert_eq!(FakeApiserver::accept(&crd), Ok(()));
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Bounds {
+    min: u32,
+    max: u32,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Autoscaler")]
+struct AutoscalerSpec {
+    #[kube(validation(rule = "self.min <= self.max", message = "min must not exceed max"))]
+    #[kube(validation(rule = "self.max <= 100"))]
+    replicas: Bounds,
+}
+
+#[test]
+fn validation_attr_appends_cel_rules() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Autoscaler::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(
+        props["replicas"]["x-kubernetes-validations"],
+        serde_json::json!([
+            { "rule": "self.min <= self.max", "message": "min must not exceed max" },
+            { "rule": "self.max <= 100" }
+        ])
+    );
+}
+
+#[test]
+fn owner_ref_points_at_derived_resource() {
+    use kube::core::{crd::OwnerReferenceExt, ObjectMeta};
+    let mut job = Job::new("job", JobSpec {
+        phase: "Running".into(),
+        retries: 0,
+    });
+    assert_eq!(job.owner_ref(), None);
+
+    job.metadata = ObjectMeta {
+        name: Some("job".into()),
+        uid: Some("1234".into()),
+        ..ObjectMeta::default()
+    };
+    let owner = job.owner_ref().unwrap();
+    assert_eq!(owner.api_version, "clux.dev/v1");
+    assert_eq!(owner.kind, "Job");
+    assert_eq!((owner.name.as_str(), owner.uid.as_str()), ("job", "1234"));
+    assert_eq!(owner.controller, Some(true));
+    assert_eq!(owner.block_owner_deletion, Some(true));
+
+    let owner = job.owner_ref_builder().controller(false).build().unwrap();
+    assert_eq!(owner.controller, Some(false));
+    assert_eq!(owner.block_owner_deletion, Some(true));
+}
+
+mod status_types {
+    use schemars::JsonSchema;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    pub struct DeploymentStatus {
+        #[serde(default)]
+        pub ready: bool,
+        #[schemars(range(min = 0))]
+        pub replicas: i32,
+    }
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "clux.dev",
+    version = "v1",
+    kind = "Deployment",
+    status = "status_types::DeploymentStatus"
+)]
+struct DeploymentSpec {
+    image: String,
+}
+
+#[test]
+fn status_type_from_other_module_is_embedded() {
+    use kube::core::{object::HasStatus, CustomResourceExt};
+    let crd = serde_json::to_value(Deployment::crd()).unwrap();
+    let version = &crd["spec"]["versions"][0];
+    assert_eq!(version["subresources"]["status"], serde_json::json!({}));
+    let status = &version["schema"]["openAPIV3Schema"]["properties"]["status"];
+    assert_eq!(status["nullable"], true);
+    assert_eq!(status["properties"]["ready"]["default"], false);
+    assert_eq!(status["properties"]["replicas"]["minimum"], 0.0);
+    assert_eq!(status["required"], serde_json::json!(["replicas"]));
+
+    let deployment = Deployment::new("nginx", DeploymentSpec {
+        image: "nginx".into(),
+    });
+    assert!(deployment.status().is_none());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "JobV2", plural = "jobs")]
+struct JobV2Spec {
+    phase: String,
+    retries: u32,
+    deadline: Option<u32>,
+}
+
+#[test]
+fn schema_hash_is_stable_and_tracks_fields() {
+    use kube::core::{crd::schema_hash, CustomResourceExt};
+    assert_eq!(Job::schema_hash(), schema_hash(&Job::crd()));
+    assert!(std::ptr::eq(Job::schema_hash(), Job::schema_hash()));
+    assert_ne!(Job::schema_hash(), JobV2::schema_hash());
+}
+
+#[test]
+fn matches_live_agrees_with_semantic_eq() {
+    use kube::core::{
+        crd::{annotate_schema_hash, semantic_eq},
+        CustomResourceExt,
+    };
+    for live in [Job::crd(), JobV2::crd()] {
+        let mut annotated = live.clone();
+        annotate_schema_hash(&mut annotated);
+        let expected = semantic_eq(&Job::crd(), &live);
+        assert_eq!(Job::matches_live(&live), expected);
+        assert_eq!(Job::matches_live(&annotated), expected);
+    }
+    assert!(Job::matches_live(&Job::crd()));
+    assert!(!Job::matches_live(&JobV2::crd()));
+}
+
+mod widget {
+    use super::*;
+
+    pub mod v1 {
+        use super::*;
+        #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+        #[kube(group = "clux.dev", version = "v1", kind = "Widget")]
+        pub struct WidgetSpec {
+            pub size: u32,
+        }
+    }
+
+    pub mod v2 {
+        use super::*;
+        #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+        #[kube(group = "clux.dev", version = "v2", kind = "Widget")]
+        pub struct WidgetSpec {
+            pub size: u32,
+            pub color: String,
+        }
+    }
+}
+
+#[test]
+fn crd_merger_macro_matches_manual_construction() {
+    use kube::core::{crd::CrdMerger, CustomResourceExt};
+    let from_macro = kube_derive::crd_merger![widget::v1::Widget, widget::v2::Widget,];
+    let manual = CrdMerger::new(vec![widget::v1::Widget::crd(), widget::v2::Widget::crd()]);
+    assert_eq!(from_macro.planned_versions(), vec!["v1", "v2"]);
+    assert_eq!(
+        from_macro.storage("v2").merge().unwrap(),
+        manual.storage("v2").merge().unwrap()
+    );
+}
+
+#[test]
+fn derived_crds_are_accepted_by_fake_apiserver() {
+    use kube::core::{crd::FakeApiserver, CustomResourceExt};
+    assert_eq!(FakeApiserver::accept(&Deployment::crd()), Ok(()));
+    assert_eq!(FakeApiserver::accept(&Plugin::crd()), Ok(()));
+    assert_eq!(FakeApiserver::accept(&Team::crd()), Ok(()));
+    // `uniqueItems` is only kept in the root schema
+    let mut crd = Team::crd();
+    let schema = crd.spec.versions[0].schema.as_mut().unwrap();
+    let root = schema.open_api_v3_schema.as_mut().unwrap();
+    let spec = root.properties.as_mut().unwrap().get_mut("spec").unwrap();
+    for field in spec.properties.as_mut().unwrap().values_mut().take(2) {
+        field.unique_items = Some(true);
+    }
+    let rejected = FakeApiserver::accept(&crd).unwrap_err();
+    assert_eq!(rejected.len(), 2);
+    assert!(rejected
+        .iter()
+        .all(|r| r
+            .ends_with("uniqueItems cannot be set to true since the runtime complexity becomes quadratic")));
+}
+
+#[test]
+fn static_gvk_consts_match_crd() {
+    use kube::core::{CustomResourceExt, GroupVersionKind, StaticGroupVersionKind};
+    let crd = Foo::crd();
+    assert_eq!(Foo::GROUP, crd.spec.group);
+    assert_eq!(Foo::VERSION, crd.spec.versions[0].name);
+    assert_eq!(Foo::KIND, crd.spec.names.kind);
+    assert_eq!(Foo::gvk(), GroupVersionKind::gvk("clux.dev", "v1", "Foo"));
+    assert!(Foo::matches_gvk(&GroupVersionKind::gvk("clux.dev", "v1", "Foo")));
+    assert!(!Foo::matches_gvk(&GroupVersionKind::gvk("clux.dev", "v2", "Foo")));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Gadget", register)]
+struct GadgetSpec {
+    name: String,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Widget", namespaced, register)]
+struct WidgetSpec {
+    size: u32,
+}
+
+#[test]
+fn registered_types_are_collected() {
+    use kube::core::crd::{merge_all, registered_crds};
+    let crds = registered_crds();
+    let names = crds
+        .iter()
+        .map(|crd| crd.metadata.name.as_deref().unwrap())
+        .collect::<Vec<_>>();
+    // other registered types of the test binary (or its dependencies) may be collected too
+    for expected in ["gadgets.clux.dev", "widgets.clux.dev"] {
+        assert!(names.contains(&expected), "{} not in {:?}", expected, names);
+    }
+    let merged = merge_all(crds).unwrap();
+    assert!(merged
+        .iter()
+        .any(|crd| crd.metadata.name.as_deref() == Some("widgets.clux.dev")));
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Retention {
+    days: u32,
+    #[serde(default)]
+    compress: bool,
+    archive: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct Placement {
+    zone: String,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Archive")]
+struct ArchiveSpec {
+    bucket: String,
+    #[serde(rename = "storageClass")]
+    storage_class: String,
+    #[kube(default = "standard")]
+    tier: String,
+    prefix: Option<String>,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default = "default_replicas")]
+    replicas: u32,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    regions: Vec<String>,
+    #[serde(flatten)]
+    retention: Retention,
+    #[serde(flatten)]
+    placement: Option<Placement>,
+    retained: Retention,
+}
+
+fn default_replicas() -> u32 {
+    1
+}
+
+#[test]
+fn required_lists_fields_without_defaults() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Archive::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    // serde defaults (on the field or the flattened struct) and `Option`s are optional, schema defaults are not
+    assert_eq!(
+        spec["required"],
+        serde_json::json!(["bucket", "days", "retained", "storageClass", "tier"])
+    );
+    assert_eq!(
+        spec["properties"]["retained"]["required"],
+        serde_json::json!(["days"])
+    );
+    assert_eq!(spec["properties"]["zone"]["type"], "string");
+}
+
+#[test]
+fn minimal_instance_is_valid() {
+    use kube::core::{crd::validate_instance, CustomResourceExt};
+    let archive = Archive::minimal("logs");
+    assert_eq!(archive.metadata.name.as_deref(), Some("logs"));
+    assert_eq!(archive.data["spec"]["tier"], "standard");
+    assert_eq!(archive.data["spec"]["retained"], serde_json::json!({ "days": 0 }));
+    assert_eq!(validate_instance(&Archive::crd(), &archive), Ok(()));
+    let _: Archive = serde_json::from_value(serde_json::to_value(&archive).unwrap()).unwrap();
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Counter")]
+#[serde(rename_all = "camelCase")]
+struct CounterSpec {
+    small: i32,
+    unsigned: u32,
+    tiny: Option<u8>,
+    large: i64,
+    unsigned_large: u64,
+    #[kube(schema_format = "int64")]
+    widened: u16,
+    counts: Vec<u16>,
+}
+
+#[test]
+fn integer_formats_are_openapi_formats() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Counter::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    for (field, format) in [
+        ("small", "int32"),
+        ("unsigned", "int64"),
+        ("tiny", "int32"),
+        ("large", "int64"),
+        ("unsignedLarge", "int64"),
+        ("widened", "int64"),
+    ] {
+        assert_eq!(props[field]["format"], format, "format of {}", field);
+    }
+    assert_eq!(props["counts"]["items"]["format"], "int32");
+    assert_eq!(props["unsigned"]["minimum"], 0.0);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Ticket", age_column)]
+#[kube(printcolumn = r#"{"name":"Title", "type":"string", "jsonPath":".spec.title"}"#)]
+struct TicketSpec {
+    title: String,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Session", age_column)]
+#[kube(printcolumn = r#"{"name":"Age", "type":"date", "jsonPath":".status.startTime"}"#)]
+struct SessionSpec {
+    user: String,
+}
+
+#[test]
+fn age_column_is_added_once() {
+    use kube::core::CustomResourceExt;
+    let columns = |crd: k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition| {
+        let columns = crd.spec.versions[0].additional_printer_columns.clone().unwrap();
+        columns.into_iter().map(|c| (c.name, c.json_path)).collect::<Vec<_>>()
+    };
+    assert_eq!(columns(Ticket::crd()), vec![
+        ("Title".to_string(), ".spec.title".to_string()),
+        ("Age".to_string(), ".metadata.creationTimestamp".to_string()),
+    ]);
+    assert_eq!(columns(Session::crd()), vec![(
+        "Age".to_string(),
+        ".status.startTime".to_string()
+    )]);
+    assert_eq!(
+        Ticket::crd().spec.versions[0]
+            .additional_printer_columns
+            .as_ref()
+            .unwrap()[1]
+            .type_,
+        "date"
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "TlsRoute")]
+#[kube(dependent_required(tls_cert, tls_key))]
+#[serde(rename_all = "camelCase")]
+struct TlsRouteSpec {
+    port: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_cert: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tls_key: Option<String>,
+}
+
+#[test]
+fn dependent_required_emits_presence_rule() {
+    use kube::core::{crd::validate_instance, CustomResourceExt, DynamicObject};
+    let crd = serde_json::to_value(TlsRoute::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(
+        spec["x-kubernetes-validations"],
+        serde_json::json!([{
+            "rule": "!has(self.tlsCert) || has(self.tlsKey)",
+            "message": "tlsKey is required when tlsCert is set",
+        }])
+    );
+
+    let route = |tls_cert: Option<&str>, tls_key: Option<&str>| {
+        let route = TlsRoute::new("https", TlsRouteSpec {
+            port: 443,
+            tls_cert: tls_cert.map(String::from),
+            tls_key: tls_key.map(String::from),
+        });
+        serde_json::from_value::<DynamicObject>(serde_json::to_value(route).unwrap()).unwrap()
+    };
+    assert_eq!(validate_instance(&TlsRoute::crd(), &route(None, None)), Ok(()));
+    assert_eq!(
+        validate_instance(&TlsRoute::crd(), &route(Some("cert"), Some("key"))),
+        Ok(())
+    );
+    assert_eq!(
+        validate_instance(&TlsRoute::crd(), &route(Some("cert"), None)),
+        Err(vec![
+            r#"spec: Invalid value: "object": tlsKey is required when tlsCert is set"#.to_string()
+        ])
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Backup")]
+#[kube(one_of(s3_bucket, gcs_bucket, local_path))]
+#[serde(rename_all = "camelCase")]
+struct BackupSpec {
+    schedule: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    s3_bucket: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    gcs_bucket: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    local_path: Option<String>,
+}
+
+#[test]
+fn one_of_emits_exactly_one_rule() {
+    use kube::core::{crd::validate_instance, CustomResourceExt, DynamicObject};
+    let crd = serde_json::to_value(Backup::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(
+        spec["x-kubernetes-validations"],
+        serde_json::json!([{
+            "rule": "[has(self.s3Bucket), has(self.gcsBucket), has(self.localPath)].filter(x, x).size() == 1",
+            "message": "exactly one of s3Bucket, gcsBucket, localPath must be set",
+        }])
+    );
+
+    let backup = |s3_bucket: Option<&str>, gcs_bucket: Option<&str>| {
+        let backup = Backup::new("nightly", BackupSpec {
+            schedule: "@daily".into(),
+            s3_bucket: s3_bucket.map(String::from),
+            gcs_bucket: gcs_bucket.map(String::from),
+            local_path: None,
+        });
+        serde_json::from_value::<DynamicObject>(serde_json::to_value(backup).unwrap()).unwrap()
+    };
+    assert_eq!(
+        validate_instance(&Backup::crd(), &backup(Some("b"), None)),
+        Ok(())
+    );
+    assert_eq!(
+        validate_instance(&Backup::crd(), &backup(None, Some("b"))),
+        Ok(())
+    );
+    let failure = Err(vec![
+        r#"spec: Invalid value: "object": exactly one of s3Bucket, gcsBucket, localPath must be set"#
+            .to_string(),
+    ]);
+    assert_eq!(validate_instance(&Backup::crd(), &backup(None, None)), failure);
+    assert_eq!(
+        validate_instance(&Backup::crd(), &backup(Some("b"), Some("b"))),
+        failure
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Firewall")]
+#[serde(rename_all = "camelCase")]
+struct FirewallSpec {
+    #[kube(example = "deny")]
+    default_action: String,
+    #[kube(example = "8080")]
+    port: Option<u16>,
+    #[kube(example = r#"["10.0.0.0/8", "192.168.0.0/16"]"#)]
+    allowed_cidrs: Vec<String>,
+}
+
+#[test]
+fn example_attr_sets_schema_example() {
+    use kube::core::{crd::roundtrip_check, CustomResourceExt};
+    let crd = serde_json::to_value(Firewall::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["defaultAction"]["example"], "deny");
+    assert_eq!(props["port"]["example"], 8080);
+    assert_eq!(
+        props["allowedCidrs"]["example"],
+        serde_json::json!(["10.0.0.0/8", "192.168.0.0/16"])
+    );
+    assert!(props["allowedCidrs"]["items"].get("example").is_none());
+    // examples are part of the crd schema, so they survive applying the crd
+    assert!(roundtrip_check(&crd).is_ok());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Mailbox")]
+#[serde(rename_all = "camelCase")]
+struct MailboxSpec {
+    address: String,
+    #[serde(skip)]
+    #[allow(dead_code)]
+    cached_quota: u64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    aliases: Vec<String>,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    forward_to: String,
+    #[serde(skip_serializing)]
+    #[allow(dead_code)]
+    password: String,
+    #[serde(skip_deserializing)]
+    last_seen: String,
+}
+
+#[test]
+fn serde_skipped_fields_are_absent_or_optional() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Mailbox::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert!(spec["properties"].get("cachedQuota").is_none());
+    for field in ["aliases", "forwardTo", "password", "lastSeen"] {
+        assert!(
+            spec["properties"].get(field).is_some(),
+            "{} is in the schema",
+            field
+        );
+    }
+    assert_eq!(spec["required"], serde_json::json!(["address"]));
+}
+
+mod shard {
+    use super::*;
+    use kube::core::crd::ConversionWebhook;
+
+    const SHARD_CONVERSION: ConversionWebhook =
+        ConversionWebhook::service("shard-system", "shard-webhook").port(8443);
+
+    pub mod v1 {
+        use super::*;
+        #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+        #[kube(group = "clux.dev", version = "v1", kind = "Shard")]
+        #[kube(conversion = "webhook", conversion_webhook = "SHARD_CONVERSION")]
+        pub struct ShardSpec {
+            pub replicas: u32,
+        }
+    }
+
+    pub mod v2 {
+        use super::*;
+        #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+        #[kube(group = "clux.dev", version = "v2", kind = "Shard", conversion = "none")]
+        pub struct ShardSpec {
+            pub replicas: u32,
+            pub zone: String,
+        }
+    }
+}
+
+#[test]
+fn conversion_attr_sets_crd_conversion() {
+    use kube::core::{
+        crd::{CrdError, CrdMerger},
+        CustomResourceExt,
+    };
+    let conversion = shard::v1::Shard::crd().spec.conversion.unwrap();
+    assert_eq!(conversion.strategy, "Webhook");
+    let service = conversion
+        .webhook
+        .unwrap()
+        .client_config
+        .unwrap()
+        .service
+        .unwrap();
+    assert_eq!(
+        (service.namespace.as_str(), service.name.as_str(), service.port),
+        ("shard-system", "shard-webhook", Some(8443))
+    );
+    assert_eq!(shard::v2::Shard::crd().spec.conversion.unwrap().strategy, "None");
+
+    // `None` conversion can not convert between the differing schemas
+    let crds = vec![shard::v2::Shard::crd(), shard::v1::Shard::crd()];
+    assert_eq!(
+        CrdMerger::new(crds.clone()).merge().unwrap_err(),
+        CrdError::NoneConversionMultiVersion(vec!["v2".into(), "v1".into()])
+    );
+    assert!(CrdMerger::new(crds).with_root("v1").storage("v2").merge().is_ok());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Pipeline")]
+struct PipelineSpec {
+    #[kube(const_value = "Foo")]
+    engine: String,
+    #[kube(const_value = "2")]
+    schema_version: u32,
+    stages: Vec<String>,
+}
+
+#[test]
+fn const_value_attr_emits_single_value_enum() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Pipeline::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["engine"]["enum"], serde_json::json!(["Foo"]));
+    assert_eq!(props["schema_version"]["enum"], serde_json::json!([2]));
+    assert!(props["stages"].get("enum").is_none());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Replica")]
+#[kube(validation(field = "spec.count", rule = "self > 0", message = "must be positive"))]
+#[kube(validation(field = "spec.limits.cpu", rule = "self <= 64"))]
+#[kube(validation(field = "spec.image", rule = "self.startsWith('registry.')"))]
+#[kube(validation = "self.count <= self.limits.cpu")]
+#[serde(rename_all = "camelCase")]
+struct ReplicaSpec {
+    count: u32,
+    limits: ReplicaLimits,
+    image: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct ReplicaLimits {
+    cpu: u32,
+}
+
+#[test]
+fn field_validation_lands_on_field_node() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Replica::crd()).unwrap();
+    let spec = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert_eq!(
+        spec["properties"]["count"]["x-kubernetes-validations"],
+        serde_json::json!([{ "rule": "self > 0", "message": "must be positive" }])
+    );
+    assert_eq!(
+        spec["properties"]["limits"]["properties"]["cpu"]["x-kubernetes-validations"],
+        serde_json::json!([{ "rule": "self <= 64" }])
+    );
+    assert_eq!(
+        spec["properties"]["image"]["x-kubernetes-validations"],
+        serde_json::json!([{ "rule": "self.startsWith('registry.')" }])
+    );
+    assert_eq!(
+        spec["x-kubernetes-validations"],
+        serde_json::json!([{ "rule": "self.count <= self.limits.cpu" }])
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Cache")]
+struct CacheSpec {
+    /// Size of the cache in megabytes
+    #[kube(deprecated_field = "use size_bytes instead")]
+    size_mb: Option<u32>,
+    #[kube(deprecated_field)]
+    legacy_mode: Option<bool>,
+    size_bytes: Option<u64>,
+}
+
+#[test]
+fn deprecated_field_prefixes_description() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Cache::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(
+        props["size_mb"]["description"],
+        "Deprecated: use size_bytes instead\n\nSize of the cache in megabytes"
+    );
+    assert_eq!(
+        props["legacy_mode"]["description"],
+        "Deprecated: this field may be removed in a future version"
+    );
+    assert!(props["size_bytes"].get("description").is_none());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Bucket", auto_status)]
+struct BucketSpec {
+    region: String,
+}
+
+#[test]
+fn auto_status_generates_conditions_status() {
+    use k8s_openapi::apimachinery::pkg::apis::meta::v1::{Condition, Time};
+    use kube::core::{object::HasStatus, CustomResourceExt};
+
+    let mut bucket = Bucket::new("b", BucketSpec { region: "eu".into() });
+    *bucket.status_mut() = Some(BucketStatus {
+        conditions: vec![Condition {
+            type_: "Ready".into(),
+            status: "True".into(),
+            reason: "Created".into(),
+            message: String::new(),
+            last_transition_time: Time(chrono::Utc::now()),
+            observed_generation: Some(1),
+        }],
+        observed_generation: Some(1),
+    });
+    let value = serde_json::to_value(&bucket).unwrap();
+    assert_eq!(value["status"]["observedGeneration"], 1);
+    assert_eq!(value["status"]["conditions"][0]["type"], "Ready");
+
+    let crd = serde_json::to_value(Bucket::crd()).unwrap();
+    let version = &crd["spec"]["versions"][0];
+    assert_eq!(version["subresources"]["status"], serde_json::json!({}));
+    let status = &version["schema"]["openAPIV3Schema"]["properties"]["status"];
+    assert_eq!(status["properties"]["observedGeneration"]["type"], "integer");
+    assert_eq!(status["properties"]["conditions"]["type"], "array");
+    assert_eq!(
+        status["properties"]["conditions"]["x-kubernetes-list-map-keys"],
+        serde_json::json!(["type"])
+    );
+    assert!(status.get("required").is_none());
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Tree", max_recursion_depth = 2)]
+struct TreeSpec {
+    root: TreeNode,
+}
+
+/// A node of the tree
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct TreeNode {
+    name: String,
+    children: Vec<TreeNode>,
+    /// The node this one was copied from
+    origin: Option<Box<TreeNode>>,
+}
+
+#[test]
+fn recursive_types_are_expanded_to_max_recursion_depth() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Tree::crd()).unwrap();
+    let schema = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"];
+    assert!(schema.get("definitions").is_none());
+    assert!(!schema.to_string().contains("$ref"));
+
+    let root = &schema["properties"]["spec"]["properties"]["root"];
+    let level1 = &root["properties"]["children"]["items"];
+    let level2 = &level1["properties"]["children"]["items"];
+    assert_eq!(level1["properties"]["name"]["type"], "string");
+    assert_eq!(level2["properties"]["name"]["type"], "string");
+    assert_eq!(
+        level2["properties"]["children"]["items"],
+        serde_json::json!({ "type": "object", "x-kubernetes-preserve-unknown-fields": true })
+    );
+    // optional references keep the description and nullable of the field
+    assert_eq!(
+        level2["properties"]["origin"],
+        serde_json::json!({
+            "description": "The node this one was copied from",
+            "nullable": true,
+            "type": "object",
+            "x-kubernetes-preserve-unknown-fields": true
+        })
+    );
+    assert_eq!(
+        level1["properties"]["origin"]["description"],
+        "The node this one was copied from"
+    );
+    assert_eq!(
+        level1["properties"]["origin"]["properties"]["name"]["type"],
+        "string"
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Registry")]
+struct RegistrySpec {
+    #[kube(format_pattern = "dns1123-label")]
+    name: String,
+    #[kube(format_pattern = "dns1123-subdomain")]
+    host: Option<String>,
+    #[kube(format_pattern = "semver")]
+    version: String,
+}
+
+#[test]
+fn format_pattern_attr_emits_anchored_regex() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Registry::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["name"]["pattern"], "^[a-z0-9]([-a-z0-9]*[a-z0-9])?$");
+    assert_eq!(
+        props["host"]["pattern"],
+        r"^[a-z0-9]([-a-z0-9]*[a-z0-9])?(\.[a-z0-9]([-a-z0-9]*[a-z0-9])?)*$"
+    );
+    let semver = regex::Regex::new(props["version"]["pattern"].as_str().unwrap()).unwrap();
+    assert!(semver.is_match("1.2.3-rc.1+build.5"));
+    assert!(!semver.is_match("1.02.3"));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "clux.dev",
+    version = "v1",
+    kind = "Database",
+    auto_status,
+    status_ready_column,
+    age_column
+)]
+struct DatabaseSpec {
+    engine: String,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Replication", auto_status)]
+#[kube(status_ready_column(jsonPath = ".status.conditions[?(@.type=='Synced')].status"))]
+struct ReplicationSpec {
+    source: String,
+}
+
+#[test]
+fn status_ready_column_shows_ready_condition() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Database::crd()).unwrap();
+    assert_eq!(
+        crd["spec"]["versions"][0]["additionalPrinterColumns"],
+        serde_json::json!([
+            { "name": "Ready", "type": "string", "jsonPath": ".status.conditions[?(@.type=='Ready')].status" },
+            { "name": "Age", "type": "date", "jsonPath": ".metadata.creationTimestamp" }
+        ])
+    );
+    let crd = serde_json::to_value(Replication::crd()).unwrap();
+    assert_eq!(
+        crd["spec"]["versions"][0]["additionalPrinterColumns"][0]["jsonPath"],
+        ".status.conditions[?(@.type=='Synced')].status"
+    );
+}
+
+mod seconds {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+    use std::time::Duration;
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{}s", duration.as_secs()))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let secs = s.strip_suffix('s').and_then(|s| s.parse().ok());
+        secs.map(Duration::from_secs)
+            .ok_or_else(|| D::Error::custom(format!("invalid duration {:?}", s)))
+    }
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Timer")]
+#[serde(rename_all = "camelCase")]
+struct TimerSpec {
+    #[kube(schema = "duration")]
+    #[serde(
+        serialize_with = "seconds::serialize",
+        deserialize_with = "seconds::deserialize"
+    )]
+    interval: std::time::Duration,
+    #[kube(schema = "duration")]
+    timeout: Option<String>,
+}
+
+#[test]
+fn duration_schema_attr_emits_duration_string() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Timer::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["interval"]["type"], "string");
+    assert!(props["interval"].get("properties").is_none());
+    assert_eq!(props["timeout"]["type"], "string");
+    assert_eq!(props["timeout"]["nullable"], true);
+    let duration = regex::Regex::new(props["interval"]["pattern"].as_str().unwrap()).unwrap();
+    for valid in ["30s", "5m", "1h30m", "1.5h", "-2ms", "0"] {
+        assert!(duration.is_match(valid), "{}", valid);
+    }
+    for invalid in ["30", "5 m", "1d", ""] {
+        assert!(!duration.is_match(invalid), "{}", invalid);
+    }
+
+    let timer = Timer::new("t", TimerSpec {
+        interval: std::time::Duration::from_secs(30),
+        timeout: None,
+    });
+    assert_eq!(serde_json::to_value(&timer).unwrap()["spec"]["interval"], "30s");
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Subnet")]
+struct SubnetSpec {
+    #[kube(format = "cidr")]
+    cidr: String,
+    #[kube(format = "ipv4")]
+    gateway: Option<String>,
+}
+
+#[test]
+fn format_attr_emits_format_and_pattern() {
+    use kube::core::{crd::validate_instance, CustomResourceExt};
+    let crd = Subnet::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let props = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["cidr"]["format"], "cidr");
+    assert_eq!(props["gateway"]["format"], "ipv4");
+    let cidr = regex::Regex::new(props["cidr"]["pattern"].as_str().unwrap()).unwrap();
+    assert!(cidr.is_match("10.0.0.0/8"));
+    assert!(cidr.is_match("2001:db8::/32"));
+    assert!(!cidr.is_match("10.0.0.0"));
+
+    let subnet = |cidr: &str| {
+        let subnet = Subnet::new("s", SubnetSpec {
+            cidr: cidr.into(),
+            gateway: Some("10.0.0.1".into()),
+        });
+        serde_json::from_value(serde_json::to_value(subnet).unwrap()).unwrap()
+    };
+    assert_eq!(validate_instance(&crd, &subnet("10.0.0.0/8")), Ok(()));
+    assert_eq!(
+        validate_instance(&crd, &subnet("10.0.0.300/8")).unwrap_err(),
+        vec![
+            r#"spec.cidr: Invalid value: "10.0.0.300/8": spec.cidr in body must be of type cidr: "10.0.0.300/8""#
+        ]
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Hook")]
+struct HookSpec {
+    #[kube(format = "url")]
+    endpoint: String,
+}
+
+#[test]
+fn url_format_emits_uri_format_and_pattern() {
+    use kube::core::{crd::validate_instance, CustomResourceExt};
+    let crd = Hook::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let endpoint = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]
+        ["properties"]["endpoint"];
+    assert_eq!(endpoint["format"], "uri");
+    let url = regex::Regex::new(endpoint["pattern"].as_str().unwrap()).unwrap();
+    assert!(url.is_match("https://hooks.example.com:8443/notify?team=a"));
+    assert!(url.is_match("http://10.0.0.1"));
+    assert!(!url.is_match("ftp://example.com"));
+    assert!(!url.is_match("https://"));
+    assert!(!url.is_match("example.com/notify"));
+
+    let hook = |endpoint: &str| {
+        let hook = Hook::new("h", HookSpec {
+            endpoint: endpoint.into(),
+        });
+        serde_json::from_value(serde_json::to_value(hook).unwrap()).unwrap()
+    };
+    assert_eq!(
+        validate_instance(&crd, &hook("https://example.com/notify")),
+        Ok(())
+    );
+    assert_eq!(validate_instance(&crd, &hook("not a url")).unwrap_err(), vec![
+        r#"spec.endpoint: Invalid value: "not a url": spec.endpoint in body must be of type uri: "not a url""#
+    ]);
+}
+
+#[test]
+fn crd_scoped_overrides_scope() {
+    use kube::core::{discovery::Scope, CustomResourceExt};
+    assert_eq!(Foo::crd().spec.scope, "Namespaced");
+    let cluster = Foo::crd_scoped(Scope::Cluster);
+    let namespaced = Foo::crd_scoped(Scope::Namespaced);
+    assert_eq!(cluster.spec.scope, "Cluster");
+    assert_eq!(namespaced.spec.scope, "Namespaced");
+    for crd in [cluster, namespaced] {
+        let mut scoped = crd.clone();
+        scoped.spec.scope = Foo::crd().spec.scope;
+        assert_eq!(scoped, Foo::crd());
+        assert_eq!(crd.spec.names.plural, Foo::api_resource().plural);
+    }
+}
+
+#[test]
+fn try_from_dynamic_reports_mismatches() {
+    use kube::core::{
+        crd::{ConversionError, FieldViolation},
+        CustomResourceExt, DynamicObject,
+    };
+    let dynamic = |value: serde_json::Value| serde_json::from_value::<DynamicObject>(value).unwrap();
+
+    let sandbox = Sandbox::try_from_dynamic(&dynamic(serde_json::json!({
+        "apiVersion": "clux.dev/v1",
+        "kind": "Sandbox",
+        "metadata": { "name": "s" },
+        "spec": { "memoryMib": 128 }
+    })))
+    .unwrap();
+    assert_eq!(sandbox.spec.memory_mib, 128);
+
+    let err = Sandbox::try_from_dynamic(&dynamic(serde_json::json!({
+        "apiVersion": "clux.dev/v2",
+        "kind": "Sandbox",
+        "metadata": { "name": "s" },
+        "spec": { "memoryMib": 128 }
+    })))
+    .unwrap_err();
+    assert!(
+        matches!(&err, ConversionError::TypeMismatch { expected, found }
+            if expected == "clux.dev/v1 Sandbox" && found == "clux.dev/v2 Sandbox"),
+        "{}",
+        err
+    );
+
+    let err = Sandbox::try_from_dynamic(&dynamic(serde_json::json!({
+        "apiVersion": "clux.dev/v1",
+        "kind": "Sandbox",
+        "metadata": { "name": "s" },
+        "spec": { "memoryMib": "lots", "cpus": 1.5 }
+    })))
+    .unwrap_err();
+    match err {
+        ConversionError::InvalidFields(violations) => assert_eq!(violations, vec![FieldViolation {
+            path: "spec.memoryMib".into(),
+            reason: r#"Invalid value: "lots": must be of type integer"#.into(),
+        }]),
+        err => panic!("unexpected error {}", err),
+    }
+}
+
+#[test]
+fn crd_cached_is_generated_once() {
+    use kube::core::CustomResourceExt;
+    let first = Foo::crd_cached();
+    assert_eq!(first, &Foo::crd());
+    let threads = (0..4)
+        .map(|_| std::thread::spawn(|| Foo::crd_cached() as *const _ as usize))
+        .collect::<Vec<_>>();
+    for thread in threads {
+        assert_eq!(thread.join().unwrap(), first as *const _ as usize);
+    }
+    assert!(std::ptr::eq(first, Foo::crd_cached()));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Quota", validate)]
+struct QuotaSpec {
+    #[schemars(schema_with = "kube::core::schema::quantity_schema")]
+    memory: k8s_openapi::apimachinery::pkg::api::resource::Quantity,
+    #[schemars(with = "Option<String>")]
+    cpu: Option<k8s_openapi::apimachinery::pkg::api::resource::Quantity>,
+    #[kube(schema = "quantity")]
+    storage: String,
+}
+
+#[test]
+fn quantity_fields_get_quantity_schema() {
+    use kube::core::{crd::FakeApiserver, CustomResourceExt};
+    let crd = Quota::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let props = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    for field in ["memory", "cpu", "storage"] {
+        assert_eq!(
+            props[field]["anyOf"],
+            serde_json::json!([{ "type": "integer" }, { "type": "string" }]),
+            "{}",
+            field
+        );
+        assert_eq!(props[field]["x-kubernetes-int-or-string"], true);
+        assert!(props[field].get("type").is_none());
+        assert_eq!(props[field]["pattern"], kube::core::schema::QUANTITY_PATTERN);
+        let quantity = regex::Regex::new(props[field]["pattern"].as_str().unwrap()).unwrap();
+        for valid in ["1Gi", "500m", "2", "1.5", "1e3"] {
+            assert!(quantity.is_match(valid), "{}", valid);
+        }
+        assert!(!quantity.is_match("1GB"));
+    }
+    assert_eq!(props["cpu"]["nullable"], true);
+    assert_eq!(FakeApiserver::accept(&crd), Ok(()));
+}
+
+#[test]
+fn validate_checks_quantity_patterns() {
+    use k8s_openapi::apimachinery::pkg::api::resource::Quantity;
+    let quota = |storage: &str| QuotaSpec {
+        memory: Quantity("1Gi".into()),
+        cpu: None,
+        storage: storage.into(),
+    };
+    assert_eq!(quota("10Gi").validate(), Ok(()));
+    let violations = quota("10GB").validate().unwrap_err();
+    assert_eq!(violations.len(), 1);
+    assert_eq!(violations[0].path, "storage");
+}
+
+#[test]
+fn controller_policy_rules_follow_the_crd() {
+    use kube::core::CustomResourceExt;
+    let rules = Deployment::controller_policy_rules();
+    assert_eq!(rules[0].api_groups, Some(vec!["clux.dev".to_string()]));
+    assert_eq!(rules[0].resources, Some(vec!["deployments".to_string()]));
+    assert!(rules[0].verbs.contains(&"watch".to_string()));
+    assert_eq!(rules[1].resources, Some(vec!["deployments/status".to_string()]));
+    assert_eq!(Foo::controller_policy_rules().len(), 1);
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct StrictListener {
+    port: u16,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Strict", deny_unknown_fields)]
+struct StrictSpec {
+    name: String,
+    listeners: Vec<StrictListener>,
+    labels: std::collections::BTreeMap<String, String>,
+    options: StrictOptions,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct StrictOptions {
+    verbose: bool,
+    #[serde(flatten)]
+    extra: std::collections::HashMap<String, serde_json::Value>,
+}
+
+#[test]
+fn deny_unknown_fields_closes_root_schema_and_prunes_in_crd() {
+    use kube::core::{crd::FakeApiserver, CustomResourceExt};
+    let root = serde_json::to_value(Strict::root_schema().unwrap()).unwrap();
+    let spec = &root["properties"]["spec"];
+    assert_eq!(spec["additionalProperties"], false);
+    assert_eq!(
+        spec["properties"]["listeners"]["items"]["additionalProperties"],
+        false
+    );
+    assert_eq!(
+        spec["properties"]["labels"]["additionalProperties"]["type"],
+        "string"
+    );
+    // flattened maps keep unknown fields
+    let options = &spec["properties"]["options"];
+    assert_eq!(options["x-kubernetes-preserve-unknown-fields"], true);
+    assert!(options.get("additionalProperties").is_none());
+    assert!(root.get("additionalProperties").is_none());
+
+    let crd = Strict::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let spec = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert!(spec.get("additionalProperties").is_none());
+    assert!(spec["properties"]["listeners"]["items"]
+        .get("additionalProperties")
+        .is_none());
+    assert_eq!(
+        spec["properties"]["labels"]["additionalProperties"]["type"],
+        "string"
+    );
+    assert_eq!(FakeApiserver::accept(&crd), Ok(()));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Website", namespaced)]
+struct WebsiteSpec {
+    #[kube(example = "example.com")]
+    domain: String,
+    #[kube(example = r#"["10.0.0.0/8"]"#)]
+    allowed: Vec<String>,
+    #[kube(default = "3")]
+    #[serde(default = "default_website_replicas")]
+    replicas: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[kube(example = "dark")]
+    theme: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    footer: Option<String>,
+}
+
+fn default_website_replicas() -> u32 {
+    3
+}
+
+#[test]
+fn example_manifest_uses_examples_and_validates() {
+    use kube::core::{crd::validate_instance, CustomResourceExt, DynamicObject};
+    let manifest = serde_json::to_value(Website::example_manifest("my-site")).unwrap();
+    assert_eq!(manifest["apiVersion"], "clux.dev/v1");
+    assert_eq!(manifest["kind"], "Website");
+    assert_eq!(manifest["metadata"]["name"], "my-site");
+    let spec = &manifest["spec"];
+    assert_eq!(spec["domain"], "example.com");
+    assert_eq!(spec["allowed"][0], "10.0.0.0/8");
+    assert_eq!(spec["replicas"], 3);
+    assert_eq!(spec["theme"], "dark");
+    assert!(spec.get("footer").is_none());
+
+    let object: DynamicObject = serde_json::from_value(manifest).unwrap();
+    assert_eq!(validate_instance(&Website::crd(), &object), Ok(()));
+    let website: Website = serde_json::from_value(serde_json::to_value(&object).unwrap()).unwrap();
+    assert_eq!(website.spec.domain, "example.com");
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct PortRange {
+    min: u16,
+    max: u16,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Portal")]
+#[kube(validation(
+    path = "spec.range",
+    rule = "self.min <= self.max",
+    message = "min must not exceed max"
+))]
+struct PortalSpec {
+    range: PortRange,
+}
+
+#[test]
+fn path_validation_lands_on_nested_object() {
+    use kube::core::{crd::validate_instance, CustomResourceExt};
+    let crd = Portal::crd();
+    let json = serde_json::to_value(&crd).unwrap();
+    let spec = &json["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"];
+    assert!(spec.get("x-kubernetes-validations").is_none());
+    assert_eq!(
+        spec["properties"]["range"]["x-kubernetes-validations"],
+        serde_json::json!([{ "rule": "self.min <= self.max", "message": "min must not exceed max" }])
+    );
+
+    let portal = |min, max| {
+        let portal = Portal::new("l", PortalSpec {
+            range: PortRange { min, max },
+        });
+        serde_json::from_value(serde_json::to_value(portal).unwrap()).unwrap()
+    };
+    assert_eq!(validate_instance(&crd, &portal(80, 443)), Ok(()));
+    assert_eq!(
+        validate_instance(&crd, &portal(443, 80)),
+        Err(vec![
+            "spec.range: Invalid value: \"object\": min must not exceed max".to_string()
+        ])
+    );
+}
+
+#[test]
+fn validate_name_rejects_invalid_names() {
+    use kube::core::{crd::NameError, CustomResourceExt};
+    assert_eq!(Foo::validate_name("my-foo.v2"), Ok(()));
+    assert_eq!(
+        Foo::validate_name("MyFoo"),
+        Err(NameError::Invalid("MyFoo".into()))
+    );
+    assert!(matches!(
+        Foo::validate_name(&"f".repeat(300)),
+        Err(NameError::TooLong { len: 300, .. })
+    ));
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Probe")]
+pub struct ProbeSpec {
+    /// Passed on to the probe as is
+    config: serde_json::Value,
+    overrides: Option<serde_json::Value>,
+    labels: serde_json::Map<String, serde_json::Value>,
+    results: Vec<serde_json::Value>,
+}
+
+#[test]
+fn json_values_preserve_unknown_fields() {
+    use kube::core::{crd::check_structural, CustomResourceExt};
+    use serde_json::json;
+    let crd = Probe::crd();
+    let schema = crd.spec.versions[0].schema.as_ref().unwrap();
+    let schema = schema.open_api_v3_schema.as_ref().unwrap();
+    assert_eq!(check_structural(schema), Vec::<String>::new());
+
+    let json = serde_json::to_value(schema).unwrap();
+    let props = &json["properties"]["spec"]["properties"];
+    assert_eq!(
+        props["config"],
+        json!({
+            "description": "Passed on to the probe as is",
+            "x-kubernetes-preserve-unknown-fields": true
+        })
+    );
+    assert_eq!(
+        props["overrides"],
+        json!({ "nullable": true, "x-kubernetes-preserve-unknown-fields": true })
+    );
+    assert_eq!(
+        props["labels"],
+        json!({ "type": "object", "x-kubernetes-preserve-unknown-fields": true })
+    );
+    assert_eq!(
+        props["results"]["items"],
+        json!({ "x-kubernetes-preserve-unknown-fields": true })
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Rollout", builder)]
+#[serde(rename_all = "camelCase")]
+struct RolloutSpec {
+    image: String,
+    #[kube(default = "3")]
+    replicas: i32,
+    #[serde(default = "default_strategy")]
+    strategy: RolloutStrategy,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_surge: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[kube(default = "stable")]
+    channel: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, PartialEq, Clone, JsonSchema)]
+struct RolloutStrategy {
+    kind: String,
+    #[serde(default)]
+    pause: bool,
+}
+
+fn default_strategy() -> RolloutStrategy {
+    RolloutStrategy {
+        kind: "Canary".into(),
+        pause: false,
+    }
+}
+
+#[test]
+fn builder_fills_in_schema_defaults() {
+    let spec = RolloutSpec::builder()
+        .image("nginx")
+        .max_surge(1)
+        .build()
+        .unwrap();
+    assert_eq!(spec, RolloutSpec {
+        image: "nginx".into(),
+        replicas: 3,
+        strategy: default_strategy(),
+        max_surge: Some(1),
+        channel: Some("stable".into()),
+    });
+
+    let spec = RolloutSpec::builder()
+        .image("nginx")
+        .replicas(5)
+        .channel("beta")
+        .build()
+        .unwrap();
+    assert_eq!(spec.replicas, 5);
+    assert_eq!(spec.channel.as_deref(), Some("beta"));
+    assert_eq!(spec.max_surge, None);
+
+    let err = RolloutSpec::builder().replicas(1).build().unwrap_err();
+    assert_eq!(err.to_string(), "missing field `image`");
+}
+
+mod units {
+    use super::*;
+
+    /// A day of the year, without a year
+    #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    pub struct Date {
+        pub month: u8,
+        pub day: u8,
+    }
+
+    #[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+    pub struct Quantity(pub u32);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Holiday")]
+#[serde(rename_all = "camelCase")]
+struct HolidaySpec {
+    day: chrono::NaiveDate,
+    observed_on: Option<chrono::NaiveDate>,
+    announced_at: DateTime<Utc>,
+    #[kube(schema = "date")]
+    ends: String,
+    recurs_on: units::Date,
+    guests: units::Quantity,
+}
+
+#[test]
+fn dates_are_not_date_times() {
+    use kube::core::CustomResourceExt;
+    use serde_json::json;
+    let crd = serde_json::to_value(Holiday::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["day"], json!({ "type": "string", "format": "date" }));
+    assert_eq!(
+        props["observedOn"],
+        json!({ "type": "string", "format": "date", "nullable": true })
+    );
+    assert_eq!(
+        props["announcedAt"],
+        json!({ "type": "string", "format": "date-time" })
+    );
+    assert_eq!(props["ends"], json!({ "type": "string", "format": "date" }));
+    // types that only share the name keep their own schemas
+    assert_eq!(props["recursOn"]["type"], "object");
+    assert_eq!(props["guests"]["type"], "integer");
+    assert!(props["guests"].get("x-kubernetes-int-or-string").is_none());
+}
+
+macro_rules! rollback_version {
+    ($module:ident, $version:literal) => {
+        mod $module {
+            use super::*;
+
+            #[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+            #[kube(group = "clux.dev", version = $version, kind = "Rollback")]
+            #[kube(validation(rule = "self.revision > 0", version = "v1"))]
+            #[kube(validation(
+                field = "spec.reason",
+                rule = "self != ''",
+                version = "v1",
+                version = "v1beta1"
+            ))]
+            #[kube(validation = "self.revision < 1000")]
+            pub struct RollbackSpec {
+                pub revision: i64,
+                pub reason: String,
+            }
+        }
+    };
+}
+rollback_version!(rollback_v1alpha1, "v1alpha1");
+rollback_version!(rollback_v1, "v1");
+
+#[test]
+fn version_scoped_rules_only_apply_to_their_versions() {
+    use kube::core::{crd::CrdMerger, CustomResourceExt};
+    use serde_json::json;
+    let crd = CrdMerger::new(vec![
+        rollback_v1alpha1::Rollback::crd(),
+        rollback_v1::Rollback::crd(),
+    ])
+    .storage("v1")
+    .merge()
+    .unwrap();
+    let crd = serde_json::to_value(crd).unwrap();
+    let spec = |index: usize| {
+        crd["spec"]["versions"][index]["schema"]["openAPIV3Schema"]["properties"]["spec"].clone()
+    };
+
+    let alpha = spec(0);
+    assert_eq!(
+        alpha["x-kubernetes-validations"],
+        json!([{ "rule": "self.revision < 1000" }])
+    );
+    assert!(alpha["properties"]["reason"]
+        .get("x-kubernetes-validations")
+        .is_none());
+
+    let ga = spec(1);
+    assert_eq!(
+        ga["x-kubernetes-validations"],
+        json!([{ "rule": "self.revision > 0" }, { "rule": "self.revision < 1000" }])
+    );
+    assert_eq!(
+        ga["properties"]["reason"]["x-kubernetes-validations"],
+        json!([{ "rule": "self != ''" }])
+    );
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Roster")]
+struct RosterSpec {
+    members: std::collections::BTreeSet<String>,
+    ports: Option<std::collections::HashSet<u16>>,
+    #[kube(list_type = "atomic")]
+    tags: std::collections::BTreeSet<String>,
+    shifts: std::collections::BTreeSet<RosterShift>,
+    history: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema, PartialEq, Eq, PartialOrd, Ord)]
+struct RosterShift {
+    day: String,
+}
+
+#[test]
+fn sets_are_list_sets() {
+    use kube::core::{crd::FakeApiserver, CustomResourceExt};
+    let schema = serde_json::to_value(Roster::root_schema().unwrap()).unwrap();
+    let props = &schema["properties"]["spec"]["properties"];
+    for set in ["members", "ports", "tags", "shifts"] {
+        assert_eq!(props[set]["uniqueItems"], true, "{}", set);
+    }
+    assert_eq!(props["history"].get("uniqueItems"), None);
+
+    let crd = Roster::crd();
+    assert_eq!(FakeApiserver::accept(&crd), Ok(()));
+    let crd = serde_json::to_value(crd).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(props["members"]["x-kubernetes-list-type"], "set");
+    assert_eq!(props["ports"]["x-kubernetes-list-type"], "set");
+    assert_eq!(props["tags"]["x-kubernetes-list-type"], "atomic");
+    // only lists of scalars can be sets
+    assert_eq!(props["shifts"].get("x-kubernetes-list-type"), None);
+    assert_eq!(props["history"].get("x-kubernetes-list-type"), None);
+    for field in ["members", "ports", "tags", "shifts"] {
+        assert_eq!(props[field].get("uniqueItems"), None, "{}", field);
+    }
+}
+
+const DOCUMENTED_DOCS: &[(&str, &str)] = &[
+    ("spec", "Desired state of the documented thing"),
+    ("spec.replicas", "Number of replicas"),
+    ("spec.ports[*].name", "Name of the port"),
+    ("spec.labels.*", "Value of a label"),
+    ("status.phase", "Current phase"),
+];
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "clux.dev",
+    version = "v1",
+    kind = "Documented",
+    docs = "DOCUMENTED_DOCS"
+)]
+#[kube(status = "DocumentedStatus")]
+struct DocumentedSpec {
+    /// Replaced by the docs map
+    replicas: i32,
+    /// Kept, as the docs map has no entry
+    image: String,
+    ports: Vec<DocumentedPort>,
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct DocumentedPort {
+    name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, JsonSchema)]
+struct DocumentedStatus {
+    phase: String,
+}
+
+#[test]
+fn docs_map_sets_descriptions() {
+    use kube::core::CustomResourceExt;
+    let crd = serde_json::to_value(Documented::crd()).unwrap();
+    let root = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"];
+    let spec = &root["properties"]["spec"];
+    assert_eq!(spec["description"], "Desired state of the documented thing");
+    assert_eq!(
+        spec["properties"]["replicas"]["description"],
+        "Number of replicas"
+    );
+    assert_eq!(
+        spec["properties"]["image"]["description"],
+        "Kept, as the docs map has no entry"
+    );
+    assert_eq!(
+        spec["properties"]["ports"]["items"]["properties"]["name"]["description"],
+        "Name of the port"
+    );
+    assert_eq!(
+        spec["properties"]["labels"]["additionalProperties"]["description"],
+        "Value of a label"
+    );
+    assert_eq!(
+        root["properties"]["status"]["properties"]["phase"]["description"],
+        "Current phase"
+    );
+}
+
+const MISDOCUMENTED_DOCS: &[(&str, &str)] = &[
+    ("spec.replicas", "Number of replicas"),
+    ("spec.replica", "Misspelled"),
+];
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(
+    group = "clux.dev",
+    version = "v1",
+    kind = "Misdocumented",
+    docs = "MISDOCUMENTED_DOCS"
+)]
+struct MisdocumentedSpec {
+    replicas: i32,
+}
+
+#[test]
+#[should_panic(expected = r#"#[kube(docs)] paths are not in the schema of Misdocumented: ["spec.replica"]"#)]
+fn docs_map_paths_must_be_in_the_schema() {
+    use kube::core::CustomResourceExt;
+    Misdocumented::crd();
+}
+
+#[test]
+fn discovery_entry_follows_the_crd() {
+    use kube::core::CustomResourceExt;
+    let entry = Foo::api_resource_discovery_entry();
+    assert_eq!(entry.name, "foos");
+    assert_eq!(entry.singular_name, "foo");
+    assert_eq!(entry.kind, "Foo");
+    assert!(entry.namespaced);
+    assert_eq!(entry.short_names, Some(vec!["fo".to_string(), "f".to_string()]));
+    assert_eq!(entry.categories, Some(vec!["clux".to_string()]));
+    assert_eq!(entry.verbs, vec![
+        "delete",
+        "deletecollection",
+        "get",
+        "list",
+        "patch",
+        "create",
+        "update",
+        "watch"
+    ]);
+    assert_eq!(entry.group, None);
+    assert_eq!(entry.version, None);
+
+    let entry = Deployment::api_resource_discovery_entry();
+    assert_eq!(entry.name, "deployments");
+    assert_eq!(entry.short_names, None);
+}
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone, JsonSchema)]
+#[kube(group = "clux.dev", version = "v1", kind = "Meter")]
+struct MeterSpec {
+    /// Serialized as a `"<low>-<high>"` string
+    #[serde(serialize_with = "range_to_string", deserialize_with = "range_from_string")]
+    #[kube(schema_with = "range_schema", title = "Range")]
+    range: (u32, u32),
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[kube(schema_with = "self::range_schema")]
+    fallback: Option<(u32, u32)>,
+}
+
+fn range_schema() -> k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::JSONSchemaProps {
+    serde_json::from_value(serde_json::json!({
+        "type": "string",
+        "pattern": "^[0-9]+-[0-9]+$"
+    }))
+    .unwrap()
+}
+
+fn range_to_string<S: serde::Serializer>(range: &(u32, u32), serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&format!("{}-{}", range.0, range.1))
+}
+
+fn range_from_string<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<(u32, u32), D::Error> {
+    let range = String::deserialize(deserializer)?;
+    let (low, high) = range
+        .split_once('-')
+        .ok_or_else(|| serde::de::Error::custom("expected <low>-<high>"))?;
+    let parse = |s: &str| s.parse().map_err(serde::de::Error::custom);
+    Ok((parse(low)?, parse(high)?))
+}
+
+#[test]
+fn schema_functions_replace_the_derived_schema() {
+    use kube::core::CustomResourceExt;
+    use serde_json::json;
+    let crd = serde_json::to_value(Meter::crd()).unwrap();
+    let props = &crd["spec"]["versions"][0]["schema"]["openAPIV3Schema"]["properties"]["spec"]["properties"];
+    assert_eq!(
+        props["range"],
+        json!({
+            "description": "Serialized as a `\"<low>-<high>\"` string",
+            "type": "string",
+            "pattern": "^[0-9]+-[0-9]+$",
+            "title": "Range"
+        })
+    );
+    assert_eq!(
+        props["fallback"],
+        json!({ "type": "string", "pattern": "^[0-9]+-[0-9]+$", "nullable": true })
+    );
+
+    let meter = Meter::new("m", MeterSpec {
+        range: (1, 10),
+        fallback: None,
+    });
+    assert_eq!(
+        serde_json::to_value(&meter).unwrap()["spec"],
+        json!({ "range": "1-10" })
+    );
+}
@@ -0,0 +1,13 @@
+use kube::core::CustomResourceExt;
+use kube_derive::CustomResource;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone)]
+#[kube(group = "metrics.clux.dev", version = "v1beta1", kind = "NodeMetric", aggregated)]
+struct NodeMetricSpec {
+    usage: String,
+}
+
+fn main() {
+    let _ = NodeMetric::crd();
+}
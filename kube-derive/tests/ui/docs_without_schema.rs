@@ -0,0 +1,12 @@
+use kube_derive::CustomResource;
+use serde::{Deserialize, Serialize};
+
+const FOO_DOCS: &[(&str, &str)] = &[("spec.replicas", "Number of replicas")];
+
+#[derive(CustomResource, Serialize, Deserialize, Debug, Clone)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo", schema = "disabled", docs = "FOO_DOCS")]
+struct FooSpec {
+    replicas: i32,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use kube_derive::CustomResource;
+
+#[derive(CustomResource)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo", validation = " ")]
+struct FooSpec {
+    mode: String,
+}
+
+fn main() {}
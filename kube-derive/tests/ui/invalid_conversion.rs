@@ -0,0 +1,11 @@
+use kube_derive::CustomResource;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo", conversion = "webhook")]
+#[kube(schema = "disabled")]
+struct FooSpec {
+    replicas: u32,
+}
+
+fn main() {}
@@ -0,0 +1,10 @@
+use kube_derive::CustomResource;
+
+#[derive(CustomResource)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+struct FooSpec {
+    #[kube(example = "true")]
+    ports: Vec<u16>,
+}
+
+fn main() {}
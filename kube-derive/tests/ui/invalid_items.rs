@@ -0,0 +1,10 @@
+use kube_derive::CustomResource;
+
+#[derive(CustomResource)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+struct FooSpec {
+    #[kube(min_items = 1)]
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use kube_derive::CustomResource;
+
+#[derive(CustomResource)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+struct FooSpec {
+    #[kube(map_type = "separable")]
+    settings: Settings,
+}
+
+struct Settings {}
+
+fn main() {}
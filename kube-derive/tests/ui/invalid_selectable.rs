@@ -0,0 +1,9 @@
+use kube_derive::CustomResource;
+
+#[derive(CustomResource)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo", selectable = "spec.phase")]
+struct FooSpec {
+    phase: String,
+}
+
+fn main() {}
@@ -0,0 +1,18 @@
+use kube_derive::CustomResource;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+struct Port {
+    name: String,
+    container_port: u16,
+}
+
+#[derive(CustomResource, Serialize, Deserialize, JsonSchema, Clone, Debug)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+struct FooSpec {
+    #[kube(list_type = "map", list_map_key = "containerPort", list_map_key = "protocol")]
+    ports: Vec<Port>,
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use kube_derive::CustomResource;
+
+#[derive(CustomResource)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo", status_ready_column)]
+struct FooSpec {
+    replicas: i32,
+}
+
+fn main() {}
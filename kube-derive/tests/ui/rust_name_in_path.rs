@@ -0,0 +1,12 @@
+use kube_derive::CustomResource;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo", selectable = ".spec.node_name")]
+#[kube(schema = "disabled")]
+#[serde(rename_all = "camelCase")]
+struct FooSpec {
+    node_name: String,
+}
+
+fn main() {}
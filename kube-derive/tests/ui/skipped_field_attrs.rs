@@ -0,0 +1,13 @@
+use kube_derive::CustomResource;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+#[kube(schema = "disabled")]
+struct FooSpec {
+    #[serde(skip)]
+    #[kube(min_length = 1)]
+    cache: String,
+}
+
+fn main() {}
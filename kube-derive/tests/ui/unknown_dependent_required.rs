@@ -0,0 +1,12 @@
+use kube_derive::CustomResource;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo", dependent_required(tls_cert, tls_secret))]
+#[kube(schema = "disabled")]
+struct FooSpec {
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+}
+
+fn main() {}
@@ -0,0 +1,12 @@
+use kube_derive::CustomResource;
+use serde::{Deserialize, Serialize};
+
+#[derive(CustomResource, Serialize, Deserialize)]
+#[kube(group = "clux.dev", version = "v1", kind = "Foo")]
+#[kube(validation(field = "spec.nmae", rule = "self.startsWith('foo')"))]
+#[kube(schema = "disabled")]
+struct FooSpec {
+    name: String,
+}
+
+fn main() {}
@@ -0,0 +1,1046 @@
+//! Helpers for working with custom resources across their `CustomResourceDefinition` versions
+use crate::watcher::{self, watch_object};
+use futures::{future, stream, Stream, StreamExt, TryFutureExt, TryStreamExt};
+use json_patch::{AddOperation, PatchOperation, ReplaceOperation, TestOperation};
+use k8s_openapi::{
+    api::core::v1::{Endpoints, Service},
+    apiextensions_apiserver::pkg::apis::apiextensions::v1::{
+        CustomResourceDefinition, CustomResourceDefinitionCondition, JSONSchemaProps,
+    },
+};
+use kube_client::{
+    api::{Api, DynamicObject, ListParams, Patch, PatchParams},
+    core::{ApiResource, CustomResourceExt, TypeMeta},
+    discovery::{self, verbs, ApiGroup},
+    error::DiscoveryError,
+    Client, ResourceExt,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use thiserror::Error;
+
+/// List objects of a resource through every version its group serves
+///
+/// This is useful during version migrations, where objects may have been written through different versions.
+/// The group of `ar` is discovered, and every served version that contains `ar.kind` is listed (across all namespaces).
+///
+/// Versions are queried in descending [`Version`](kube_client::core::Version) priority, and objects seen through
+/// more than one version are only returned once (from the highest priority version), deduplicated by `uid`.
+/// Every returned object has its `types` set to the apiVersion it was listed through.
+///
+/// Served versions that do not contain the kind, do not support `list`, or return no objects are skipped.
+///
+/// # Errors
+///
+/// Fails if discovery of the group fails, or if any of the list calls fail.
+pub async fn list_all_versions(client: &Client, ar: &ApiResource) -> kube_client::Result<Vec<DynamicObject>> {
+    let group = discovery::group(client, &ar.group).await?;
+    let mut seen = HashSet::new();
+    let mut objects = vec![];
+    for version in group.versions() {
+        let versioned = group
+            .versioned_resources(version)
+            .into_iter()
+            .find(|(res, caps)| res.kind == ar.kind && caps.supports_operation(verbs::LIST));
+        let versioned_ar = match versioned {
+            Some((res, _)) => res,
+            None => continue,
+        };
+        let api: Api<DynamicObject> = Api::all_with(client.clone(), &versioned_ar);
+        for mut obj in api.list(&ListParams::default()).await? {
+            if let Some(uid) = &obj.metadata.uid {
+                if !seen.insert(uid.clone()) {
+                    continue;
+                }
+            }
+            obj.types = Some(TypeMeta {
+                api_version: versioned_ar.api_version.clone(),
+                kind: versioned_ar.kind.clone(),
+            });
+            objects.push(obj);
+        }
+    }
+    Ok(objects)
+}
+
+/// Resolves the [`ApiResource`] of custom resources at the version preferred by the cluster
+///
+/// The compile-time [`CustomResourceExt::api_resource`] always uses the version of the derived type, which may not be
+/// the version the cluster prefers. Discovered groups are cached, so resolving several kinds of a group only discovers
+/// it once. Failed discoveries are not cached.
+pub struct ApiResourceResolver {
+    client: Client,
+    groups: HashMap<String, ApiGroup>,
+}
+
+impl ApiResourceResolver {
+    /// Create a resolver with an empty cache
+    #[must_use]
+    pub fn new(client: Client) -> Self {
+        Self {
+            client,
+            groups: HashMap::new(),
+        }
+    }
+
+    /// The [`ApiResource`] of `K` at the preferred version of its group (or its latest version)
+    ///
+    /// Falls back to the compile-time [`CustomResourceExt::api_resource`] if the group can not be discovered.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the group is discovered, but its recommended version does not serve the kind of `K`.
+    pub async fn resolve<K: CustomResourceExt>(&mut self) -> kube_client::Result<ApiResource> {
+        let fallback = K::api_resource();
+        if !self.groups.contains_key(&fallback.group) {
+            match discovery::group(&self.client, &fallback.group).await {
+                Ok(group) => {
+                    self.groups.insert(fallback.group.clone(), group);
+                }
+                Err(err) => {
+                    tracing::debug!(group = %fallback.group, error = %err, "discovery failed, using compile-time api resource");
+                    return Ok(fallback);
+                }
+            }
+        }
+        self.groups[&fallback.group]
+            .recommended_kind(&fallback.kind)
+            .map(|(ar, _)| ar)
+            .ok_or_else(|| kube_client::Error::Discovery(DiscoveryError::MissingKind(fallback.kind.clone())))
+    }
+}
+
+/// The [`ApiResource`] of `K` at the version preferred by the cluster
+///
+/// This is a one-off [`ApiResourceResolver::resolve`]; keep an [`ApiResourceResolver`] around to reuse discovery results.
+///
+/// ```no_run
+/// use kube::{api::{Api, DynamicObject}, runtime::crd::resolve_api_resource};
+/// # use kube::CustomResourceExt;
+/// # async fn wrapper<MyCrd: CustomResourceExt>(client: kube::Client) -> Result<(), Box<dyn std::error::Error>> {
+/// let ar = resolve_api_resource::<MyCrd>(&client).await?;
+/// let api: Api<DynamicObject> = Api::all_with(client, &ar);
+/// # Ok(())
+/// # }
+/// ```
+///
+/// # Errors
+///
+/// Fails if the group is discovered, but its recommended version does not serve the kind of `K`.
+pub async fn resolve_api_resource<K: CustomResourceExt>(client: &Client) -> kube_client::Result<ApiResource> {
+    ApiResourceResolver::new(client.clone()).resolve::<K>().await
+}
+
+/// Progress of a `CustomResourceDefinition` towards being established, as reported by [`apply_and_watch`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum CrdEstablishmentEvent {
+    /// The crd was applied
+    Applied,
+    /// The apiserver accepted the names of the crd (`NamesAccepted=True`)
+    NamesAccepted,
+    /// The crd is established, and its resources are being served (`Established=True`)
+    Established,
+    /// Any other condition changed its status
+    ConditionChanged(CustomResourceDefinitionCondition),
+}
+
+/// Errors from [`apply_and_watch`]
+#[derive(Debug, Error)]
+pub enum EstablishError {
+    /// Applying the crd failed
+    #[error("failed to apply crd: {0}")]
+    Apply(#[source] kube_client::Error),
+    /// Watching the crd failed
+    #[error("failed to watch crd: {0}")]
+    Watch(#[source] watcher::Error),
+    /// The apiserver rejected the names of the crd (`NamesAccepted=False`)
+    #[error("crd names were not accepted: {reason}: {message}")]
+    NamesRejected {
+        /// Reason of the `NamesAccepted` condition
+        reason: String,
+        /// Message of the `NamesAccepted` condition
+        message: String,
+    },
+}
+
+/// Server-side apply a crd, and stream its progress towards being established
+///
+/// Every change of a status condition of the crd is reported once. The stream ends after
+/// [`CrdEstablishmentEvent::Established`], or after the first error (including the names being rejected).
+///
+/// Like [`await_condition`](crate::wait::await_condition), this does not time out on its own.
+///
+/// ```no_run
+/// use futures::TryStreamExt;
+/// use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+/// use kube::{Api, runtime::crd::apply_and_watch};
+/// # async fn wrapper(crd: CustomResourceDefinition) -> Result<(), Box<dyn std::error::Error>> {
+/// # let client: kube::Client = todo!();
+/// let crds: Api<CustomResourceDefinition> = Api::all(client);
+/// let mut progress = Box::pin(apply_and_watch(crds, crd, "my-installer"));
+/// while let Some(event) = progress.try_next().await? {
+///     println!("{:?}", event);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub fn apply_and_watch(
+    api: Api<CustomResourceDefinition>,
+    crd: CustomResourceDefinition,
+    field_manager: &str,
+) -> impl Stream<Item = Result<CrdEstablishmentEvent, EstablishError>> + Send {
+    let params = PatchParams::apply(field_manager).force();
+    let applied = async move {
+        let name = crd.name();
+        api.patch(&name, &params, &Patch::Apply(&crd))
+            .await
+            .map_err(EstablishError::Apply)?;
+        let progress = watch_object(api, &name)
+            .map_err(EstablishError::Watch)
+            .scan(BTreeMap::new(), |seen, obj| {
+                let events = match obj {
+                    Ok(Some(crd)) => establishment_events(seen, &crd),
+                    Ok(None) => vec![],
+                    Err(err) => vec![Err(err)],
+                };
+                future::ready(Some(stream::iter(events)))
+            })
+            .flatten();
+        Ok::<_, EstablishError>(
+            stream::once(future::ready(Ok(CrdEstablishmentEvent::Applied))).chain(progress),
+        )
+    };
+    stream::once(applied).try_flatten().scan(false, |done, event| {
+        if *done {
+            return future::ready(None);
+        }
+        *done = matches!(event, Ok(CrdEstablishmentEvent::Established) | Err(_));
+        future::ready(Some(event))
+    })
+}
+
+/// Field manager of the server-side applies of [`apply_crds`]
+pub const APPLY_CRDS_FIELD_MANAGER: &str = "kube-crd-installer";
+
+/// A crd that [`apply_crds`] failed to install
+#[derive(Debug, Error)]
+#[error("failed to install crd {name}: {source}")]
+pub struct CrdInstallError {
+    /// Name of the crd
+    pub name: String,
+    /// Why applying or establishing the crd failed
+    #[source]
+    pub source: EstablishError,
+}
+
+/// Errors from [`apply_crds`], when some of the crds failed to install
+#[derive(Debug, Error)]
+#[error("failed to install {} crds: {}", .failed.len(), .failed.iter().map(|e| e.name.as_str()).collect::<Vec<_>>().join(", "))]
+pub struct ApplyCrdsError {
+    /// The crds that were established, in input order
+    pub established: Vec<CustomResourceDefinition>,
+    /// The crds that failed, in input order
+    pub failed: Vec<CrdInstallError>,
+}
+
+/// Server-side apply a batch of crds, applying at most `concurrency` of them at a time, and wait until each is established
+///
+/// A failing crd does not stop the others from being installed; every failure is reported in the [`ApplyCrdsError`],
+/// next to the crds that were established. The applies use the [`APPLY_CRDS_FIELD_MANAGER`] field manager, and
+/// force conflicts like [`apply_and_watch`]. A `concurrency` of `0` is treated as `1`.
+///
+/// Like [`apply_and_watch`], this does not time out on its own.
+///
+/// # Errors
+///
+/// Fails if any crd could not be applied or established.
+pub async fn apply_crds(
+    client: &Client,
+    crds: Vec<CustomResourceDefinition>,
+    concurrency: usize,
+) -> Result<Vec<CustomResourceDefinition>, ApplyCrdsError> {
+    let api: Api<CustomResourceDefinition> = Api::all(client.clone());
+    let results = stream::iter(crds)
+        .map(|crd| {
+            let name = crd.name();
+            apply_and_establish(api.clone(), crd).map_err(|source| CrdInstallError { name, source })
+        })
+        .buffered(concurrency.max(1))
+        .collect::<Vec<_>>()
+        .await;
+    let mut established = vec![];
+    let mut failed = vec![];
+    for result in results {
+        match result {
+            Ok(crd) => established.push(crd),
+            Err(err) => failed.push(err),
+        }
+    }
+    if failed.is_empty() {
+        Ok(established)
+    } else {
+        Err(ApplyCrdsError { established, failed })
+    }
+}
+
+/// Server-side apply a crd, and return it once it is established
+async fn apply_and_establish(
+    api: Api<CustomResourceDefinition>,
+    crd: CustomResourceDefinition,
+) -> Result<CustomResourceDefinition, EstablishError> {
+    let name = crd.name();
+    let params = PatchParams::apply(APPLY_CRDS_FIELD_MANAGER).force();
+    api.patch(&name, &params, &Patch::Apply(&crd))
+        .await
+        .map_err(EstablishError::Apply)?;
+    let mut seen = BTreeMap::new();
+    let progress = watch_object(api, &name).map_err(EstablishError::Watch);
+    futures::pin_mut!(progress);
+    loop {
+        // the watcher keeps watching until it fails, so the stream never ends
+        if let Some(crd) = progress.try_next().await?.expect("watcher streams never end") {
+            for event in establishment_events(&mut seen, &crd) {
+                if let CrdEstablishmentEvent::Established = event? {
+                    return Ok(crd);
+                }
+            }
+        }
+    }
+}
+
+/// Errors from [`patch_crd_version_schema`]
+#[derive(Debug, Error)]
+pub enum SchemaPatchError {
+    /// The crd has no version of the given name
+    #[error("crd {name} has no version {version}")]
+    MissingVersion {
+        /// Name of the crd
+        name: String,
+        /// The missing version
+        version: String,
+    },
+    /// Reading or patching the crd failed
+    #[error("failed to patch crd: {0}")]
+    Api(#[source] kube_client::Error),
+    /// The crd kept changing between reading and patching it
+    #[error("crd {0} was modified concurrently on every attempt")]
+    Conflict(String),
+}
+
+/// How often [`patch_crd_version_schema`] reads and patches the crd before giving up on conflicts
+const SCHEMA_PATCH_ATTEMPTS: usize = 5;
+
+/// Replace the `openAPIV3Schema` of one version of an existing crd, leaving everything else untouched
+///
+/// As json patches can not select array items by name, the crd is read to find the index of `version`.
+/// The patch then checks that the crd is unchanged (by `resourceVersion`) since it was read, and the
+/// read and patch are retried when it was modified concurrently.
+///
+/// This is meant for iterating on a schema during development; installers should apply the whole crd instead.
+///
+/// # Errors
+///
+/// Fails if the crd does not have `version`, if reading or patching it fails,
+/// or if it was modified concurrently on every attempt.
+pub async fn patch_crd_version_schema(
+    api: &Api<CustomResourceDefinition>,
+    name: &str,
+    version: &str,
+    schema: &JSONSchemaProps,
+) -> Result<CustomResourceDefinition, SchemaPatchError> {
+    for _ in 0..SCHEMA_PATCH_ATTEMPTS {
+        let crd = api.get(name).await.map_err(SchemaPatchError::Api)?;
+        let index = crd
+            .spec
+            .versions
+            .iter()
+            .position(|v| v.name == version)
+            .ok_or_else(|| SchemaPatchError::MissingVersion {
+                name: name.to_string(),
+                version: version.to_string(),
+            })?;
+        let patch = json_patch::Patch(vec![
+            PatchOperation::Replace(ReplaceOperation {
+                path: "/metadata/resourceVersion".into(),
+                value: crd.resource_version().into(),
+            }),
+            PatchOperation::Test(TestOperation {
+                path: format!("/spec/versions/{}/name", index),
+                value: version.into(),
+            }),
+            PatchOperation::Add(AddOperation {
+                path: format!("/spec/versions/{}/schema", index),
+                value: serde_json::json!({ "openAPIV3Schema": schema }),
+            }),
+        ]);
+        match api
+            .patch(name, &PatchParams::default(), &Patch::Json::<()>(patch))
+            .await
+        {
+            Err(kube_client::Error::Api(err)) if err.code == 409 => {}
+            result => return result.map_err(SchemaPatchError::Api),
+        }
+    }
+    Err(SchemaPatchError::Conflict(name.to_string()))
+}
+
+/// Whether the conversion webhook of a crd is likely reachable, as reported by [`verify_conversion_webhook`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WebhookReachability {
+    /// The crd uses the `None` conversion strategy, so there is no webhook to check
+    NoWebhook,
+    /// The webhook is called through a `url` rather than a service, which can not be checked through the cluster
+    Url(String),
+    /// The crd uses the `Webhook` conversion strategy, but configures neither a service nor a `url` to call
+    MissingClientConfig,
+    /// The service of the webhook does not exist
+    MissingService {
+        /// Namespace of the service
+        namespace: String,
+        /// Name of the service
+        name: String,
+    },
+    /// The service of the webhook exists, but has no ready endpoints (like when no pod matches its selector)
+    NoEndpoints {
+        /// Namespace of the service
+        namespace: String,
+        /// Name of the service
+        name: String,
+    },
+    /// The service of the webhook exists and has ready endpoints
+    Ready {
+        /// Namespace of the service
+        namespace: String,
+        /// Name of the service
+        name: String,
+    },
+}
+
+impl WebhookReachability {
+    /// Whether nothing indicates the webhook would fail to be called
+    ///
+    /// This is the case for [`NoWebhook`](Self::NoWebhook), [`Url`](Self::Url) and [`Ready`](Self::Ready).
+    #[must_use]
+    pub fn is_likely_reachable(&self) -> bool {
+        !matches!(
+            self,
+            Self::MissingClientConfig | Self::MissingService { .. } | Self::NoEndpoints { .. }
+        )
+    }
+}
+
+/// Check that the service of the conversion webhook of `crd` exists and has ready endpoints
+///
+/// This is a pre-deploy sanity check for the common misconfigurations of a webhook service that does not exist, or
+/// that no ready pod backs. It can not tell whether the webhook converts correctly, or whether its port and
+/// certificates are right, so a [`WebhookReachability::Ready`] webhook may still fail.
+///
+/// # Errors
+///
+/// Fails if reading the service or its endpoints fails for other reasons than them not existing.
+pub async fn verify_conversion_webhook(
+    client: &Client,
+    crd: &CustomResourceDefinition,
+) -> kube_client::Result<WebhookReachability> {
+    let conversion = match &crd.spec.conversion {
+        Some(conversion) if conversion.strategy == "Webhook" => conversion,
+        _ => return Ok(WebhookReachability::NoWebhook),
+    };
+    let config = conversion.webhook.as_ref().and_then(|w| w.client_config.as_ref());
+    let service = match config {
+        Some(config) => match (&config.service, &config.url) {
+            (Some(service), _) => service,
+            (None, Some(url)) => return Ok(WebhookReachability::Url(url.clone())),
+            (None, None) => return Ok(WebhookReachability::MissingClientConfig),
+        },
+        None => return Ok(WebhookReachability::MissingClientConfig),
+    };
+    let (namespace, name) = (service.namespace.clone(), service.name.clone());
+    let services: Api<Service> = Api::namespaced(client.clone(), &namespace);
+    if services.get_opt(&name).await?.is_none() {
+        return Ok(WebhookReachability::MissingService { namespace, name });
+    }
+    let endpoints: Api<Endpoints> = Api::namespaced(client.clone(), &namespace);
+    let ready = endpoints
+        .get_opt(&name)
+        .await?
+        .and_then(|e| e.subsets)
+        .into_iter()
+        .flatten()
+        .any(|subset| subset.addresses.map_or(false, |a| !a.is_empty()));
+    Ok(if ready {
+        WebhookReachability::Ready { namespace, name }
+    } else {
+        WebhookReachability::NoEndpoints { namespace, name }
+    })
+}
+
+/// Events for the conditions of `crd` whose status differs from the last `seen` status
+fn establishment_events(
+    seen: &mut BTreeMap<String, String>,
+    crd: &CustomResourceDefinition,
+) -> Vec<Result<CrdEstablishmentEvent, EstablishError>> {
+    let conditions = crd.status.as_ref().and_then(|s| s.conditions.as_ref());
+    let mut events = vec![];
+    for cond in conditions.into_iter().flatten() {
+        if seen.get(&cond.type_) == Some(&cond.status) {
+            continue;
+        }
+        seen.insert(cond.type_.clone(), cond.status.clone());
+        events.push(match (cond.type_.as_str(), cond.status.as_str()) {
+            ("NamesAccepted", "True") => Ok(CrdEstablishmentEvent::NamesAccepted),
+            ("NamesAccepted", "False") => Err(EstablishError::NamesRejected {
+                reason: cond.reason.clone().unwrap_or_default(),
+                message: cond.message.clone().unwrap_or_default(),
+            }),
+            ("Established", "True") => Ok(CrdEstablishmentEvent::Established),
+            _ => Ok(CrdEstablishmentEvent::ConditionChanged(cond.clone())),
+        });
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_crds, establishment_events, list_all_versions, patch_crd_version_schema,
+        verify_conversion_webhook, ApiResourceResolver, CrdEstablishmentEvent, EstablishError,
+        SchemaPatchError, WebhookReachability,
+    };
+    use futures::pin_mut;
+    use http::{Request, Response};
+    use hyper::Body;
+    use k8s_openapi::apiextensions_apiserver::pkg::apis::apiextensions::v1::CustomResourceDefinition;
+    use kube_client::{
+        core::{ApiResource, GroupVersionKind},
+        Api, Client, ResourceExt,
+    };
+    use serde_json::json;
+    use std::collections::BTreeMap;
+    use tower_test::mock;
+
+    fn resource_list(gv: &str) -> serde_json::Value {
+        json!({
+            "kind": "APIResourceList",
+            "apiVersion": "v1",
+            "groupVersion": gv,
+            "resources": [{
+                "name": "foos",
+                "singularName": "foo",
+                "namespaced": true,
+                "kind": "Foo",
+                "verbs": ["get", "list", "watch"]
+            }]
+        })
+    }
+
+    fn object_list(api_version: &str, uids: &[&str]) -> serde_json::Value {
+        let items = uids
+            .iter()
+            .map(|uid| {
+                json!({
+                    "apiVersion": api_version,
+                    "kind": "Foo",
+                    "metadata": { "name": format!("foo-{}", uid), "uid": uid },
+                    "spec": {}
+                })
+            })
+            .collect::<Vec<_>>();
+        json!({
+            "apiVersion": api_version,
+            "kind": "FooList",
+            "metadata": {},
+            "items": items,
+        })
+    }
+
+    #[tokio::test]
+    async fn list_all_versions_dedups_and_annotates() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let responses = vec![
+                (
+                    "/apis",
+                    json!({
+                        "kind": "APIGroupList",
+                        "apiVersion": "v1",
+                        "groups": [{
+                            "name": "clux.dev",
+                            "versions": [
+                                { "groupVersion": "clux.dev/v1beta1", "version": "v1beta1" },
+                                { "groupVersion": "clux.dev/v1", "version": "v1" }
+                            ],
+                            "preferredVersion": { "groupVersion": "clux.dev/v1", "version": "v1" }
+                        }]
+                    }),
+                ),
+                ("/apis/clux.dev/v1beta1", resource_list("clux.dev/v1beta1")),
+                ("/apis/clux.dev/v1", resource_list("clux.dev/v1")),
+                ("/apis/clux.dev/v1/foos", object_list("clux.dev/v1", &["a", "b"])),
+                (
+                    "/apis/clux.dev/v1beta1/foos",
+                    object_list("clux.dev/v1beta1", &["a", "c"]),
+                ),
+            ];
+            for (path, body) in responses {
+                let (request, send) = handle.next_request().await.expect("service not called");
+                assert_eq!(request.uri().path(), path);
+                send.send_response(Response::builder().body(Body::from(body.to_string())).unwrap());
+            }
+        });
+
+        let client = Client::new(mock_service, "default");
+        let ar = ApiResource::from_gvk(&GroupVersionKind::gvk("clux.dev", "v1", "Foo"));
+        let objects = list_all_versions(&client, &ar).await.unwrap();
+        spawned.await.unwrap();
+
+        let found = objects
+            .iter()
+            .map(|o| (o.name(), o.types.clone().unwrap().api_version))
+            .collect::<Vec<_>>();
+        assert_eq!(found, vec![
+            ("foo-a".to_string(), "clux.dev/v1".to_string()),
+            ("foo-b".to_string(), "clux.dev/v1".to_string()),
+            ("foo-c".to_string(), "clux.dev/v1beta1".to_string()),
+        ]);
+    }
+
+    #[derive(
+        kube::CustomResource, serde::Serialize, serde::Deserialize, Clone, Debug, schemars::JsonSchema,
+    )]
+    #[kube(group = "clux.dev", version = "v1beta1", kind = "Foo", namespaced)]
+    struct FooSpec {}
+
+    #[tokio::test]
+    async fn resolver_prefers_server_version_and_caches_groups() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let responses = vec![
+                (
+                    "/apis",
+                    json!({
+                        "kind": "APIGroupList",
+                        "apiVersion": "v1",
+                        "groups": [{
+                            "name": "clux.dev",
+                            "versions": [
+                                { "groupVersion": "clux.dev/v1", "version": "v1" },
+                                { "groupVersion": "clux.dev/v1beta1", "version": "v1beta1" }
+                            ],
+                            "preferredVersion": { "groupVersion": "clux.dev/v1", "version": "v1" }
+                        }]
+                    }),
+                ),
+                ("/apis/clux.dev/v1", resource_list("clux.dev/v1")),
+                ("/apis/clux.dev/v1beta1", resource_list("clux.dev/v1beta1")),
+            ];
+            for (path, body) in responses {
+                let (request, send) = handle.next_request().await.expect("service not called");
+                assert_eq!(request.uri().path(), path);
+                send.send_response(Response::builder().body(Body::from(body.to_string())).unwrap());
+            }
+        });
+
+        let mut resolver = ApiResourceResolver::new(Client::new(mock_service, "default"));
+        let ar = resolver.resolve::<Foo>().await.unwrap();
+        assert_eq!(ar.api_version, "clux.dev/v1");
+        assert_eq!(ar.plural, "foos");
+        let cached = resolver.resolve::<Foo>().await.unwrap();
+        assert_eq!(cached.api_version, "clux.dev/v1");
+        spawned.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn resolver_falls_back_when_discovery_fails() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(request.uri().path(), "/apis");
+            send.send_response(
+                Response::builder()
+                    .status(500)
+                    .body(Body::from(
+                        json!({ "kind": "Status", "apiVersion": "v1", "status": "Failure", "message": "down", "reason": "InternalError", "code": 500 }).to_string(),
+                    ))
+                    .unwrap(),
+            );
+        });
+
+        let ar = super::resolve_api_resource::<Foo>(&Client::new(mock_service, "default"))
+            .await
+            .unwrap();
+        assert_eq!(ar.api_version, "clux.dev/v1beta1");
+        spawned.await.unwrap();
+    }
+
+    fn crd_with_conditions(conditions: &[(&str, &str)]) -> CustomResourceDefinition {
+        let conditions = conditions
+            .iter()
+            .map(|(type_, status)| json!({ "type": type_, "status": status, "reason": "Test", "message": "test" }))
+            .collect::<Vec<_>>();
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": []
+            },
+            "status": {
+                "acceptedNames": { "kind": "Foo", "plural": "foos" },
+                "storedVersions": [],
+                "conditions": conditions
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn establishment_events_report_transitions_once() {
+        let mut seen = BTreeMap::new();
+        let events = establishment_events(
+            &mut seen,
+            &crd_with_conditions(&[("NamesAccepted", "True"), ("Established", "False")]),
+        );
+        assert!(matches!(events[0], Ok(CrdEstablishmentEvent::NamesAccepted)));
+        assert!(
+            matches!(&events[1], Ok(CrdEstablishmentEvent::ConditionChanged(c)) if c.type_ == "Established")
+        );
+
+        let events = establishment_events(
+            &mut seen,
+            &crd_with_conditions(&[("NamesAccepted", "True"), ("Established", "True")]),
+        );
+        assert_eq!(events.len(), 1);
+        assert!(matches!(events[0], Ok(CrdEstablishmentEvent::Established)));
+    }
+
+    #[test]
+    fn establishment_events_fail_on_rejected_names() {
+        let events = establishment_events(
+            &mut BTreeMap::new(),
+            &crd_with_conditions(&[("NamesAccepted", "False")]),
+        );
+        assert!(matches!(&events[0], Err(EstablishError::NamesRejected { reason, .. }) if reason == "Test"));
+    }
+
+    fn two_version_crd(resource_version: &str) -> serde_json::Value {
+        let schema = json!({ "openAPIV3Schema": { "type": "object" } });
+        json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": "foos.clux.dev", "resourceVersion": resource_version },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [
+                    { "name": "v1", "served": true, "storage": true, "schema": schema },
+                    { "name": "v2", "served": true, "storage": false, "schema": schema }
+                ]
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn patch_crd_version_schema_targets_version_index_and_retries_conflicts() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let crd_path = "/apis/apiextensions.k8s.io/v1/customresourcedefinitions/foos.clux.dev";
+            for (attempt, resource_version) in [(0, "1"), (1, "2")] {
+                let (request, send) = handle.next_request().await.expect("service not called");
+                assert_eq!(request.method(), http::Method::GET);
+                assert_eq!(request.uri().path(), crd_path);
+                send.send_response(
+                    Response::builder()
+                        .body(Body::from(two_version_crd(resource_version).to_string()))
+                        .unwrap(),
+                );
+
+                let (request, send) = handle.next_request().await.expect("service not called");
+                assert_eq!(request.method(), http::Method::PATCH);
+                assert_eq!(request.headers()["content-type"], "application/json-patch+json");
+                let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                let patch: serde_json::Value = serde_json::from_slice(&body).unwrap();
+                assert_eq!(
+                    patch,
+                    json!([
+                        { "op": "replace", "path": "/metadata/resourceVersion", "value": resource_version },
+                        { "op": "test", "path": "/spec/versions/1/name", "value": "v2" },
+                        {
+                            "op": "add",
+                            "path": "/spec/versions/1/schema",
+                            "value": { "openAPIV3Schema": { "type": "object", "x-kubernetes-preserve-unknown-fields": true } }
+                        },
+                    ])
+                );
+                if attempt == 0 {
+                    let status = json!({ "kind": "Status", "apiVersion": "v1", "status": "Failure", "message": "conflict", "reason": "Conflict", "code": 409 });
+                    send.send_response(
+                        Response::builder()
+                            .status(409)
+                            .body(Body::from(status.to_string()))
+                            .unwrap(),
+                    );
+                } else {
+                    let mut patched = two_version_crd("3");
+                    patched["spec"]["versions"][1]["schema"]["openAPIV3Schema"]
+                        ["x-kubernetes-preserve-unknown-fields"] = json!(true);
+                    send.send_response(Response::builder().body(Body::from(patched.to_string())).unwrap());
+                }
+            }
+        });
+
+        let api: Api<CustomResourceDefinition> = Api::all(Client::new(mock_service, "default"));
+        let schema =
+            serde_json::from_value(json!({ "type": "object", "x-kubernetes-preserve-unknown-fields": true }))
+                .unwrap();
+        let patched = patch_crd_version_schema(&api, "foos.clux.dev", "v2", &schema)
+            .await
+            .unwrap();
+        spawned.await.unwrap();
+        let schemas = patched
+            .spec
+            .versions
+            .iter()
+            .map(|v| {
+                v.schema
+                    .as_ref()
+                    .unwrap()
+                    .open_api_v3_schema
+                    .as_ref()
+                    .unwrap()
+                    .x_kubernetes_preserve_unknown_fields
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(schemas, vec![None, Some(true)]);
+    }
+
+    #[tokio::test]
+    async fn patch_crd_version_schema_rejects_missing_version() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (_, send) = handle.next_request().await.expect("service not called");
+            send.send_response(
+                Response::builder()
+                    .body(Body::from(two_version_crd("1").to_string()))
+                    .unwrap(),
+            );
+        });
+
+        let api: Api<CustomResourceDefinition> = Api::all(Client::new(mock_service, "default"));
+        let err = patch_crd_version_schema(&api, "foos.clux.dev", "v3", &Default::default())
+            .await
+            .unwrap_err();
+        spawned.await.unwrap();
+        assert!(matches!(err, SchemaPatchError::MissingVersion { version, .. } if version == "v3"));
+    }
+
+    fn named_crd(name: &str, established: bool) -> serde_json::Value {
+        let conditions = if established {
+            json!([{ "type": "Established", "status": "True" }])
+        } else {
+            json!([])
+        };
+        json!({
+            "apiVersion": "apiextensions.k8s.io/v1",
+            "kind": "CustomResourceDefinition",
+            "metadata": { "name": format!("{}.clux.dev", name), "resourceVersion": "1" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": name, "plural": name },
+                "scope": "Namespaced",
+                "versions": []
+            },
+            "status": {
+                "acceptedNames": { "kind": name, "plural": name },
+                "storedVersions": [],
+                "conditions": conditions
+            }
+        })
+    }
+
+    #[tokio::test]
+    async fn apply_crds_bounds_concurrency_and_reports_failures() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let respond = |request: Request<Body>| {
+                let name = request.uri().path().rsplit('/').next().unwrap().to_string();
+                if request.method() == http::Method::PATCH && name == "bars.clux.dev" {
+                    let status = json!({ "kind": "Status", "apiVersion": "v1", "status": "Failure", "message": "invalid", "reason": "Invalid", "code": 422 });
+                    return Response::builder()
+                        .status(422)
+                        .body(Body::from(status.to_string()))
+                        .unwrap();
+                }
+                let body = if request.method() == http::Method::PATCH {
+                    named_crd(name.trim_end_matches(".clux.dev"), false)
+                } else {
+                    // the watcher lists the crd by name
+                    let query = request.uri().query().unwrap().to_string();
+                    let name = query
+                        .split("metadata.name%3D")
+                        .nth(1)
+                        .unwrap()
+                        .split('.')
+                        .next()
+                        .unwrap();
+                    json!({
+                        "apiVersion": "apiextensions.k8s.io/v1",
+                        "kind": "CustomResourceDefinitionList",
+                        "metadata": { "resourceVersion": "1" },
+                        "items": [named_crd(name, true)]
+                    })
+                };
+                Response::builder().body(Body::from(body.to_string())).unwrap()
+            };
+
+            // two crds are applied concurrently, and the third waits for one of them
+            let (first, send_first) = handle.next_request().await.expect("service not called");
+            let (second, send_second) = handle.next_request().await.expect("service not called");
+            assert_eq!(first.method(), http::Method::PATCH);
+            assert_eq!(second.method(), http::Method::PATCH);
+            let third =
+                tokio::time::timeout(std::time::Duration::from_millis(100), handle.next_request()).await;
+            assert!(third.is_err(), "concurrency limit exceeded");
+            send_first.send_response(respond(first));
+            send_second.send_response(respond(second));
+            while let Some((request, send)) = handle.next_request().await {
+                send.send_response(respond(request));
+            }
+        });
+
+        let client = Client::new(mock_service, "default");
+        let crds = ["foos", "bars", "bazs"]
+            .iter()
+            .map(|name| serde_json::from_value(named_crd(name, false)).unwrap())
+            .collect::<Vec<CustomResourceDefinition>>();
+        let err = apply_crds(&client, crds, 2).await.unwrap_err();
+        drop(client);
+        spawned.await.unwrap();
+
+        let established = err.established.iter().map(ResourceExt::name).collect::<Vec<_>>();
+        assert_eq!(established, vec!["foos.clux.dev", "bazs.clux.dev"]);
+        assert_eq!(err.failed.len(), 1);
+        assert_eq!(err.failed[0].name, "bars.clux.dev");
+        assert!(matches!(err.failed[0].source, EstablishError::Apply(_)));
+    }
+
+    fn webhook_crd(conversion: &serde_json::Value) -> CustomResourceDefinition {
+        serde_json::from_value(json!({
+            "metadata": { "name": "foos.clux.dev" },
+            "spec": {
+                "group": "clux.dev",
+                "names": { "kind": "Foo", "plural": "foos" },
+                "scope": "Namespaced",
+                "versions": [],
+                "conversion": conversion
+            }
+        }))
+        .unwrap()
+    }
+
+    fn not_found() -> Response<Body> {
+        let status = json!({ "kind": "Status", "apiVersion": "v1", "status": "Failure", "message": "not found", "reason": "NotFound", "code": 404 });
+        Response::builder()
+            .status(404)
+            .body(Body::from(status.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn verify_conversion_webhook_flags_missing_service() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let (request, send) = handle.next_request().await.expect("service not called");
+            assert_eq!(
+                request.uri().path(),
+                "/api/v1/namespaces/ops/services/foo-webhook"
+            );
+            send.send_response(not_found());
+        });
+
+        let client = Client::new(mock_service, "default");
+        let crd = webhook_crd(&json!({
+            "strategy": "Webhook",
+            "webhook": {
+                "clientConfig": { "service": { "namespace": "ops", "name": "foo-webhook" } },
+                "conversionReviewVersions": ["v1"]
+            }
+        }));
+        let reachability = verify_conversion_webhook(&client, &crd).await.unwrap();
+        spawned.await.unwrap();
+        assert_eq!(reachability, WebhookReachability::MissingService {
+            namespace: "ops".into(),
+            name: "foo-webhook".into(),
+        });
+        assert!(!reachability.is_likely_reachable());
+    }
+
+    #[tokio::test]
+    async fn verify_conversion_webhook_checks_endpoints() {
+        let (mock_service, handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let spawned = tokio::spawn(async move {
+            pin_mut!(handle);
+            let service = json!({
+                "apiVersion": "v1",
+                "kind": "Service",
+                "metadata": { "name": "foo-webhook", "namespace": "ops" }
+            });
+            let endpoints = json!({
+                "apiVersion": "v1",
+                "kind": "Endpoints",
+                "metadata": { "name": "foo-webhook", "namespace": "ops" },
+                "subsets": [{ "notReadyAddresses": [{ "ip": "10.0.0.1" }] }]
+            });
+            for (path, body) in [
+                ("/api/v1/namespaces/ops/services/foo-webhook", service),
+                ("/api/v1/namespaces/ops/endpoints/foo-webhook", endpoints),
+            ] {
+                let (request, send) = handle.next_request().await.expect("service not called");
+                assert_eq!(request.uri().path(), path);
+                send.send_response(Response::builder().body(Body::from(body.to_string())).unwrap());
+            }
+        });
+
+        let client = Client::new(mock_service, "default");
+        let crd = webhook_crd(&json!({
+            "strategy": "Webhook",
+            "webhook": {
+                "clientConfig": { "service": { "namespace": "ops", "name": "foo-webhook" } },
+                "conversionReviewVersions": ["v1"]
+            }
+        }));
+        let reachability = verify_conversion_webhook(&client, &crd).await.unwrap();
+        spawned.await.unwrap();
+        assert_eq!(reachability, WebhookReachability::NoEndpoints {
+            namespace: "ops".into(),
+            name: "foo-webhook".into(),
+        });
+
+        // nothing is requested without a webhook
+        let (mock_service, _handle) = mock::pair::<Request<Body>, Response<Body>>();
+        let client = Client::new(mock_service, "default");
+        let none = webhook_crd(&json!({ "strategy": "None" }));
+        assert_eq!(
+            verify_conversion_webhook(&client, &none).await.unwrap(),
+            WebhookReachability::NoWebhook
+        );
+        for conversion in [
+            json!({ "strategy": "Webhook" }),
+            json!({ "strategy": "Webhook", "webhook": { "clientConfig": {}, "conversionReviewVersions": ["v1"] } }),
+        ] {
+            let reachability = verify_conversion_webhook(&client, &webhook_crd(&conversion))
+                .await
+                .unwrap();
+            assert_eq!(reachability, WebhookReachability::MissingClientConfig);
+            assert!(!reachability.is_likely_reachable());
+        }
+    }
+}
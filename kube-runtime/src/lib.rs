@@ -18,6 +18,7 @@
 #![allow(clippy::semicolon_if_nothing_returned)]
 
 pub mod controller;
+pub mod crd;
 k8s_openapi::k8s_if_ge_1_19! {
     pub mod events;
 }
@@ -166,7 +166,7 @@ cfg_error! {
 /// Re-exports from [`kube-derive`](kube_derive)
 #[cfg(feature = "derive")]
 #[cfg_attr(docsrs, doc(cfg(feature = "derive")))]
-pub use kube_derive::CustomResource;
+pub use kube_derive::{crd_merger, CustomResource};
 
 /// Re-exports from [`kube-runtime`](kube_runtime)
 #[cfg(feature = "runtime")]